@@ -0,0 +1,361 @@
+use super::smtp::{self, SmtpConfig};
+use super::{net, AlertEvent};
+use crate::host::Host;
+use crate::secrets::decrypt;
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(15);
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where notifications go. Each configured channel gets every notification.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelConfig {
+    /// Unique; identifies the channel in logs and for `test_alert_channel`.
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub channel: Channel,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Channel {
+    /// System notification from the desktop app; ignored by the headless service.
+    Desktop,
+    Email(SmtpConfig),
+    /// POST of the alert as JSON. The payload also has a `text` field, which
+    /// Slack, Mattermost and Teams incoming webhooks display as is.
+    Webhook {
+        url: String,
+    },
+    /// Run a local program with the alert in `VISOR_ALERT_*` variables and as
+    /// JSON on its standard input. A non-zero exit status is a failure.
+    Script {
+        path: String,
+    },
+}
+
+impl ChannelConfig {
+    pub(crate) async fn send(&self, host: &Host, event: &AlertEvent) -> Result<(), String> {
+        match &self.channel {
+            Channel::Desktop => host.notify(&event.title(), &event.message),
+            Channel::Email(config) => {
+                let password = config.password.as_deref().map(decrypt).transpose()?;
+                smtp::send_mail(config, password.as_deref(), &event.title(), &event.body()).await
+            }
+            Channel::Webhook { url } => post_webhook(url, event).await,
+            Channel::Script { path } => run_script(path, event).await,
+        }
+    }
+}
+
+struct Url<'a> {
+    tls: bool,
+    host: &'a str,
+    port: u16,
+    /// Host header value: the host, with the port when it is not the default.
+    authority: &'a str,
+    target: &'a str,
+}
+
+fn parse_url(url: &str) -> Result<Url<'_>, String> {
+    let invalid = || format!("URL invalide: {}", url);
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        return Err(format!(
+            "URL invalide (http:// ou https:// attendu): {}",
+            url
+        ));
+    };
+    let (authority, target) = match rest.find(['/', '?']) {
+        Some(i) if rest[i..].starts_with('/') => (&rest[..i], &rest[i..]),
+        Some(_) => return Err(invalid()),
+        None => (rest, "/"),
+    };
+    if authority.contains('@') || target.contains(char::is_whitespace) {
+        return Err(invalid());
+    }
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => {
+            (host, port.parse::<u16>().map_err(|_| invalid())?)
+        }
+        _ => (authority, if tls { 443 } else { 80 }),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok(Url {
+        tls,
+        host,
+        port,
+        authority,
+        target,
+    })
+}
+
+pub(crate) fn validate_url(url: &str) -> Result<(), String> {
+    parse_url(url).map(|_| ())
+}
+
+async fn post_webhook(url: &str, event: &AlertEvent) -> Result<(), String> {
+    let url = parse_url(url)?;
+    let mut payload = serde_json::to_value(event).map_err(|e| e.to_string())?;
+    payload["text"] = format!("{}\n{}", event.title(), event.message).into();
+    let body = payload.to_string();
+
+    let exchange = async {
+        let mut stream = net::connect(url.host, url.port, url.tls).await?;
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: visor/{}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            url.target,
+            url.authority,
+            env!("CARGO_PKG_VERSION"),
+            body.len(),
+            body
+        );
+        stream
+            .write_all(request.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        stream.flush().await.map_err(|e| e.to_string())?;
+
+        // Only the status line matters; read until it is complete.
+        let mut head = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !head.contains(&b'\n') && head.len() < 8192 {
+            let read = stream.read(&mut buf).await.map_err(|e| e.to_string())?;
+            if read == 0 {
+                break;
+            }
+            head.extend_from_slice(&buf[..read]);
+        }
+        let head = String::from_utf8_lossy(&head);
+        let status_line = head.lines().next().unwrap_or_default();
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse::<u16>().ok())
+            .ok_or_else(|| format!("Webhook: réponse invalide: {}", status_line))?;
+        if !(200..300).contains(&status) {
+            return Err(format!("Webhook: {}", status_line));
+        }
+        Ok(())
+    };
+    tokio::time::timeout(WEBHOOK_TIMEOUT, exchange)
+        .await
+        .map_err(|_| format!("Webhook {}: délai dépassé", url.host))?
+}
+
+async fn run_script(path: &str, event: &AlertEvent) -> Result<(), String> {
+    let payload = serde_json::to_string(event).map_err(|e| e.to_string())?;
+    let mut child = tokio::process::Command::new(path)
+        .env("VISOR_ALERT_ID", event.alert_id.to_string())
+        .env("VISOR_ALERT_STATE", &event.state)
+        .env("VISOR_ALERT_KIND", &event.kind)
+        .env("VISOR_ALERT_SEVERITY", &event.severity)
+        .env(
+            "VISOR_ALERT_LINE_ID",
+            event.line_id.map(|id| id.to_string()).unwrap_or_default(),
+        )
+        .env("VISOR_ALERT_LINE", &event.line)
+        .env("VISOR_ALERT_MESSAGE", &event.message)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Script {}: {}", path, e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A script that ignores its input may exit before reading it.
+        let _ = stdin.write_all(payload.as_bytes()).await;
+    }
+    let output = tokio::time::timeout(SCRIPT_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| {
+            format!(
+                "Script {}: délai de {}s dépassé",
+                path,
+                SCRIPT_TIMEOUT.as_secs()
+            )
+        })?
+        .map_err(|e| format!("Script {}: {}", path, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "Script {}: {} {}",
+            path,
+            output.status,
+            stderr.trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// One-connection HTTP server on a local port answering with `status_line`;
+    /// yields the request head and body it received.
+    async fn stub(status_line: &'static str) -> (u16, JoinHandle<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            let head_end = loop {
+                let read = socket.read(&mut buf).await.unwrap();
+                assert!(read > 0, "connection closed before the end of the headers");
+                request.extend_from_slice(&buf[..read]);
+                if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break i;
+                }
+            };
+            let head = String::from_utf8(request[..head_end].to_vec()).unwrap();
+            let length: usize = head
+                .lines()
+                .find_map(|l| l.strip_prefix("Content-Length: "))
+                .unwrap()
+                .parse()
+                .unwrap();
+            while request.len() < head_end + 4 + length {
+                let read = socket.read(&mut buf).await.unwrap();
+                assert!(read > 0, "connection closed before the end of the body");
+                request.extend_from_slice(&buf[..read]);
+            }
+            let body = String::from_utf8(request[head_end + 4..].to_vec()).unwrap();
+            let response = format!(
+                "{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status_line
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            (head, body)
+        });
+        (port, handle)
+    }
+
+    fn event() -> AlertEvent {
+        AlertEvent {
+            alert_id: 7,
+            state: "FIRING".to_string(),
+            kind: "SILENCE".to_string(),
+            severity: "CRITICAL".to_string(),
+            line_id: Some(2),
+            line: "Ligne 2".to_string(),
+            message: "Aucun fichier depuis 45 min".to_string(),
+            first_seen: "2024-01-31 08:00:00".to_string(),
+            occurrences: 3,
+        }
+    }
+
+    #[tokio::test]
+    async fn posts_the_alert_as_json() {
+        let (port, server) = stub("HTTP/1.1 204 No Content").await;
+        post_webhook(
+            &format!("http://127.0.0.1:{}/hooks/visor?canal=prod", port),
+            &event(),
+        )
+        .await
+        .unwrap();
+        let (head, body) = server.await.unwrap();
+
+        let mut lines = head.lines();
+        assert_eq!(lines.next(), Some("POST /hooks/visor?canal=prod HTTP/1.1"));
+        assert!(lines
+            .clone()
+            .any(|l| l == format!("Host: 127.0.0.1:{}", port)));
+        assert!(lines.any(|l| l == "Content-Type: application/json"));
+        let payload: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(payload["alert_id"], 7);
+        assert_eq!(payload["severity"], "CRITICAL");
+        assert_eq!(payload["line_id"], 2);
+        assert_eq!(
+            payload["text"],
+            format!("{}\nAucun fichier depuis 45 min", event().title())
+        );
+    }
+
+    #[tokio::test]
+    async fn non_2xx_responses_are_errors() {
+        for status in [
+            "HTTP/1.1 500 Internal Server Error",
+            "HTTP/1.1 404 Not Found",
+            "HTTP/1.1 302 Found",
+        ] {
+            let (port, server) = stub(status).await;
+            let err = post_webhook(&format!("http://127.0.0.1:{}/", port), &event())
+                .await
+                .unwrap_err();
+            assert_eq!(err, format!("Webhook: {}", status));
+            server.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_responses_and_refused_connections_are_errors() {
+        let (port, _server) = stub("garbage").await;
+        let err = post_webhook(&format!("http://127.0.0.1:{}/", port), &event())
+            .await
+            .unwrap_err();
+        assert_eq!(err, "Webhook: réponse invalide: garbage");
+
+        // Bound then dropped: nothing listens on the port any more.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        assert!(
+            post_webhook(&format!("http://127.0.0.1:{}/", port), &event())
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn urls_are_checked() {
+        let url = parse_url("https://hooks.example.com/services/T0").unwrap();
+        assert!(url.tls);
+        assert_eq!(
+            (url.host, url.port, url.target),
+            ("hooks.example.com", 443, "/services/T0")
+        );
+        let url = parse_url("http://[::1]:8080").unwrap();
+        assert_eq!(
+            (url.host, url.port, url.authority, url.target),
+            ("::1", 8080, "[::1]:8080", "/")
+        );
+        for invalid in [
+            "ftp://x/",
+            "http://",
+            "http://user@host/",
+            "http://host:port/",
+            "http://host?x=1",
+            "http://h/a b",
+        ] {
+            assert!(
+                validate_url(invalid).is_err(),
+                "{} should be rejected",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,693 @@
+//! Background monitor: evaluates every active line's status and error rate,
+//! records one alert per line and condition, and notifies the configured
+//! channels when an alert opens, escalates, is still unacknowledged after
+//! `renotify_minutes`, or clears.
+
+mod channels;
+mod net;
+mod smtp;
+
+pub use channels::{Channel, ChannelConfig};
+
+use crate::commands::dashboard::{line_status, parse_processed_at};
+use crate::host::Host;
+use crate::secrets::{mask_password, password_to_store};
+use chrono::{Duration as ChronoDuration, Local};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Row, Sqlite};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Let watchers catch up on files that arrived while the app was closed.
+const STARTUP_DELAY: Duration = Duration::from_secs(60);
+const MIN_CHECK_INTERVAL_SECONDS: i64 = 10;
+/// Resolved alerts are deleted after this many days.
+const HISTORY_DAYS: i64 = 90;
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// No file within `interval_check` (WARNING) or `interval_alert` (CRITICAL).
+pub(crate) const KIND_SILENCE: &str = "SILENCE";
+/// Too many files in error over the last `error_window_minutes`.
+pub(crate) const KIND_ERROR_RATE: &str = "ERROR_RATE";
+
+/// Monitor settings, stored in the `config` table; channels as JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertSettings {
+    pub enabled: bool,
+    pub check_interval_seconds: i64,
+    pub error_window_minutes: i64,
+    /// Share of files in error, in percent, that opens an ERROR_RATE alert.
+    pub error_rate_percent: i64,
+    /// Fewer files than this in the window never open an ERROR_RATE alert.
+    pub error_min_files: i64,
+    /// Repeat an unacknowledged alert this often; `0` notifies once.
+    pub renotify_minutes: i64,
+    pub notify_recovery: bool,
+    pub channels: Vec<ChannelConfig>,
+}
+
+impl Default for AlertSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            check_interval_seconds: 60,
+            error_window_minutes: 15,
+            error_rate_percent: 50,
+            error_min_files: 3,
+            renotify_minutes: 60,
+            notify_recovery: true,
+            channels: Vec::new(),
+        }
+    }
+}
+
+impl AlertSettings {
+    /// Settings as stored; SMTP passwords are still encrypted.
+    pub(crate) async fn load(pool: &Pool<Sqlite>) -> Self {
+        let defaults = Self::default();
+        let get = |key: &'static str| async move {
+            sqlx::query_scalar::<_, String>("SELECT value FROM config WHERE key = ?")
+                .bind(key)
+                .fetch_optional(pool)
+                .await
+                .ok()
+                .flatten()
+        };
+        let get_int = |key: &'static str, default: i64| async move {
+            get(key)
+                .await
+                .and_then(|v| v.trim().parse::<i64>().ok())
+                .unwrap_or(default)
+                .max(0)
+        };
+
+        let channels = match get("alerts_channels").await {
+            Some(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                log::error!(
+                    "Alertes: canaux illisibles, aucune notification envoyée: {}",
+                    e
+                );
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        Self {
+            enabled: get("alerts_enabled")
+                .await
+                .map(|v| v == "1")
+                .unwrap_or(defaults.enabled),
+            check_interval_seconds: get_int(
+                "alerts_check_interval_seconds",
+                defaults.check_interval_seconds,
+            )
+            .await
+            .max(MIN_CHECK_INTERVAL_SECONDS),
+            error_window_minutes: get_int(
+                "alerts_error_window_minutes",
+                defaults.error_window_minutes,
+            )
+            .await,
+            error_rate_percent: get_int("alerts_error_rate_percent", defaults.error_rate_percent)
+                .await,
+            error_min_files: get_int("alerts_error_min_files", defaults.error_min_files).await,
+            renotify_minutes: get_int("alerts_renotify_minutes", defaults.renotify_minutes).await,
+            notify_recovery: get("alerts_notify_recovery")
+                .await
+                .map(|v| v == "1")
+                .unwrap_or(defaults.notify_recovery),
+            channels,
+        }
+    }
+
+    /// For the settings screen: SMTP passwords are masked.
+    pub(crate) async fn load_masked(pool: &Pool<Sqlite>) -> Self {
+        let mut settings = Self::load(pool).await;
+        for channel in &mut settings.channels {
+            if let Channel::Email(smtp) = &mut channel.channel {
+                mask_password(&mut smtp.password);
+            }
+        }
+        settings
+    }
+
+    /// Validate and store; an SMTP password sent back masked keeps the one
+    /// stored for the channel of the same name.
+    pub(crate) async fn save(&self, pool: &Pool<Sqlite>) -> Result<(), String> {
+        if self.check_interval_seconds < MIN_CHECK_INTERVAL_SECONDS {
+            return Err(format!(
+                "Intervalle de vérification: {} secondes minimum",
+                MIN_CHECK_INTERVAL_SECONDS
+            ));
+        }
+        if !(1..=100).contains(&self.error_rate_percent) {
+            return Err("Taux d'erreur: entre 1 et 100 %".to_string());
+        }
+        if self.error_window_minutes < 1 || self.error_min_files < 1 || self.renotify_minutes < 0 {
+            return Err(
+                "Fenêtre d'erreurs, nombre minimal de fichiers et rappel doivent être positifs"
+                    .to_string(),
+            );
+        }
+
+        let stored = Self::load(pool).await;
+        let mut names = HashSet::new();
+        let mut to_store = self.channels.clone();
+        for channel in &mut to_store {
+            let name = channel.name.trim().to_string();
+            if name.is_empty() || !names.insert(name.clone()) {
+                return Err(format!(
+                    "Nom de canal vide ou en double: '{}'",
+                    channel.name
+                ));
+            }
+            channel.name = name;
+            match &mut channel.channel {
+                Channel::Desktop => {}
+                Channel::Email(smtp) => {
+                    smtp.validate()
+                        .map_err(|e| format!("{}: {}", channel.name, e))?;
+                    smtp.password = match password_to_store(smtp.password.as_deref())? {
+                        Some(password) => Some(password),
+                        None => stored
+                            .channels
+                            .iter()
+                            .find(|c| c.name == channel.name)
+                            .and_then(|c| match &c.channel {
+                                Channel::Email(old) => old.password.clone(),
+                                _ => None,
+                            }),
+                    };
+                }
+                Channel::Webhook { url } => {
+                    channels::validate_url(url.trim())
+                        .map_err(|e| format!("{}: {}", channel.name, e))?;
+                    *url = url.trim().to_string();
+                }
+                Channel::Script { path } => {
+                    if path.trim().is_empty() {
+                        return Err(format!("{}: chemin du script manquant", channel.name));
+                    }
+                }
+            }
+        }
+        let channels_json = serde_json::to_string(&to_store).map_err(|e| e.to_string())?;
+
+        for (key, value) in [
+            (
+                "alerts_enabled",
+                if self.enabled { "1" } else { "0" }.to_string(),
+            ),
+            (
+                "alerts_check_interval_seconds",
+                self.check_interval_seconds.to_string(),
+            ),
+            (
+                "alerts_error_window_minutes",
+                self.error_window_minutes.to_string(),
+            ),
+            (
+                "alerts_error_rate_percent",
+                self.error_rate_percent.to_string(),
+            ),
+            ("alerts_error_min_files", self.error_min_files.to_string()),
+            ("alerts_renotify_minutes", self.renotify_minutes.to_string()),
+            (
+                "alerts_notify_recovery",
+                if self.notify_recovery { "1" } else { "0" }.to_string(),
+            ),
+            ("alerts_channels", channels_json),
+        ] {
+            sqlx::query("INSERT OR REPLACE INTO config (key, value) VALUES (?, ?)")
+                .bind(key)
+                .bind(value)
+                .execute(pool)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// A row of the `alerts` table. Status is OPEN, ACKNOWLEDGED or RESOLVED.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Alert {
+    pub id: i64,
+    pub line_id: Option<i64>,
+    pub line_name: Option<String>,
+    pub kind: String,
+    pub severity: String,
+    pub status: String,
+    pub message: String,
+    pub occurrences: i64,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub last_notified: Option<String>,
+    pub acknowledged_at: Option<String>,
+    pub acknowledged_by: Option<String>,
+    pub resolved_at: Option<String>,
+}
+
+/// What the channels receive.
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertEvent {
+    pub alert_id: i64,
+    /// FIRING, REMINDER, RESOLVED, or TEST from `test_alert_channel`.
+    pub state: String,
+    pub kind: String,
+    pub severity: String,
+    pub line_id: Option<i64>,
+    pub line: String,
+    pub message: String,
+    pub first_seen: String,
+    pub occurrences: i64,
+}
+
+impl AlertEvent {
+    pub(crate) fn title(&self) -> String {
+        let what = match self.kind.as_str() {
+            KIND_SILENCE => "aucun fichier",
+            KIND_ERROR_RATE => "fichiers en erreur",
+            other => other,
+        };
+        let state = match self.state.as_str() {
+            "FIRING" => self.severity.clone(),
+            "REMINDER" => format!("RAPPEL {}", self.severity),
+            "RESOLVED" => "RÉSOLU".to_string(),
+            other => other.to_string(),
+        };
+        format!("Visor [{}] {}: {}", state, self.line, what)
+    }
+
+    pub(crate) fn body(&self) -> String {
+        let mut body = format!(
+            "{}\n\nLigne: {}\nDepuis: {}\nOccurrences: {}\n",
+            self.message, self.line, self.first_seen, self.occurrences
+        );
+        if self.state == "FIRING" || self.state == "REMINDER" {
+            body.push_str(&format!(
+                "\nAcquitter: visor alerts ack {} (ou depuis l'application)\n",
+                self.alert_id
+            ));
+        }
+        body
+    }
+}
+
+pub(crate) async fn load_alerts(
+    pool: &Pool<Sqlite>,
+    include_resolved: bool,
+    limit: Option<i64>,
+) -> Result<Vec<Alert>, String> {
+    sqlx::query_as::<_, Alert>(
+        "SELECT a.id, a.line_id, l.name AS line_name, a.kind, a.severity, a.status, a.message, a.occurrences, \
+                a.first_seen, a.last_seen, a.last_notified, a.acknowledged_at, a.acknowledged_by, a.resolved_at \
+         FROM alerts a LEFT JOIN lines l ON l.id = a.line_id \
+         WHERE ? OR a.status != 'RESOLVED' \
+         ORDER BY a.id DESC LIMIT ?",
+    )
+    .bind(include_resolved)
+    .bind(limit.unwrap_or(200))
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Stop reminders for an open alert. It stays listed until its condition
+/// clears, and reopens if the condition escalates.
+pub(crate) async fn acknowledge(
+    pool: &Pool<Sqlite>,
+    id: i64,
+    by: Option<&str>,
+) -> Result<(), String> {
+    let updated = sqlx::query(
+        "UPDATE alerts SET status = 'ACKNOWLEDGED', acknowledged_at = ?, acknowledged_by = ? \
+         WHERE id = ? AND status = 'OPEN'",
+    )
+    .bind(now())
+    .bind(by.filter(|b| !b.trim().is_empty()))
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .rows_affected();
+    if updated == 0 {
+        return Err(format!(
+            "Alerte {} introuvable, déjà acquittée ou résolue",
+            id
+        ));
+    }
+    Ok(())
+}
+
+fn now() -> String {
+    Local::now().format(TIME_FORMAT).to_string()
+}
+
+fn severity_rank(severity: &str) -> u8 {
+    match severity {
+        "CRITICAL" => 2,
+        "WARNING" => 1,
+        _ => 0,
+    }
+}
+
+/// A condition currently true for a line.
+struct Condition {
+    kind: &'static str,
+    severity: &'static str,
+    message: String,
+}
+
+struct OpenAlert {
+    id: i64,
+    severity: String,
+    status: String,
+    first_seen: String,
+    occurrences: i64,
+    last_notified: Option<String>,
+}
+
+struct LineState {
+    name: String,
+    conditions: Vec<Condition>,
+}
+
+/// Conditions of every active line; inactive lines are stopped on purpose
+/// and never alert.
+async fn current_conditions(
+    pool: &Pool<Sqlite>,
+    settings: &AlertSettings,
+) -> Result<HashMap<i64, LineState>, String> {
+    let window_start = (Local::now() - ChronoDuration::minutes(settings.error_window_minutes))
+        .format(TIME_FORMAT)
+        .to_string();
+    let error_counts: HashMap<i64, (i64, i64)> = sqlx::query(
+        "SELECT line_id, COUNT(1) AS total, SUM(CASE WHEN status IN ('ERROR', 'IN_DOUBT') THEN 1 ELSE 0 END) AS errors \
+         FROM production_data WHERE processed_at >= ? GROUP BY line_id",
+    )
+    .bind(&window_start)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .iter()
+    .map(|row| (row.get("line_id"), (row.get("total"), row.get("errors"))))
+    .collect();
+
+    let lines = sqlx::query(
+        "SELECT l.id, l.name, l.interval_check, l.interval_alert, \
+                (SELECT MAX(processed_at) FROM production_data p WHERE p.line_id = l.id) AS last_processed \
+         FROM lines l WHERE l.active = 1",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut states = HashMap::new();
+    for line in lines {
+        let id: i64 = line.get("id");
+        let interval_check: i64 = line.get("interval_check");
+        let interval_alert: i64 = line.get("interval_alert");
+        let last_processed: Option<String> = line.get("last_processed");
+        let mut conditions = Vec::new();
+
+        let status = line_status(
+            true,
+            last_processed.as_deref(),
+            interval_check,
+            interval_alert,
+        );
+        if status != "MARCHE" {
+            let since = match last_processed.as_deref().and_then(parse_processed_at) {
+                Some(dt) => format!("depuis {} min", (Local::now() - dt).num_minutes()),
+                None => "depuis la création de la ligne".to_string(),
+            };
+            conditions.push(Condition {
+                kind: KIND_SILENCE,
+                severity: if status == "ARRET" { "CRITICAL" } else { "WARNING" },
+                message: format!(
+                    "Ligne en {}: aucun fichier traité {} (ALERTE après {} min, ARRET après {} min)",
+                    status, since, interval_check, interval_alert
+                ),
+            });
+        }
+
+        if let Some(&(total, errors)) = error_counts.get(&id) {
+            if total >= settings.error_min_files
+                && errors * 100 >= settings.error_rate_percent * total
+            {
+                conditions.push(Condition {
+                    kind: KIND_ERROR_RATE,
+                    severity: "CRITICAL",
+                    message: format!(
+                        "{} fichier(s) en erreur sur {} ces {} dernières minutes (seuil {} %)",
+                        errors, total, settings.error_window_minutes, settings.error_rate_percent
+                    ),
+                });
+            }
+        }
+
+        states.insert(
+            id,
+            LineState {
+                name: line.get("name"),
+                conditions,
+            },
+        );
+    }
+    Ok(states)
+}
+
+/// One evaluation pass; returns the notifications to send.
+async fn evaluate(
+    pool: &Pool<Sqlite>,
+    settings: &AlertSettings,
+) -> Result<Vec<AlertEvent>, String> {
+    let states = current_conditions(pool, settings).await?;
+    let open: HashMap<(i64, String), OpenAlert> = sqlx::query(
+        "SELECT id, line_id, kind, severity, status, first_seen, occurrences, last_notified \
+         FROM alerts WHERE status != 'RESOLVED'",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .iter()
+    .map(|row| {
+        let alert = OpenAlert {
+            id: row.get("id"),
+            severity: row.get("severity"),
+            status: row.get("status"),
+            first_seen: row.get("first_seen"),
+            occurrences: row.get("occurrences"),
+            last_notified: row.get("last_notified"),
+        };
+        (
+            (
+                row.get::<Option<i64>, _>("line_id").unwrap_or_default(),
+                row.get("kind"),
+            ),
+            alert,
+        )
+    })
+    .collect();
+
+    let now = now();
+    let renotify_before = (settings.renotify_minutes > 0).then(|| {
+        (Local::now() - ChronoDuration::minutes(settings.renotify_minutes))
+            .format(TIME_FORMAT)
+            .to_string()
+    });
+    let mut events = Vec::new();
+
+    for (line_id, state) in &states {
+        for condition in &state.conditions {
+            let event =
+                |alert_id: i64, state_name: &str, first_seen: &str, occurrences: i64| AlertEvent {
+                    alert_id,
+                    state: state_name.to_string(),
+                    kind: condition.kind.to_string(),
+                    severity: condition.severity.to_string(),
+                    line_id: Some(*line_id),
+                    line: state.name.clone(),
+                    message: condition.message.clone(),
+                    first_seen: first_seen.to_string(),
+                    occurrences,
+                };
+
+            let Some(existing) = open.get(&(*line_id, condition.kind.to_string())) else {
+                let inserted = sqlx::query(
+                    "INSERT INTO alerts (line_id, kind, severity, status, message, occurrences, first_seen, last_seen, last_notified) \
+                     VALUES (?, ?, ?, 'OPEN', ?, 1, ?, ?, ?) ON CONFLICT DO NOTHING",
+                )
+                .bind(line_id)
+                .bind(condition.kind)
+                .bind(condition.severity)
+                .bind(&condition.message)
+                .bind(&now)
+                .bind(&now)
+                .bind(&now)
+                .execute(pool)
+                .await
+                .map_err(|e| e.to_string())?;
+                // Another monitor on the same database opened it first and notifies for it.
+                if inserted.rows_affected() > 0 {
+                    events.push(event(inserted.last_insert_rowid(), "FIRING", &now, 1));
+                }
+                continue;
+            };
+
+            let occurrences = existing.occurrences + 1;
+            let escalated = severity_rank(condition.severity) > severity_rank(&existing.severity);
+            let reminder = !escalated
+                && existing.status == "OPEN"
+                && renotify_before.as_deref().is_some_and(|before| {
+                    existing.last_notified.as_deref().unwrap_or_default() < before
+                });
+
+            // Escalation reopens an acknowledged alert.
+            sqlx::query(
+                "UPDATE alerts SET severity = ?, message = ?, occurrences = ?, last_seen = ?, \
+                        status = CASE WHEN ? THEN 'OPEN' ELSE status END, \
+                        acknowledged_at = CASE WHEN ? THEN NULL ELSE acknowledged_at END, \
+                        acknowledged_by = CASE WHEN ? THEN NULL ELSE acknowledged_by END, \
+                        last_notified = CASE WHEN ? THEN ? ELSE last_notified END \
+                 WHERE id = ?",
+            )
+            .bind(condition.severity)
+            .bind(&condition.message)
+            .bind(occurrences)
+            .bind(&now)
+            .bind(escalated)
+            .bind(escalated)
+            .bind(escalated)
+            .bind(escalated || reminder)
+            .bind(&now)
+            .bind(existing.id)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+            if escalated {
+                events.push(event(
+                    existing.id,
+                    "FIRING",
+                    &existing.first_seen,
+                    occurrences,
+                ));
+            } else if reminder {
+                events.push(event(
+                    existing.id,
+                    "REMINDER",
+                    &existing.first_seen,
+                    occurrences,
+                ));
+            }
+        }
+    }
+
+    // Alerts whose condition no longer holds.
+    for ((line_id, kind), existing) in &open {
+        let state = states.get(line_id);
+        if state.is_some_and(|s| s.conditions.iter().any(|c| c.kind == kind)) {
+            continue;
+        }
+        sqlx::query("UPDATE alerts SET status = 'RESOLVED', resolved_at = ? WHERE id = ?")
+            .bind(&now)
+            .bind(existing.id)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // A line that was stopped or deleted resolves without notice.
+        if let (Some(state), true) = (state, settings.notify_recovery) {
+            events.push(AlertEvent {
+                alert_id: existing.id,
+                state: "RESOLVED".to_string(),
+                kind: kind.clone(),
+                severity: existing.severity.clone(),
+                line_id: Some(*line_id),
+                line: state.name.clone(),
+                message: match kind.as_str() {
+                    KIND_SILENCE => "Ligne de nouveau en MARCHE".to_string(),
+                    _ => "Taux d'erreur revenu sous le seuil".to_string(),
+                },
+                first_seen: existing.first_seen.clone(),
+                occurrences: existing.occurrences,
+            });
+        }
+    }
+
+    let history_limit = (Local::now() - ChronoDuration::days(HISTORY_DAYS))
+        .format(TIME_FORMAT)
+        .to_string();
+    sqlx::query("DELETE FROM alerts WHERE status = 'RESOLVED' AND resolved_at < ?")
+        .bind(history_limit)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(events)
+}
+
+/// Send to every enabled channel; a failing channel does not stop the others.
+async fn dispatch(host: &Host, settings: &AlertSettings, event: &AlertEvent) {
+    log::warn!("{} - {}", event.title(), event.message);
+    let _ = host.emit("alert-event", event);
+    for channel in settings.channels.iter().filter(|c| c.enabled) {
+        if let Err(e) = channel.send(host, event).await {
+            log::error!("Alerte {}: canal {}: {}", event.alert_id, channel.name, e);
+        }
+    }
+}
+
+/// Send a test notification through one channel, with the saved settings.
+pub(crate) async fn test_channel(
+    host: &Host,
+    pool: &Pool<Sqlite>,
+    name: &str,
+) -> Result<(), String> {
+    let settings = AlertSettings::load(pool).await;
+    let channel = settings
+        .channels
+        .iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| {
+            format!(
+                "Canal {} introuvable (enregistrez les paramètres d'abord)",
+                name
+            )
+        })?;
+    let event = AlertEvent {
+        alert_id: 0,
+        state: "TEST".to_string(),
+        kind: "TEST".to_string(),
+        severity: "INFO".to_string(),
+        line_id: None,
+        line: "-".to_string(),
+        message: format!("Notification de test du canal {}", name),
+        first_seen: now(),
+        occurrences: 1,
+    };
+    channel.send(host, &event).await
+}
+
+/// Evaluate lines continuously, re-reading the settings before each pass so
+/// changes apply without a restart.
+pub fn spawn_monitor(host: Host, pool: Pool<Sqlite>) {
+    crate::runtime::spawn(async move {
+        tokio::time::sleep(STARTUP_DELAY).await;
+        loop {
+            let settings = AlertSettings::load(&pool).await;
+            if settings.enabled {
+                match evaluate(&pool, &settings).await {
+                    Ok(events) => {
+                        for event in &events {
+                            dispatch(&host, &settings, event).await;
+                        }
+                    }
+                    Err(e) => log::error!("Alertes: {}", e),
+                }
+            }
+            tokio::time::sleep(Duration::from_secs(settings.check_interval_seconds as u64)).await;
+        }
+    });
+}
@@ -0,0 +1,70 @@
+//! Outgoing connections for the SMTP and webhook channels, plain or TLS.
+
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+
+pub(crate) const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+pub(crate) type Stream = Box<dyn Io>;
+
+static TLS_CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+
+/// Certificates are checked against the system's trusted roots.
+fn tls_config() -> Arc<ClientConfig> {
+    TLS_CONFIG
+        .get_or_init(|| {
+            let mut roots = RootCertStore::empty();
+            match rustls_native_certs::load_native_certs() {
+                Ok(certs) => {
+                    for cert in certs {
+                        let _ = roots.add(&rustls::Certificate(cert.0));
+                    }
+                }
+                Err(e) => log::warn!("Certificats racine du système illisibles: {}", e),
+            }
+            Arc::new(
+                ClientConfig::builder()
+                    .with_safe_defaults()
+                    .with_root_certificates(roots)
+                    .with_no_client_auth(),
+            )
+        })
+        .clone()
+}
+
+pub(crate) async fn connect_tcp(host: &str, port: u16) -> Result<TcpStream, String> {
+    tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| format!("{}:{}: délai de connexion dépassé", host, port))?
+        .map_err(|e| format!("{}:{}: {}", host, port, e))
+}
+
+/// TLS handshake over an established connection (also used for STARTTLS).
+pub(crate) async fn start_tls(stream: TcpStream, host: &str) -> Result<Stream, String> {
+    let name = ServerName::try_from(host)
+        .map_err(|_| format!("Nom d'hôte invalide pour TLS: {}", host))?;
+    let tls = tokio::time::timeout(
+        CONNECT_TIMEOUT,
+        TlsConnector::from(tls_config()).connect(name, stream),
+    )
+    .await
+    .map_err(|_| format!("{}: délai de négociation TLS dépassé", host))?
+    .map_err(|e| format!("{}: TLS: {}", host, e))?;
+    Ok(Box::new(tls))
+}
+
+pub(crate) async fn connect(host: &str, port: u16, tls: bool) -> Result<Stream, String> {
+    let stream = connect_tcp(host, port).await?;
+    if tls {
+        start_tls(stream, host).await
+    } else {
+        Ok(Box::new(stream))
+    }
+}
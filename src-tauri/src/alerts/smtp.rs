@@ -0,0 +1,473 @@
+//! Minimal SMTP submission: EHLO, optional STARTTLS or implicit TLS, AUTH
+//! PLAIN/LOGIN, one plain-text message.
+
+use super::net;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_REPLY_LINES: usize = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Plain connection, for a relay on the local network.
+    None,
+    /// Upgrade with STARTTLS (usually port 587).
+    #[default]
+    Starttls,
+    /// TLS from the start (usually port 465).
+    Tls,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    /// Stored encrypted, like the connection passwords.
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+impl SmtpConfig {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.host.trim().is_empty() || self.port == 0 {
+            return Err("SMTP: serveur ou port manquant".to_string());
+        }
+        if self.to.is_empty() {
+            return Err("SMTP: aucun destinataire".to_string());
+        }
+        for address in std::iter::once(&self.from).chain(&self.to) {
+            let valid = address.contains('@')
+                && !address.contains(['\r', '\n', '<', '>', ',', ' '])
+                && !address.starts_with('@')
+                && !address.ends_with('@');
+            if !valid {
+                return Err(format!("SMTP: adresse invalide: {}", address));
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Client<S> {
+    stream: BufReader<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Client<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+
+    /// A possibly multi-line reply: its code and its text lines.
+    async fn reply(&mut self) -> Result<(u16, Vec<String>), String> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            let read = self
+                .stream
+                .read_line(&mut line)
+                .await
+                .map_err(|e| e.to_string())?;
+            if read == 0 {
+                return Err("SMTP: connexion fermée par le serveur".to_string());
+            }
+            let line = line.trim_end();
+            let code = line
+                .get(..3)
+                .and_then(|c| c.parse::<u16>().ok())
+                .ok_or_else(|| format!("SMTP: réponse invalide: {}", line))?;
+            lines.push(line.get(4..).unwrap_or_default().to_string());
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code, lines));
+            }
+            if lines.len() > MAX_REPLY_LINES {
+                return Err("SMTP: réponse trop longue".to_string());
+            }
+        }
+    }
+
+    async fn expect(&mut self, expected: u16, context: &str) -> Result<Vec<String>, String> {
+        let (code, lines) = self.reply().await?;
+        if code != expected {
+            return Err(format!("SMTP {}: {} {}", context, code, lines.join(" ")));
+        }
+        Ok(lines)
+    }
+
+    async fn send(&mut self, line: &str) -> Result<(), String> {
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(line.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        stream.write_all(b"\r\n").await.map_err(|e| e.to_string())?;
+        stream.flush().await.map_err(|e| e.to_string())
+    }
+
+    async fn command(
+        &mut self,
+        line: &str,
+        expected: u16,
+        context: &str,
+    ) -> Result<Vec<String>, String> {
+        self.send(line).await?;
+        self.expect(expected, context).await
+    }
+
+    /// Extensions announced by the server, uppercased (`STARTTLS`, `AUTH PLAIN LOGIN`...).
+    async fn ehlo(&mut self) -> Result<Vec<String>, String> {
+        let lines = self.command("EHLO visor", 250, "EHLO").await?;
+        Ok(lines
+            .into_iter()
+            .skip(1)
+            .map(|l| l.to_uppercase())
+            .collect())
+    }
+
+    async fn authenticate(
+        &mut self,
+        extensions: &[String],
+        username: &str,
+        password: &str,
+    ) -> Result<(), String> {
+        let mechanisms = extensions
+            .iter()
+            .find_map(|e| e.strip_prefix("AUTH").map(str::to_string))
+            .ok_or("SMTP: le serveur ne propose pas d'authentification")?;
+        if mechanisms.split_whitespace().any(|m| m == "PLAIN") {
+            let credentials = BASE64.encode(format!("\0{}\0{}", username, password));
+            self.command(&format!("AUTH PLAIN {}", credentials), 235, "AUTH")
+                .await?;
+        } else if mechanisms.split_whitespace().any(|m| m == "LOGIN") {
+            self.command("AUTH LOGIN", 334, "AUTH").await?;
+            self.command(&BASE64.encode(username), 334, "AUTH").await?;
+            self.command(&BASE64.encode(password), 235, "AUTH").await?;
+        } else {
+            return Err(format!(
+                "SMTP: aucune méthode d'authentification prise en charge ({})",
+                mechanisms.trim()
+            ));
+        }
+        Ok(())
+    }
+
+    async fn deliver(
+        &mut self,
+        config: &SmtpConfig,
+        extensions: &[String],
+        password: Option<&str>,
+        message: &str,
+    ) -> Result<(), String> {
+        if let Some(username) = config.username.as_deref().filter(|u| !u.is_empty()) {
+            self.authenticate(extensions, username, password.unwrap_or_default())
+                .await?;
+        }
+        self.command(&format!("MAIL FROM:<{}>", config.from), 250, "MAIL FROM")
+            .await?;
+        for to in &config.to {
+            self.command(&format!("RCPT TO:<{}>", to), 250, "RCPT TO")
+                .await?;
+        }
+        self.command("DATA", 354, "DATA").await?;
+        self.stream
+            .get_mut()
+            .write_all(message.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        self.command(".", 250, "DATA").await?;
+        let _ = self.send("QUIT").await;
+        Ok(())
+    }
+}
+
+/// RFC 2047 encoded-word when the subject is not plain ASCII.
+fn encode_header(value: &str) -> String {
+    let value = value.replace(['\r', '\n'], " ");
+    if value.is_ascii() {
+        value
+    } else {
+        format!("=?UTF-8?B?{}?=", BASE64.encode(value))
+    }
+}
+
+/// The message as sent after DATA: headers, base64 body (so any text is 7-bit
+/// safe and needs no dot-stuffing), without the final `.` line.
+fn build_message(config: &SmtpConfig, subject: &str, body: &str) -> String {
+    let now = Local::now();
+    let encoded = BASE64.encode(body.replace("\r\n", "\n").replace('\n', "\r\n"));
+    let mut message = format!(
+        "Date: {}\r\nFrom: <{}>\r\nTo: {}\r\nSubject: {}\r\nMessage-ID: <{}.{}@visor>\r\n\
+         MIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n",
+        now.to_rfc2822(),
+        config.from,
+        config.to.iter().map(|t| format!("<{}>", t)).collect::<Vec<_>>().join(", "),
+        encode_header(subject),
+        now.timestamp_nanos_opt().unwrap_or_default(),
+        std::process::id(),
+    );
+    for chunk in encoded.as_bytes().chunks(76) {
+        message.push_str(&String::from_utf8_lossy(chunk));
+        message.push_str("\r\n");
+    }
+    message
+}
+
+/// Send one message; `password` is the decrypted password.
+pub(crate) async fn send_mail(
+    config: &SmtpConfig,
+    password: Option<&str>,
+    subject: &str,
+    body: &str,
+) -> Result<(), String> {
+    config.validate()?;
+    if config.security == SmtpSecurity::None
+        && config.username.as_deref().is_some_and(|u| !u.is_empty())
+        && !is_loopback(&config.host)
+    {
+        return Err(
+            "SMTP: authentification refusée sans TLS (choisissez STARTTLS ou TLS)".to_string(),
+        );
+    }
+    let message = build_message(config, subject, body);
+    let host = config.host.trim();
+
+    let session = async {
+        let tcp = net::connect_tcp(host, config.port).await?;
+        match config.security {
+            SmtpSecurity::Tls => {
+                let mut client = Client::new(net::start_tls(tcp, host).await?);
+                client.expect(220, "accueil").await?;
+                let extensions = client.ehlo().await?;
+                client
+                    .deliver(config, &extensions, password, &message)
+                    .await
+            }
+            SmtpSecurity::None => {
+                let mut client = Client::new(tcp);
+                client.expect(220, "accueil").await?;
+                let extensions = client.ehlo().await?;
+                client
+                    .deliver(config, &extensions, password, &message)
+                    .await
+            }
+            SmtpSecurity::Starttls => {
+                let mut client = Client::new(tcp);
+                client.expect(220, "accueil").await?;
+                let extensions = client.ehlo().await?;
+                if !extensions.iter().any(|e| e == "STARTTLS") {
+                    return Err("SMTP: le serveur ne propose pas STARTTLS".to_string());
+                }
+                client.command("STARTTLS", 220, "STARTTLS").await?;
+                let mut client = Client::new(net::start_tls(client.into_inner(), host).await?);
+                let extensions = client.ehlo().await?;
+                client
+                    .deliver(config, &extensions, password, &message)
+                    .await
+            }
+        }
+    };
+    tokio::time::timeout(SESSION_TIMEOUT, session)
+        .await
+        .map_err(|_| format!("SMTP {}: délai dépassé", host))?
+}
+
+fn is_loopback(host: &str) -> bool {
+    host == "localhost"
+        || host
+            .trim_matches(['[', ']'])
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// What the stub server saw: the commands in order, and the DATA payload.
+    struct Session {
+        commands: Vec<String>,
+        data: String,
+    }
+
+    /// One-connection SMTP server on a local port. `reject` answers the first
+    /// command starting with its prefix with the given reply instead of 250.
+    async fn stub(
+        extensions: &'static [&'static str],
+        reject: Option<(&'static str, &'static str)>,
+    ) -> (u16, JoinHandle<Session>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(socket);
+            let mut session = Session {
+                commands: Vec::new(),
+                data: String::new(),
+            };
+            stream
+                .get_mut()
+                .write_all(b"220 stub ESMTP\r\n")
+                .await
+                .unwrap();
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                let command = line.trim_end().to_string();
+                session.commands.push(command.clone());
+                let reply = match reject.filter(|(prefix, _)| command.starts_with(prefix)) {
+                    Some((_, reply)) => format!("{}\r\n", reply),
+                    None if command.starts_with("EHLO") => {
+                        let mut reply = "250-stub".to_string();
+                        for extension in extensions {
+                            reply.push_str(&format!("\r\n250-{}", extension));
+                        }
+                        reply.push_str("\r\n250 8BITMIME\r\n");
+                        reply
+                    }
+                    None if command.starts_with("AUTH") => "235 ok\r\n".to_string(),
+                    None if command == "DATA" => {
+                        stream.get_mut().write_all(b"354 go\r\n").await.unwrap();
+                        loop {
+                            let mut line = String::new();
+                            stream.read_line(&mut line).await.unwrap();
+                            if line == ".\r\n" {
+                                break;
+                            }
+                            session.data.push_str(&line);
+                        }
+                        "250 queued\r\n".to_string()
+                    }
+                    None if command == "QUIT" => {
+                        let _ = stream.get_mut().write_all(b"221 bye\r\n").await;
+                        break;
+                    }
+                    None => "250 ok\r\n".to_string(),
+                };
+                stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+            }
+            session
+        });
+        (port, handle)
+    }
+
+    fn config(port: u16) -> SmtpConfig {
+        SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            security: SmtpSecurity::None,
+            username: None,
+            password: None,
+            from: "visor@example.com".to_string(),
+            to: vec![
+                "ops@example.com".to_string(),
+                "lead@example.com".to_string(),
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_the_command_sequence_and_a_base64_body() {
+        let (port, server) = stub(&[], None).await;
+        let body = "Ligne 1 arrêtée\n.\nfin";
+        send_mail(
+            &config(port),
+            None,
+            "Visor [CRITICAL] Ligne 1: aucun fichier",
+            body,
+        )
+        .await
+        .unwrap();
+        let session = server.await.unwrap();
+
+        assert_eq!(
+            session.commands,
+            [
+                "EHLO visor",
+                "MAIL FROM:<visor@example.com>",
+                "RCPT TO:<ops@example.com>",
+                "RCPT TO:<lead@example.com>",
+                "DATA",
+                "QUIT",
+            ]
+        );
+        let (headers, encoded) = session.data.split_once("\r\n\r\n").unwrap();
+        assert!(headers.contains("Subject: Visor [CRITICAL] Ligne 1: aucun fichier\r\n"));
+        assert!(headers.contains("To: <ops@example.com>, <lead@example.com>\r\n"));
+        assert!(headers.ends_with("Content-Transfer-Encoding: base64"));
+        assert!(encoded.lines().all(|l| l.len() <= 76));
+        let decoded = BASE64.decode(encoded.replace("\r\n", "")).unwrap();
+        assert_eq!(
+            String::from_utf8(decoded).unwrap(),
+            "Ligne 1 arrêtée\r\n.\r\nfin"
+        );
+    }
+
+    #[tokio::test]
+    async fn authenticates_with_plain_on_loopback() {
+        let (port, server) = stub(&["AUTH LOGIN PLAIN"], None).await;
+        let mut cfg = config(port);
+        cfg.username = Some("visor".to_string());
+        send_mail(&cfg, Some("s3cret"), "Sujet éèà", "corps")
+            .await
+            .unwrap();
+        let session = server.await.unwrap();
+
+        assert_eq!(
+            session.commands[1],
+            format!("AUTH PLAIN {}", BASE64.encode("\0visor\0s3cret"))
+        );
+        assert!(session.data.contains(&format!(
+            "Subject: =?UTF-8?B?{}?=\r\n",
+            BASE64.encode("Sujet éèà")
+        )));
+    }
+
+    #[tokio::test]
+    async fn reports_rejected_commands() {
+        let (port, server) = stub(&[], Some(("RCPT TO:<lead", "550 5.1.1 unknown user"))).await;
+        let err = send_mail(&config(port), None, "s", "b").await.unwrap_err();
+        assert_eq!(err, "SMTP RCPT TO: 550 5.1.1 unknown user");
+        let session = server.await.unwrap();
+        assert!(!session.commands.iter().any(|c| c == "DATA"));
+
+        let (port, _server) = stub(&[], Some(("MAIL", "421 4.3.2 shutting down"))).await;
+        let err = send_mail(&config(port), None, "s", "b").await.unwrap_err();
+        assert!(err.starts_with("SMTP MAIL FROM: 421"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn refuses_missing_starttls_and_auth_in_clear() {
+        let (port, _server) = stub(&[], None).await;
+        let mut cfg = config(port);
+        cfg.security = SmtpSecurity::Starttls;
+        let err = send_mail(&cfg, None, "s", "b").await.unwrap_err();
+        assert_eq!(err, "SMTP: le serveur ne propose pas STARTTLS");
+
+        let mut cfg = config(25);
+        cfg.host = "smtp.example.com".to_string();
+        cfg.username = Some("visor".to_string());
+        assert!(send_mail(&cfg, Some("x"), "s", "b")
+            .await
+            .unwrap_err()
+            .contains("sans TLS"));
+    }
+}
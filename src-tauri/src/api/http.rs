@@ -0,0 +1,269 @@
+//! Just enough HTTP/1.1 for a local API: one request per connection,
+//! `Content-Length` bodies only.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_CONNECTIONS: usize = 32;
+
+pub(crate) struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// Header names are lowercase.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Non-empty path segments, percent-decoded.
+    pub fn segments(&self) -> Vec<String> {
+        self.path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(percent_decode)
+            .collect()
+    }
+
+    pub fn bearer_token(&self) -> Option<&str> {
+        self.headers
+            .get("authorization")
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::trim)
+    }
+}
+
+pub(crate) struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json<T: Serialize>(status: u16, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => Self {
+                status,
+                content_type: "application/json",
+                body,
+            },
+            Err(e) => Self::error(500, &e.to_string()),
+        }
+    }
+
+    pub fn text(status: u16, content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        Self::json(status, &serde_json::json!({ "error": message }))
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        _ => "",
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(high), Some(low)) => {
+                    out.push(high * 16 + low);
+                    i += 3;
+                    continue;
+                }
+                _ => out.push(b'%'),
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Query strings also encode spaces as `+`.
+fn form_decode(value: &str) -> String {
+    percent_decode(&value.replace('+', " "))
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((k, v)) => (form_decode(k), form_decode(v)),
+            None => (form_decode(pair), String::new()),
+        })
+        .collect()
+}
+
+/// Read the request line and headers, at most `MAX_HEADER_BYTES`; the body is
+/// left in `reader`, its announced length returned. The error is the response to send back.
+async fn read_head<S: AsyncBufRead + Unpin>(
+    reader: &mut S,
+    max_body: usize,
+) -> Result<(Request, usize), Response> {
+    let mut limited = reader.take(MAX_HEADER_BYTES as u64);
+    let mut head = Vec::new();
+    loop {
+        let start = head.len();
+        let read = limited
+            .read_until(b'\n', &mut head)
+            .await
+            .map_err(|e| Response::error(400, &e.to_string()))?;
+        if read == 0 {
+            if limited.limit() == 0 {
+                return Err(Response::error(431, "En-têtes trop volumineux"));
+            }
+            return Err(Response::error(400, "Requête incomplète"));
+        }
+        if &head[start..] == b"\r\n" || &head[start..] == b"\n" {
+            break;
+        }
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.lines();
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(Response::error(400, "Ligne de requête invalide"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect();
+
+    if headers.contains_key("transfer-encoding") {
+        return Err(Response::error(411, "Content-Length requis"));
+    }
+    let length = match headers.get("content-length") {
+        Some(v) => v
+            .parse::<usize>()
+            .map_err(|_| Response::error(400, "Content-Length invalide"))?,
+        None => 0,
+    };
+    if length > max_body {
+        return Err(Response::error(413, "Corps de requête trop volumineux"));
+    }
+
+    let request = Request {
+        method: method.to_uppercase(),
+        path: path.to_string(),
+        query: parse_query(query),
+        headers,
+        body: Vec::new(),
+    };
+    Ok((request, length))
+}
+
+/// Read one request. `gate` sees it before its body is read; a response from it
+/// is sent instead, so unauthenticated clients cannot make the server buffer a body.
+async fn read_request<S, G>(reader: &mut S, max_body: usize, gate: &G) -> Result<Request, Response>
+where
+    S: AsyncBufRead + Unpin,
+    G: Fn(&Request) -> Option<Response>,
+{
+    let (mut request, length) = read_head(reader, max_body).await?;
+    if let Some(response) = gate(&request) {
+        return Err(response);
+    }
+    request.body = vec![0; length];
+    reader
+        .read_exact(&mut request.body)
+        .await
+        .map_err(|e| Response::error(400, &e.to_string()))?;
+    Ok(request)
+}
+
+pub(crate) async fn write_response<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    response: &Response,
+) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.flush().await
+}
+
+/// Accept connections until the task is aborted, one spawned task per connection
+/// and at most `MAX_CONNECTIONS` at a time; further clients wait in the backlog.
+pub(crate) async fn serve<G, H, F>(listener: TcpListener, max_body: usize, gate: G, handler: H)
+where
+    G: Fn(&Request) -> Option<Response> + Clone + Send + Sync + 'static,
+    H: Fn(Request) -> F + Clone + Send + Sync + 'static,
+    F: Future<Output = Response> + Send + 'static,
+{
+    let permits = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    loop {
+        let Ok(permit) = permits.clone().acquire_owned().await else {
+            return;
+        };
+        let (stream, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                log::warn!("HTTP: connexion refusée: {}", e);
+                continue;
+            }
+        };
+        let gate = gate.clone();
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let mut reader = BufReader::new(stream);
+            let response = match tokio::time::timeout(
+                READ_TIMEOUT,
+                read_request(&mut reader, max_body, &gate),
+            )
+            .await
+            {
+                Ok(Ok(request)) => handler(request).await,
+                Ok(Err(response)) => response,
+                Err(_) => Response::error(400, "Délai de lecture dépassé"),
+            };
+            let _ = write_response(reader.get_mut(), &response).await;
+        });
+    }
+}
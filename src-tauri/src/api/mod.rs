@@ -0,0 +1,432 @@
+pub(crate) mod http;
+
+use crate::alerts;
+use crate::commands::{dashboard, lines, logs, production};
+use crate::db::DbState;
+use crate::host::Host;
+use crate::scheduler::{self, ScheduledTask, SchedulerState};
+use crate::secrets::{decrypt, mask_password, password_to_store};
+use crate::stock::{self, FileOutcome, StockProcessor, WatcherState};
+use http::{Request, Response};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+
+pub(crate) const UPLOADS_DIR_NAME: &str = "uploads";
+const DEFAULT_BIND_ADDRESS: &str = "127.0.0.1:8765";
+const MIN_TOKEN_LEN: usize = 16;
+const MAX_UPLOAD_BYTES: usize = 50 * 1024 * 1024;
+const OPENAPI: &str = include_str!("openapi.json");
+
+/// Embedded HTTP API settings, stored in the `config` table. The token is
+/// stored encrypted like the connection passwords.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiSettings {
+    pub enabled: bool,
+    pub bind_address: String,
+    pub token: Option<String>,
+}
+
+impl ApiSettings {
+    /// Settings as stored; the token is still encrypted.
+    pub(crate) async fn load(pool: &Pool<Sqlite>) -> Self {
+        let get = |key: &'static str| async move {
+            sqlx::query_scalar::<_, String>("SELECT value FROM config WHERE key = ?")
+                .bind(key)
+                .fetch_optional(pool)
+                .await
+                .ok()
+                .flatten()
+        };
+        Self {
+            enabled: get("api_enabled").await.map(|v| v == "1").unwrap_or(false),
+            bind_address: get("api_bind_address")
+                .await
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_BIND_ADDRESS.to_string()),
+            token: get("api_token").await.filter(|v| !v.is_empty()),
+        }
+    }
+
+    /// For the settings screen: the token is masked.
+    pub(crate) async fn load_masked(pool: &Pool<Sqlite>) -> Self {
+        let mut settings = Self::load(pool).await;
+        mask_password(&mut settings.token);
+        settings
+    }
+
+    /// Validate and store; sending the mask back keeps the stored token.
+    pub(crate) async fn save(&self, pool: &Pool<Sqlite>) -> Result<(), String> {
+        self.bind_address
+            .trim()
+            .parse::<SocketAddr>()
+            .map_err(|_| {
+                format!(
+                    "Adresse d'écoute invalide: {} (ex. {})",
+                    self.bind_address, DEFAULT_BIND_ADDRESS
+                )
+            })?;
+        let token = password_to_store(self.token.as_deref())?;
+        if let Some(plain) = self.token.as_deref().filter(|_| token.is_some()) {
+            if !plain.is_empty() && plain.len() < MIN_TOKEN_LEN {
+                return Err(format!(
+                    "Le jeton d'accès doit faire au moins {} caractères",
+                    MIN_TOKEN_LEN
+                ));
+            }
+        }
+        let has_token = match &token {
+            Some(token) => !token.is_empty(),
+            None => Self::load(pool).await.token.is_some(),
+        };
+        if self.enabled && !has_token {
+            return Err("Un jeton d'accès est requis pour activer l'API".to_string());
+        }
+
+        let mut values = vec![
+            (
+                "api_enabled",
+                if self.enabled { "1" } else { "0" }.to_string(),
+            ),
+            ("api_bind_address", self.bind_address.trim().to_string()),
+        ];
+        if let Some(token) = token {
+            values.push(("api_token", token));
+        }
+        for (key, value) in values {
+            sqlx::query("INSERT OR REPLACE INTO config (key, value) VALUES (?, ?)")
+                .bind(key)
+                .bind(value)
+                .execute(pool)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// What the API acts on: the same registries the app or service uses.
+#[derive(Clone)]
+pub(crate) struct ApiContext {
+    pub db: DbState,
+    pub watchers: WatcherState,
+    pub scheduler: SchedulerState,
+    pub host: Host,
+    /// Uploaded files are processed from here, outside any watched folder.
+    pub uploads_dir: PathBuf,
+}
+
+pub struct ApiServer {
+    ctx: ApiContext,
+    running: Mutex<Option<crate::runtime::JoinHandle<()>>>,
+}
+
+impl ApiServer {
+    pub(crate) fn new(ctx: ApiContext) -> Self {
+        Self {
+            ctx,
+            running: Mutex::new(None),
+        }
+    }
+
+    /// Apply the saved settings: (re)bind when enabled, stop otherwise.
+    pub(crate) async fn restart(&self) -> Result<(), String> {
+        self.stop().await;
+
+        let settings = ApiSettings::load(&self.ctx.db.pool).await;
+        if !settings.enabled {
+            return Ok(());
+        }
+        let token = decrypt(settings.token.as_deref().unwrap_or_default())?;
+        if token.len() < MIN_TOKEN_LEN {
+            return Err("API HTTP: jeton d'accès manquant, serveur non démarré".to_string());
+        }
+        let listener = tokio::net::TcpListener::bind(&settings.bind_address)
+            .await
+            .map_err(|e| {
+                format!(
+                    "API HTTP: écoute sur {} impossible: {}",
+                    settings.bind_address, e
+                )
+            })?;
+
+        let api = Arc::new(Api {
+            ctx: self.ctx.clone(),
+            token,
+        });
+        let gate = api.clone();
+        let handle = crate::runtime::spawn(http::serve(
+            listener,
+            MAX_UPLOAD_BYTES,
+            move |request| gate.authorize(request),
+            move |request| {
+                let api = api.clone();
+                async move { api.handle(request).await }
+            },
+        ));
+        if let Ok(mut running) = self.running.lock() {
+            *running = Some(handle);
+        }
+        log::info!("API HTTP à l'écoute sur {}", settings.bind_address);
+        Ok(())
+    }
+
+    /// Returns once the listener is closed, so the address can be bound again.
+    pub(crate) async fn stop(&self) {
+        let handle = self.running.lock().ok().and_then(|mut r| r.take());
+        if let Some(handle) = handle {
+            handle.abort();
+            let _ = handle.await;
+        }
+    }
+}
+
+struct Api {
+    ctx: ApiContext,
+    token: String,
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn result<T: Serialize>(value: Result<T, String>) -> Response {
+    match value {
+        Ok(value) => Response::json(200, &value),
+        Err(e) => Response::error(500, &e),
+    }
+}
+
+fn parse_id(value: &str) -> Result<i64, Response> {
+    value
+        .parse()
+        .map_err(|_| Response::error(400, &format!("Identifiant invalide: {}", value)))
+}
+
+fn query_i64(request: &Request, name: &str) -> Result<Option<i64>, Response> {
+    request
+        .query
+        .get(name)
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            v.parse()
+                .map_err(|_| Response::error(400, &format!("{} invalide: {}", name, v)))
+        })
+        .transpose()
+}
+
+fn is_openapi(request: &Request, segments: &[String]) -> bool {
+    request.method == "GET" && segments == ["api", "openapi.json"]
+}
+
+impl Api {
+    /// Checked on the headers alone, before any body is read.
+    fn authorize(&self, request: &Request) -> Option<Response> {
+        let authorized = is_openapi(request, &request.segments())
+            || request
+                .bearer_token()
+                .is_some_and(|t| constant_time_eq(t.as_bytes(), self.token.as_bytes()));
+        (!authorized).then(|| Response::error(401, "Jeton d'accès manquant ou invalide"))
+    }
+
+    async fn handle(&self, request: Request) -> Response {
+        let segments = request.segments();
+        if is_openapi(&request, &segments) {
+            return Response::text(200, "application/json", OPENAPI);
+        }
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+        match self.route(&request, &segments).await {
+            Ok(response) | Err(response) => response,
+        }
+    }
+
+    async fn route(&self, request: &Request, segments: &[&str]) -> Result<Response, Response> {
+        let pool = &self.ctx.db.pool;
+        let response = match (request.method.as_str(), segments) {
+            ("GET", ["api", "dashboard"]) => result(dashboard::load_dashboard(pool).await),
+            ("GET", ["api", "lines"]) => result(lines::load_lines(pool).await),
+            ("GET", ["api", "lines", id, "production"]) => {
+                result(production::load_production_data(pool, parse_id(id)?).await)
+            }
+            ("POST", ["api", "lines", id, action @ ("start" | "stop")]) => {
+                let id = parse_id(id)?;
+                match lines::set_line_active(
+                    &self.ctx.db,
+                    &self.ctx.watchers,
+                    id,
+                    *action == "start",
+                )
+                .await
+                {
+                    Ok(()) => Response::json(
+                        200,
+                        &serde_json::json!({ "id": id, "active": *action == "start" }),
+                    ),
+                    Err(e) => Response::error(404, &e),
+                }
+            }
+            ("PUT", ["api", "lines", id, "files", filename]) => {
+                self.upload(parse_id(id)?, filename, &request.body).await?
+            }
+            ("GET", ["api", "logs"]) => result(
+                logs::load_logs(
+                    pool,
+                    query_i64(request, "line_id")?,
+                    request
+                        .query
+                        .get("level")
+                        .filter(|l| !l.is_empty())
+                        .map(|l| l.to_uppercase()),
+                    query_i64(request, "limit")?,
+                )
+                .await,
+            ),
+            ("GET", ["api", "alerts"]) => result(
+                alerts::load_alerts(
+                    pool,
+                    request
+                        .query
+                        .get("all")
+                        .is_some_and(|v| v == "1" || v == "true"),
+                    query_i64(request, "limit")?,
+                )
+                .await,
+            ),
+            ("POST", ["api", "alerts", id, "ack"]) => {
+                let id = parse_id(id)?;
+                let by = serde_json::from_slice::<serde_json::Value>(&request.body)
+                    .ok()
+                    .and_then(|body| body.get("by").and_then(|b| b.as_str()).map(str::to_string));
+                match alerts::acknowledge(pool, id, Some(by.as_deref().unwrap_or("api"))).await {
+                    Ok(()) => Response::json(
+                        200,
+                        &serde_json::json!({ "id": id, "status": "ACKNOWLEDGED" }),
+                    ),
+                    Err(e) => Response::error(404, &e),
+                }
+            }
+            ("GET", ["api", "scheduler"]) => result(scheduler::running_tasks(&self.ctx.scheduler)),
+            ("POST", ["api", "scheduler", task_type, "start"]) => {
+                let task: ScheduledTask = serde_json::from_slice(&request.body)
+                    .map_err(|e| Response::error(400, &format!("Corps JSON invalide: {}", e)))?;
+                if !scheduler::TASK_TYPES.contains(task_type) {
+                    return Err(Response::error(
+                        404,
+                        &format!("Tâche inconnue: {}", task_type),
+                    ));
+                }
+                if task.interval_minutes == 0 {
+                    return Err(Response::error(
+                        400,
+                        "interval_minutes doit être supérieur à 0",
+                    ));
+                }
+                result(
+                    scheduler::start_task(
+                        &self.ctx.scheduler,
+                        self.ctx.host.clone(),
+                        &self.ctx.db,
+                        task_type.to_string(),
+                        task,
+                    )
+                    .await,
+                )
+            }
+            ("POST", ["api", "scheduler", task_type, "stop"]) => {
+                result(scheduler::stop_task(&self.ctx.scheduler, pool, task_type).await)
+            }
+            (_, ["api", ..]) => Response::error(404, "Route inconnue (voir /api/openapi.json)"),
+            _ => Response::error(404, "Route inconnue"),
+        };
+        Ok(response)
+    }
+
+    /// Process an uploaded file for a line, as if it had been dropped in its folder.
+    async fn upload(
+        &self,
+        line_id: i64,
+        filename: &str,
+        body: &[u8],
+    ) -> Result<Response, Response> {
+        if filename.is_empty()
+            || filename.len() > 255
+            || filename.contains(['/', '\\'])
+            || filename.starts_with('.')
+        {
+            return Err(Response::error(400, "Nom de fichier invalide"));
+        }
+
+        let line = sqlx::query("SELECT prefix, archived_path FROM lines WHERE id = ?")
+            .bind(line_id)
+            .fetch_optional(&self.ctx.db.pool)
+            .await
+            .map_err(|e| Response::error(500, &e.to_string()))?
+            .ok_or_else(|| Response::error(404, &format!("Ligne {} introuvable", line_id)))?;
+        let prefix: String = line.get("prefix");
+        let archived_path: Option<String> = line.get("archived_path");
+        if !stock::is_input_file(filename, &prefix) {
+            return Err(Response::error(
+                400,
+                &format!(
+                    "{} ne correspond pas à la ligne (préfixe {}, extension .csv/.txt/.tmp)",
+                    filename, prefix
+                ),
+            ));
+        }
+
+        let dir = self.ctx.uploads_dir.join(line_id.to_string());
+        let path = dir.join(filename);
+        let internal = |e: std::io::Error| Response::error(500, &e.to_string());
+        tokio::fs::create_dir_all(&dir).await.map_err(internal)?;
+        // create_new: a concurrent upload of the same name gets 409 instead of
+        // overwriting the file being processed.
+        let written = async {
+            let mut file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await?;
+            if let Err(e) = file.write_all(body).await {
+                let _ = tokio::fs::remove_file(&path).await;
+                return Err(e);
+            }
+            Ok(())
+        };
+        match written.await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(Response::error(
+                    409,
+                    &format!("{} est déjà en cours de traitement", filename),
+                ));
+            }
+            Err(e) => return Err(internal(e)),
+        }
+
+        let outcome = StockProcessor::new(self.ctx.db.pool.clone(), self.ctx.db.sql.clone())
+            .process_file(line_id, path.clone(), prefix, archived_path)
+            .await
+            .map_err(|e| e.to_string());
+        // Processing moves the file away; if it is still here (ignored, or an error
+        // before it was moved), drop it so the name can be uploaded again.
+        if path.exists() {
+            let _ = tokio::fs::remove_file(&path).await;
+        }
+        let outcome = outcome.map_err(|e| Response::error(500, &e))?;
+
+        let mut body =
+            serde_json::to_value(&outcome).map_err(|e| Response::error(500, &e.to_string()))?;
+        body["filename"] = filename.into();
+        body["message"] = outcome.to_string().into();
+        // Queued: accepted, to be retried by the process that watches the line.
+        let status = match outcome {
+            FileOutcome::Queued { .. } => 202,
+            _ => 200,
+        };
+        Ok(Response::json(status, &body))
+    }
+}
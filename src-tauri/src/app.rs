@@ -1,4 +1,5 @@
 use tauri::Manager;
+use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
 use tauri_plugin_opener;
 use tauri_plugin_process;
 use tauri_plugin_single_instance::init as single_instance_init;
@@ -9,6 +10,7 @@ pub fn run_app() {
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_notification::init())
         .plugin(single_instance_init(|app, _args, _cwd| {
             // On second launch, focus existing window instead of spawning another instance.
             if let Some(window) = app.get_webview_window("main") {
@@ -22,24 +24,97 @@ pub fn run_app() {
             crate::logging::setup(app)?;
 
             // Watcher registry (prevents duplicates and enables stop/start).
-            app.manage(crate::stock::WatcherState::new());
-            app.manage(crate::scheduler::SchedulerState::new());
+            let watchers = crate::stock::WatcherState::new();
+            let scheduler = crate::scheduler::SchedulerState::new();
+            app.manage(watchers.clone());
+            app.manage(scheduler.clone());
 
             let handle = app.handle();
             let handle_clone = handle.clone();
             tauri::async_runtime::block_on(async move {
-                let pool = crate::db::init_db(&handle_clone)
-                    .await
-                    .expect("failed to init db");
-                handle_clone.manage(crate::db::DbState { pool: pool.clone() });
+                let app_dir = handle_clone.path().app_data_dir()?;
+                // With the service or `visor watch` running on the same folder, the
+                // app still opens, to consult and configure, but watches nothing.
+                let watching = match crate::watch_lock::acquire(&app_dir) {
+                    Ok(lock) => {
+                        handle_clone.manage(lock);
+                        true
+                    }
+                    Err(e) => {
+                        log::warn!("{}", e);
+                        watchers.set_unavailable(e.clone());
+                        handle_clone
+                            .dialog()
+                            .message(format!(
+                                "{}\n\nL'application s'ouvre sans surveiller les lignes: \
+                                 l'autre processus continue de les traiter.",
+                                e
+                            ))
+                            .title("Visor")
+                            .kind(MessageDialogKind::Warning)
+                            .show(|_| {});
+                        false
+                    }
+                };
+
+                // A failed migration stops startup rather than running on a half-upgraded schema.
+                let pool = crate::db::init_db(&handle_clone).await.map_err(|e| {
+                    log::error!("Initialisation de la base impossible: {}", e);
+                    e
+                })?;
+                if watching {
+                    crate::stock::queue::recover_interrupted(&pool).await;
+                }
+                let sql = crate::sql_pool::SqlServerPools::default();
+                sql.spawn_reaper();
+                let db = crate::db::DbState {
+                    pool: pool.clone(),
+                    sql,
+                };
+                handle_clone.manage(db.clone());
+
+                crate::retention::spawn_job(
+                    pool.clone(),
+                    app_dir.join(crate::retention::ARCHIVE_DIR_NAME),
+                );
+                crate::alerts::spawn_monitor(
+                    crate::host::Host::Desktop(handle_clone.clone()),
+                    pool.clone(),
+                );
+
+                let metrics = crate::metrics::MetricsServer::new(
+                    pool.clone(),
+                    watchers.clone(),
+                    scheduler.clone(),
+                );
+                if let Err(e) = metrics.restart().await {
+                    log::error!("{}", e);
+                }
+                handle_clone.manage(metrics);
+
+                let api = crate::api::ApiServer::new(crate::api::ApiContext {
+                    db,
+                    watchers,
+                    scheduler,
+                    host: crate::host::Host::Desktop(handle_clone.clone()),
+                    uploads_dir: app_dir.join(crate::api::UPLOADS_DIR_NAME),
+                });
+                if let Err(e) = api.restart().await {
+                    log::error!("{}", e);
+                }
+                handle_clone.manage(api);
 
                 // Start watchers for active lines
-                let lines = sqlx::query(
-                    "SELECT id, path, prefix, archived_path FROM lines WHERE active = 1",
-                )
-                .fetch_all(&pool)
-                .await
-                .expect("failed to fetch lines");
+                let lines = if watching {
+                    sqlx::query(
+                        "SELECT id, path, prefix, archived_path FROM lines WHERE active = 1",
+                    )
+                    .fetch_all(&pool)
+                    .await
+                    .expect("failed to fetch lines")
+                } else {
+                    Vec::new()
+                };
 
                 for line in lines {
                     use sqlx::Row;
@@ -51,7 +126,8 @@ pub fn run_app() {
                         line.get("archived_path"),
                     );
                 }
-            });
+                Ok::<(), Box<dyn std::error::Error>>(())
+            })?;
 
             // Tray Setup
             let quit_i = tauri::menu::MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
@@ -106,17 +182,45 @@ pub fn run_app() {
             crate::commands::dashboard::get_dashboard_snapshot,
             crate::commands::mappings::get_mappings,
             crate::commands::mappings::save_mappings,
+            crate::commands::mappings::get_effective_mappings,
             crate::commands::mappings::get_model_mappings,
             crate::commands::mappings::save_model_mappings,
             crate::commands::mappings::reset_model_mappings,
             crate::commands::production::get_production_data,
+            crate::commands::queue::get_queue_items,
+            crate::commands::queue::retry_queue_item,
+            crate::commands::queue::cancel_queue_item,
+            crate::commands::queue::get_retry_policy,
+            crate::commands::queue::save_retry_policy,
+            crate::commands::retention::get_retention_policy,
+            crate::commands::retention::save_retention_policy,
+            crate::commands::retention::run_retention_now,
+            crate::commands::alerts::get_alerts,
+            crate::commands::alerts::acknowledge_alert,
+            crate::commands::alerts::get_alert_settings,
+            crate::commands::alerts::save_alert_settings,
+            crate::commands::alerts::test_alert_channel,
+            crate::commands::api::get_api_settings,
+            crate::commands::api::save_api_settings,
+            crate::commands::metrics::get_metrics_settings,
+            crate::commands::metrics::save_metrics_settings,
+            crate::commands::preview::preview_file,
+            crate::commands::mappings::get_timezone,
+            crate::commands::mappings::save_timezone,
             crate::commands::sql_server::get_sql_server_config,
             crate::commands::sql_server::save_sql_server_config,
+            crate::commands::sql_server::get_sql_server_profiles,
+            crate::commands::sql_server::save_sql_server_profile,
+            crate::commands::sql_server::delete_sql_server_profile,
+            crate::commands::sql_server::test_sql_server_profile,
+            crate::commands::sql_server::get_sql_profile_bindings,
+            crate::commands::sql_server::set_sql_profile_binding,
             crate::commands::logs::get_logs,
             crate::commands::logs::add_log,
             crate::commands::logs::clear_logs,
             crate::commands::sql_queries::get_sql_queries,
             crate::commands::sql_queries::save_sql_query,
+            crate::commands::sql_queries::validate_sql_template,
             crate::commands::defaults::get_default_mappings,
             crate::commands::defaults::get_default_mappings,
             crate::commands::logs::reset_line_stats,
@@ -0,0 +1,679 @@
+use crate::api::ApiSettings;
+use crate::commands::exports::{self, ExportDatResult};
+use crate::commands::hfsql::{self, ArticleSyncResult};
+use crate::commands::lines::{load_lines, store_line, Line};
+use crate::commands::logs::{load_logs, LogEntry};
+use crate::commands::mappings::{
+    fetch_line_mappings, fetch_model_mappings, store_line_mappings, store_model_mappings,
+    MappingRow,
+};
+use crate::commands::sql_queries::{load_sql_queries, store_sql_query};
+use crate::db::DbState;
+use crate::host::Host;
+use crate::secrets::decrypt;
+use crate::sql_pool::SqlServerPools;
+use crate::stock::{self, FileOutcome, StockProcessor, WatcherState};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Pool, Sqlite};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// First arguments that select the command-line interface instead of the window.
+pub(crate) const COMMANDS: [&str; 11] = [
+    "lines", "watch", "process", "export", "sync", "dump", "restore", "logs", "alerts", "help",
+    "--help",
+];
+
+const USAGE: &str = "\
+Utilisation: visor <commande> [--data-dir <dossier>]
+
+  lines list                          lister les lignes
+  lines show <id>                     afficher une ligne (JSON)
+  lines add <fichier.json|->          créer une ligne depuis un JSON
+  lines edit <id> <fichier.json|->    modifier les champs donnés d'une ligne
+  lines enable <id> | disable <id>    activer/désactiver la surveillance
+                                      (pris en compte au prochain démarrage)
+  watch [<id>...]                     surveiller des lignes (par défaut les lignes
+                                      actives) jusqu'à Ctrl+C; refusé si
+                                      l'application ou le service surveille déjà
+  watch start <id> | stop <id>        démarrer/arrêter la surveillance d'une ligne
+                                      dans l'application ou le service en cours
+                                      (via son API HTTP, qui doit être activée)
+  process <id> <fichier>              traiter un fichier pour une ligne
+  export <type> <chemin>              logitron-produit, logitron-of, ateis-produit, ateis-of
+  sync <type>                         ateis-produit, ateis-of
+  dump <fichier.json|->               exporter mappings et requêtes SQL
+  restore <fichier.json|->            restaurer mappings et requêtes SQL
+  logs [--line <id>] [--level <niveau>] [-n <nombre>] [--follow]
+  alerts [--all] [-n <nombre>]        alertes en cours (--all: aussi les résolues)
+  alerts ack <id>                     acquitter une alerte (plus de rappels)
+  alerts test <canal>                 envoyer une notification de test
+
+Sans --data-dir: $VISOR_DATA_DIR, sinon le dossier de l'application.";
+
+/// Options that take a value; everything else starting with `-` is a flag.
+const VALUE_OPTIONS: [&str; 4] = ["--data-dir", "--line", "--level", "-n"];
+
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            options: HashMap::new(),
+            flags: Vec::new(),
+        };
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if VALUE_OPTIONS.contains(&arg.as_str()) {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("Valeur manquante pour {}", arg))?;
+                parsed.options.insert(arg.clone(), value.clone());
+            } else if arg.starts_with('-') && arg != "-" {
+                parsed.flags.push(arg.clone());
+            } else {
+                parsed.positional.push(arg.clone());
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn arg(&self, index: usize, name: &str) -> Result<&str, String> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| format!("Argument manquant: {}\n\n{}", name, USAGE))
+    }
+
+    fn id(&self, index: usize) -> Result<i64, String> {
+        let value = self.arg(index, "<id>")?;
+        value
+            .parse()
+            .map_err(|_| format!("Identifiant de ligne invalide: {}", value))
+    }
+
+    fn option_i64(&self, name: &str) -> Result<Option<i64>, String> {
+        self.options
+            .get(name)
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| format!("Nombre invalide pour {}: {}", name, v))
+            })
+            .transpose()
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|f| f == name)
+    }
+}
+
+/// Mappings and SQL queries, for `dump` / `restore`. Line mappings are keyed by
+/// line name so they can be restored on another machine.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ConfigDump {
+    #[serde(default)]
+    model_mappings: BTreeMap<String, Vec<MappingRow>>,
+    #[serde(default)]
+    line_mappings: BTreeMap<String, Vec<MappingRow>>,
+    #[serde(default)]
+    sql_queries: BTreeMap<String, String>,
+}
+
+/// Run a command (`args` as from `std::env::args`); returns the exit code.
+pub fn run(args: Vec<String>) -> i32 {
+    #[cfg(windows)]
+    attach_parent_console();
+
+    let parsed = match Args::parse(&args[1..]) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    if matches!(
+        parsed.positional.first().map(String::as_str),
+        None | Some("help")
+    ) || parsed.flag("--help")
+    {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let Some(data_dir) = crate::headless::data_dir(&args) else {
+        eprintln!("Dossier de données introuvable: utilisez --data-dir ou $VISOR_DATA_DIR");
+        return 2;
+    };
+
+    match crate::runtime::block_on(execute(parsed, data_dir)) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Erreur: {}", e);
+            1
+        }
+    }
+}
+
+async fn execute(args: Args, data_dir: PathBuf) -> Result<(), String> {
+    let pool = crate::db::init_db_at(&data_dir)
+        .await
+        .map_err(|e| format!("Initialisation de la base impossible: {}", e))?;
+    let db = DbState {
+        pool: pool.clone(),
+        sql: SqlServerPools::default(),
+    };
+    let host = Host::Headless {
+        data_dir: data_dir.clone(),
+    };
+
+    let result = match args.arg(0, "<commande>")? {
+        "lines" => lines(&args, &pool).await,
+        "watch" => watch(&args, &db, &data_dir).await,
+        "process" => process(&args, &db).await,
+        "export" => export(&args, &host, &db).await,
+        "sync" => sync(&args, &host, &db).await,
+        "dump" => dump(&args, &pool).await,
+        "restore" => restore(&args, &pool).await,
+        "logs" => logs(&args, &pool).await,
+        "alerts" => alerts(&args, &host, &pool).await,
+        other => Err(format!("Commande inconnue: {}\n\n{}", other, USAGE)),
+    };
+    pool.close().await;
+    result
+}
+
+fn read_input(source: &str) -> Result<String, String> {
+    if source == "-" {
+        let mut input = String::new();
+        std::io::stdin()
+            .read_to_string(&mut input)
+            .map_err(|e| e.to_string())?;
+        Ok(input)
+    } else {
+        std::fs::read_to_string(source).map_err(|e| format!("{}: {}", source, e))
+    }
+}
+
+fn write_output(target: &str, content: &str) -> Result<(), String> {
+    if target == "-" {
+        println!("{}", content);
+        Ok(())
+    } else {
+        std::fs::write(target, content).map_err(|e| format!("{}: {}", target, e))
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string_pretty(value).map_err(|e| e.to_string())
+}
+
+async fn find_line(pool: &Pool<Sqlite>, id: i64) -> Result<Line, String> {
+    load_lines(pool)
+        .await?
+        .into_iter()
+        .find(|l| l.id == Some(id))
+        .ok_or_else(|| format!("Ligne {} introuvable", id))
+}
+
+async fn lines(args: &Args, pool: &Pool<Sqlite>) -> Result<(), String> {
+    match args.arg(1, "list|show|add|edit|enable|disable")? {
+        "list" => {
+            println!(
+                "{:>4}  {:<5}  {:<24}  {:<9}  CHEMIN",
+                "ID", "ACTIF", "NOM", "FORMAT"
+            );
+            for line in load_lines(pool).await? {
+                println!(
+                    "{:>4}  {:<5}  {:<24}  {:<9}  {}",
+                    line.id.unwrap_or_default(),
+                    if line.active { "oui" } else { "non" },
+                    line.name,
+                    line.file_format.as_deref().unwrap_or("ATEIS"),
+                    line.path
+                );
+            }
+            Ok(())
+        }
+        "show" => {
+            let line = find_line(pool, args.id(2)?).await?;
+            println!("{}", to_json(&line)?);
+            Ok(())
+        }
+        "add" => {
+            let mut line: Line =
+                serde_json::from_str(&read_input(args.arg(2, "<fichier.json|->")?)?)
+                    .map_err(|e| format!("Ligne invalide: {}", e))?;
+            line.id = None;
+            let id = store_line(pool, &line).await?;
+            println!("Ligne {} créée", id);
+            Ok(())
+        }
+        "edit" => {
+            let id = args.id(2)?;
+            let changes: Value =
+                serde_json::from_str(&read_input(args.arg(3, "<fichier.json|->")?)?)
+                    .map_err(|e| format!("JSON invalide: {}", e))?;
+            let Value::Object(changes) = changes else {
+                return Err("Un objet JSON est attendu".to_string());
+            };
+
+            let mut current =
+                serde_json::to_value(find_line(pool, id).await?).map_err(|e| e.to_string())?;
+            if let Value::Object(fields) = &mut current {
+                fields.extend(changes);
+                fields.insert("id".to_string(), id.into());
+            }
+            let line: Line =
+                serde_json::from_value(current).map_err(|e| format!("Ligne invalide: {}", e))?;
+            store_line(pool, &line).await?;
+            println!("Ligne {} modifiée", id);
+            Ok(())
+        }
+        action @ ("enable" | "disable") => {
+            let id = args.id(2)?;
+            let updated = sqlx::query("UPDATE lines SET active = ? WHERE id = ?")
+                .bind(action == "enable")
+                .bind(id)
+                .execute(pool)
+                .await
+                .map_err(|e| e.to_string())?
+                .rows_affected();
+            if updated == 0 {
+                return Err(format!("Ligne {} introuvable", id));
+            }
+            println!(
+                "Ligne {} {}",
+                id,
+                if action == "enable" {
+                    "activée"
+                } else {
+                    "désactivée"
+                }
+            );
+            Ok(())
+        }
+        other => Err(format!("Action inconnue: lines {}\n\n{}", other, USAGE)),
+    }
+}
+
+async fn watch(args: &Args, db: &DbState, data_dir: &Path) -> Result<(), String> {
+    if let Some(action @ ("start" | "stop")) = args.positional.get(1).map(String::as_str) {
+        return watch_remote(&db.pool, args.id(2)?, action).await;
+    }
+    let ids = args.positional[1..]
+        .iter()
+        .map(|v| {
+            v.parse::<i64>()
+                .map_err(|_| format!("Identifiant de ligne invalide: {}", v))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let lines: Vec<Line> = load_lines(&db.pool)
+        .await?
+        .into_iter()
+        .filter(|l| {
+            if ids.is_empty() {
+                l.active
+            } else {
+                l.id.is_some_and(|id| ids.contains(&id))
+            }
+        })
+        .collect();
+    if lines.is_empty() {
+        return Err("Aucune ligne à surveiller".to_string());
+    }
+
+    let _lock = crate::watch_lock::acquire(data_dir)?;
+    stock::queue::recover_interrupted(&db.pool).await;
+    db.sql.spawn_reaper();
+    let watchers = WatcherState::new();
+    for line in lines {
+        println!("Surveillance de {} ({})", line.name, line.path);
+        stock::watch_line(
+            &watchers,
+            db,
+            line.id.unwrap_or_default(),
+            line.path,
+            line.prefix,
+            line.archived_path,
+        );
+    }
+
+    crate::headless::wait_for_shutdown().await;
+    watchers.stop_all();
+    Ok(())
+}
+
+/// Start or stop a line's watcher in the running app or service, through its
+/// HTTP API: watchers live in that process, not in this one.
+async fn watch_remote(pool: &Pool<Sqlite>, id: i64, action: &str) -> Result<(), String> {
+    let settings = ApiSettings::load(pool).await;
+    if !settings.enabled {
+        return Err(
+            "API HTTP désactivée: activez-la dans Paramètres pour piloter la surveillance \
+                    depuis la ligne de commande, ou utilisez lines enable/disable (pris en compte \
+                    au prochain démarrage)"
+                .to_string(),
+        );
+    }
+    let token = decrypt(settings.token.as_deref().unwrap_or_default())?;
+    let mut address: SocketAddr = settings
+        .bind_address
+        .parse()
+        .map_err(|_| format!("Adresse de l'API invalide: {}", settings.bind_address))?;
+    if address.ip().is_unspecified() {
+        address.set_ip(match address {
+            SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+            SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+        });
+    }
+
+    let exchange = async {
+        let mut stream = TcpStream::connect(address).await.map_err(|e| {
+            format!(
+                "API HTTP injoignable sur {} (application ou service démarré ?): {}",
+                address, e
+            )
+        })?;
+        let request = format!(
+            "POST /api/lines/{}/{} HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\n\
+             Content-Length: 0\r\nConnection: close\r\n\r\n",
+            id, action, address, token
+        );
+        stream
+            .write_all(request.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut response = Vec::new();
+        stream
+            .read_to_end(&mut response)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok::<_, String>(String::from_utf8_lossy(&response).to_string())
+    };
+    let response = tokio::time::timeout(Duration::from_secs(30), exchange)
+        .await
+        .map_err(|_| format!("API HTTP {}: délai dépassé", address))??;
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head.split_whitespace().nth(1).unwrap_or_default();
+    if status != "200" {
+        let error = serde_json::from_str::<Value>(body)
+            .ok()
+            .and_then(|b| b.get("error").and_then(Value::as_str).map(str::to_string))
+            .unwrap_or_else(|| head.lines().next().unwrap_or_default().to_string());
+        return Err(format!("Ligne {}: {}", id, error));
+    }
+    println!(
+        "Surveillance de la ligne {} {}",
+        id,
+        if action == "start" {
+            "démarrée"
+        } else {
+            "arrêtée"
+        }
+    );
+    Ok(())
+}
+
+async fn process(args: &Args, db: &DbState) -> Result<(), String> {
+    let line = find_line(&db.pool, args.id(1)?).await?;
+    let path = PathBuf::from(args.arg(2, "<fichier>")?);
+    let filename = path
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .filter(|_| path.is_file())
+        .ok_or_else(|| format!("Fichier introuvable: {}", path.display()))?;
+    if !stock::is_input_file(&filename, &line.prefix) {
+        return Err(format!(
+            "{} ne correspond pas à la ligne (préfixe {}, extension .csv/.txt/.tmp)",
+            filename, line.prefix
+        ));
+    }
+
+    let outcome = StockProcessor::new(db.pool.clone(), db.sql.clone())
+        .process_file(
+            line.id.unwrap_or_default(),
+            path,
+            line.prefix,
+            line.archived_path,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    println!("{}: {}", filename, outcome);
+    if let FileOutcome::Queued { .. } = outcome {
+        println!(
+            "Le fichier ne sera réessayé que par un processus qui surveille la ligne \
+             (application, service ou visor watch)."
+        );
+    }
+    Ok(())
+}
+
+fn print_export(result: ExportDatResult) {
+    println!(
+        "{} ligne(s) écrite(s) dans {}",
+        result.rows, result.output_path
+    );
+}
+
+async fn export(args: &Args, host: &Host, db: &DbState) -> Result<(), String> {
+    let kind = args.arg(1, "<type>")?;
+    let output_path = args.arg(2, "<chemin>")?.to_string();
+    let result = match kind {
+        "logitron-produit" => {
+            exports::run_logitron_produit_export(host, db, output_path, None).await?
+        }
+        "logitron-of" => exports::run_ordre_fabrication_export(host, db, output_path).await?,
+        "ateis-produit" => exports::run_ateis_produit_export(db, output_path).await?,
+        "ateis-of" => exports::run_ateis_of_export(db, output_path).await?,
+        other => return Err(format!("Export inconnu: {}\n\n{}", other, USAGE)),
+    };
+    print_export(result);
+    Ok(())
+}
+
+fn print_sync(result: ArticleSyncResult) {
+    println!(
+        "{} traité(s): {} mis à jour, {} inséré(s), {} erreur(s)",
+        result.total_processed, result.updated, result.inserted, result.errors
+    );
+    for detail in result.error_details {
+        println!("  {}", detail);
+    }
+}
+
+async fn sync(args: &Args, host: &Host, db: &DbState) -> Result<(), String> {
+    let result = match args.arg(1, "<type>")? {
+        "ateis-produit" => hfsql::run_ateis_produit_sync(host.clone(), db).await?,
+        "ateis-of" => hfsql::run_ateis_of_sync(host.clone(), db).await?,
+        other => return Err(format!("Synchronisation inconnue: {}\n\n{}", other, USAGE)),
+    };
+    print_sync(result);
+    Ok(())
+}
+
+async fn dump(args: &Args, pool: &Pool<Sqlite>) -> Result<(), String> {
+    let target = args.arg(1, "<fichier.json|->")?;
+    let mut config = ConfigDump::default();
+
+    let formats: Vec<String> =
+        sqlx::query_scalar("SELECT DISTINCT format_name FROM model_mappings ORDER BY format_name")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    for format_name in formats {
+        let rows = fetch_model_mappings(pool, &format_name).await?;
+        config.model_mappings.insert(format_name, rows);
+    }
+    for line in load_lines(pool).await? {
+        let rows = fetch_line_mappings(pool, line.id.unwrap_or_default()).await?;
+        if !rows.is_empty() {
+            config.line_mappings.insert(line.name, rows);
+        }
+    }
+    for query in load_sql_queries(pool).await? {
+        config
+            .sql_queries
+            .insert(query.format_name, query.query_template);
+    }
+
+    write_output(target, &to_json(&config)?)?;
+    if target != "-" {
+        eprintln!(
+            "{} modèle(s), {} ligne(s), {} requête(s) exportés dans {}",
+            config.model_mappings.len(),
+            config.line_mappings.len(),
+            config.sql_queries.len(),
+            target
+        );
+    }
+    Ok(())
+}
+
+async fn restore(args: &Args, pool: &Pool<Sqlite>) -> Result<(), String> {
+    let config: ConfigDump = serde_json::from_str(&read_input(args.arg(1, "<fichier.json|->")?)?)
+        .map_err(|e| format!("Fichier de configuration invalide: {}", e))?;
+
+    // Check everything before writing anything, so a bad entry restores nothing.
+    for (name, rows) in config
+        .model_mappings
+        .iter()
+        .chain(config.line_mappings.iter())
+    {
+        stock::check_transformations(rows)
+            .and_then(|_| stock::check_mapping_rules(rows))
+            .and_then(|_| stock::check_sql_types(rows))
+            .map_err(|e| format!("{}: {}", name, e))?;
+    }
+    for (name, template) in &config.sql_queries {
+        stock::check_sql_template(template).map_err(|e| format!("{}: {}", name, e))?;
+    }
+
+    let line_ids: HashMap<String, i64> = load_lines(pool)
+        .await?
+        .into_iter()
+        .filter_map(|l| Some((l.name, l.id?)))
+        .collect();
+
+    for (format_name, rows) in config.model_mappings {
+        store_model_mappings(pool, &format_name, rows).await?;
+    }
+    for (name, rows) in config.line_mappings {
+        match line_ids.get(&name) {
+            Some(&line_id) => store_line_mappings(pool, line_id, rows).await?,
+            None => eprintln!("Ligne {} absente, mappings ignorés", name),
+        }
+    }
+    for (format_name, template) in &config.sql_queries {
+        store_sql_query(pool, format_name, template).await?;
+    }
+
+    println!("Configuration restaurée");
+    Ok(())
+}
+
+fn print_log(entry: &LogEntry) {
+    println!(
+        "{} [{}] {}: {}",
+        entry.created_at,
+        entry.level,
+        entry.source.as_deref().unwrap_or("-"),
+        entry.message
+    );
+}
+
+async fn logs(args: &Args, pool: &Pool<Sqlite>) -> Result<(), String> {
+    let line_id = args.option_i64("--line")?;
+    let level = args.options.get("--level").map(|l| l.to_uppercase());
+    let limit = args.option_i64("-n")?.unwrap_or(50);
+
+    let mut entries = load_logs(pool, line_id, level.clone(), Some(limit)).await?;
+    entries.reverse();
+    entries.iter().for_each(print_log);
+    if !args.flag("--follow") {
+        return Ok(());
+    }
+
+    let mut last_id = entries.iter().map(|e| e.id).max().unwrap_or(0);
+    let shutdown = crate::headless::wait_for_shutdown();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(2)) => {}
+            _ = &mut shutdown => return Ok(()),
+        }
+        let mut entries = load_logs(pool, line_id, level.clone(), Some(500)).await?;
+        entries.retain(|e| e.id > last_id);
+        entries.reverse();
+        entries.iter().for_each(print_log);
+        last_id = entries.iter().map(|e| e.id).max().unwrap_or(last_id);
+    }
+}
+
+async fn alerts(args: &Args, host: &Host, pool: &Pool<Sqlite>) -> Result<(), String> {
+    match args.positional.get(1).map(String::as_str) {
+        None => {
+            let alerts =
+                crate::alerts::load_alerts(pool, args.flag("--all"), args.option_i64("-n")?)
+                    .await?;
+            for alert in alerts.iter().rev() {
+                println!(
+                    "#{} {} [{}] {} {} ({}x depuis {}): {}",
+                    alert.id,
+                    alert.status,
+                    alert.severity,
+                    alert.line_name.as_deref().unwrap_or("-"),
+                    alert.kind,
+                    alert.occurrences,
+                    alert.first_seen,
+                    alert.message
+                );
+            }
+            Ok(())
+        }
+        Some("ack") => {
+            let value = args.arg(2, "<id>")?;
+            let id: i64 = value
+                .parse()
+                .map_err(|_| format!("Identifiant d'alerte invalide: {}", value))?;
+            let user = std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_else(|_| "cli".to_string());
+            crate::alerts::acknowledge(pool, id, Some(&user)).await?;
+            println!("Alerte {} acquittée", id);
+            Ok(())
+        }
+        Some("test") => {
+            let name = args.arg(2, "<canal>")?;
+            crate::alerts::test_channel(host, pool, name).await?;
+            println!("Notification de test envoyée via {}", name);
+            Ok(())
+        }
+        Some(other) => Err(format!("Action inconnue: alerts {}\n\n{}", other, USAGE)),
+    }
+}
+
+/// Release builds are GUI-subsystem executables on Windows: without this, output
+/// from a terminal launch goes nowhere.
+#[cfg(windows)]
+fn attach_parent_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // SAFETY: plain Win32 call; failing (no parent console) is harmless.
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
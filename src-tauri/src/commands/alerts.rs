@@ -0,0 +1,51 @@
+use crate::alerts::{self, Alert, AlertSettings};
+use crate::db::DbState;
+use crate::host::Host;
+#[cfg(feature = "desktop")]
+use tauri::{AppHandle, State};
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_alerts(
+    state: State<'_, DbState>,
+    include_resolved: Option<bool>,
+    limit: Option<i64>,
+) -> Result<Vec<Alert>, String> {
+    alerts::load_alerts(&state.pool, include_resolved.unwrap_or(false), limit).await
+}
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn acknowledge_alert(
+    state: State<'_, DbState>,
+    id: i64,
+    by: Option<String>,
+) -> Result<(), String> {
+    alerts::acknowledge(&state.pool, id, by.as_deref()).await
+}
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_alert_settings(state: State<'_, DbState>) -> Result<AlertSettings, String> {
+    Ok(AlertSettings::load_masked(&state.pool).await)
+}
+
+/// Taken into account at the monitor's next pass.
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn save_alert_settings(
+    state: State<'_, DbState>,
+    settings: AlertSettings,
+) -> Result<(), String> {
+    settings.save(&state.pool).await
+}
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn test_alert_channel(
+    app: AppHandle,
+    state: State<'_, DbState>,
+    name: String,
+) -> Result<(), String> {
+    alerts::test_channel(&Host::Desktop(app), &state.pool, &name).await
+}
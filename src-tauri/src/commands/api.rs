@@ -0,0 +1,22 @@
+use crate::api::{ApiServer, ApiSettings};
+use crate::db::DbState;
+#[cfg(feature = "desktop")]
+use tauri::State;
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_api_settings(state: State<'_, DbState>) -> Result<ApiSettings, String> {
+    Ok(ApiSettings::load_masked(&state.pool).await)
+}
+
+/// Save the settings and restart the HTTP server with them.
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn save_api_settings(
+    state: State<'_, DbState>,
+    server: State<'_, ApiServer>,
+    settings: ApiSettings,
+) -> Result<(), String> {
+    settings.save(&state.pool).await?;
+    server.restart().await
+}
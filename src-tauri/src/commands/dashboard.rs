@@ -1,7 +1,10 @@
-use crate::commands::lines::Line;
+use crate::commands::lines::{today_bounds, Line};
 use crate::db::DbState;
+use crate::stock;
 use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
 use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+#[cfg(feature = "desktop")]
 use tauri::State;
 
 #[derive(Debug, Serialize)]
@@ -17,20 +20,27 @@ pub struct DashboardLine {
     pub site: Option<String>,
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn get_dashboard_snapshot(
     state: State<'_, DbState>,
 ) -> Result<Vec<DashboardLine>, String> {
+    load_dashboard(&state.pool).await
+}
+
+/// Every line with its MARCHE/ALERTE/ARRET status and today's activity.
+pub(crate) async fn load_dashboard(pool: &Pool<Sqlite>) -> Result<Vec<DashboardLine>, String> {
     let lines = sqlx::query_as::<_, Line>(
         "SELECT id, name, path, prefix, interval_check, interval_alert, archived_path, rejected_path, active, \
                 site, unite, code_ligne, log_path, file_format,\
                 total_traites, total_erreurs, last_file_time, etat_actuel, created_at, flag_dec \
          FROM lines ORDER BY created_at DESC",
     )
-    .fetch_all(&state.pool)
+    .fetch_all(pool)
     .await
     .map_err(|e| e.to_string())?;
 
+    let (day_start, day_end) = today_bounds();
     let mut result = Vec::new();
 
     for line in lines {
@@ -40,17 +50,18 @@ pub async fn get_dashboard_snapshot(
             "SELECT processed_at FROM production_data WHERE line_id = ? ORDER BY processed_at DESC LIMIT 1",
         )
         .bind(id)
-        .fetch_optional(&state.pool)
+        .fetch_optional(pool)
         .await
         .map_err(|e| e.to_string())?;
 
-        let today_prefix = Local::now().format("%Y-%m-%d").to_string() + "%";
         let total_processed: i64 = sqlx::query_scalar(
-            "SELECT COUNT(1) FROM production_data WHERE line_id = ? AND status = 'SUCCESS' AND processed_at LIKE ?",
+            "SELECT COUNT(1) FROM production_data \
+             WHERE line_id = ? AND processed_at >= ? AND processed_at < ? AND status = 'SUCCESS'",
         )
         .bind(id)
-        .bind(today_prefix)
-        .fetch_one(&state.pool)
+        .bind(&day_start)
+        .bind(&day_end)
+        .fetch_one(pool)
         .await
         .map_err(|e| e.to_string())?;
 
@@ -62,12 +73,8 @@ pub async fn get_dashboard_snapshot(
                     if !p.is_file() {
                         return false;
                     }
-                    let name = p
-                        .file_name()
-                        .and_then(|s| s.to_str())
-                        .unwrap_or("")
-                        .to_uppercase();
-                    name.ends_with(".TMP") && name.contains(&line.prefix.to_uppercase())
+                    let name = p.file_name().and_then(|s| s.to_str()).unwrap_or("");
+                    stock::is_input_file(name, &line.prefix)
                 })
                 .count() as i64,
             Err(_) => 0,
@@ -82,33 +89,13 @@ pub async fn get_dashboard_snapshot(
             0
         };
 
-        let status = if !line.active {
-            "ARRET".to_string()
-        } else if let Some(lp) = &last_processed {
-            let parsed = DateTime::parse_from_rfc3339(lp)
-                .ok()
-                .map(|dt| dt.with_timezone(&Local))
-                .or_else(|| {
-                    NaiveDateTime::parse_from_str(lp, "%Y-%m-%d %H:%M:%S")
-                        .ok()
-                        .and_then(|ndt| Local.from_local_datetime(&ndt).single())
-                });
-
-            if let Some(dt) = parsed {
-                let minutes = (Local::now() - dt).num_minutes();
-                if minutes <= line.interval_check {
-                    "MARCHE".to_string()
-                } else if minutes <= line.interval_alert {
-                    "ALERTE".to_string()
-                } else {
-                    "ARRET".to_string()
-                }
-            } else {
-                "ALERTE".to_string()
-            }
-        } else {
-            "ALERTE".to_string()
-        };
+        let status = line_status(
+            line.active,
+            last_processed.as_deref(),
+            line.interval_check,
+            line.interval_alert,
+        )
+        .to_string();
 
         result.push(DashboardLine {
             id,
@@ -125,3 +112,41 @@ pub async fn get_dashboard_snapshot(
 
     Ok(result)
 }
+
+/// `production_data.processed_at`: local `%Y-%m-%d %H:%M:%S`, or RFC 3339 in older rows.
+pub(crate) fn parse_processed_at(value: &str) -> Option<DateTime<Local>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|dt| dt.with_timezone(&Local))
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .and_then(|ndt| Local.from_local_datetime(&ndt).single())
+        })
+}
+
+/// MARCHE while the last file is at most `interval_check` minutes old, ALERTE
+/// up to `interval_alert`, ARRET beyond that or when the line is stopped.
+pub(crate) fn line_status(
+    active: bool,
+    last_processed: Option<&str>,
+    interval_check: i64,
+    interval_alert: i64,
+) -> &'static str {
+    if !active {
+        return "ARRET";
+    }
+    match last_processed.and_then(parse_processed_at) {
+        Some(dt) => {
+            let minutes = (Local::now() - dt).num_minutes();
+            if minutes <= interval_check {
+                "MARCHE"
+            } else if minutes <= interval_alert {
+                "ALERTE"
+            } else {
+                "ARRET"
+            }
+        }
+        None => "ALERTE",
+    }
+}
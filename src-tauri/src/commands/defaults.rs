@@ -1,5 +1,6 @@
 use crate::commands::mappings::{get_ateis_default_mappings, get_logitron_default_mappings, MappingRow};
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn get_default_mappings(format_name: String) -> Result<Vec<MappingRow>, String> {
     // This command is kept for backwards compatibility.
@@ -1,14 +1,16 @@
 use crate::commands::sql_queries::{
     get_or_init_sql_query, DEFAULT_LOGITRON_PRODUIT_QUERY, DEFAULT_ORDRE_FABRICATION_QUERY,
 };
-use crate::commands::sql_server::{connect_sql_server, get_sql_server_config};
+use crate::commands::sql_server::{load_bound_profile, query_timeout, with_query_timeout};
 use crate::db::DbState;
+use crate::host::Host;
 use futures_util::TryStreamExt;
 use serde::Serialize;
 use std::fs;
 use std::io::{BufWriter, Write};
 use std::path::Path;
-use tauri::{Emitter, State};
+#[cfg(feature = "desktop")]
+use tauri::State;
 use tiberius::numeric::Decimal;
 use tiberius::QueryItem;
 
@@ -31,20 +33,30 @@ fn format_left_any<T: ToString>(value: Option<T>, width: usize) -> String {
     format_left(value.map(|v| v.to_string()), width)
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn export_logitron_produit_dat(
     app: tauri::AppHandle,
     state: State<'_, DbState>,
     output_path: String,
     is_auto: Option<bool>,
+) -> Result<ExportDatResult, String> {
+    run_logitron_produit_export(&Host::Desktop(app), &state, output_path, is_auto).await
+}
+
+pub(crate) async fn run_logitron_produit_export(
+    app: &Host,
+    state: &DbState,
+    output_path: String,
+    is_auto: Option<bool>,
 ) -> Result<ExportDatResult, String> {
     let result = async {
         if output_path.trim().is_empty() {
             return Err("Chemin de sortie manquant".to_string());
         }
 
-        let cfg = get_sql_server_config(state.clone()).await?;
-        let mut client = connect_sql_server(cfg).await?;
+        let cfg = load_bound_profile(&state.pool, "LOGITRON_PRODUIT").await?;
+        let mut client = state.sql.connect(&cfg).await?;
 
         let query = get_or_init_sql_query(
             &state.pool,
@@ -53,10 +65,13 @@ pub async fn export_logitron_produit_dat(
         )
         .await?;
 
-        let mut stream = client
-            .query(query.as_str(), &[])
-            .await
-            .map_err(|e| e.to_string())?;
+        let mut stream = with_query_timeout(query_timeout(&cfg), async {
+            client
+                .query(query.as_str(), &[])
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await?;
 
         let out_path = Path::new(&output_path);
         if let Some(parent) = out_path.parent() {
@@ -162,6 +177,8 @@ pub async fn export_logitron_produit_dat(
 
         writer.flush().map_err(|e| e.to_string())?;
         drop(writer);
+        drop(stream);
+        client.release();
 
         if out_path.exists() {
             fs::remove_file(out_path).map_err(|e| e.to_string())?;
@@ -198,11 +215,20 @@ fn format_date_yyyymmdd(date: Option<chrono::NaiveDate>) -> String {
         .unwrap_or_default()
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn export_ordre_fabrication_dat(
     app: tauri::AppHandle,
     state: State<'_, DbState>,
     output_path: String,
+) -> Result<ExportDatResult, String> {
+    run_ordre_fabrication_export(&Host::Desktop(app), &state, output_path).await
+}
+
+pub(crate) async fn run_ordre_fabrication_export(
+    app: &Host,
+    state: &DbState,
+    output_path: String,
 ) -> Result<ExportDatResult, String> {
     use chrono::NaiveDateTime;
 
@@ -211,8 +237,8 @@ pub async fn export_ordre_fabrication_dat(
             return Err("Chemin de sortie manquant".to_string());
         }
 
-        let cfg = get_sql_server_config(state.clone()).await?;
-        let mut client = connect_sql_server(cfg).await?;
+        let cfg = load_bound_profile(&state.pool, "LOGITRON_OF").await?;
+        let mut client = state.sql.connect(&cfg).await?;
 
         let query = get_or_init_sql_query(
             &state.pool,
@@ -221,10 +247,13 @@ pub async fn export_ordre_fabrication_dat(
         )
         .await?;
 
-        let mut stream = client
-            .query(query.as_str(), &[])
-            .await
-            .map_err(|e| e.to_string())?;
+        let mut stream = with_query_timeout(query_timeout(&cfg), async {
+            client
+                .query(query.as_str(), &[])
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await?;
 
         let out_path = Path::new(&output_path);
         if let Some(parent) = out_path.parent() {
@@ -332,6 +361,8 @@ pub async fn export_ordre_fabrication_dat(
 
         writer.flush().map_err(|e| e.to_string())?;
         drop(writer);
+        drop(stream);
+        client.release();
 
         if out_path.exists() {
             fs::remove_file(out_path).map_err(|e| e.to_string())?;
@@ -359,17 +390,25 @@ pub async fn export_ordre_fabrication_dat(
     result
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn export_ateis_produit_dat(
     state: State<'_, DbState>,
     output_path: String,
+) -> Result<ExportDatResult, String> {
+    run_ateis_produit_export(&state, output_path).await
+}
+
+pub(crate) async fn run_ateis_produit_export(
+    state: &DbState,
+    output_path: String,
 ) -> Result<ExportDatResult, String> {
     if output_path.trim().is_empty() {
         return Err("Chemin de sortie manquant".to_string());
     }
 
-    let cfg = get_sql_server_config(state.clone()).await?;
-    let mut client = connect_sql_server(cfg).await?;
+    let cfg = load_bound_profile(&state.pool, "ATEIS_PRODUIT").await?;
+    let mut client = state.sql.connect(&cfg).await?;
 
     let query = get_or_init_sql_query(
         &state.pool,
@@ -378,10 +417,13 @@ pub async fn export_ateis_produit_dat(
     )
     .await?;
 
-    let mut stream = client
-        .query(query.as_str(), &[])
-        .await
-        .map_err(|e| e.to_string())?;
+    let mut stream = with_query_timeout(query_timeout(&cfg), async {
+        client
+            .query(query.as_str(), &[])
+            .await
+            .map_err(|e| e.to_string())
+    })
+    .await?;
 
     let out_path = Path::new(&output_path);
     if let Some(parent) = out_path.parent() {
@@ -474,6 +516,8 @@ pub async fn export_ateis_produit_dat(
 
     writer.flush().map_err(|e| e.to_string())?;
     drop(writer);
+    drop(stream);
+    client.release();
 
     if out_path.exists() {
         fs::remove_file(out_path).map_err(|e| e.to_string())?;
@@ -486,18 +530,26 @@ pub async fn export_ateis_produit_dat(
     })
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn export_ateis_of_dat(
     state: State<'_, DbState>,
     output_path: String,
+) -> Result<ExportDatResult, String> {
+    run_ateis_of_export(&state, output_path).await
+}
+
+pub(crate) async fn run_ateis_of_export(
+    state: &DbState,
+    output_path: String,
 ) -> Result<ExportDatResult, String> {
     use chrono::NaiveDateTime;
     if output_path.trim().is_empty() {
         return Err("Chemin de sortie manquant".to_string());
     }
 
-    let cfg = get_sql_server_config(state.clone()).await?;
-    let mut client = connect_sql_server(cfg).await?;
+    let cfg = load_bound_profile(&state.pool, "ATEIS_OF").await?;
+    let mut client = state.sql.connect(&cfg).await?;
 
     let query = get_or_init_sql_query(
         &state.pool,
@@ -506,10 +558,13 @@ pub async fn export_ateis_of_dat(
     )
     .await?;
 
-    let mut stream = client
-        .query(query.as_str(), &[])
-        .await
-        .map_err(|e| e.to_string())?;
+    let mut stream = with_query_timeout(query_timeout(&cfg), async {
+        client
+            .query(query.as_str(), &[])
+            .await
+            .map_err(|e| e.to_string())
+    })
+    .await?;
 
     let out_path = Path::new(&output_path);
     if let Some(parent) = out_path.parent() {
@@ -588,6 +643,8 @@ pub async fn export_ateis_of_dat(
 
     writer.flush().map_err(|e| e.to_string())?;
     drop(writer);
+    drop(stream);
+    client.release();
 
     if out_path.exists() {
         fs::remove_file(out_path).map_err(|e| e.to_string())?;
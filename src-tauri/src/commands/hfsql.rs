@@ -1,8 +1,11 @@
 use crate::db::DbState;
+use crate::secrets::{decrypt_password, mask_password, password_to_store};
 use odbc_api::{ConnectionOptions, Cursor, Environment};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
-use tauri::{AppHandle, Emitter, Manager, State};
+use sqlx::{FromRow, Pool, Sqlite};
+use crate::host::Host;
+#[cfg(feature = "desktop")]
+use tauri::{AppHandle, State};
 use std::io::Write;
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
@@ -27,17 +30,27 @@ struct SyncProgress {
     status: String,
 }
 
-#[tauri::command]
-pub async fn get_hfsql_config(state: State<'_, DbState>) -> Result<HfsqlConfig, String> {
-    let row = sqlx::query_as::<_, HfsqlConfig>(
+/// HFSQL settings with the password decrypted, for connecting.
+pub(crate) async fn load_hfsql_config(pool: &Pool<Sqlite>) -> Result<HfsqlConfig, String> {
+    let mut row = sqlx::query_as::<_, HfsqlConfig>(
         "SELECT id, dsn, username, password, log_path FROM hfsql_config WHERE id = 1",
     )
-    .fetch_one(&state.pool)
+    .fetch_one(pool)
     .await
     .map_err(|e| e.to_string())?;
+    decrypt_password(&mut row.password)?;
     Ok(row)
 }
 
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_hfsql_config(state: State<'_, DbState>) -> Result<HfsqlConfig, String> {
+    let mut row = load_hfsql_config(&state.pool).await?;
+    mask_password(&mut row.password);
+    Ok(row)
+}
+
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn save_hfsql_config(
     state: State<'_, DbState>,
@@ -46,14 +59,16 @@ pub async fn save_hfsql_config(
     password: String,
     log_path: String,
 ) -> Result<(), String> {
-    sqlx::query("UPDATE hfsql_config SET dsn = ?, username = ?, password = ?, log_path = ? WHERE id = 1")
-        .bind(&dsn)
-        .bind(&username)
-        .bind(&password)
-        .bind(&log_path)
-        .execute(&state.pool)
-        .await
-        .map_err(|e| e.to_string())?;
+    sqlx::query(
+        "UPDATE hfsql_config SET dsn = ?, username = ?, password = COALESCE(?, password), log_path = ? WHERE id = 1",
+    )
+    .bind(&dsn)
+    .bind(&username)
+    .bind(password_to_store(Some(&password))?)
+    .bind(&log_path)
+    .execute(&state.pool)
+    .await
+    .map_err(|e| e.to_string())?;
     Ok(())
 }
 
@@ -98,11 +113,12 @@ fn do_test_connection(dsn: String, user: String, pwd: String) -> HfsqlConnection
     result
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn test_hfsql_connection(
     state: State<'_, DbState>,
 ) -> Result<HfsqlConnectionTestResult, String> {
-    let cfg = get_hfsql_config(state).await?;
+    let cfg = load_hfsql_config(&state.pool).await?;
 
     let dsn = cfg.dsn.unwrap_or_default();
     let user = cfg.username.unwrap_or_default();
@@ -133,27 +149,26 @@ pub struct ArticleSyncResult {
     pub error_details: Vec<String>,
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn sync_ateis_produit(app: AppHandle, state: State<'_, DbState>) -> Result<ArticleSyncResult, String> {
+    run_ateis_produit_sync(Host::Desktop(app), &state).await
+}
+
+pub(crate) async fn run_ateis_produit_sync(app: Host, state: &DbState) -> Result<ArticleSyncResult, String> {
     use crate::commands::sql_queries::get_or_init_sql_query;
-    use crate::commands::sql_server::get_sql_server_config;
+    use crate::commands::sql_server::{load_bound_profile, query_timeout, with_query_timeout};
     use futures_util::TryStreamExt;
-    use tiberius::{AuthMethod, Client, Config as SqlConfig, QueryItem};
-    use tokio_util::compat::TokioAsyncWriteCompatExt;
+    use tiberius::QueryItem;
 
-    // 1. Get Global SQL Server Config (Source) - IGNORE ENABLED FLAG
-    let sql_cfg = get_sql_server_config(state.clone()).await?;
-    let sql_host = sql_cfg.server.unwrap_or_default();
-    let sql_db = sql_cfg.database.unwrap_or_default();
-    let sql_user = sql_cfg.username.unwrap_or_default();
-    let sql_pwd = sql_cfg.password.unwrap_or_default();
-
-    if sql_host.trim().is_empty() {
+    // 1. Get the SQL Server profile bound to this sync (Source) - IGNORE ENABLED FLAG
+    let sql_cfg = load_bound_profile(&state.pool, "ATEIS_PRODUIT_SYNC").await?;
+    if sql_cfg.server.as_deref().unwrap_or("").trim().is_empty() {
         return Err("Configuration SQL Server manquante (voir Paramètres)".to_string());
     }
 
     // 2. Get Global HFSQL Config (Destination)
-    let hfsql_cfg = get_hfsql_config(state.clone()).await?;
+    let hfsql_cfg = load_hfsql_config(&state.pool).await?;
     let dsn = hfsql_cfg.dsn.unwrap_or_default();
     let user = hfsql_cfg.username.unwrap_or_default();
     let pwd = hfsql_cfg.password.unwrap_or_default();
@@ -161,14 +176,8 @@ pub async fn sync_ateis_produit(app: AppHandle, state: State<'_, DbState>) -> Re
     // Resolve Log Directory
     let log_dir_base = match hfsql_cfg.log_path {
         Some(path) if !path.is_empty() => path,
-        _ => {
-            // Default to Desktop/T/BLOG if not configured
-            app.path()
-                .desktop_dir()
-                .ok()
-                .map(|p| p.join("T").join("BLOG").to_string_lossy().to_string())
-                .unwrap_or_else(|| r"C:\T\BLOG".to_string())
-        }
+        // Default to Desktop/T/BLOG if not configured
+        _ => app.default_output_dir(),
     };
 
     if dsn.trim().is_empty() {
@@ -176,23 +185,9 @@ pub async fn sync_ateis_produit(app: AppHandle, state: State<'_, DbState>) -> Re
     }
 
     // 3. Connect to SQL Server (Independent)
-    let mut tiberius_config = SqlConfig::new();
-    tiberius_config.host(sql_host.as_str());
-    tiberius_config.port(1433);
-    tiberius_config.authentication(AuthMethod::sql_server(sql_user, sql_pwd));
-    tiberius_config.trust_cert();
-    if !sql_db.trim().is_empty() {
-        tiberius_config.database(sql_db);
-    }
-
-    let tcp = tokio::net::TcpStream::connect(tiberius_config.get_addr())
+    let mut sql_client = state.sql.get(&sql_cfg)
         .await
         .map_err(|e| format!("Erreur connexion SQL Server: {}", e))?;
-    tcp.set_nodelay(true).map_err(|e| e.to_string())?;
-
-    let mut sql_client = Client::connect(tiberius_config, tcp.compat_write())
-        .await
-        .map_err(|e| format!("Erreur auth SQL Server: {}", e))?;
 
     // 3. Fetch articles from SQL Server using ATEIS_PRODUIT query
     let query = get_or_init_sql_query(
@@ -202,10 +197,10 @@ pub async fn sync_ateis_produit(app: AppHandle, state: State<'_, DbState>) -> Re
     )
     .await?;
 
-    let mut stream = sql_client
-        .query(query.as_str(), &[])
-        .await
-        .map_err(|e| e.to_string())?;
+    let mut stream = with_query_timeout(query_timeout(&sql_cfg), async {
+        sql_client.query(query.as_str(), &[]).await.map_err(|e| e.to_string())
+    })
+    .await?;
 
     // Collect articles from SQL Server
     let mut articles = Vec::new();
@@ -251,6 +246,8 @@ pub async fn sync_ateis_produit(app: AppHandle, state: State<'_, DbState>) -> Re
             get_str(8), // EAN_Palette_Export
         ));
     }
+    drop(stream);
+    sql_client.release();
 
     let total_articles = articles.len() as i64;
     let app_handle = app.clone();
@@ -495,29 +492,27 @@ pub async fn sync_ateis_produit(app: AppHandle, state: State<'_, DbState>) -> Re
     Ok(result)
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn sync_ateis_of(app: AppHandle, state: State<'_, DbState>) -> Result<ArticleSyncResult, String> {
+    run_ateis_of_sync(Host::Desktop(app), &state).await
+}
+
+pub(crate) async fn run_ateis_of_sync(app: Host, state: &DbState) -> Result<ArticleSyncResult, String> {
     use crate::commands::sql_queries::get_or_init_sql_query;
-    use crate::commands::sql_server::get_sql_server_config;
-    use tiberius::{AuthMethod, Client, Config as SqlConfig};
-    use tokio_util::compat::TokioAsyncWriteCompatExt;
+    use crate::commands::sql_server::{load_bound_profile, query_timeout, with_query_timeout};
     use chrono::NaiveDateTime;
     use rust_decimal::Decimal;
     use rust_decimal::prelude::ToPrimitive;
 
-    // 1. Get Global SQL Server Config (Source)
-    let sql_cfg = get_sql_server_config(state.clone()).await?;
-    let sql_host = sql_cfg.server.unwrap_or_default();
-    let sql_db = sql_cfg.database.unwrap_or_default();
-    let sql_user = sql_cfg.username.unwrap_or_default();
-    let sql_pwd = sql_cfg.password.unwrap_or_default();
-
-    if sql_host.trim().is_empty() {
+    // 1. Get the SQL Server profile bound to this sync (Source)
+    let sql_cfg = load_bound_profile(&state.pool, "ATEIS_OF_SYNC").await?;
+    if sql_cfg.server.as_deref().unwrap_or("").trim().is_empty() {
         return Err("Configuration SQL Server manquante (voir Paramètres)".to_string());
     }
 
     // 2. Get Global HFSQL Config (Destination)
-    let hfsql_cfg = get_hfsql_config(state.clone()).await?;
+    let hfsql_cfg = load_hfsql_config(&state.pool).await?;
     let dsn = hfsql_cfg.dsn.unwrap_or_default();
     let user = hfsql_cfg.username.unwrap_or_default();
     let pwd = hfsql_cfg.password.unwrap_or_default();
@@ -525,13 +520,7 @@ pub async fn sync_ateis_of(app: AppHandle, state: State<'_, DbState>) -> Result<
     // Resolve Log Directory
     let log_dir_base = match hfsql_cfg.log_path {
         Some(path) if !path.is_empty() => path,
-        _ => {
-            app.path()
-                .desktop_dir()
-                .ok()
-                .map(|p| p.join("T").join("BLOG").to_string_lossy().to_string())
-                .unwrap_or_else(|| r"C:\T\BLOG".to_string())
-        }
+        _ => app.default_output_dir(),
     };
 
     if dsn.trim().is_empty() {
@@ -539,23 +528,9 @@ pub async fn sync_ateis_of(app: AppHandle, state: State<'_, DbState>) -> Result<
     }
 
     // 3. Connect to SQL Server
-    let mut tiberius_config = SqlConfig::new();
-    tiberius_config.host(sql_host.as_str());
-    tiberius_config.port(1433);
-    tiberius_config.authentication(AuthMethod::sql_server(sql_user, sql_pwd));
-    tiberius_config.trust_cert();
-    if !sql_db.trim().is_empty() {
-        tiberius_config.database(sql_db);
-    }
-
-    let tcp = tokio::net::TcpStream::connect(tiberius_config.get_addr())
+    let mut sql_client = state.sql.get(&sql_cfg)
         .await
         .map_err(|e| format!("Erreur connexion SQL Server: {}", e))?;
-    tcp.set_nodelay(true).map_err(|e| e.to_string())?;
-
-    let mut sql_client = Client::connect(tiberius_config, tcp.compat_write())
-        .await
-        .map_err(|e| format!("Erreur auth SQL Server: {}", e))?;
 
     // 4. Fetch OFs
     let query = get_or_init_sql_query(
@@ -565,8 +540,12 @@ pub async fn sync_ateis_of(app: AppHandle, state: State<'_, DbState>) -> Result<
     )
     .await?;
 
-    let stream = sql_client.query(query, &[]).await.map_err(|e| e.to_string())?;
-    let rows = stream.into_first_result().await.map_err(|e| e.to_string())?;
+    let rows = with_query_timeout(query_timeout(&sql_cfg), async {
+        let stream = sql_client.query(query, &[]).await.map_err(|e| e.to_string())?;
+        stream.into_first_result().await.map_err(|e| e.to_string())
+    })
+    .await?;
+    sql_client.release();
 
     let mut of_list = Vec::new();
     for row in rows {
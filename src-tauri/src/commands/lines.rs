@@ -1,8 +1,11 @@
+use crate::commands::sql_server::load_sql_profile;
 use crate::db::DbState;
 use crate::stock;
+use crate::stock::WatcherState;
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
-use tauri::{AppHandle, State};
+use sqlx::{FromRow, Pool, Sqlite};
+#[cfg(feature = "desktop")]
+use tauri::{AppHandle, Manager, State};
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Line {
@@ -26,47 +29,97 @@ pub struct Line {
     pub last_file_time: Option<String>,
     pub etat_actuel: Option<String>,
     pub created_at: Option<String>,
+    #[sqlx(default)]
+    pub insert_batch_size: Option<i64>,
+    #[sqlx(default)]
+    pub duplicate_policy: Option<String>,
+    #[sqlx(default)]
+    pub dedup_key_field: Option<String>,
+    #[sqlx(default)]
+    pub csv_delimiter: Option<String>,
+    #[sqlx(default)]
+    pub csv_quote: Option<String>,
+    #[sqlx(default)]
+    pub csv_header_rows: Option<i64>,
+    #[sqlx(default)]
+    pub csv_footer_rows: Option<i64>,
+    #[sqlx(default)]
+    pub csv_comment_prefix: Option<String>,
+    #[sqlx(default)]
+    pub file_encoding: Option<String>,
+    #[sqlx(default)]
+    pub input_mode: Option<String>,
+    #[sqlx(default)]
+    pub fixed_trim: Option<String>,
+    #[sqlx(default)]
+    pub fixed_pad_char: Option<String>,
+    #[sqlx(default)]
+    pub invalid_rows_policy: Option<String>,
+    #[sqlx(default)]
+    pub sql_profile_id: Option<i64>,
 }
 
 use chrono::Local;
 
+/// Bounds of today as `YYYY-MM-DD` text: `processed_at >= start AND processed_at < end`
+/// can use the `(line_id, processed_at)` index, unlike `LIKE 'YYYY-MM-DD%'`.
+pub(crate) fn today_bounds() -> (String, String) {
+    let today = Local::now().date_naive();
+    let tomorrow = today.succ_opt().unwrap_or(today);
+    (
+        today.format("%Y-%m-%d").to_string(),
+        tomorrow.format("%Y-%m-%d").to_string(),
+    )
+}
+
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn get_lines(state: State<'_, DbState>) -> Result<Vec<Line>, String> {
+    load_lines(&state.pool).await
+}
+
+/// Every line, newest first, with today's processed/error counts.
+pub(crate) async fn load_lines(pool: &Pool<Sqlite>) -> Result<Vec<Line>, String> {
     // 1. Fetch all lines
     let mut lines = sqlx::query_as::<_, Line>(
         "SELECT id, name, path, prefix, interval_check, interval_alert, archived_path, rejected_path, active, \
                 site, unite, flag_dec, code_ligne, log_path, file_format,\
-                0 as total_traites, 0 as total_erreurs, last_file_time, etat_actuel, created_at \
+                0 as total_traites, 0 as total_erreurs, last_file_time, etat_actuel, created_at, insert_batch_size, \
+                duplicate_policy, dedup_key_field, csv_delimiter, csv_quote, csv_header_rows, \
+                csv_footer_rows, csv_comment_prefix, file_encoding, input_mode, fixed_trim, fixed_pad_char, \
+                invalid_rows_policy, sql_profile_id \
          FROM lines ORDER BY created_at DESC",
     )
-    .fetch_all(&state.pool)
+    .fetch_all(pool)
     .await
     .map_err(|e| e.to_string())?;
 
     // 2. Calculate today's stats for each line
-    let today_prefix = Local::now().format("%Y-%m-%d").to_string() + "%";
+    let (day_start, day_end) = today_bounds();
 
     for line in &mut lines {
         if let Some(id) = line.id {
             // Count successes
             let traites: i64 = sqlx::query_scalar(
                 "SELECT COUNT(*) FROM production_data 
-                 WHERE line_id = ? AND status = 'SUCCESS' AND processed_at LIKE ?",
+                 WHERE line_id = ? AND processed_at >= ? AND processed_at < ? AND status = 'SUCCESS'",
             )
             .bind(id)
-            .bind(&today_prefix)
-            .fetch_one(&state.pool)
+            .bind(&day_start)
+            .bind(&day_end)
+            .fetch_one(pool)
             .await
             .unwrap_or(0);
 
             // Count errors
             let erreurs: i64 = sqlx::query_scalar(
                 "SELECT COUNT(*) FROM production_data 
-                 WHERE line_id = ? AND status != 'SUCCESS' AND processed_at LIKE ?",
+                 WHERE line_id = ? AND processed_at >= ? AND processed_at < ? AND status NOT IN ('SUCCESS', 'SKIPPED')",
             )
             .bind(id)
-            .bind(&today_prefix)
-            .fetch_one(&state.pool)
+            .bind(&day_start)
+            .bind(&day_end)
+            .fetch_one(pool)
             .await
             .unwrap_or(0);
 
@@ -78,14 +131,33 @@ pub async fn get_lines(state: State<'_, DbState>) -> Result<Vec<Line>, String> {
     Ok(lines)
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn save_line(state: State<'_, DbState>, line: Line) -> Result<i64, String> {
+    store_line(&state.pool, &line).await
+}
+
+/// Insert (no `id`) or update a line after validation; returns its id.
+pub(crate) async fn store_line(pool: &Pool<Sqlite>, line: &Line) -> Result<i64, String> {
+    stock::validate_file_settings(line)?;
+    if line.sql_profile_id.is_some() {
+        load_sql_profile(pool, line.sql_profile_id).await?;
+    }
+
     if let Some(id) = line.id {
         sqlx::query(
             "UPDATE lines SET \
                 name = ?, path = ?, prefix = ?, interval_check = ?, \
                 interval_alert = ?, archived_path = ?, rejected_path = ?, active = ?,\
-                site = ?, unite = ?, flag_dec = ?, code_ligne = ?, log_path = ?, file_format = ?\
+                site = ?, unite = ?, flag_dec = ?, code_ligne = ?, log_path = ?, file_format = ?,\
+                insert_batch_size = COALESCE(?, insert_batch_size),\
+                duplicate_policy = COALESCE(?, duplicate_policy), dedup_key_field = COALESCE(?, dedup_key_field),\
+                csv_delimiter = COALESCE(?, csv_delimiter), csv_quote = COALESCE(?, csv_quote),\
+                csv_header_rows = COALESCE(?, csv_header_rows), csv_footer_rows = COALESCE(?, csv_footer_rows),\
+                csv_comment_prefix = COALESCE(?, csv_comment_prefix), file_encoding = COALESCE(?, file_encoding),\
+                input_mode = COALESCE(?, input_mode), fixed_trim = COALESCE(?, fixed_trim),\
+                fixed_pad_char = COALESCE(?, fixed_pad_char), invalid_rows_policy = COALESCE(?, invalid_rows_policy),\
+                sql_profile_id = COALESCE(?, sql_profile_id)\
             WHERE id = ?",
         )
         .bind(&line.name)
@@ -102,8 +174,22 @@ pub async fn save_line(state: State<'_, DbState>, line: Line) -> Result<i64, Str
         .bind(&line.code_ligne)
         .bind(&line.log_path)
         .bind(&line.file_format)
+        .bind(line.insert_batch_size)
+        .bind(&line.duplicate_policy)
+        .bind(&line.dedup_key_field)
+        .bind(&line.csv_delimiter)
+        .bind(&line.csv_quote)
+        .bind(line.csv_header_rows)
+        .bind(line.csv_footer_rows)
+        .bind(&line.csv_comment_prefix)
+        .bind(&line.file_encoding)
+        .bind(&line.input_mode)
+        .bind(&line.fixed_trim)
+        .bind(&line.fixed_pad_char)
+        .bind(&line.invalid_rows_policy)
+        .bind(line.sql_profile_id)
         .bind(id)
-        .execute(&state.pool)
+        .execute(pool)
         .await
         .map_err(|e| e.to_string())?;
 
@@ -111,8 +197,14 @@ pub async fn save_line(state: State<'_, DbState>, line: Line) -> Result<i64, Str
     } else {
         let id = sqlx::query(
             "INSERT INTO lines (name, path, prefix, interval_check, interval_alert, archived_path, rejected_path, active, \
-                               site, unite, flag_dec, code_ligne, log_path, file_format) \
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                               site, unite, flag_dec, code_ligne, log_path, file_format, insert_batch_size, \
+                               duplicate_policy, dedup_key_field, csv_delimiter, csv_quote, csv_header_rows, \
+                               csv_footer_rows, csv_comment_prefix, file_encoding, input_mode, fixed_trim, \
+                               fixed_pad_char, invalid_rows_policy, sql_profile_id) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, COALESCE(?, 'SKIP'), ?, \
+                     COALESCE(?, ';'), COALESCE(?, '\"'), COALESCE(?, 0), COALESCE(?, 0), ?, COALESCE(?, 'AUTO'), \
+                     COALESCE(?, 'CSV'), COALESCE(?, 'BOTH'), COALESCE(?, ' '), \
+                     COALESCE(?, 'REJECT_FILE'), ?)",
         )
         .bind(&line.name)
         .bind(&line.path)
@@ -128,7 +220,21 @@ pub async fn save_line(state: State<'_, DbState>, line: Line) -> Result<i64, Str
         .bind(&line.code_ligne)
         .bind(&line.log_path)
         .bind(&line.file_format)
-        .execute(&state.pool)
+        .bind(line.insert_batch_size)
+        .bind(&line.duplicate_policy)
+        .bind(&line.dedup_key_field)
+        .bind(&line.csv_delimiter)
+        .bind(&line.csv_quote)
+        .bind(line.csv_header_rows)
+        .bind(line.csv_footer_rows)
+        .bind(&line.csv_comment_prefix)
+        .bind(&line.file_encoding)
+        .bind(&line.input_mode)
+        .bind(&line.fixed_trim)
+        .bind(&line.fixed_pad_char)
+        .bind(&line.invalid_rows_policy)
+        .bind(line.sql_profile_id)
+        .execute(pool)
         .await
         .map_err(|e| e.to_string())?
         .last_insert_rowid();
@@ -137,6 +243,7 @@ pub async fn save_line(state: State<'_, DbState>, line: Line) -> Result<i64, Str
     }
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn delete_line(
     app_handle: AppHandle,
@@ -153,6 +260,7 @@ pub async fn delete_line(
     Ok(())
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn toggle_line_active(
     app_handle: AppHandle,
@@ -160,35 +268,54 @@ pub async fn toggle_line_active(
     id: i64,
     active: bool,
 ) -> Result<(), String> {
-    sqlx::query("UPDATE lines SET active = ? WHERE id = ?")
+    set_line_active(&state, &app_handle.state::<WatcherState>(), id, active).await
+}
+
+/// Persist a line's `active` flag and start or stop its watcher to match.
+pub(crate) async fn set_line_active(
+    db: &DbState,
+    watchers: &WatcherState,
+    id: i64,
+    active: bool,
+) -> Result<(), String> {
+    let updated = sqlx::query("UPDATE lines SET active = ? WHERE id = ?")
         .bind(active)
         .bind(id)
-        .execute(&state.pool)
+        .execute(&db.pool)
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| e.to_string())?
+        .rows_affected();
+    if updated == 0 {
+        return Err(format!("Ligne {} introuvable", id));
+    }
 
     if active {
+        if let Some(reason) = watchers.unavailable() {
+            return Err(format!("Ligne activée mais non surveillée ici: {}", reason));
+        }
         let row = sqlx::query("SELECT path, prefix, archived_path FROM lines WHERE id = ?")
             .bind(id)
-            .fetch_one(&state.pool)
+            .fetch_one(&db.pool)
             .await
             .map_err(|e| e.to_string())?;
 
         use sqlx::Row;
-        stock::start_watcher(
-            app_handle,
+        stock::watch_line(
+            watchers,
+            db,
             id,
             row.get::<String, _>("path"),
             row.get::<String, _>("prefix"),
             row.get::<Option<String>, _>("archived_path"),
         );
     } else {
-        stock::stop_watcher(app_handle, id);
+        stock::unwatch_line(watchers, id);
     }
 
     Ok(())
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn start_line_watcher(
     app_handle: AppHandle,
@@ -197,10 +324,14 @@ pub async fn start_line_watcher(
     prefix: String,
     archived_path: Option<String>,
 ) -> Result<(), String> {
+    if let Some(reason) = app_handle.state::<WatcherState>().unavailable() {
+        return Err(reason);
+    }
     stock::start_watcher(app_handle, id, path, prefix, archived_path);
     Ok(())
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn stop_line_watcher(app_handle: AppHandle, id: i64) -> Result<(), String> {
     stock::stop_watcher(app_handle, id);
@@ -1,6 +1,7 @@
 use crate::db::DbState;
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, Pool, Sqlite};
+#[cfg(feature = "desktop")]
 use tauri::State;
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
@@ -14,12 +15,23 @@ pub struct LogEntry {
     pub created_at: String,
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn get_logs(
     state: State<'_, DbState>,
     line_id: Option<i64>,
     level: Option<String>,
     limit: Option<i64>,
+) -> Result<Vec<LogEntry>, String> {
+    load_logs(&state.pool, line_id, level, limit).await
+}
+
+/// Newest log entries first, optionally for one line and/or one level.
+pub(crate) async fn load_logs(
+    pool: &Pool<Sqlite>,
+    line_id: Option<i64>,
+    level: Option<String>,
+    limit: Option<i64>,
 ) -> Result<Vec<LogEntry>, String> {
     let limit_val = limit.unwrap_or(200);
 
@@ -32,7 +44,7 @@ pub async fn get_logs(
             .bind(lid)
             .bind(&lvl)
             .bind(limit_val)
-            .fetch_all(&state.pool)
+            .fetch_all(pool)
             .await
         } else {
             sqlx::query_as::<_, LogEntry>(
@@ -41,7 +53,7 @@ pub async fn get_logs(
             )
             .bind(lid)
             .bind(limit_val)
-            .fetch_all(&state.pool)
+            .fetch_all(pool)
             .await
         }
     } else if let Some(lvl) = level {
@@ -51,7 +63,7 @@ pub async fn get_logs(
         )
         .bind(&lvl)
         .bind(limit_val)
-        .fetch_all(&state.pool)
+        .fetch_all(pool)
         .await
     } else {
         sqlx::query_as::<_, LogEntry>(
@@ -59,13 +71,14 @@ pub async fn get_logs(
              FROM logs ORDER BY created_at DESC LIMIT ?",
         )
         .bind(limit_val)
-        .fetch_all(&state.pool)
+        .fetch_all(pool)
         .await
     };
 
     logs.map_err(|e| e.to_string())
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn add_log(
     state: State<'_, DbState>,
@@ -89,6 +102,7 @@ pub async fn add_log(
     Ok(id)
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn clear_logs(state: State<'_, DbState>, line_id: Option<i64>) -> Result<(), String> {
     if let Some(lid) = line_id {
@@ -106,6 +120,7 @@ pub async fn clear_logs(state: State<'_, DbState>, line_id: Option<i64>) -> Resu
     Ok(())
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn reset_line_stats(state: State<'_, DbState>, line_id: i64) -> Result<(), String> {
     sqlx::query(
@@ -1,9 +1,11 @@
 use crate::db::DbState;
+use crate::stock;
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, Pool, Sqlite};
+#[cfg(feature = "desktop")]
 use tauri::State;
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct MappingRow {
     pub id: Option<i64>,
     pub line_id: i64,
@@ -13,6 +15,13 @@ pub struct MappingRow {
     pub parameter: Option<String>,
     pub transformation: Option<String>,
     pub description: Option<String>,
+    pub enabled: Option<bool>,
+    #[sqlx(default)]
+    pub validation: Option<String>,
+    #[sqlx(default)]
+    pub date_format: Option<String>,
+    #[sqlx(default)]
+    pub sql_type: Option<String>,
 }
 
 async fn save_model_mappings_to_db(
@@ -33,8 +42,8 @@ async fn save_model_mappings_to_db(
         let sort_order = if m.sort_order != 0 { m.sort_order } else { idx as i64 };
 
         sqlx::query(
-            "INSERT INTO model_mappings (format_name, sort_order, sql_field, file_column, parameter, transformation, description) \
-             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO model_mappings (format_name, sort_order, sql_field, file_column, parameter, transformation, description, validation, date_format, sql_type) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         )
         .bind(&fmt)
         .bind(sort_order)
@@ -43,6 +52,9 @@ async fn save_model_mappings_to_db(
         .bind(m.parameter)
         .bind(m.transformation)
         .bind(m.description)
+        .bind(m.validation)
+        .bind(m.date_format)
+        .bind(m.sql_type)
         .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
@@ -52,62 +64,124 @@ async fn save_model_mappings_to_db(
     Ok(())
 }
 
+/// Layer a line's mapping overrides on top of its model mapping.
+/// A line row replaces the model row with the same `sql_field` (or removes it
+/// when disabled); rows without a model counterpart are appended.
+pub(crate) fn merge_line_mappings(
+    model: Vec<MappingRow>,
+    overrides: Vec<MappingRow>,
+) -> Vec<MappingRow> {
+    let mut merged = model;
+
+    for row in overrides {
+        let enabled = row.enabled.unwrap_or(true);
+        let existing = merged
+            .iter()
+            .position(|m| m.sql_field.eq_ignore_ascii_case(&row.sql_field));
+
+        match existing {
+            Some(pos) if enabled => merged[pos] = row,
+            Some(pos) => {
+                merged.remove(pos);
+            }
+            None if enabled => merged.push(row),
+            None => {}
+        }
+    }
+
+    merged
+}
+
+/// Mapping actually used by the processor for a line: model rows for `format_name`
+/// with the line's own rows layered on top.
+pub(crate) async fn load_effective_mappings(
+    pool: &Pool<Sqlite>,
+    line_id: i64,
+    format_name: &str,
+) -> Result<Vec<MappingRow>, String> {
+    let model = sqlx::query_as::<_, MappingRow>(
+        "SELECT id, 0 as line_id, sort_order, sql_field, file_column, parameter, transformation, description, 1 as enabled, validation, date_format, sql_type \
+         FROM model_mappings WHERE format_name = ? ORDER BY sort_order ASC, id ASC",
+    )
+    .bind(format_name.to_uppercase())
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let overrides = sqlx::query_as::<_, MappingRow>(
+        "SELECT id, line_id, sort_order, sql_field, file_column, parameter, transformation, description, enabled, validation, date_format, sql_type \
+         FROM mappings WHERE line_id = ? ORDER BY sort_order ASC, id ASC",
+    )
+    .bind(line_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(merge_line_mappings(model, overrides))
+}
+
 pub(crate) fn get_ateis_default_mappings() -> Vec<MappingRow> {
     vec![
-        MappingRow { id: None, line_id: 0, sort_order: 0, sql_field: "YSSCC_0".to_string(), file_column: Some("0".to_string()), parameter: None, transformation: None, description: Some("Code SCC".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 1, sql_field: "YDATE_0".to_string(), file_column: Some("1".to_string()), parameter: None, transformation: Some("date".to_string()), description: Some("Date déclaration".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 2, sql_field: "YHEURE_0".to_string(), file_column: Some("1".to_string()), parameter: None, transformation: Some("heure".to_string()), description: Some("Heure déclaration".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 3, sql_field: "ITMREF_0".to_string(), file_column: Some("5".to_string()), parameter: None, transformation: None, description: Some("Référence article".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 4, sql_field: "LOT_0".to_string(), file_column: Some("7".to_string()), parameter: None, transformation: None, description: Some("Numéro de lot".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 5, sql_field: "QTY_0".to_string(), file_column: Some("9".to_string()), parameter: None, transformation: Some("decimal".to_string()), description: Some("Quantité".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 6, sql_field: "YDATDL_0".to_string(), file_column: Some("8".to_string()), parameter: None, transformation: Some("date".to_string()), description: Some("Date livraison".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 7, sql_field: "YNLIGN_0".to_string(), file_column: Some("12".to_string()), parameter: None, transformation: None, description: Some("Numéro de ligne".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 8, sql_field: "MFGNUM_0".to_string(), file_column: Some("18".to_string()), parameter: None, transformation: None, description: Some("Numéro de fabrication".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 9, sql_field: "YCODEPOT_0".to_string(), file_column: Some("4".to_string()), parameter: None, transformation: None, description: Some("Code dépôt".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 10, sql_field: "YPALETTE_0".to_string(), file_column: Some("16".to_string()), parameter: None, transformation: None, description: Some("Palette".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 11, sql_field: "YINTERCAL_0".to_string(), file_column: Some("17".to_string()), parameter: None, transformation: None, description: Some("Intercalaire".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 12, sql_field: "FCY_0".to_string(), file_column: None, parameter: Some("site".to_string()), transformation: None, description: Some("Site de production".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 13, sql_field: "UOM_0".to_string(), file_column: None, parameter: Some("unite".to_string()), transformation: None, description: Some("Unité de mesure".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 14, sql_field: "YFLGDEC_0".to_string(), file_column: None, parameter: Some("flag_dec".to_string()), transformation: Some("tinyint".to_string()), description: Some("Flag déclaration".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 15, sql_field: "CREUSR_0".to_string(), file_column: None, parameter: Some("code_ligne".to_string()), transformation: None, description: Some("Utilisateur création".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 16, sql_field: "CREDATTIM_0".to_string(), file_column: Some("1".to_string()), parameter: None, transformation: Some("datetime".to_string()), description: Some("Date/heure création".to_string()) },
+        MappingRow { id: None, line_id: 0, sort_order: 0, sql_field: "YSSCC_0".to_string(), file_column: Some("0".to_string()), parameter: None, transformation: None, description: Some("Code SCC".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 1, sql_field: "YDATE_0".to_string(), file_column: Some("1".to_string()), parameter: None, transformation: Some("date".to_string()), description: Some("Date déclaration".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 2, sql_field: "YHEURE_0".to_string(), file_column: Some("1".to_string()), parameter: None, transformation: Some("heure".to_string()), description: Some("Heure déclaration".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 3, sql_field: "ITMREF_0".to_string(), file_column: Some("5".to_string()), parameter: None, transformation: None, description: Some("Référence article".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 4, sql_field: "LOT_0".to_string(), file_column: Some("7".to_string()), parameter: None, transformation: None, description: Some("Numéro de lot".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 5, sql_field: "QTY_0".to_string(), file_column: Some("9".to_string()), parameter: None, transformation: Some("decimal".to_string()), description: Some("Quantité".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 6, sql_field: "YDATDL_0".to_string(), file_column: Some("8".to_string()), parameter: None, transformation: Some("date".to_string()), description: Some("Date livraison".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 7, sql_field: "YNLIGN_0".to_string(), file_column: Some("12".to_string()), parameter: None, transformation: None, description: Some("Numéro de ligne".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 8, sql_field: "MFGNUM_0".to_string(), file_column: Some("18".to_string()), parameter: None, transformation: None, description: Some("Numéro de fabrication".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 9, sql_field: "YCODEPOT_0".to_string(), file_column: Some("4".to_string()), parameter: None, transformation: None, description: Some("Code dépôt".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 10, sql_field: "YPALETTE_0".to_string(), file_column: Some("16".to_string()), parameter: None, transformation: None, description: Some("Palette".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 11, sql_field: "YINTERCAL_0".to_string(), file_column: Some("17".to_string()), parameter: None, transformation: None, description: Some("Intercalaire".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 12, sql_field: "FCY_0".to_string(), file_column: None, parameter: Some("site".to_string()), transformation: None, description: Some("Site de production".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 13, sql_field: "UOM_0".to_string(), file_column: None, parameter: Some("unite".to_string()), transformation: None, description: Some("Unité de mesure".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 14, sql_field: "YFLGDEC_0".to_string(), file_column: None, parameter: Some("flag_dec".to_string()), transformation: Some("tinyint".to_string()), description: Some("Flag déclaration".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 15, sql_field: "CREUSR_0".to_string(), file_column: None, parameter: Some("code_ligne".to_string()), transformation: None, description: Some("Utilisateur création".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 16, sql_field: "CREDATTIM_0".to_string(), file_column: Some("1".to_string()), parameter: None, transformation: Some("datetime".to_string()), description: Some("Date/heure création".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
     ]
 }
 
 pub(crate) fn get_logitron_default_mappings() -> Vec<MappingRow> {
     vec![
-        MappingRow { id: None, line_id: 0, sort_order: 0, sql_field: "YSSCC_0".to_string(), file_column: Some("0".to_string()), parameter: None, transformation: None, description: Some("Code SCC".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 1, sql_field: "YDATE_0".to_string(), file_column: Some("1".to_string()), parameter: None, transformation: Some("date".to_string()), description: Some("Date déclaration".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 2, sql_field: "YHEURE_0".to_string(), file_column: Some("2".to_string()), parameter: None, transformation: Some("heure".to_string()), description: Some("Heure déclaration".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 3, sql_field: "CREDATTIM_0".to_string(), file_column: Some("1-2".to_string()), parameter: None, transformation: Some("datetime_combine".to_string()), description: Some("Date/heure création combinée".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 4, sql_field: "ITMREF_0".to_string(), file_column: Some("3".to_string()), parameter: None, transformation: None, description: Some("Référence article".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 5, sql_field: "LOT_0".to_string(), file_column: Some("4".to_string()), parameter: None, transformation: None, description: Some("Numéro de lot".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 6, sql_field: "QTY_0".to_string(), file_column: Some("5".to_string()), parameter: None, transformation: Some("decimal".to_string()), description: Some("Quantité".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 7, sql_field: "YDATDL_0".to_string(), file_column: Some("7".to_string()), parameter: None, transformation: Some("date".to_string()), description: Some("Date livraison".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 8, sql_field: "YNLIGN_0".to_string(), file_column: Some("8".to_string()), parameter: None, transformation: None, description: Some("Numéro de ligne".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 9, sql_field: "MFGNUM_0".to_string(), file_column: Some("13".to_string()), parameter: None, transformation: None, description: Some("Numéro de fabrication".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 10, sql_field: "YCODEPOT_0".to_string(), file_column: Some("14".to_string()), parameter: None, transformation: None, description: Some("Code dépôt".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 11, sql_field: "YPALETTE_0".to_string(), file_column: Some("15".to_string()), parameter: None, transformation: Some("split_before_plus".to_string()), description: Some("Partie avant +".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 12, sql_field: "YINTERCAL_0".to_string(), file_column: Some("15".to_string()), parameter: None, transformation: Some("split_after_plus".to_string()), description: Some("Partie après +".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 13, sql_field: "FCY_0".to_string(), file_column: None, parameter: Some("site".to_string()), transformation: None, description: Some("Site de production".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 14, sql_field: "UOM_0".to_string(), file_column: None, parameter: Some("unite".to_string()), transformation: None, description: Some("Unité de mesure".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 15, sql_field: "YFLGDEC_0".to_string(), file_column: None, parameter: Some("flag_dec".to_string()), transformation: Some("tinyint".to_string()), description: Some("Flag déclaration".to_string()) },
-        MappingRow { id: None, line_id: 0, sort_order: 16, sql_field: "CREUSR_0".to_string(), file_column: None, parameter: Some("code_ligne".to_string()), transformation: None, description: Some("Utilisateur création".to_string()) },
+        MappingRow { id: None, line_id: 0, sort_order: 0, sql_field: "YSSCC_0".to_string(), file_column: Some("0".to_string()), parameter: None, transformation: None, description: Some("Code SCC".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 1, sql_field: "YDATE_0".to_string(), file_column: Some("1".to_string()), parameter: None, transformation: Some("date".to_string()), description: Some("Date déclaration".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 2, sql_field: "YHEURE_0".to_string(), file_column: Some("2".to_string()), parameter: None, transformation: Some("heure".to_string()), description: Some("Heure déclaration".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 3, sql_field: "CREDATTIM_0".to_string(), file_column: Some("1-2".to_string()), parameter: None, transformation: Some("datetime_combine".to_string()), description: Some("Date/heure création combinée".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 4, sql_field: "ITMREF_0".to_string(), file_column: Some("3".to_string()), parameter: None, transformation: None, description: Some("Référence article".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 5, sql_field: "LOT_0".to_string(), file_column: Some("4".to_string()), parameter: None, transformation: None, description: Some("Numéro de lot".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 6, sql_field: "QTY_0".to_string(), file_column: Some("5".to_string()), parameter: None, transformation: Some("decimal".to_string()), description: Some("Quantité".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 7, sql_field: "YDATDL_0".to_string(), file_column: Some("7".to_string()), parameter: None, transformation: Some("date".to_string()), description: Some("Date livraison".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 8, sql_field: "YNLIGN_0".to_string(), file_column: Some("8".to_string()), parameter: None, transformation: None, description: Some("Numéro de ligne".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 9, sql_field: "MFGNUM_0".to_string(), file_column: Some("13".to_string()), parameter: None, transformation: None, description: Some("Numéro de fabrication".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 10, sql_field: "YCODEPOT_0".to_string(), file_column: Some("14".to_string()), parameter: None, transformation: None, description: Some("Code dépôt".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 11, sql_field: "YPALETTE_0".to_string(), file_column: Some("15".to_string()), parameter: None, transformation: Some("split_before_plus".to_string()), description: Some("Partie avant +".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 12, sql_field: "YINTERCAL_0".to_string(), file_column: Some("15".to_string()), parameter: None, transformation: Some("split_after_plus".to_string()), description: Some("Partie après +".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 13, sql_field: "FCY_0".to_string(), file_column: None, parameter: Some("site".to_string()), transformation: None, description: Some("Site de production".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 14, sql_field: "UOM_0".to_string(), file_column: None, parameter: Some("unite".to_string()), transformation: None, description: Some("Unité de mesure".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 15, sql_field: "YFLGDEC_0".to_string(), file_column: None, parameter: Some("flag_dec".to_string()), transformation: Some("tinyint".to_string()), description: Some("Flag déclaration".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
+        MappingRow { id: None, line_id: 0, sort_order: 16, sql_field: "CREUSR_0".to_string(), file_column: None, parameter: Some("code_ligne".to_string()), transformation: None, description: Some("Utilisateur création".to_string()), enabled: None, validation: None, date_format: None, sql_type: None },
     ]
 }
 
+/// Model mappings stored for a format, without falling back to the defaults.
+pub(crate) async fn fetch_model_mappings(pool: &Pool<Sqlite>, format_name: &str) -> Result<Vec<MappingRow>, String> {
+    sqlx::query_as::<_, MappingRow>(
+        "SELECT id, 0 as line_id, sort_order, sql_field, file_column, parameter, transformation, description, 1 as enabled, validation, date_format, sql_type \
+         FROM model_mappings WHERE format_name = ? ORDER BY sort_order ASC, id ASC",
+    )
+    .bind(format_name)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn get_model_mappings(state: State<'_, DbState>, format_name: String) -> Result<Vec<MappingRow>, String> {
     let fmt = format_name.to_uppercase();
 
-    let rows = sqlx::query_as::<_, MappingRow>(
-        "SELECT id, 0 as line_id, sort_order, sql_field, file_column, parameter, transformation, description \
-         FROM model_mappings WHERE format_name = ? ORDER BY sort_order ASC, id ASC",
-    )
-    .bind(&fmt)
-    .fetch_all(&state.pool)
-    .await
-    .map_err(|e| e.to_string())?;
+    let rows = fetch_model_mappings(&state.pool, &fmt).await?;
 
     if !rows.is_empty() {
         return Ok(rows);
@@ -121,27 +195,33 @@ pub async fn get_model_mappings(state: State<'_, DbState>, format_name: String)
 
     save_model_mappings_to_db(&state.pool, &fmt, defaults).await?;
 
-    let rows = sqlx::query_as::<_, MappingRow>(
-        "SELECT id, 0 as line_id, sort_order, sql_field, file_column, parameter, transformation, description \
-         FROM model_mappings WHERE format_name = ? ORDER BY sort_order ASC, id ASC",
-    )
-    .bind(&fmt)
-    .fetch_all(&state.pool)
-    .await
-    .map_err(|e| e.to_string())?;
+    let rows = fetch_model_mappings(&state.pool, &fmt).await?;
 
     Ok(rows)
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn save_model_mappings(
     state: State<'_, DbState>,
     format_name: String,
     mappings: Vec<MappingRow>,
 ) -> Result<(), String> {
-    save_model_mappings_to_db(&state.pool, &format_name, mappings).await
+    store_model_mappings(&state.pool, &format_name, mappings).await
 }
 
+pub(crate) async fn store_model_mappings(
+    pool: &Pool<Sqlite>,
+    format_name: &str,
+    mappings: Vec<MappingRow>,
+) -> Result<(), String> {
+    stock::check_transformations(&mappings)?;
+    stock::check_mapping_rules(&mappings)?;
+    stock::check_sql_types(&mappings)?;
+    save_model_mappings_to_db(pool, format_name, mappings).await
+}
+
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn reset_model_mappings(state: State<'_, DbState>, format_name: String) -> Result<(), String> {
     let fmt = format_name.to_uppercase();
@@ -153,27 +233,47 @@ pub async fn reset_model_mappings(state: State<'_, DbState>, format_name: String
     save_model_mappings_to_db(&state.pool, &fmt, defaults).await
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn get_mappings(state: State<'_, DbState>, line_id: i64) -> Result<Vec<MappingRow>, String> {
+    fetch_line_mappings(&state.pool, line_id).await
+}
+
+/// Mappings set on the line itself (model mappings not merged in).
+pub(crate) async fn fetch_line_mappings(pool: &Pool<Sqlite>, line_id: i64) -> Result<Vec<MappingRow>, String> {
     let rows = sqlx::query_as::<_, MappingRow>(
-        "SELECT id, line_id, sort_order, sql_field, file_column, parameter, transformation, description \
+        "SELECT id, line_id, sort_order, sql_field, file_column, parameter, transformation, description, enabled, validation, date_format, sql_type \
          FROM mappings WHERE line_id = ? ORDER BY sort_order ASC, id ASC",
     )
     .bind(line_id)
-    .fetch_all(&state.pool)
+    .fetch_all(pool)
     .await
     .map_err(|e| e.to_string())?;
 
     Ok(rows)
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn save_mappings(
     state: State<'_, DbState>,
     line_id: i64,
     mappings: Vec<MappingRow>,
 ) -> Result<(), String> {
-    let mut tx = state.pool.begin().await.map_err(|e| e.to_string())?;
+    store_line_mappings(&state.pool, line_id, mappings).await
+}
+
+/// Replace a line's mappings after validation.
+pub(crate) async fn store_line_mappings(
+    pool: &Pool<Sqlite>,
+    line_id: i64,
+    mappings: Vec<MappingRow>,
+) -> Result<(), String> {
+    stock::check_transformations(&mappings)?;
+    stock::check_mapping_rules(&mappings)?;
+    stock::check_sql_types(&mappings)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
 
     sqlx::query("DELETE FROM mappings WHERE line_id = ?")
         .bind(line_id)
@@ -185,8 +285,8 @@ pub async fn save_mappings(
         let sort_order = if m.sort_order != 0 { m.sort_order } else { idx as i64 };
 
         sqlx::query(
-            "INSERT INTO mappings (line_id, sort_order, sql_field, file_column, parameter, transformation, description) \
-             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO mappings (line_id, sort_order, sql_field, file_column, parameter, transformation, description, enabled, validation, date_format, sql_type) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         )
         .bind(line_id)
         .bind(sort_order)
@@ -195,6 +295,10 @@ pub async fn save_mappings(
         .bind(m.parameter)
         .bind(m.transformation)
         .bind(m.description)
+        .bind(m.enabled.unwrap_or(true))
+        .bind(m.validation)
+        .bind(m.date_format)
+        .bind(m.sql_type)
         .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
@@ -203,3 +307,42 @@ pub async fn save_mappings(
     tx.commit().await.map_err(|e| e.to_string())?;
     Ok(())
 }
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_effective_mappings(state: State<'_, DbState>, line_id: i64) -> Result<Vec<MappingRow>, String> {
+    let format_name: Option<String> = sqlx::query_scalar("SELECT file_format FROM lines WHERE id = ?")
+        .bind(line_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .flatten();
+
+    let format_name = format_name.unwrap_or_else(|| "ATEIS".to_string());
+    load_effective_mappings(&state.pool, line_id, &format_name).await
+}
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_timezone(state: State<'_, DbState>) -> Result<Option<String>, String> {
+    sqlx::query_scalar("SELECT value FROM config WHERE key = ?")
+        .bind(stock::TIMEZONE_KEY)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// IANA name (e.g. `Europe/Paris`); empty or `local` uses the system zone.
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn save_timezone(state: State<'_, DbState>, timezone: String) -> Result<(), String> {
+    stock::parse_timezone(&timezone)?;
+
+    sqlx::query("INSERT OR REPLACE INTO config (key, value) VALUES (?, ?)")
+        .bind(stock::TIMEZONE_KEY)
+        .bind(timezone.trim())
+        .execute(&state.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use crate::db::DbState;
+use crate::metrics::{MetricsServer, MetricsSettings};
+#[cfg(feature = "desktop")]
+use tauri::State;
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_metrics_settings(state: State<'_, DbState>) -> Result<MetricsSettings, String> {
+    Ok(MetricsSettings::load(&state.pool).await)
+}
+
+/// Save the settings and restart the metrics endpoint with them.
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn save_metrics_settings(
+    state: State<'_, DbState>,
+    server: State<'_, MetricsServer>,
+    settings: MetricsSettings,
+) -> Result<(), String> {
+    settings.save(&state.pool).await?;
+    server.restart().await
+}
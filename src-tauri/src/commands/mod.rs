@@ -1,3 +1,5 @@
+pub mod alerts;
+pub mod api;
 pub mod dashboard;
 pub mod defaults;
 pub mod exports;
@@ -5,6 +7,10 @@ pub mod hfsql;
 pub mod lines;
 pub mod logs;
 pub mod mappings;
+pub mod metrics;
+pub mod preview;
 pub mod production;
+pub mod queue;
+pub mod retention;
 pub mod sql_queries;
 pub mod sql_server;
@@ -0,0 +1,31 @@
+use crate::db::DbState;
+use crate::stock::{FilePreview, StockProcessor};
+use std::path::Path;
+#[cfg(feature = "desktop")]
+use tauri::State;
+
+const DEFAULT_PREVIEW_ROWS: usize = 200;
+
+/// Dry-run a file (or pasted content) against a line's mapping and SQL template.
+/// Nothing is moved and SQL Server is not contacted.
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn preview_file(
+    state: State<'_, DbState>,
+    line_id: i64,
+    file_path: Option<String>,
+    content: Option<String>,
+    max_rows: Option<usize>,
+) -> Result<FilePreview, String> {
+    let content = content.filter(|c| !c.trim().is_empty());
+    let file_path = file_path.filter(|p| !p.trim().is_empty());
+
+    StockProcessor::new(state.pool.clone(), state.sql.clone())
+        .preview(
+            line_id,
+            file_path.as_deref().map(Path::new),
+            content,
+            max_rows.unwrap_or(DEFAULT_PREVIEW_ROWS),
+        )
+        .await
+}
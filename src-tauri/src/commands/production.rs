@@ -1,16 +1,27 @@
 use crate::db::DbState;
+use sqlx::{Pool, Sqlite};
+#[cfg(feature = "desktop")]
 use tauri::State;
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn get_production_data(
     state: State<'_, DbState>,
     line_id: i64,
+) -> Result<Vec<serde_json::Value>, String> {
+    load_production_data(&state.pool, line_id).await
+}
+
+/// The line's 100 most recent processed files.
+pub(crate) async fn load_production_data(
+    pool: &Pool<Sqlite>,
+    line_id: i64,
 ) -> Result<Vec<serde_json::Value>, String> {
     let rows = sqlx::query(
         "SELECT id, filename, processed_at, status, message FROM production_data WHERE line_id = ? ORDER BY processed_at DESC LIMIT 100",
     )
     .bind(line_id)
-    .fetch_all(&state.pool)
+    .fetch_all(pool)
     .await
     .map_err(|e| e.to_string())?;
 
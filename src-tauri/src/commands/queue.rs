@@ -0,0 +1,71 @@
+use crate::db::DbState;
+use crate::stock::queue::{self, QueueItem, RetryPolicy};
+use chrono::Local;
+#[cfg(feature = "desktop")]
+use tauri::State;
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_queue_items(
+    state: State<'_, DbState>,
+    line_id: Option<i64>,
+) -> Result<Vec<QueueItem>, String> {
+    queue::list(&state.pool, line_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn retry_queue_item(state: State<'_, DbState>, id: i64) -> Result<(), String> {
+    let updated = queue::retry_now(&state.pool, id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !updated {
+        return Err("Élément introuvable ou déjà en cours de traitement".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn cancel_queue_item(state: State<'_, DbState>, id: i64) -> Result<(), String> {
+    let item = queue::cancel(&state.pool, id).await?;
+
+    let now = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let _ = sqlx::query(
+        "INSERT INTO logs (line_id, level, source, message, details, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(item.line_id)
+    .bind("WARNING")
+    .bind("Queue")
+    .bind(format!(
+        "Fichier {} retiré de la file d'attente après {} tentative(s)",
+        item.filename, item.attempts
+    ))
+    .bind(item.last_error.as_deref())
+    .bind(now)
+    .execute(&state.pool)
+    .await;
+
+    Ok(())
+}
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_retry_policy(state: State<'_, DbState>) -> Result<RetryPolicy, String> {
+    Ok(RetryPolicy::load(&state.pool).await)
+}
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn save_retry_policy(
+    state: State<'_, DbState>,
+    policy: RetryPolicy,
+) -> Result<(), String> {
+    if policy.max_attempts < 1 || policy.base_delay_secs < 1 || policy.max_delay_secs < 1 {
+        return Err("Les valeurs de la politique de reprise doivent être positives".to_string());
+    }
+    policy.save(&state.pool).await.map_err(|e| e.to_string())
+}
@@ -0,0 +1,46 @@
+use crate::db::DbState;
+use crate::retention::{self, RetentionPolicy, RetentionReport, ARCHIVE_DIR_NAME};
+#[cfg(feature = "desktop")]
+use tauri::{AppHandle, Manager, State};
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_retention_policy(state: State<'_, DbState>) -> Result<RetentionPolicy, String> {
+    Ok(RetentionPolicy::load(&state.pool).await)
+}
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn save_retention_policy(
+    state: State<'_, DbState>,
+    policy: RetentionPolicy,
+) -> Result<(), String> {
+    if [
+        policy.production_max_age_days,
+        policy.production_max_rows,
+        policy.logs_max_age_days,
+        policy.logs_max_rows,
+    ]
+    .iter()
+    .any(|v| *v < 0)
+    {
+        return Err("Les limites de rétention doivent être positives (0 = illimité)".to_string());
+    }
+    policy.save(&state.pool).await.map_err(|e| e.to_string())
+}
+
+/// Apply the retention policy now instead of waiting for the background job.
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn run_retention_now(
+    app: AppHandle,
+    state: State<'_, DbState>,
+) -> Result<RetentionReport, String> {
+    let default_archive_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join(ARCHIVE_DIR_NAME);
+    let policy = RetentionPolicy::load(&state.pool).await;
+    retention::enforce(&state.pool, &policy, &default_archive_dir).await
+}
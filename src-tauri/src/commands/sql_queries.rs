@@ -1,6 +1,10 @@
+use crate::commands::mappings::load_effective_mappings;
+use crate::commands::sql_server::{load_sql_profile, SqlClient};
 use crate::db::DbState;
+use crate::stock::{self, parse_insert_columns};
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, Pool, Sqlite};
+#[cfg(feature = "desktop")]
 use tauri::State;
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
@@ -186,36 +190,53 @@ pub(crate) async fn get_or_init_sql_query(
     Ok(final_val)
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn get_sql_queries(state: State<'_, DbState>) -> Result<Vec<SqlQuery>, String> {
+    load_sql_queries(&state.pool).await
+}
+
+pub(crate) async fn load_sql_queries(pool: &Pool<Sqlite>) -> Result<Vec<SqlQuery>, String> {
     let queries = sqlx::query_as::<_, SqlQuery>(
         "SELECT id, format_name, query_template FROM sql_queries ORDER BY format_name",
     )
-    .fetch_all(&state.pool)
+    .fetch_all(pool)
     .await
     .map_err(|e| e.to_string())?;
 
     Ok(queries)
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn save_sql_query(
     state: State<'_, DbState>,
     format_name: String,
     query_template: String,
 ) -> Result<(), String> {
+    store_sql_query(&state.pool, &format_name, &query_template).await
+}
+
+pub(crate) async fn store_sql_query(
+    pool: &Pool<Sqlite>,
+    format_name: &str,
+    query_template: &str,
+) -> Result<(), String> {
+    stock::check_sql_template(query_template)?;
+
     sqlx::query(
         "INSERT INTO sql_queries (format_name, query_template) VALUES (?, ?)\n         ON CONFLICT(format_name) DO UPDATE SET query_template = excluded.query_template",
     )
-    .bind(&format_name)
-    .bind(&query_template)
-    .execute(&state.pool)
+    .bind(format_name)
+    .bind(query_template)
+    .execute(pool)
     .await
     .map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn get_sql_query(
     state: State<'_, DbState>,
@@ -237,6 +258,7 @@ pub async fn get_sql_query(
     }
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn reset_sql_query(state: State<'_, DbState>, format_name: String) -> Result<(), String> {
     let fname = format_name.to_uppercase();
@@ -266,3 +288,236 @@ pub async fn reset_sql_query(state: State<'_, DbState>, format_name: String) ->
 
     Ok(())
 }
+
+#[derive(Debug, Serialize)]
+pub struct TemplateParameter {
+    pub placeholder: String,
+    pub field: Option<String>,
+    /// Type SQL Server infers from where the parameter is used, e.g. `nvarchar(20)`.
+    pub server_type: Option<String>,
+    pub declared_type: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TemplateColumn {
+    pub name: String,
+    pub sql_type: String,
+    pub nullable: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TemplateValidation {
+    pub valid: bool,
+    pub parameters: Vec<TemplateParameter>,
+    /// Target table columns for an INSERT, result columns otherwise.
+    pub columns: Vec<TemplateColumn>,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+async fn describe_parameters(
+    client: &mut SqlClient,
+    sql: &str,
+) -> Result<Vec<(usize, String)>, String> {
+    let rows = client
+        .query(
+            "EXEC sp_describe_undeclared_parameters @tsql = @P1",
+            &[&sql],
+        )
+        .await
+        .map_err(|e| e.to_string())?
+        .into_first_result()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let name: &str = row.get("name")?;
+            let index = name
+                .trim_start_matches(['@', 'P', 'p'])
+                .parse::<usize>()
+                .ok()?;
+            let ty: &str = row.get("suggested_system_type_name").unwrap_or("");
+            Some((index, ty.to_string()))
+        })
+        .collect())
+}
+
+async fn describe_result_set(
+    client: &mut SqlClient,
+    sql: &str,
+    params: Option<String>,
+) -> Result<Vec<TemplateColumn>, String> {
+    let rows = client
+        .query(
+            "EXEC sp_describe_first_result_set @tsql = @P1, @params = @P2",
+            &[&sql, &params],
+        )
+        .await
+        .map_err(|e| e.to_string())?
+        .into_first_result()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .iter()
+        .filter(|row| !row.get::<bool, _>("is_hidden").unwrap_or(false))
+        .map(|row| TemplateColumn {
+            name: row.get::<&str, _>("name").unwrap_or("").to_string(),
+            sql_type: row
+                .get::<&str, _>("system_type_name")
+                .unwrap_or("")
+                .to_string(),
+            nullable: row.get::<bool, _>("is_nullable").unwrap_or(true),
+        })
+        .collect())
+}
+
+/// Check a template against the configured SQL Server without running it:
+/// parameters vs mapping, unknown target columns and column types.
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn validate_sql_template(
+    state: State<'_, DbState>,
+    format_name: String,
+    line_id: Option<i64>,
+    query_template: Option<String>,
+) -> Result<TemplateValidation, String> {
+    let fname = format_name.to_uppercase();
+    let query = match query_template {
+        Some(q) => q,
+        None => get_sql_query(state.clone(), fname.clone()).await?,
+    };
+    if query.trim().is_empty() {
+        return Err("Template SQL vide".to_string());
+    }
+
+    // Without a line, line 0 has no overrides and yields the model mapping.
+    let mappings = load_effective_mappings(&state.pool, line_id.unwrap_or(0), &fname).await?;
+    let template = stock::SqlTemplate::compile(&query, &mappings)?;
+
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    let profile_id = match line_id {
+        Some(id) => {
+            sqlx::query_scalar::<_, Option<i64>>("SELECT sql_profile_id FROM lines WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&state.pool)
+                .await
+                .map_err(|e| e.to_string())?
+                .flatten()
+        }
+        None => None,
+    };
+    let cfg = load_sql_profile(&state.pool, profile_id).await?;
+    let mut client = state.sql.connect(&cfg).await?;
+
+    let described = match describe_parameters(&mut client, &template.sql).await {
+        Ok(d) => d,
+        Err(e) => {
+            errors.push(format!("SQL Server refuse le template: {}", e));
+            Vec::new()
+        }
+    };
+
+    let mut parameters = Vec::new();
+    for (index, server_type) in &described {
+        let field = index
+            .checked_sub(1)
+            .and_then(|i| template.fields.get(i))
+            .cloned();
+        let declared_type = field.as_ref().and_then(|f| {
+            mappings
+                .iter()
+                .find(|m| &m.sql_field == f)
+                .and_then(|m| m.sql_type.clone())
+                .filter(|t| !t.trim().is_empty())
+        });
+        match &field {
+            None => errors.push(format!("@P{} n'est lié à aucun champ mappé", index)),
+            Some(f) => {
+                if let Some(declared) = &declared_type {
+                    let same = match (
+                        stock::SqlType::parse(declared),
+                        stock::SqlType::parse(server_type),
+                    ) {
+                        (Ok(a), Ok(b)) => a == b,
+                        _ => declared.trim().eq_ignore_ascii_case(server_type.trim()),
+                    };
+                    if !same {
+                        warnings.push(format!(
+                            "{} déclaré {} mais la colonne cible est {}",
+                            f, declared, server_type
+                        ));
+                    }
+                }
+            }
+        }
+        parameters.push(TemplateParameter {
+            placeholder: format!("@P{}", index),
+            field,
+            server_type: (!server_type.is_empty()).then(|| server_type.clone()),
+            declared_type,
+        });
+    }
+    if errors.is_empty() {
+        for (i, field) in template.fields.iter().enumerate() {
+            if !described.iter().any(|(index, _)| *index == i + 1) {
+                warnings.push(format!(
+                    "{} (@P{}) n'est pas utilisé par le template",
+                    field,
+                    i + 1
+                ));
+            }
+        }
+    }
+
+    let columns = match stock::insert_target(&template.sql) {
+        Some(table) => {
+            let columns =
+                describe_result_set(&mut client, &format!("SELECT TOP 0 * FROM {}", table), None)
+                    .await
+                    .unwrap_or_else(|e| {
+                        errors.push(format!("Table cible {} introuvable: {}", table, e));
+                        Vec::new()
+                    });
+            if !columns.is_empty() {
+                for col in parse_insert_columns(&template.sql) {
+                    if !columns.iter().any(|c| c.name.eq_ignore_ascii_case(&col)) {
+                        errors.push(format!("Colonne inconnue dans {}: {}", table, col));
+                    }
+                }
+            }
+            columns
+        }
+        None if errors.is_empty() => {
+            let params = described
+                .iter()
+                .map(|(index, ty)| format!("@P{} {}", index, ty))
+                .collect::<Vec<_>>()
+                .join(", ");
+            describe_result_set(
+                &mut client,
+                &template.sql,
+                (!params.is_empty()).then_some(params),
+            )
+            .await
+            .unwrap_or_else(|e| {
+                errors.push(e);
+                Vec::new()
+            })
+        }
+        None => Vec::new(),
+    };
+    client.release();
+
+    Ok(TemplateValidation {
+        valid: errors.is_empty(),
+        parameters,
+        columns,
+        errors,
+        warnings,
+    })
+}
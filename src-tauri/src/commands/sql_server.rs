@@ -1,18 +1,80 @@
 use crate::db::DbState;
+use crate::secrets::{decrypt_password, mask_password, password_to_store, PASSWORD_MASK};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, Pool, Sqlite};
+use std::time::Duration;
+#[cfg(feature = "desktop")]
 use tauri::State;
-use tiberius::{AuthMethod, Client, Config as SqlConfig};
-use tokio_util::compat::TokioAsyncWriteCompatExt;
+use tiberius::{AuthMethod, Client, Config as SqlConfig, EncryptionLevel, SqlBrowser};
+use tokio::net::TcpStream;
+use tokio_util::compat::{Compat, TokioAsyncWriteCompatExt};
 
+/// A named SQL Server connection profile. Profile 1 is the default one, used
+/// by lines, exports and syncs that are not bound to another profile.
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct SqlServerConfig {
     pub id: i64,
+    #[sqlx(default)]
+    pub name: String,
     pub server: Option<String>,
     pub database: Option<String>,
     pub username: Option<String>,
     pub password: Option<String>,
     pub enabled: bool,
+    #[sqlx(default)]
+    pub port: Option<i64>,
+    /// Named instance, resolved through the SQL Browser service (UDP 1434).
+    #[sqlx(default)]
+    pub instance_name: Option<String>,
+    /// OFF, ON, REQUIRED (default) or NOT_SUPPORTED.
+    #[sqlx(default)]
+    pub encryption: Option<String>,
+    #[sqlx(default)]
+    pub trust_cert: Option<bool>,
+    /// CA certificate the server certificate must chain to; overrides `trust_cert`.
+    #[sqlx(default)]
+    pub ca_cert_path: Option<String>,
+    #[sqlx(default)]
+    pub connect_timeout_secs: Option<i64>,
+    #[sqlx(default)]
+    pub query_timeout_secs: Option<i64>,
+    #[sqlx(default)]
+    pub app_name: Option<String>,
+    /// Maximum number of simultaneous connections kept for this profile.
+    #[sqlx(default)]
+    pub pool_max_size: Option<i64>,
+    /// Idle pooled connections are closed after this delay.
+    #[sqlx(default)]
+    pub pool_idle_timeout_secs: Option<i64>,
+}
+
+pub(crate) type SqlClient = Client<Compat<TcpStream>>;
+
+const DEFAULT_PORT: u16 = 1433;
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 15;
+const DEFAULT_QUERY_TIMEOUT_SECS: u64 = 300;
+const DEFAULT_APP_NAME: &str = "Visor";
+
+pub(crate) const DEFAULT_PROFILE_ID: i64 = 1;
+
+/// Exports and syncs reading from SQL Server; each can be bound to a profile.
+pub(crate) const PROFILE_TARGETS: [&str; 6] = [
+    "LOGITRON_PRODUIT",
+    "LOGITRON_OF",
+    "ATEIS_PRODUIT",
+    "ATEIS_OF",
+    "ATEIS_PRODUIT_SYNC",
+    "ATEIS_OF_SYNC",
+];
+
+fn binding_key(target: &str) -> String {
+    format!("sql_profile.{}", target)
+}
+
+#[derive(Debug, Serialize)]
+pub struct SqlProfileBinding {
+    pub target: String,
+    pub profile_id: Option<i64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -21,59 +83,200 @@ pub struct ConnectionTestResult {
     pub error: Option<String>,
 }
 
-pub(crate) async fn connect_sql_server(
-    cfg: SqlServerConfig,
-) -> Result<Client<tokio_util::compat::Compat<tokio::net::TcpStream>>, String> {
-    if !cfg.enabled {
-        return Err("SQL Server désactivé (activez la connexion dans Paramètres)".to_string());
+fn parse_encryption(value: Option<&str>) -> Result<EncryptionLevel, String> {
+    match value.map(|v| v.trim().to_uppercase()).as_deref() {
+        None | Some("") | Some("REQUIRED") => Ok(EncryptionLevel::Required),
+        Some("ON") => Ok(EncryptionLevel::On),
+        Some("OFF") => Ok(EncryptionLevel::Off),
+        Some("NOT_SUPPORTED") => Ok(EncryptionLevel::NotSupported),
+        Some(other) => Err(format!("Mode de chiffrement inconnu: {}", other)),
     }
+}
 
-    let server = cfg.server.unwrap_or_default();
-    let database = cfg.database.unwrap_or_default();
-    let username = cfg.username.unwrap_or_default();
-    let password = cfg.password.unwrap_or_default();
+fn check_timeout(value: Option<i64>, label: &str) -> Result<(), String> {
+    match value {
+        Some(secs) if secs <= 0 => Err(format!("{} invalide: {}", label, secs)),
+        _ => Ok(()),
+    }
+}
 
-    if server.trim().is_empty() {
-        return Err("SQL Server: serveur manquant".to_string());
+/// Check a profile's connection options; used when profiles are saved.
+pub(crate) fn validate_profile(cfg: &SqlServerConfig) -> Result<(), String> {
+    if let Some(port) = cfg.port {
+        if u16::try_from(port).map(|p| p == 0).unwrap_or(true) {
+            return Err(format!("Port invalide: {}", port));
+        }
     }
-    if username.trim().is_empty() {
-        return Err("SQL Server: utilisateur manquant".to_string());
+    parse_encryption(cfg.encryption.as_deref())?;
+    check_timeout(cfg.connect_timeout_secs, "Délai de connexion")?;
+    check_timeout(cfg.query_timeout_secs, "Délai d'exécution")?;
+    check_timeout(cfg.pool_idle_timeout_secs, "Délai d'inactivité du pool")?;
+    if let Some(size) = cfg.pool_max_size {
+        if !(1..=50).contains(&size) {
+            return Err(format!("Taille du pool invalide: {} (1 à 50)", size));
+        }
     }
-    if password.trim().is_empty() {
-        return Err("SQL Server: mot de passe manquant".to_string());
+    if let Some(path) = cfg.ca_cert_path.as_deref().filter(|p| !p.trim().is_empty()) {
+        if !std::path::Path::new(path.trim()).is_file() {
+            return Err(format!("Certificat CA introuvable: {}", path));
+        }
     }
+    Ok(())
+}
 
-    let mut tiberius_config = SqlConfig::new();
-    tiberius_config.host(server.as_str());
-    tiberius_config.port(1433);
-    tiberius_config.authentication(AuthMethod::sql_server(username, password));
-    tiberius_config.trust_cert();
-    if !database.trim().is_empty() {
-        tiberius_config.database(database);
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Shared tiberius configuration for a profile: address or named instance,
+/// encryption and certificate trust, application name.
+pub(crate) fn build_tiberius_config(cfg: &SqlServerConfig) -> Result<SqlConfig, String> {
+    let mut config = SqlConfig::new();
+    config.host(non_empty(&cfg.server).unwrap_or(""));
+    match non_empty(&cfg.instance_name) {
+        // The port then designates the SQL Browser, which tiberius defaults to 1434.
+        Some(instance) => config.instance_name(instance),
+        None => config.port(
+            cfg.port
+                .and_then(|p| u16::try_from(p).ok())
+                .filter(|p| *p != 0)
+                .unwrap_or(DEFAULT_PORT),
+        ),
+    }
+    config.authentication(AuthMethod::sql_server(
+        cfg.username.clone().unwrap_or_default(),
+        cfg.password.clone().unwrap_or_default(),
+    ));
+    config.encryption(parse_encryption(cfg.encryption.as_deref())?);
+    match non_empty(&cfg.ca_cert_path) {
+        Some(ca) => config.trust_cert_ca(ca),
+        None if cfg.trust_cert.unwrap_or(true) => config.trust_cert(),
+        None => {}
     }
+    config.application_name(non_empty(&cfg.app_name).unwrap_or(DEFAULT_APP_NAME));
+    if let Some(db) = non_empty(&cfg.database) {
+        config.database(db);
+    }
+    Ok(config)
+}
 
-    let tcp = tokio::net::TcpStream::connect(tiberius_config.get_addr())
+pub(crate) fn connect_timeout(cfg: &SqlServerConfig) -> Duration {
+    Duration::from_secs(
+        cfg.connect_timeout_secs
+            .and_then(|s| u64::try_from(s).ok())
+            .filter(|s| *s > 0)
+            .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS),
+    )
+}
+
+pub(crate) fn query_timeout(cfg: &SqlServerConfig) -> Duration {
+    Duration::from_secs(
+        cfg.query_timeout_secs
+            .and_then(|s| u64::try_from(s).ok())
+            .filter(|s| *s > 0)
+            .unwrap_or(DEFAULT_QUERY_TIMEOUT_SECS),
+    )
+}
+
+/// Run `fut` within a query timeout (see `query_timeout`). On a timeout the
+/// response is left unread on the wire: drop the connection rather than
+/// releasing it to the pool.
+pub(crate) async fn with_query_timeout<T>(
+    limit: Duration,
+    fut: impl std::future::Future<Output = Result<T, String>>,
+) -> Result<T, String> {
+    tokio::time::timeout(limit, fut)
         .await
-        .map_err(|e| e.to_string())?;
-    tcp.set_nodelay(true).map_err(|e| e.to_string())?;
+        .map_err(|_| format!("Délai d'exécution dépassé ({}s)", limit.as_secs()))?
+}
 
-    Client::connect(tiberius_config, tcp.compat_write())
+/// Open a connection with the profile's options, without checking whether it is enabled.
+pub(crate) async fn open_connection(cfg: &SqlServerConfig) -> Result<SqlClient, String> {
+    let config = build_tiberius_config(cfg)?;
+    let limit = connect_timeout(cfg);
+
+    let connect = async {
+        let tcp = if let Some(instance) = non_empty(&cfg.instance_name) {
+            TcpStream::connect_named(&config)
+                .await
+                .map_err(|e| format!("La connexion a échoué (instance {}): {}", instance, e))?
+        } else {
+            TcpStream::connect(config.get_addr())
+                .await
+                .map_err(|e| e.to_string())?
+        };
+        tcp.set_nodelay(true).map_err(|e| e.to_string())?;
+        Client::connect(config, tcp.compat_write())
+            .await
+            .map_err(|e| e.to_string())
+    };
+
+    tokio::time::timeout(limit, connect)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|_| format!("Timeout de connexion ({}s)", limit.as_secs()))?
 }
 
-#[tauri::command]
-pub async fn get_sql_server_config(state: State<'_, DbState>) -> Result<SqlServerConfig, String> {
-    let config = sqlx::query_as::<_, SqlServerConfig>(
-        "SELECT id, server, database, username, password, enabled FROM sql_server_config WHERE id = 1",
+/// Reject disabled or incomplete profiles before connecting.
+pub(crate) fn check_connectable(cfg: &SqlServerConfig) -> Result<(), String> {
+    if !cfg.enabled {
+        return Err("SQL Server désactivé (activez la connexion dans Paramètres)".to_string());
+    }
+    if non_empty(&cfg.server).is_none() {
+        return Err("SQL Server: serveur manquant".to_string());
+    }
+    if non_empty(&cfg.username).is_none() {
+        return Err("SQL Server: utilisateur manquant".to_string());
+    }
+    if non_empty(&cfg.password).is_none() {
+        return Err("SQL Server: mot de passe manquant".to_string());
+    }
+    Ok(())
+}
+
+/// Profile `id`, or the default profile when `None`.
+pub(crate) async fn load_sql_profile(
+    pool: &Pool<Sqlite>,
+    id: Option<i64>,
+) -> Result<SqlServerConfig, String> {
+    let id = id.unwrap_or(DEFAULT_PROFILE_ID);
+    let mut profile = sqlx::query_as::<_, SqlServerConfig>(
+        "SELECT id, name, server, database, username, password, enabled, port, instance_name, encryption, trust_cert, \
+         ca_cert_path, connect_timeout_secs, query_timeout_secs, app_name, pool_max_size, pool_idle_timeout_secs \
+         FROM sql_server_profiles WHERE id = ?",
     )
-    .fetch_one(&state.pool)
+    .bind(id)
+    .fetch_optional(pool)
     .await
-    .map_err(|e| e.to_string())?;
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Profil SQL Server {} introuvable", id))?;
+    decrypt_password(&mut profile.password)?;
+    Ok(profile)
+}
 
-    Ok(config)
+/// Profile bound to an export or sync (see `PROFILE_TARGETS`).
+pub(crate) async fn load_bound_profile(
+    pool: &Pool<Sqlite>,
+    target: &str,
+) -> Result<SqlServerConfig, String> {
+    let id = sqlx::query_scalar::<_, String>("SELECT value FROM config WHERE key = ?")
+        .bind(binding_key(target))
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|v| v.parse::<i64>().ok());
+    load_sql_profile(pool, id).await
 }
 
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_sql_server_config(state: State<'_, DbState>) -> Result<SqlServerConfig, String> {
+    let mut cfg = load_sql_profile(&state.pool, None).await?;
+    mask_password(&mut cfg.password);
+    Ok(cfg)
+}
+
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn save_sql_server_config(
     state: State<'_, DbState>,
@@ -84,13 +287,14 @@ pub async fn save_sql_server_config(
     enabled: bool,
 ) -> Result<(), String> {
     sqlx::query(
-        "UPDATE sql_server_config SET server = ?, database = ?, username = ?, password = ?, enabled = ? WHERE id = 1",
+        "UPDATE sql_server_profiles SET server = ?, database = ?, username = ?, password = COALESCE(?, password), enabled = ? WHERE id = ?",
     )
     .bind(&server)
     .bind(&database)
     .bind(&username)
-    .bind(&password)
+    .bind(password_to_store(Some(&password))?)
     .bind(enabled)
+    .bind(DEFAULT_PROFILE_ID)
     .execute(&state.pool)
     .await
     .map_err(|e| e.to_string())?;
@@ -98,8 +302,224 @@ pub async fn save_sql_server_config(
     Ok(())
 }
 
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_sql_server_profiles(
+    state: State<'_, DbState>,
+) -> Result<Vec<SqlServerConfig>, String> {
+    sqlx::query_as::<_, SqlServerConfig>(
+        "SELECT id, name, server, database, username, password, enabled, port, instance_name, encryption, trust_cert, \
+         ca_cert_path, connect_timeout_secs, query_timeout_secs, app_name, pool_max_size, pool_idle_timeout_secs \
+         FROM sql_server_profiles ORDER BY id",
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| e.to_string())
+    .map(|mut profiles| {
+        for profile in &mut profiles {
+            mask_password(&mut profile.password);
+        }
+        profiles
+    })
+}
+
+/// Create (`id` 0) or update a profile. Returns its id.
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn save_sql_server_profile(
+    state: State<'_, DbState>,
+    profile: SqlServerConfig,
+) -> Result<i64, String> {
+    let name = profile.name.trim().to_string();
+    if name.is_empty() {
+        return Err("Nom du profil manquant".to_string());
+    }
+    validate_profile(&profile)?;
+
+    let taken: Option<i64> =
+        sqlx::query_scalar("SELECT id FROM sql_server_profiles WHERE name = ? AND id != ?")
+            .bind(&name)
+            .bind(profile.id)
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    if taken.is_some() {
+        return Err(format!("Un profil nommé {} existe déjà", name));
+    }
+
+    let query = if profile.id > 0 {
+        "UPDATE sql_server_profiles SET name = ?, server = ?, database = ?, username = ?, password = COALESCE(?, password), enabled = ?, \
+             port = ?, instance_name = ?, encryption = ?, trust_cert = ?, ca_cert_path = ?, \
+             connect_timeout_secs = ?, query_timeout_secs = ?, app_name = ?, pool_max_size = ?, pool_idle_timeout_secs = ? \
+         WHERE id = ?"
+    } else {
+        "INSERT INTO sql_server_profiles (name, server, database, username, password, enabled, \
+             port, instance_name, encryption, trust_cert, ca_cert_path, connect_timeout_secs, query_timeout_secs, app_name, \
+             pool_max_size, pool_idle_timeout_secs) \
+         VALUES (?, ?, ?, ?, ?, ?, COALESCE(?, 1433), ?, COALESCE(?, 'REQUIRED'), COALESCE(?, 1), ?, ?, ?, ?, \
+             COALESCE(?, 4), COALESCE(?, 300))"
+    };
+    let mut statement = sqlx::query(query)
+        .bind(&name)
+        .bind(&profile.server)
+        .bind(&profile.database)
+        .bind(&profile.username)
+        .bind(password_to_store(profile.password.as_deref())?)
+        .bind(profile.enabled)
+        .bind(profile.port)
+        .bind(&profile.instance_name)
+        .bind(profile.encryption.as_ref().map(|e| e.trim().to_uppercase()))
+        .bind(profile.trust_cert)
+        .bind(&profile.ca_cert_path)
+        .bind(profile.connect_timeout_secs)
+        .bind(profile.query_timeout_secs)
+        .bind(&profile.app_name)
+        .bind(profile.pool_max_size)
+        .bind(profile.pool_idle_timeout_secs);
+    if profile.id > 0 {
+        statement = statement.bind(profile.id);
+    }
+    let result = statement
+        .execute(&state.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(if profile.id > 0 {
+        profile.id
+    } else {
+        result.last_insert_rowid()
+    })
+}
+
+/// Delete a profile; lines, exports and syncs bound to it fall back to the default profile.
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn delete_sql_server_profile(state: State<'_, DbState>, id: i64) -> Result<(), String> {
+    if id == DEFAULT_PROFILE_ID {
+        return Err("Le profil par défaut ne peut pas être supprimé".to_string());
+    }
+
+    let mut tx = state.pool.begin().await.map_err(|e| e.to_string())?;
+    sqlx::query("UPDATE lines SET sql_profile_id = NULL WHERE sql_profile_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM config WHERE key LIKE 'sql_profile.%' AND value = ?")
+        .bind(id.to_string())
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM sql_server_profiles WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+    state.sql.forget(id);
+
+    Ok(())
+}
+
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn get_sql_profile_bindings(
+    state: State<'_, DbState>,
+) -> Result<Vec<SqlProfileBinding>, String> {
+    let mut bindings = Vec::with_capacity(PROFILE_TARGETS.len());
+    for target in PROFILE_TARGETS {
+        let profile_id = sqlx::query_scalar::<_, String>("SELECT value FROM config WHERE key = ?")
+            .bind(binding_key(target))
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(|e| e.to_string())?
+            .and_then(|v| v.parse::<i64>().ok());
+        bindings.push(SqlProfileBinding {
+            target: target.to_string(),
+            profile_id,
+        });
+    }
+    Ok(bindings)
+}
+
+/// Bind an export or sync to a profile; `None` goes back to the default profile.
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn set_sql_profile_binding(
+    state: State<'_, DbState>,
+    target: String,
+    profile_id: Option<i64>,
+) -> Result<(), String> {
+    let target = target.to_uppercase();
+    if !PROFILE_TARGETS.contains(&target.as_str()) {
+        return Err(format!("Cible inconnue: {}", target));
+    }
+
+    match profile_id {
+        Some(id) => {
+            load_sql_profile(&state.pool, Some(id)).await?;
+            sqlx::query("INSERT OR REPLACE INTO config (key, value) VALUES (?, ?)")
+                .bind(binding_key(&target))
+                .bind(id.to_string())
+                .execute(&state.pool)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        None => {
+            sqlx::query("DELETE FROM config WHERE key = ?")
+                .bind(binding_key(&target))
+                .execute(&state.pool)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+async fn run_connection_test(cfg: &SqlServerConfig) -> ConnectionTestResult {
+    if let Err(e) = validate_profile(cfg) {
+        return ConnectionTestResult {
+            success: false,
+            error: Some(e),
+        };
+    }
+
+    let outcome = async {
+        let mut client = open_connection(cfg).await?;
+        with_query_timeout(query_timeout(cfg), async {
+            client
+                .query("SELECT 1", &[])
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .map(|_| ())
+    }
+    .await;
+
+    ConnectionTestResult {
+        success: outcome.is_ok(),
+        error: outcome.err(),
+    }
+}
+
+/// The settings screens send the mask back for an unchanged password.
+async fn stored_password_if_masked(
+    state: &DbState,
+    profile_id: i64,
+    password: String,
+) -> Result<String, String> {
+    if password != PASSWORD_MASK || profile_id <= 0 {
+        return Ok(password);
+    }
+    let profile = load_sql_profile(&state.pool, Some(profile_id)).await?;
+    Ok(profile.password.unwrap_or_default())
+}
+
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn test_sql_server_connection(
+    state: State<'_, DbState>,
     server: String,
     database: String,
     username: String,
@@ -120,71 +540,33 @@ pub async fn test_sql_server_connection(
         });
     }
 
-    let mut tiberius_config = SqlConfig::new();
-    tiberius_config.host(server.as_str());
-    tiberius_config.port(1433);
-    tiberius_config.authentication(AuthMethod::sql_server(username, password));
-    tiberius_config.trust_cert();
-    if !database.trim().is_empty() {
-        tiberius_config.database(database);
+    // The other connection options (port, instance, encryption...) are the default profile's.
+    let mut cfg = load_sql_profile(&state.pool, None).await?;
+    cfg.server = Some(server);
+    cfg.database = Some(database);
+    cfg.username = Some(username);
+    if password != PASSWORD_MASK {
+        cfg.password = Some(password);
     }
+    cfg.enabled = enabled;
+    Ok(run_connection_test(&cfg).await)
+}
 
-    let connect_res = tokio::time::timeout(
-        std::time::Duration::from_secs(10),
-        tokio::net::TcpStream::connect(tiberius_config.get_addr()),
-    )
-    .await;
-
-    let tcp = match connect_res {
-        Ok(Ok(tcp)) => tcp,
-        Ok(Err(e)) => {
-            return Ok(ConnectionTestResult {
-                success: false,
-                error: Some(e.to_string()),
-            });
-        }
-        Err(_) => {
-            return Ok(ConnectionTestResult {
-                success: false,
-                error: Some("Timeout de connexion (10s)".to_string()),
-            });
-        }
-    };
-
-    if let Err(e) = tcp.set_nodelay(true) {
+/// Test a profile as edited, with all its connection options.
+#[cfg(feature = "desktop")]
+#[tauri::command]
+pub async fn test_sql_server_profile(
+    state: State<'_, DbState>,
+    mut profile: SqlServerConfig,
+) -> Result<ConnectionTestResult, String> {
+    if non_empty(&profile.server).is_none() {
         return Ok(ConnectionTestResult {
             success: false,
-            error: Some(e.to_string()),
+            error: Some("Serveur SQL Server manquant".to_string()),
         });
     }
-
-    let client_res = tokio::time::timeout(
-        std::time::Duration::from_secs(10),
-        Client::connect(tiberius_config, tcp.compat_write()),
-    )
-    .await;
-
-    match client_res {
-        Ok(Ok(mut client)) => {
-            let query_res = client.query("SELECT 1", &[]).await;
-            match query_res {
-                Ok(_) => Ok(ConnectionTestResult {
-                    success: true,
-                    error: None,
-                }),
-                Err(e) => Ok(ConnectionTestResult {
-                    success: false,
-                    error: Some(e.to_string()),
-                }),
-            }
-        }
-        Ok(Err(e)) => Ok(ConnectionTestResult {
-            success: false,
-            error: Some(e.to_string()),
-        }),
-        Err(_) => Ok(ConnectionTestResult {
-            success: false,
-            error: Some("Timeout de connexion (10s)".to_string()),
-        }),
+    if let Some(password) = profile.password.take() {
+        profile.password = Some(stored_password_if_masked(&state, profile.id, password).await?);
     }
+    Ok(run_connection_test(&profile).await)
 }
@@ -1,6 +1,9 @@
 use sqlx::{migrate::MigrateDatabase, sqlite::SqlitePoolOptions, Pool, Sqlite};
 use std::fs;
+use std::path::Path;
+#[cfg(feature = "desktop")]
 use tauri::AppHandle;
+#[cfg(feature = "desktop")]
 use tauri::Manager;
 
 async fn seed_model(
@@ -45,15 +48,47 @@ async fn seed_model(
     Ok(())
 }
 
+#[derive(Clone)]
 pub struct DbState {
     pub pool: Pool<Sqlite>,
+    pub sql: crate::sql_pool::SqlServerPools,
 }
 
+#[cfg(feature = "desktop")]
 pub async fn init_db(app_handle: &AppHandle) -> Result<Pool<Sqlite>, Box<dyn std::error::Error>> {
     let app_dir = app_handle.path().app_data_dir()?;
+    let pool = init_db_at(&app_dir).await?;
 
+    // Version check: Disable SQL Server if version changed. Desktop only: the
+    // CLI and the service must not have this side effect on every start.
+    let current_version = app_handle.package_info().version.to_string();
+    let stored_version: Option<String> =
+        sqlx::query_scalar("SELECT value FROM config WHERE key = 'last_version'")
+            .fetch_optional(&pool)
+            .await
+            .unwrap_or(None);
+
+    if stored_version.as_deref() != Some(current_version.as_str()) {
+        // Version mismatch (update or first run) -> Disable SQL Server (every profile)
+        let _ = sqlx::query("UPDATE sql_server_profiles SET enabled = 0")
+            .execute(&pool)
+            .await;
+
+        // Update stored version
+        let _ =
+            sqlx::query("INSERT OR REPLACE INTO config (key, value) VALUES ('last_version', ?)")
+                .bind(&current_version)
+                .execute(&pool)
+                .await;
+    }
+
+    Ok(pool)
+}
+
+/// Open (creating and migrating if needed) `visor.db` in `app_dir`.
+pub(crate) async fn init_db_at(app_dir: &Path) -> Result<Pool<Sqlite>, Box<dyn std::error::Error>> {
     if !app_dir.exists() {
-        fs::create_dir_all(&app_dir)?;
+        fs::create_dir_all(app_dir)?;
     }
 
     let db_path = app_dir.join("visor.db");
@@ -68,100 +103,8 @@ pub async fn init_db(app_handle: &AppHandle) -> Result<Pool<Sqlite>, Box<dyn std
         .connect(&db_url)
         .await?;
 
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS lines (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL,
-            path TEXT NOT NULL,
-            prefix TEXT NOT NULL,
-            interval_check INTEGER DEFAULT 60,
-            interval_alert INTEGER DEFAULT 120,
-            archived_path TEXT,
-            rejected_path TEXT,
-            active BOOLEAN DEFAULT 1,
-            site TEXT,
-            unite TEXT,
-            flag_dec TEXT,
-            code_ligne TEXT,
-            log_path TEXT,
-            file_format TEXT DEFAULT 'ATEIS',
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )",
-    )
-    .execute(&pool)
-    .await?;
-
-    // Migration: add new columns if they don't exist (for existing DBs)
-    let _ = sqlx::query("ALTER TABLE lines ADD COLUMN site TEXT")
-        .execute(&pool)
-        .await;
-    let _ = sqlx::query("ALTER TABLE lines ADD COLUMN unite TEXT")
-        .execute(&pool)
-        .await;
-    let _ = sqlx::query("ALTER TABLE lines ADD COLUMN flag_dec TEXT")
-        .execute(&pool)
-        .await;
-    let _ = sqlx::query("ALTER TABLE lines ADD COLUMN code_ligne TEXT")
-        .execute(&pool)
-        .await;
-    let _ = sqlx::query("ALTER TABLE lines ADD COLUMN log_path TEXT")
-        .execute(&pool)
-        .await;
-    let _ = sqlx::query("ALTER TABLE lines ADD COLUMN file_format TEXT DEFAULT 'ATEIS'")
-        .execute(&pool)
-        .await;
-    let _ = sqlx::query("ALTER TABLE lines ADD COLUMN total_traites INTEGER DEFAULT 0")
-        .execute(&pool)
-        .await;
-    let _ = sqlx::query("ALTER TABLE lines ADD COLUMN total_erreurs INTEGER DEFAULT 0")
-        .execute(&pool)
-        .await;
-    let _ = sqlx::query("ALTER TABLE lines ADD COLUMN last_file_time TEXT")
-        .execute(&pool)
-        .await;
-    let _ = sqlx::query("ALTER TABLE lines ADD COLUMN etat_actuel TEXT DEFAULT 'ARRET'")
-        .execute(&pool)
-        .await;
-    let _ = sqlx::query("ALTER TABLE lines ADD COLUMN rejected_path TEXT")
-        .execute(&pool)
-        .await;
-
-    // Migration: Add log_path to hfsql_config
-    let _ = sqlx::query("ALTER TABLE hfsql_config ADD COLUMN log_path TEXT")
-        .execute(&pool)
-        .await;
-
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS mappings (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            line_id INTEGER NOT NULL,
-            sort_order INTEGER DEFAULT 0,
-            sql_field TEXT NOT NULL,
-            file_column TEXT,
-            parameter TEXT,
-            transformation TEXT,
-            description TEXT,
-            FOREIGN KEY(line_id) REFERENCES lines(id) ON DELETE CASCADE
-        )",
-    )
-    .execute(&pool)
-    .await?;
-
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS model_mappings (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            format_name TEXT NOT NULL,
-            sort_order INTEGER DEFAULT 0,
-            sql_field TEXT NOT NULL,
-            file_column TEXT,
-            parameter TEXT,
-            transformation TEXT,
-            description TEXT,
-            UNIQUE(format_name, sort_order)
-        )",
-    )
-    .execute(&pool)
-    .await?;
+    crate::migrations::run(&pool).await?;
+    crate::secrets::init(app_dir, &pool).await?;
 
     seed_model(
         &pool,
@@ -409,100 +352,8 @@ pub async fn init_db(app_handle: &AppHandle) -> Result<Pool<Sqlite>, Box<dyn std
     )
     .await?;
 
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS production_data (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            line_id INTEGER,
-            filename TEXT,
-            processed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            status TEXT NOT NULL,
-            message TEXT,
-            FOREIGN KEY(line_id) REFERENCES lines(id)
-        )",
-    )
-    .execute(&pool)
-    .await?;
-
-    // Create a generic key-value store for app configuration
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS config (
-            key TEXT PRIMARY KEY,
-            value TEXT
-        )",
-    )
-    .execute(&pool)
-    .await?;
-
-    // Logs table for Journaux page
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS logs (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            line_id INTEGER,
-            level TEXT NOT NULL,
-            source TEXT,
-            message TEXT NOT NULL,
-            details TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY(line_id) REFERENCES lines(id) ON DELETE SET NULL
-        )",
-    )
-    .execute(&pool)
-    .await?;
-
-    // SQL Server connection settings table
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS sql_server_config (
-            id INTEGER PRIMARY KEY CHECK (id = 1),
-            server TEXT,
-            database TEXT,
-            username TEXT,
-            password TEXT,
-            enabled BOOLEAN DEFAULT 0
-        )",
-    )
-    .execute(&pool)
-    .await?;
-
-    // Insert default SQL Server config row if not exists
-    sqlx::query(
-        "INSERT OR IGNORE INTO sql_server_config (id, server, database, username, password, enabled) 
-         VALUES (1, '', '', '', '', 0)"
-    )
-    .execute(&pool)
-    .await?;
-
-    // SQL query templates table
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS sql_queries (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            format_name TEXT UNIQUE NOT NULL,
-            format_name TEXT UNIQUE NOT NULL,
-            query_template TEXT NOT NULL
-        )",
-    )
-    .execute(&pool)
-    .await?;
-
-    // HFSQL connection settings table (ODBC)
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS hfsql_config (
-            id INTEGER PRIMARY KEY CHECK (id = 1),
-            dsn TEXT,
-            username TEXT,
-            password TEXT,
-            log_path TEXT
-        )",
-    )
-    .execute(&pool)
-    .await?;
-
-    // Insert default HFSQL config row if not exists
-    sqlx::query(
-        "INSERT OR IGNORE INTO hfsql_config (id, dsn, username, password) 
-         VALUES (1, 'HFSQL', 'Admin', '1234')",
-    )
-    .execute(&pool)
-    .await?;
+    // Encrypt passwords still stored in plaintext (databases from older versions)
+    crate::secrets::encrypt_plaintext_passwords(&pool).await?;
 
     // Insert default SQL queries for ATEIS and LOGITRON formats
     // Use centralized defaults from commands module
@@ -510,24 +361,6 @@ pub async fn init_db(app_handle: &AppHandle) -> Result<Pool<Sqlite>, Box<dyn std
 
     let default_logitron_query = crate::commands::sql_queries::DEFAULT_LOGITRON_QUERY;
 
-    let _ = sqlx::query(
-        "DELETE FROM sql_queries 
-         WHERE id NOT IN (
-            SELECT MIN(id) 
-            FROM sql_queries 
-            GROUP BY format_name
-         )",
-    )
-    .execute(&pool)
-    .await;
-
-    let _ = sqlx::query(
-        "CREATE UNIQUE INDEX IF NOT EXISTS idx_sql_queries_format_name ON sql_queries(format_name)",
-    )
-    .execute(&pool)
-    .await;
-
-    // 3. Now safe to insert defaults using INSERT OR IGNORE
     sqlx::query(
         "INSERT OR IGNORE INTO sql_queries (format_name, query_template) VALUES ('ATEIS', ?)",
     )
@@ -542,27 +375,5 @@ pub async fn init_db(app_handle: &AppHandle) -> Result<Pool<Sqlite>, Box<dyn std
     .execute(&pool)
     .await?;
 
-    // Version check: Disable SQL Server if version changed
-    let current_version = app_handle.package_info().version.to_string();
-    let stored_version: Option<String> =
-        sqlx::query_scalar("SELECT value FROM config WHERE key = 'last_version'")
-            .fetch_optional(&pool)
-            .await
-            .unwrap_or(None);
-
-    if stored_version.as_deref() != Some(&current_version) {
-        // Version mismatch (update or first run) -> Disable SQL Server
-        let _ = sqlx::query("UPDATE sql_server_config SET enabled = 0 WHERE id = 1")
-            .execute(&pool)
-            .await;
-
-        // Update stored version
-        let _ =
-            sqlx::query("INSERT OR REPLACE INTO config (key, value) VALUES ('last_version', ?)")
-                .bind(&current_version)
-                .execute(&pool)
-                .await;
-    }
-
     Ok(pool)
 }
@@ -0,0 +1,185 @@
+use crate::api::{ApiContext, ApiServer};
+use crate::db::DbState;
+use crate::host::Host;
+use crate::metrics::MetricsServer;
+use crate::scheduler::SchedulerState;
+use crate::sql_pool::SqlServerPools;
+use crate::stock::WatcherState;
+use sqlx::Row;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Same folder as the desktop app's `app_data_dir`, so both share one database.
+const APP_IDENTIFIER: &str = "com.visor.desktop";
+const DATA_DIR_ENV: &str = "VISOR_DATA_DIR";
+/// How long in-flight database work gets to finish on shutdown.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+
+/// `--data-dir <path>`, else `$VISOR_DATA_DIR`, else the desktop app's data folder.
+pub(crate) fn data_dir(args: &[String]) -> Option<PathBuf> {
+    if let Some(i) = args.iter().position(|a| a == "--data-dir") {
+        return args.get(i + 1).map(PathBuf::from);
+    }
+    if let Some(dir) = std::env::var_os(DATA_DIR_ENV).filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME")
+            .map(|h| PathBuf::from(h).join("Library").join("Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local").join("share"))
+            })
+    };
+    base.map(|b| b.join(APP_IDENTIFIER))
+}
+
+/// Run the file watchers, scheduled tasks, retention job and alert monitor without a window,
+/// until SIGTERM (or Ctrl+C).
+pub fn run(args: Vec<String>) -> i32 {
+    let Some(data_dir) = data_dir(&args) else {
+        eprintln!(
+            "Dossier de données introuvable: utilisez --data-dir ou {}",
+            DATA_DIR_ENV
+        );
+        return 2;
+    };
+    if let Err(e) = crate::logging::setup_headless(&data_dir.join("logs")) {
+        eprintln!("Journalisation impossible: {}", e);
+        return 1;
+    }
+
+    match crate::runtime::block_on(serve(data_dir)) {
+        Ok(()) => 0,
+        Err(e) => {
+            log::error!("{}", e);
+            1
+        }
+    }
+}
+
+async fn serve(data_dir: PathBuf) -> Result<(), String> {
+    log::info!("Démarrage du service (données: {})", data_dir.display());
+    let _lock = crate::watch_lock::acquire(&data_dir)?;
+
+    let pool = crate::db::init_db_at(&data_dir)
+        .await
+        .map_err(|e| format!("Initialisation de la base impossible: {}", e))?;
+    crate::stock::queue::recover_interrupted(&pool).await;
+    let sql = SqlServerPools::default();
+    sql.spawn_reaper();
+    let db = DbState {
+        pool: pool.clone(),
+        sql,
+    };
+
+    crate::retention::spawn_job(
+        pool.clone(),
+        data_dir.join(crate::retention::ARCHIVE_DIR_NAME),
+    );
+
+    let watchers = WatcherState::new();
+    let lines = sqlx::query("SELECT id, path, prefix, archived_path FROM lines WHERE active = 1")
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    for line in &lines {
+        crate::stock::watch_line(
+            &watchers,
+            &db,
+            line.get("id"),
+            line.get("path"),
+            line.get("prefix"),
+            line.get("archived_path"),
+        );
+    }
+
+    let scheduler = SchedulerState::new();
+    let host = Host::Headless {
+        data_dir: data_dir.clone(),
+    };
+    crate::alerts::spawn_monitor(host.clone(), pool.clone());
+    let tasks = crate::scheduler::load_tasks(&pool).await?;
+    for (task_type, task) in &tasks {
+        crate::scheduler::spawn_task(
+            &scheduler,
+            host.clone(),
+            db.clone(),
+            task_type.clone(),
+            task.clone(),
+        )?;
+    }
+
+    let metrics = MetricsServer::new(pool.clone(), watchers.clone(), scheduler.clone());
+    if let Err(e) = metrics.restart().await {
+        log::error!("{}", e);
+    }
+
+    let api = ApiServer::new(ApiContext {
+        db: db.clone(),
+        watchers: watchers.clone(),
+        scheduler: scheduler.clone(),
+        host,
+        uploads_dir: data_dir.join(crate::api::UPLOADS_DIR_NAME),
+    });
+    if let Err(e) = api.restart().await {
+        log::error!("{}", e);
+    }
+
+    log::info!(
+        "Service démarré: {} ligne(s) surveillée(s), {} tâche(s) planifiée(s)",
+        lines.len(),
+        tasks.len()
+    );
+
+    wait_for_shutdown().await;
+    log::info!("Arrêt demandé");
+
+    // Stop taking new work, then let what is in flight reach the database.
+    api.stop().await;
+    metrics.stop().await;
+    watchers.stop_all();
+    scheduler.stop_all();
+    if tokio::time::timeout(SHUTDOWN_GRACE, pool.close())
+        .await
+        .is_err()
+    {
+        log::warn!(
+            "Arrêt forcé: traitements encore en cours après {}s",
+            SHUTDOWN_GRACE.as_secs()
+        );
+    }
+
+    log::info!("Service arrêté");
+    log::logger().flush();
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) async fn wait_for_shutdown() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut term) => {
+            tokio::select! {
+                _ = term.recv() => {}
+                _ = tokio::signal::ctrl_c() => {}
+            }
+        }
+        Err(e) => {
+            log::warn!("SIGTERM non disponible: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) async fn wait_for_shutdown() {
+    let _ = tokio::signal::ctrl_c().await;
+}
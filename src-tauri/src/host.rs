@@ -0,0 +1,60 @@
+use serde::Serialize;
+use std::path::PathBuf;
+#[cfg(feature = "desktop")]
+use tauri::{AppHandle, Emitter, Manager};
+#[cfg(feature = "desktop")]
+use tauri_plugin_notification::NotificationExt;
+
+/// What background work (watchers, scheduled syncs and exports) runs inside:
+/// the desktop app, or the headless service where there is no window to notify.
+#[derive(Clone)]
+pub enum Host {
+    #[cfg(feature = "desktop")]
+    Desktop(AppHandle),
+    Headless {
+        data_dir: PathBuf,
+    },
+}
+
+impl Host {
+    /// Send an event to the frontend; nothing listens in headless mode.
+    #[cfg_attr(not(feature = "desktop"), allow(unused_variables))]
+    pub(crate) fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) -> Result<(), String> {
+        match self {
+            #[cfg(feature = "desktop")]
+            Host::Desktop(app) => app.emit(event, payload).map_err(|e| e.to_string()),
+            Host::Headless { .. } => Ok(()),
+        }
+    }
+
+    /// System notification; the headless service has no desktop to show it on.
+    #[cfg_attr(not(feature = "desktop"), allow(unused_variables))]
+    pub(crate) fn notify(&self, title: &str, body: &str) -> Result<(), String> {
+        match self {
+            #[cfg(feature = "desktop")]
+            Host::Desktop(app) => app
+                .notification()
+                .builder()
+                .title(title)
+                .body(body)
+                .show()
+                .map_err(|e| e.to_string()),
+            Host::Headless { .. } => Ok(()),
+        }
+    }
+
+    /// Folder for HFSQL sync logs and `.DAT` exports when none is configured:
+    /// `Desktop/T/BLOG` on a workstation, `BLOG` next to the database otherwise.
+    pub(crate) fn default_output_dir(&self) -> String {
+        match self {
+            #[cfg(feature = "desktop")]
+            Host::Desktop(app) => app
+                .path()
+                .desktop_dir()
+                .ok()
+                .map(|p| p.join("T").join("BLOG").to_string_lossy().to_string())
+                .unwrap_or_else(|| r"C:\T\BLOG".to_string()),
+            Host::Headless { data_dir } => data_dir.join("BLOG").to_string_lossy().to_string(),
+        }
+    }
+}
@@ -1,10 +1,43 @@
+// Without the `desktop` feature the Tauri commands are compiled out, leaving
+// some helpers and imports that only they use.
+#![cfg_attr(not(feature = "desktop"), allow(dead_code, unused_imports))]
+
+mod alerts;
+mod api;
+#[cfg(feature = "desktop")]
 mod app;
+mod cli;
 mod commands;
 mod db;
+mod headless;
+mod host;
 mod logging;
+mod metrics;
+mod migrations;
+mod retention;
+mod runtime;
 pub mod scheduler;
+mod secrets;
+mod sql_pool;
 mod stock;
+mod watch_lock;
 
+#[cfg(feature = "desktop")]
 pub fn run() {
     app::run_app();
 }
+
+/// Whether the first argument selects the command-line interface.
+pub fn is_cli_command(arg: &str) -> bool {
+    cli::COMMANDS.contains(&arg)
+}
+
+/// Command-line interface: `visor lines list`, `visor process ...`, see `visor help`.
+pub fn run_cli(args: Vec<String>) -> i32 {
+    cli::run(args)
+}
+
+/// Service mode (`--headless`): no window, see `headless::run`.
+pub fn run_headless(args: Vec<String>) -> i32 {
+    headless::run(args)
+}
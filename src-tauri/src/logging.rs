@@ -1,3 +1,9 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[cfg(feature = "desktop")]
 pub fn setup(app: &tauri::App) -> tauri::Result<()> {
   if cfg!(debug_assertions) {
     app.handle().plugin(
@@ -9,3 +15,69 @@ pub fn setup(app: &tauri::App) -> tauri::Result<()> {
 
   Ok(())
 }
+
+/// Log sink for the headless service: one file per day in the `logs` folder
+/// (`visor_YYYYMMDD.log`), mirrored to stderr so the service manager's journal
+/// gets it too.
+struct FileLogger {
+  dir: PathBuf,
+  current: Mutex<Option<(String, File)>>,
+}
+
+impl FileLogger {
+  fn write_line(&self, line: &str) -> std::io::Result<()> {
+    let date = chrono::Local::now().format("%Y%m%d").to_string();
+    let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+    if current.as_ref().map(|(d, _)| d != &date).unwrap_or(true) {
+      let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(self.dir.join(format!("visor_{}.log", date)))?;
+      *current = Some((date, file));
+    }
+    if let Some((_, file)) = current.as_mut() {
+      writeln!(file, "{}", line)?;
+    }
+    Ok(())
+  }
+}
+
+impl log::Log for FileLogger {
+  fn enabled(&self, metadata: &log::Metadata) -> bool {
+    metadata.level() <= log::Level::Info
+  }
+
+  fn log(&self, record: &log::Record) {
+    if !self.enabled(record.metadata()) {
+      return;
+    }
+    let line = format!(
+      "{} [{}] {}: {}",
+      chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+      record.level(),
+      record.target(),
+      record.args()
+    );
+    eprintln!("{}", line);
+    if let Err(e) = self.write_line(&line) {
+      eprintln!("Écriture du journal impossible: {}", e);
+    }
+  }
+
+  fn flush(&self) {
+    if let Some((_, file)) = self.current.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+      let _ = file.flush();
+    }
+  }
+}
+
+pub fn setup_headless(dir: &Path) -> Result<(), String> {
+  fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+  let logger = Box::leak(Box::new(FileLogger {
+    dir: dir.to_path_buf(),
+    current: Mutex::new(None),
+  }));
+  log::set_logger(logger).map_err(|e| e.to_string())?;
+  log::set_max_level(log::LevelFilter::Info);
+  Ok(())
+}
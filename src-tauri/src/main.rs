@@ -1,6 +1,19 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
-#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+#![cfg_attr(all(not(debug_assertions), feature = "desktop"), windows_subsystem = "windows")]
 
 fn main() {
+  let args: Vec<String> = std::env::args().collect();
+  if args.get(1).is_some_and(|a| app_lib::is_cli_command(a)) {
+    std::process::exit(app_lib::run_cli(args));
+  }
+  if args.iter().any(|a| a == "--headless") {
+    std::process::exit(app_lib::run_headless(args));
+  }
+  #[cfg(feature = "desktop")]
   app_lib::run();
+  #[cfg(not(feature = "desktop"))]
+  {
+    eprintln!("Version sans interface: lancez avec --headless ou une commande (voir `visor help`)");
+    std::process::exit(2);
+  }
 }
@@ -0,0 +1,534 @@
+//! Prometheus metrics. Counters and histograms are recorded as work happens;
+//! line, queue and watcher gauges are read from the database and the
+//! registries at scrape time.
+
+use crate::api::http::{self, Response};
+use crate::commands::dashboard::{line_status, parse_processed_at};
+use crate::scheduler::{self, SchedulerState};
+use crate::stock::WatcherState;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+const DEFAULT_BIND_ADDRESS: &str = "127.0.0.1:9464";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+/// Upper bounds in seconds, from a pooled connection to a slow bulk insert.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: Duration) {
+        let secs = value.as_secs_f64();
+        for (bucket, le) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if secs <= le {
+                *bucket += 1;
+            }
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct LineCounters {
+    processed: u64,
+    failed: u64,
+    rows_inserted: u64,
+}
+
+#[derive(Default)]
+struct TaskRuns {
+    last_run: Option<i64>,
+    last_success: Option<i64>,
+}
+
+#[derive(Default)]
+struct Registry {
+    lines: BTreeMap<i64, LineCounters>,
+    sql_connect: Histogram,
+    /// By outcome: committed, rolled_back, timed_out, in_doubt or failed.
+    sql_insert: BTreeMap<&'static str, Histogram>,
+    tasks: BTreeMap<String, TaskRuns>,
+}
+
+static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+
+fn with_registry<T>(f: impl FnOnce(&mut Registry) -> T) -> T {
+    let registry = REGISTRY.get_or_init(Default::default);
+    let mut guard = registry.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut guard)
+}
+
+/// A file reached its final outcome (recorded in `production_data`).
+pub(crate) fn record_file(line_id: i64, success: bool, rows_inserted: usize) {
+    with_registry(|r| {
+        let line = r.lines.entry(line_id).or_default();
+        line.processed += 1;
+        if !success {
+            line.failed += 1;
+        }
+        line.rows_inserted += rows_inserted as u64;
+    });
+}
+
+/// Time to get a SQL Server connection from the pool (opening one if needed).
+pub(crate) fn observe_sql_connect(elapsed: Duration) {
+    with_registry(|r| r.sql_connect.observe(elapsed));
+}
+
+/// Time spent inserting one file's rows, from BEGIN to the transaction's outcome.
+pub(crate) fn observe_sql_insert(elapsed: Duration, status: &'static str) {
+    with_registry(|r| r.sql_insert.entry(status).or_default().observe(elapsed));
+}
+
+pub(crate) fn record_task_run(task_type: &str, success: bool) {
+    let now = Local::now().timestamp();
+    with_registry(|r| {
+        let task = r.tasks.entry(task_type.to_string()).or_default();
+        task.last_run = Some(now);
+        if success {
+            task.last_success = Some(now);
+        }
+    });
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, h: &Histogram) {
+    header(out, name, "histogram", help);
+    histogram_series(out, name, "", h);
+}
+
+/// One labelled series of a histogram; `labels` is empty or `key="value",...`.
+fn histogram_series(out: &mut String, name: &str, labels: &str, h: &Histogram) {
+    let (prefix, braced) = if labels.is_empty() {
+        (String::new(), String::new())
+    } else {
+        (format!("{},", labels), format!("{{{}}}", labels))
+    };
+    for (count, le) in h.buckets.iter().zip(LATENCY_BUCKETS) {
+        let _ = writeln!(out, "{}_bucket{{{}le=\"{}\"}} {}", name, prefix, le, count);
+    }
+    let _ = writeln!(out, "{}_bucket{{{}le=\"+Inf\"}} {}", name, prefix, h.count);
+    let _ = writeln!(out, "{}_sum{} {}", name, braced, h.sum);
+    let _ = writeln!(out, "{}_count{} {}", name, braced, h.count);
+}
+
+struct LineInfo {
+    labels: String,
+    active: bool,
+    interval_check: i64,
+    interval_alert: i64,
+    last_processed: Option<String>,
+}
+
+/// Everything, in the Prometheus text exposition format.
+pub(crate) async fn render(
+    pool: &Pool<Sqlite>,
+    watchers: &WatcherState,
+    scheduler_state: &SchedulerState,
+) -> Result<String, String> {
+    let rows = sqlx::query(
+        "SELECT l.id, l.name, l.active, l.interval_check, l.interval_alert, \
+                (SELECT MAX(processed_at) FROM production_data p WHERE p.line_id = l.id) AS last_processed \
+         FROM lines l ORDER BY l.id",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let lines: BTreeMap<i64, LineInfo> = rows
+        .iter()
+        .map(|row| {
+            let id: i64 = row.get("id");
+            let name: String = row.get("name");
+            let info = LineInfo {
+                labels: format!("line_id=\"{}\",line=\"{}\"", id, escape_label(&name)),
+                active: row.get("active"),
+                interval_check: row.get("interval_check"),
+                interval_alert: row.get("interval_alert"),
+                last_processed: row.get("last_processed"),
+            };
+            (id, info)
+        })
+        .collect();
+
+    let queued: HashMap<i64, i64> = sqlx::query(
+        "SELECT line_id, COUNT(1) AS pending FROM processing_queue WHERE status = 'PENDING' GROUP BY line_id",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .iter()
+    .map(|row| (row.get("line_id"), row.get("pending")))
+    .collect();
+
+    let watching = watchers.status();
+    let running_tasks = scheduler::running_tasks(scheduler_state)?;
+    let now = Local::now();
+    let mut out = String::new();
+
+    with_registry(|r| {
+        let counter =
+            |out: &mut String, name: &str, help: &str, value: fn(&LineCounters) -> u64| {
+                header(out, name, "counter", help);
+                for (id, line) in &r.lines {
+                    if let Some(info) = lines.get(id) {
+                        let _ = writeln!(out, "{}{{{}}} {}", name, info.labels, value(line));
+                    }
+                }
+            };
+        counter(
+            &mut out,
+            "visor_files_processed_total",
+            "Files processed to completion since startup, failed ones included.",
+            |l| l.processed,
+        );
+        counter(
+            &mut out,
+            "visor_files_failed_total",
+            "Files that ended in error since startup.",
+            |l| l.failed,
+        );
+        counter(
+            &mut out,
+            "visor_rows_inserted_total",
+            "Rows inserted into SQL Server since startup.",
+            |l| l.rows_inserted,
+        );
+        histogram(
+            &mut out,
+            "visor_sql_connect_duration_seconds",
+            "Time to obtain a SQL Server connection.",
+            &r.sql_connect,
+        );
+        header(
+            &mut out,
+            "visor_sql_insert_duration_seconds",
+            "histogram",
+            "Time spent inserting one file's rows in SQL Server, by transaction outcome.",
+        );
+        for (status, h) in &r.sql_insert {
+            histogram_series(
+                &mut out,
+                "visor_sql_insert_duration_seconds",
+                &format!("status=\"{}\"", status),
+                h,
+            );
+        }
+
+        header(
+            &mut out,
+            "visor_scheduler_task_last_run_timestamp_seconds",
+            "gauge",
+            "Unix time of the task's last run.",
+        );
+        for (task, runs) in &r.tasks {
+            if let Some(ts) = runs.last_run {
+                let _ = writeln!(
+                    out,
+                    "visor_scheduler_task_last_run_timestamp_seconds{{task=\"{}\"}} {}",
+                    task, ts
+                );
+            }
+        }
+        header(
+            &mut out,
+            "visor_scheduler_task_last_success_timestamp_seconds",
+            "gauge",
+            "Unix time of the task's last successful run.",
+        );
+        for (task, runs) in &r.tasks {
+            if let Some(ts) = runs.last_success {
+                let _ = writeln!(
+                    out,
+                    "visor_scheduler_task_last_success_timestamp_seconds{{task=\"{}\"}} {}",
+                    task, ts
+                );
+            }
+        }
+    });
+
+    header(
+        &mut out,
+        "visor_scheduler_task_running",
+        "gauge",
+        "1 while the task is scheduled.",
+    );
+    for task in scheduler::TASK_TYPES {
+        let running = running_tasks.iter().any(|t| t == task);
+        let _ = writeln!(
+            out,
+            "visor_scheduler_task_running{{task=\"{}\"}} {}",
+            task, running as u8
+        );
+    }
+
+    let gauge = |out: &mut String,
+                 name: &str,
+                 help: &str,
+                 value: &dyn Fn(i64, &LineInfo) -> Option<String>| {
+        header(out, name, "gauge", help);
+        for (id, info) in &lines {
+            if let Some(value) = value(*id, info) {
+                let _ = writeln!(out, "{}{{{}}} {}", name, info.labels, value);
+            }
+        }
+    };
+    gauge(
+        &mut out,
+        "visor_line_active",
+        "1 when the line is enabled.",
+        &|_, l| Some((l.active as u8).to_string()),
+    );
+    gauge(
+        &mut out,
+        "visor_watcher_up",
+        "1 while the line's folder watcher is running.",
+        &|id, _| Some((watching.get(&id).copied().unwrap_or(false) as u8).to_string()),
+    );
+    gauge(
+        &mut out,
+        "visor_queue_depth",
+        "Files waiting in the retry queue.",
+        &|id, _| Some(queued.get(&id).copied().unwrap_or(0).to_string()),
+    );
+    gauge(
+        &mut out,
+        "visor_line_last_file_age_seconds",
+        "Seconds since the line's last processed file.",
+        &|_, l| {
+            l.last_processed
+                .as_deref()
+                .and_then(parse_processed_at)
+                .map(|dt| (now - dt).num_seconds().max(0).to_string())
+        },
+    );
+    gauge(
+        &mut out,
+        "visor_line_interval_check_seconds",
+        "Age up to which the line is MARCHE.",
+        &|_, l| Some((l.interval_check * 60).to_string()),
+    );
+    gauge(
+        &mut out,
+        "visor_line_interval_alert_seconds",
+        "Age up to which the line is ALERTE, ARRET beyond.",
+        &|_, l| Some((l.interval_alert * 60).to_string()),
+    );
+
+    header(
+        &mut out,
+        "visor_line_status",
+        "gauge",
+        "Dashboard status of the line: 1 for the current one.",
+    );
+    for info in lines.values() {
+        let current = line_status(
+            info.active,
+            info.last_processed.as_deref(),
+            info.interval_check,
+            info.interval_alert,
+        );
+        for status in ["MARCHE", "ALERTE", "ARRET"] {
+            let _ = writeln!(
+                out,
+                "visor_line_status{{{},status=\"{}\"}} {}",
+                info.labels,
+                status,
+                (status == current) as u8
+            );
+        }
+    }
+
+    Ok(out)
+}
+
+/// Metrics endpoint settings, stored in the `config` table. There is no
+/// token: scrapers rarely send one, so keep the default loopback address.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsSettings {
+    pub enabled: bool,
+    pub bind_address: String,
+}
+
+impl MetricsSettings {
+    pub(crate) async fn load(pool: &Pool<Sqlite>) -> Self {
+        let get = |key: &'static str| async move {
+            sqlx::query_scalar::<_, String>("SELECT value FROM config WHERE key = ?")
+                .bind(key)
+                .fetch_optional(pool)
+                .await
+                .ok()
+                .flatten()
+        };
+        Self {
+            enabled: get("metrics_enabled")
+                .await
+                .map(|v| v == "1")
+                .unwrap_or(false),
+            bind_address: get("metrics_bind_address")
+                .await
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_BIND_ADDRESS.to_string()),
+        }
+    }
+
+    pub(crate) async fn save(&self, pool: &Pool<Sqlite>) -> Result<(), String> {
+        self.bind_address
+            .trim()
+            .parse::<SocketAddr>()
+            .map_err(|_| {
+                format!(
+                    "Adresse d'écoute invalide: {} (ex. {})",
+                    self.bind_address, DEFAULT_BIND_ADDRESS
+                )
+            })?;
+        let values = [
+            (
+                "metrics_enabled",
+                if self.enabled { "1" } else { "0" }.to_string(),
+            ),
+            ("metrics_bind_address", self.bind_address.trim().to_string()),
+        ];
+        for (key, value) in values {
+            sqlx::query("INSERT OR REPLACE INTO config (key, value) VALUES (?, ?)")
+                .bind(key)
+                .bind(value)
+                .execute(pool)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct Scrape {
+    pool: Pool<Sqlite>,
+    watchers: WatcherState,
+    scheduler: SchedulerState,
+}
+
+/// Serves `GET /metrics` on its own port.
+pub struct MetricsServer {
+    scrape: Scrape,
+    running: Mutex<Option<crate::runtime::JoinHandle<()>>>,
+}
+
+impl MetricsServer {
+    pub(crate) fn new(
+        pool: Pool<Sqlite>,
+        watchers: WatcherState,
+        scheduler: SchedulerState,
+    ) -> Self {
+        Self {
+            scrape: Scrape {
+                pool,
+                watchers,
+                scheduler,
+            },
+            running: Mutex::new(None),
+        }
+    }
+
+    /// Apply the saved settings: (re)bind when enabled, stop otherwise.
+    pub(crate) async fn restart(&self) -> Result<(), String> {
+        self.stop().await;
+
+        let settings = MetricsSettings::load(&self.scrape.pool).await;
+        if !settings.enabled {
+            return Ok(());
+        }
+        let listener = tokio::net::TcpListener::bind(&settings.bind_address)
+            .await
+            .map_err(|e| {
+                format!(
+                    "Métriques: écoute sur {} impossible: {}",
+                    settings.bind_address, e
+                )
+            })?;
+
+        let scrape = Arc::new(self.scrape.clone());
+        let handle = crate::runtime::spawn(http::serve(
+            listener,
+            0,
+            |_| None,
+            move |request| {
+                let scrape = scrape.clone();
+                async move {
+                    if request.method != "GET" || request.path != "/metrics" {
+                        return Response::error(404, "Route inconnue (voir /metrics)");
+                    }
+                    match render(&scrape.pool, &scrape.watchers, &scrape.scheduler).await {
+                        Ok(body) => Response::text(200, CONTENT_TYPE, body),
+                        Err(e) => Response::error(500, &e),
+                    }
+                }
+            },
+        ));
+        if let Ok(mut running) = self.running.lock() {
+            *running = Some(handle);
+        }
+        log::info!(
+            "Métriques Prometheus sur http://{}/metrics",
+            settings.bind_address
+        );
+        Ok(())
+    }
+
+    /// Returns once the listener is closed, so the address can be bound again.
+    pub(crate) async fn stop(&self) {
+        let handle = self.running.lock().ok().and_then(|mut r| r.take());
+        if let Some(handle) = handle {
+            handle.abort();
+            let _ = handle.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labelled_series_keep_the_bucket_label_last() {
+        let mut h = Histogram::default();
+        h.observe(Duration::from_millis(40));
+        let mut out = String::new();
+        histogram_series(&mut out, "m", "status=\"committed\"", &h);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "m_bucket{status=\"committed\",le=\"0.005\"} 0");
+        assert_eq!(lines[3], "m_bucket{status=\"committed\",le=\"0.05\"} 1");
+        assert_eq!(lines[12], "m_bucket{status=\"committed\",le=\"+Inf\"} 1");
+        assert_eq!(lines[13], "m_sum{status=\"committed\"} 0.04");
+        assert_eq!(lines[14], "m_count{status=\"committed\"} 1");
+
+        let mut out = String::new();
+        histogram_series(&mut out, "m", "", &h);
+        assert!(out.starts_with("m_bucket{le=\"0.005\"} 0\n"));
+        assert!(out.ends_with("m_sum 0.04\nm_count 1\n"));
+    }
+}
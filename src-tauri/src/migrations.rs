@@ -0,0 +1,550 @@
+use sqlx::{Pool, Sqlite, SqliteConnection};
+
+/// One schema change, applied in the same transaction as the rest of its migration.
+enum Step {
+    Sql(&'static str),
+    /// `ALTER TABLE <table> ADD COLUMN <definition>`, skipped when the column
+    /// already exists (databases upgraded before migrations were versioned).
+    AddColumn(&'static str, &'static str),
+}
+
+struct Migration {
+    version: i64,
+    description: &'static str,
+    steps: &'static [Step],
+}
+
+use Step::{AddColumn, Sql};
+
+/// Version 1: the schema as it stood before migrations were versioned.
+const INITIAL_SCHEMA: Migration = Migration {
+    version: 1,
+    description: "schéma initial",
+    steps: &[
+        Sql("CREATE TABLE IF NOT EXISTS lines (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            path TEXT NOT NULL,
+            prefix TEXT NOT NULL,
+            interval_check INTEGER DEFAULT 60,
+            interval_alert INTEGER DEFAULT 120,
+            archived_path TEXT,
+            rejected_path TEXT,
+            active BOOLEAN DEFAULT 1,
+            site TEXT,
+            unite TEXT,
+            flag_dec TEXT,
+            code_ligne TEXT,
+            log_path TEXT,
+            file_format TEXT DEFAULT 'ATEIS',
+            insert_batch_size INTEGER DEFAULT 1,
+            duplicate_policy TEXT DEFAULT 'SKIP',
+            dedup_key_field TEXT,
+            csv_delimiter TEXT DEFAULT ';',
+            csv_quote TEXT DEFAULT '\"',
+            csv_header_rows INTEGER DEFAULT 0,
+            csv_footer_rows INTEGER DEFAULT 0,
+            csv_comment_prefix TEXT,
+            file_encoding TEXT DEFAULT 'AUTO',
+            input_mode TEXT DEFAULT 'CSV',
+            fixed_trim TEXT DEFAULT 'BOTH',
+            fixed_pad_char TEXT DEFAULT ' ',
+            invalid_rows_policy TEXT DEFAULT 'REJECT_FILE',
+            sql_profile_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )"),
+        AddColumn("lines", "site TEXT"),
+        AddColumn("lines", "unite TEXT"),
+        AddColumn("lines", "flag_dec TEXT"),
+        AddColumn("lines", "code_ligne TEXT"),
+        AddColumn("lines", "log_path TEXT"),
+        AddColumn("lines", "file_format TEXT DEFAULT 'ATEIS'"),
+        AddColumn("lines", "total_traites INTEGER DEFAULT 0"),
+        AddColumn("lines", "total_erreurs INTEGER DEFAULT 0"),
+        AddColumn("lines", "last_file_time TEXT"),
+        AddColumn("lines", "etat_actuel TEXT DEFAULT 'ARRET'"),
+        AddColumn("lines", "rejected_path TEXT"),
+        AddColumn("lines", "insert_batch_size INTEGER DEFAULT 1"),
+        AddColumn("lines", "duplicate_policy TEXT DEFAULT 'SKIP'"),
+        AddColumn("lines", "dedup_key_field TEXT"),
+        AddColumn("lines", "csv_delimiter TEXT DEFAULT ';'"),
+        AddColumn("lines", "csv_quote TEXT DEFAULT '\"'"),
+        AddColumn("lines", "csv_header_rows INTEGER DEFAULT 0"),
+        AddColumn("lines", "csv_footer_rows INTEGER DEFAULT 0"),
+        AddColumn("lines", "csv_comment_prefix TEXT"),
+        AddColumn("lines", "file_encoding TEXT DEFAULT 'AUTO'"),
+        AddColumn("lines", "input_mode TEXT DEFAULT 'CSV'"),
+        AddColumn("lines", "fixed_trim TEXT DEFAULT 'BOTH'"),
+        AddColumn("lines", "fixed_pad_char TEXT DEFAULT ' '"),
+        AddColumn("lines", "invalid_rows_policy TEXT DEFAULT 'REJECT_FILE'"),
+        AddColumn("lines", "sql_profile_id INTEGER"),
+        Sql("CREATE TABLE IF NOT EXISTS mappings (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            line_id INTEGER NOT NULL,
+            sort_order INTEGER DEFAULT 0,
+            sql_field TEXT NOT NULL,
+            file_column TEXT,
+            parameter TEXT,
+            transformation TEXT,
+            description TEXT,
+            enabled BOOLEAN DEFAULT 1,
+            validation TEXT,
+            date_format TEXT,
+            sql_type TEXT,
+            FOREIGN KEY(line_id) REFERENCES lines(id) ON DELETE CASCADE
+        )"),
+        AddColumn("mappings", "enabled BOOLEAN DEFAULT 1"),
+        AddColumn("mappings", "validation TEXT"),
+        AddColumn("mappings", "date_format TEXT"),
+        AddColumn("mappings", "sql_type TEXT"),
+        Sql("CREATE TABLE IF NOT EXISTS model_mappings (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            format_name TEXT NOT NULL,
+            sort_order INTEGER DEFAULT 0,
+            sql_field TEXT NOT NULL,
+            file_column TEXT,
+            parameter TEXT,
+            transformation TEXT,
+            description TEXT,
+            validation TEXT,
+            date_format TEXT,
+            sql_type TEXT,
+            UNIQUE(format_name, sort_order)
+        )"),
+        AddColumn("model_mappings", "validation TEXT"),
+        AddColumn("model_mappings", "date_format TEXT"),
+        AddColumn("model_mappings", "sql_type TEXT"),
+        Sql("CREATE TABLE IF NOT EXISTS production_data (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            line_id INTEGER,
+            filename TEXT,
+            processed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            status TEXT NOT NULL,
+            message TEXT,
+            FOREIGN KEY(line_id) REFERENCES lines(id)
+        )"),
+        // Content hashes (and optional row keys such as SSCC) of files already inserted, per line
+        Sql("CREATE TABLE IF NOT EXISTS ingested_files (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            line_id INTEGER NOT NULL,
+            content_hash TEXT NOT NULL,
+            filename TEXT,
+            ingested_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(line_id, content_hash),
+            FOREIGN KEY(line_id) REFERENCES lines(id) ON DELETE CASCADE
+        )"),
+        Sql("CREATE TABLE IF NOT EXISTS ingested_keys (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            line_id INTEGER NOT NULL,
+            key_value TEXT NOT NULL,
+            filename TEXT,
+            ingested_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(line_id, key_value),
+            FOREIGN KEY(line_id) REFERENCES lines(id) ON DELETE CASCADE
+        )"),
+        // Files deferred after a SQL Server connection error, retried with backoff
+        Sql("CREATE TABLE IF NOT EXISTS processing_queue (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            line_id INTEGER NOT NULL,
+            filename TEXT NOT NULL,
+            file_path TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            next_attempt_at TEXT NOT NULL,
+            last_error TEXT,
+            status TEXT NOT NULL DEFAULT 'PENDING',
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(line_id) REFERENCES lines(id) ON DELETE CASCADE
+        )"),
+        // Generic key-value store for app configuration
+        Sql("CREATE TABLE IF NOT EXISTS config (
+            key TEXT PRIMARY KEY,
+            value TEXT
+        )"),
+        // Logs table for Journaux page
+        Sql("CREATE TABLE IF NOT EXISTS logs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            line_id INTEGER,
+            level TEXT NOT NULL,
+            source TEXT,
+            message TEXT NOT NULL,
+            details TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(line_id) REFERENCES lines(id) ON DELETE SET NULL
+        )"),
+        Sql("CREATE TABLE IF NOT EXISTS sql_server_config (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            server TEXT,
+            database TEXT,
+            username TEXT,
+            password TEXT,
+            enabled BOOLEAN DEFAULT 0
+        )"),
+        Sql("INSERT OR IGNORE INTO sql_server_config (id, server, database, username, password, enabled)
+             VALUES (1, '', '', '', '', 0)"),
+        // Named SQL Server connection profiles; profile 1 is the default one and
+        // takes over the single-row sql_server_config settings.
+        Sql("CREATE TABLE IF NOT EXISTS sql_server_profiles (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT UNIQUE NOT NULL,
+            server TEXT,
+            database TEXT,
+            username TEXT,
+            password TEXT,
+            enabled BOOLEAN DEFAULT 0,
+            port INTEGER DEFAULT 1433,
+            instance_name TEXT,
+            encryption TEXT DEFAULT 'REQUIRED',
+            trust_cert BOOLEAN DEFAULT 1,
+            ca_cert_path TEXT,
+            connect_timeout_secs INTEGER DEFAULT 15,
+            query_timeout_secs INTEGER DEFAULT 300,
+            app_name TEXT DEFAULT 'Visor',
+            pool_max_size INTEGER DEFAULT 4,
+            pool_idle_timeout_secs INTEGER DEFAULT 300
+        )"),
+        AddColumn("sql_server_profiles", "port INTEGER DEFAULT 1433"),
+        AddColumn("sql_server_profiles", "instance_name TEXT"),
+        AddColumn("sql_server_profiles", "encryption TEXT DEFAULT 'REQUIRED'"),
+        AddColumn("sql_server_profiles", "trust_cert BOOLEAN DEFAULT 1"),
+        AddColumn("sql_server_profiles", "ca_cert_path TEXT"),
+        AddColumn("sql_server_profiles", "connect_timeout_secs INTEGER DEFAULT 15"),
+        AddColumn("sql_server_profiles", "query_timeout_secs INTEGER DEFAULT 300"),
+        AddColumn("sql_server_profiles", "app_name TEXT DEFAULT 'Visor'"),
+        AddColumn("sql_server_profiles", "pool_max_size INTEGER DEFAULT 4"),
+        AddColumn("sql_server_profiles", "pool_idle_timeout_secs INTEGER DEFAULT 300"),
+        Sql("INSERT OR IGNORE INTO sql_server_profiles (id, name, server, database, username, password, enabled)
+             SELECT 1, 'Défaut', server, database, username, password, enabled FROM sql_server_config WHERE id = 1"),
+        Sql("CREATE TABLE IF NOT EXISTS sql_queries (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            format_name TEXT UNIQUE NOT NULL,
+            query_template TEXT NOT NULL
+        )"),
+        // Older databases could hold several templates per format: keep the first one.
+        Sql("DELETE FROM sql_queries
+             WHERE id NOT IN (SELECT MIN(id) FROM sql_queries GROUP BY format_name)"),
+        Sql("CREATE UNIQUE INDEX IF NOT EXISTS idx_sql_queries_format_name ON sql_queries(format_name)"),
+        // HFSQL connection settings table (ODBC)
+        Sql("CREATE TABLE IF NOT EXISTS hfsql_config (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            dsn TEXT,
+            username TEXT,
+            password TEXT,
+            log_path TEXT
+        )"),
+        AddColumn("hfsql_config", "log_path TEXT"),
+        Sql("INSERT OR IGNORE INTO hfsql_config (id, dsn, username, password)
+             VALUES (1, 'HFSQL', 'Admin', '')"),
+    ],
+};
+
+/// Schema history of `visor.db`. Append new migrations at the end with the
+/// next version number; never edit one that has shipped.
+const MIGRATIONS: &[Migration] = &[
+    INITIAL_SCHEMA,
+    Migration {
+        version: 2,
+        description: "index historique et journaux",
+        steps: &[
+            Sql("CREATE INDEX IF NOT EXISTS idx_production_data_line_processed ON production_data(line_id, processed_at)"),
+            Sql("CREATE INDEX IF NOT EXISTS idx_logs_line_created_level ON logs(line_id, created_at, level)"),
+        ],
+    },
+    Migration {
+        version: 3,
+        description: "alertes",
+        steps: &[
+            Sql("CREATE TABLE IF NOT EXISTS alerts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                line_id INTEGER,
+                kind TEXT NOT NULL,
+                severity TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'OPEN',
+                message TEXT NOT NULL,
+                occurrences INTEGER NOT NULL DEFAULT 1,
+                first_seen TEXT NOT NULL,
+                last_seen TEXT NOT NULL,
+                last_notified TEXT,
+                acknowledged_at TEXT,
+                acknowledged_by TEXT,
+                resolved_at TEXT
+            )"),
+            Sql("CREATE INDEX IF NOT EXISTS idx_alerts_status_line ON alerts(status, line_id, kind)"),
+            // At most one unresolved alert per line and condition, even with two monitors running.
+            Sql("CREATE UNIQUE INDEX IF NOT EXISTS idx_alerts_unresolved ON alerts(line_id, kind) WHERE status != 'RESOLVED'"),
+        ],
+    },
+];
+
+async fn has_column(
+    conn: &mut SqliteConnection,
+    table: &str,
+    column: &str,
+) -> Result<bool, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table)
+        .bind(column)
+        .fetch_one(&mut *conn)
+        .await?;
+    Ok(count > 0)
+}
+
+async fn apply_step(conn: &mut SqliteConnection, step: &Step) -> Result<(), sqlx::Error> {
+    match step {
+        Sql(sql) => {
+            sqlx::query(sql).execute(&mut *conn).await?;
+        }
+        AddColumn(table, definition) => {
+            let column = definition.split_whitespace().next().unwrap_or_default();
+            if !has_column(conn, table, column).await? {
+                sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {}", table, definition))
+                    .execute(&mut *conn)
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Bring the schema up to date. Each pending migration runs in its own
+/// transaction and is recorded in `schema_version`; the first failure stops
+/// startup with the migration that failed.
+pub(crate) async fn run(pool: &Pool<Sqlite>) -> Result<(), String> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let current: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(version), 0) FROM schema_version")
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let latest = MIGRATIONS.last().map(|m| m.version).unwrap_or(0);
+    if current > latest {
+        return Err(format!(
+            "Base de données créée par une version plus récente de l'application (schéma {} > {})",
+            current, latest
+        ));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let failed = |e: sqlx::Error| {
+            format!(
+                "Migration {} ({}) échouée: {}",
+                migration.version, migration.description, e
+            )
+        };
+
+        let mut tx = pool.begin().await.map_err(failed)?;
+        for step in migration.steps {
+            apply_step(&mut tx, step).await.map_err(failed)?;
+        }
+        sqlx::query("INSERT INTO schema_version (version, description) VALUES (?, ?)")
+            .bind(migration.version)
+            .bind(migration.description)
+            .execute(&mut *tx)
+            .await
+            .map_err(failed)?;
+        tx.commit().await.map_err(failed)?;
+
+        log::info!(
+            "Migration {} appliquée: {}",
+            migration.version,
+            migration.description
+        );
+    }
+
+    Ok(())
+}
+
+/// A migrated in-memory database. One connection: each in-memory connection
+/// would otherwise get a database of its own.
+#[cfg(test)]
+pub(crate) async fn memory_pool() -> Pool<Sqlite> {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    run(&pool).await.unwrap();
+    pool
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tables as created by releases that predate versioned migrations.
+    const UNVERSIONED_SCHEMA: &[&str] = &[
+        "CREATE TABLE lines (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            path TEXT NOT NULL,
+            prefix TEXT NOT NULL,
+            interval_check INTEGER DEFAULT 60,
+            interval_alert INTEGER DEFAULT 120,
+            archived_path TEXT,
+            rejected_path TEXT,
+            active BOOLEAN DEFAULT 1,
+            site TEXT,
+            unite TEXT,
+            flag_dec TEXT,
+            code_ligne TEXT,
+            log_path TEXT,
+            file_format TEXT DEFAULT 'ATEIS',
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            total_traites INTEGER DEFAULT 0,
+            total_erreurs INTEGER DEFAULT 0,
+            last_file_time TEXT,
+            etat_actuel TEXT DEFAULT 'ARRET'
+        )",
+        "CREATE TABLE mappings (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            line_id INTEGER NOT NULL,
+            sort_order INTEGER DEFAULT 0,
+            sql_field TEXT NOT NULL,
+            file_column TEXT,
+            parameter TEXT,
+            transformation TEXT,
+            description TEXT,
+            FOREIGN KEY(line_id) REFERENCES lines(id) ON DELETE CASCADE
+        )",
+        "CREATE TABLE model_mappings (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            format_name TEXT NOT NULL,
+            sort_order INTEGER DEFAULT 0,
+            sql_field TEXT NOT NULL,
+            file_column TEXT,
+            parameter TEXT,
+            transformation TEXT,
+            description TEXT,
+            UNIQUE(format_name, sort_order)
+        )",
+        "CREATE TABLE config (key TEXT PRIMARY KEY, value TEXT)",
+        "CREATE TABLE sql_server_config (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            server TEXT,
+            database TEXT,
+            username TEXT,
+            password TEXT,
+            enabled BOOLEAN DEFAULT 0
+        )",
+        "CREATE TABLE sql_queries (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            format_name TEXT NOT NULL,
+            query_template TEXT NOT NULL
+        )",
+        "CREATE TABLE hfsql_config (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            dsn TEXT,
+            username TEXT,
+            password TEXT
+        )",
+        "INSERT INTO lines (name, path, prefix, total_traites) VALUES ('L1', '/in', 'ATEIS', 42)",
+        "INSERT INTO mappings (line_id, sql_field, file_column) VALUES (1, 'LOT_0', '7')",
+        "INSERT INTO sql_server_config VALUES (1, 'srv', 'x3', 'visor', 'secret', 1)",
+        "INSERT INTO sql_queries (format_name, query_template) VALUES ('ATEIS', 'first')",
+        "INSERT INTO sql_queries (format_name, query_template) VALUES ('ATEIS', 'second')",
+    ];
+
+    async fn unversioned_pool() -> Pool<Sqlite> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        for sql in UNVERSIONED_SCHEMA {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    async fn versions(pool: &Pool<Sqlite>) -> Vec<i64> {
+        sqlx::query_scalar("SELECT version FROM schema_version ORDER BY version")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn unversioned_database_is_brought_up_to_date() {
+        let pool = unversioned_pool().await;
+        run(&pool).await.unwrap();
+
+        let latest = MIGRATIONS.last().unwrap().version;
+        assert_eq!(versions(&pool).await, (1..=latest).collect::<Vec<_>>());
+
+        let line: (String, i64, String, String) = sqlx::query_as(
+            "SELECT name, total_traites, duplicate_policy, csv_delimiter FROM lines WHERE id = 1",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(line, ("L1".into(), 42, "SKIP".into(), ";".into()));
+
+        let enabled: Option<bool> = sqlx::query_scalar("SELECT enabled FROM mappings")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(enabled, Some(true));
+
+        let profile: (String, String, bool, i64) = sqlx::query_as(
+            "SELECT name, server, enabled, port FROM sql_server_profiles WHERE id = 1",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(profile, ("Défaut".into(), "srv".into(), true, 1433));
+
+        let templates: Vec<String> = sqlx::query_scalar("SELECT query_template FROM sql_queries")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(templates, vec!["first".to_string()]);
+
+        for table in ["processing_queue", "ingested_keys", "alerts"] {
+            let exists: i64 =
+                sqlx::query_scalar("SELECT COUNT(1) FROM sqlite_master WHERE name = ?")
+                    .bind(table)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap();
+            assert_eq!(exists, 1, "{}", table);
+        }
+    }
+
+    #[tokio::test]
+    async fn applied_migrations_are_not_run_again() {
+        let pool = memory_pool().await;
+        sqlx::query("DELETE FROM sql_server_profiles")
+            .execute(&pool)
+            .await
+            .unwrap();
+        run(&pool).await.unwrap();
+
+        let latest = MIGRATIONS.last().unwrap().version;
+        assert_eq!(versions(&pool).await, (1..=latest).collect::<Vec<_>>());
+        let profiles: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM sql_server_profiles")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(profiles, 0);
+    }
+
+    #[tokio::test]
+    async fn newer_schema_is_refused() {
+        let pool = memory_pool().await;
+        sqlx::query("INSERT INTO schema_version (version, description) VALUES (99, 'futur')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let err = run(&pool).await.unwrap_err();
+        assert!(err.contains("99"), "{}", err);
+    }
+}
@@ -0,0 +1,467 @@
+use chrono::{Duration as ChronoDuration, Local};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Pool, Row, Sqlite};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const BATCH_SIZE: i64 = 5000;
+/// How often the background job enforces the policy.
+const RUN_INTERVAL: Duration = Duration::from_secs(6 * 3600);
+/// Let startup (watchers, first refreshes) settle before the first run.
+const FIRST_RUN_DELAY: Duration = Duration::from_secs(120);
+pub(crate) const ARCHIVE_DIR_NAME: &str = "archives";
+
+/// How long `production_data` and `logs` rows are kept, stored in the
+/// `config` table. `0` disables a limit; nothing is pruned until a limit is set,
+/// and pruned rows are archived unless archiving is turned off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub production_max_age_days: i64,
+    pub production_max_rows: i64,
+    pub logs_max_age_days: i64,
+    pub logs_max_rows: i64,
+    /// Write pruned rows to a gzip-compressed JSON Lines file before deleting them.
+    pub archive: bool,
+    /// Defaults to the `archives` folder next to the database.
+    pub archive_dir: Option<String>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            production_max_age_days: 0,
+            production_max_rows: 0,
+            logs_max_age_days: 0,
+            logs_max_rows: 0,
+            archive: true,
+            archive_dir: None,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    pub(crate) async fn load(pool: &Pool<Sqlite>) -> Self {
+        let defaults = Self::default();
+        let get = |key: &'static str| async move {
+            sqlx::query_scalar::<_, String>("SELECT value FROM config WHERE key = ?")
+                .bind(key)
+                .fetch_optional(pool)
+                .await
+                .ok()
+                .flatten()
+        };
+        let get_int = |key: &'static str, default: i64| async move {
+            get(key)
+                .await
+                .and_then(|v| v.trim().parse::<i64>().ok())
+                .unwrap_or(default)
+                .max(0)
+        };
+
+        Self {
+            production_max_age_days: get_int(
+                "retention_production_max_age_days",
+                defaults.production_max_age_days,
+            )
+            .await,
+            production_max_rows: get_int(
+                "retention_production_max_rows",
+                defaults.production_max_rows,
+            )
+            .await,
+            logs_max_age_days: get_int("retention_logs_max_age_days", defaults.logs_max_age_days)
+                .await,
+            logs_max_rows: get_int("retention_logs_max_rows", defaults.logs_max_rows).await,
+            archive: get("retention_archive")
+                .await
+                .map(|v| v == "1")
+                .unwrap_or(defaults.archive),
+            archive_dir: get("retention_archive_dir")
+                .await
+                .filter(|v| !v.trim().is_empty()),
+        }
+    }
+
+    pub(crate) async fn save(&self, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        for (key, value) in [
+            (
+                "retention_production_max_age_days",
+                self.production_max_age_days.to_string(),
+            ),
+            (
+                "retention_production_max_rows",
+                self.production_max_rows.to_string(),
+            ),
+            (
+                "retention_logs_max_age_days",
+                self.logs_max_age_days.to_string(),
+            ),
+            ("retention_logs_max_rows", self.logs_max_rows.to_string()),
+            (
+                "retention_archive",
+                if self.archive { "1" } else { "0" }.to_string(),
+            ),
+            (
+                "retention_archive_dir",
+                self.archive_dir.clone().unwrap_or_default(),
+            ),
+        ] {
+            sqlx::query("INSERT OR REPLACE INTO config (key, value) VALUES (?, ?)")
+                .bind(key)
+                .bind(value)
+                .execute(pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn is_active(&self) -> bool {
+        self.production_max_age_days > 0
+            || self.production_max_rows > 0
+            || self.logs_max_age_days > 0
+            || self.logs_max_rows > 0
+    }
+
+    fn describe(&self) -> String {
+        let limit = |days: i64, rows: i64| match (days, rows) {
+            (0, 0) => "conservé(s) sans limite".to_string(),
+            (d, 0) => format!("plus de {} jour(s) supprimé(s)", d),
+            (0, r) => format!("au-delà de {} ligne(s) supprimé(s)", r),
+            (d, r) => format!(
+                "plus de {} jour(s) ou au-delà de {} ligne(s) supprimé(s)",
+                d, r
+            ),
+        };
+        format!(
+            "historique {}, journaux {}, {}",
+            limit(self.production_max_age_days, self.production_max_rows),
+            limit(self.logs_max_age_days, self.logs_max_rows),
+            if self.archive {
+                "avec archivage"
+            } else {
+                "SANS archivage"
+            }
+        )
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RetentionReport {
+    pub production_deleted: i64,
+    pub logs_deleted: i64,
+    pub archive_files: Vec<String>,
+}
+
+/// A table subject to retention; timestamps are `YYYY-MM-DD HH:MM:SS` text.
+struct Table {
+    name: &'static str,
+    time_column: &'static str,
+    int_columns: &'static [&'static str],
+    text_columns: &'static [&'static str],
+}
+
+const PRODUCTION_DATA: Table = Table {
+    name: "production_data",
+    time_column: "processed_at",
+    int_columns: &["id", "line_id"],
+    text_columns: &["filename", "processed_at", "status", "message"],
+};
+
+const LOGS: Table = Table {
+    name: "logs",
+    time_column: "created_at",
+    int_columns: &["id", "line_id"],
+    text_columns: &["level", "source", "message", "details", "created_at"],
+};
+
+impl Table {
+    fn to_json(&self, row: &SqliteRow) -> Value {
+        let mut object = Map::new();
+        for col in self.int_columns {
+            let v: Option<i64> = row.try_get(*col).ok().flatten();
+            object.insert(col.to_string(), v.into());
+        }
+        for col in self.text_columns {
+            let v: Option<String> = row.try_get(*col).ok().flatten();
+            object.insert(col.to_string(), v.into());
+        }
+        Value::Object(object)
+    }
+}
+
+/// Rows a table's limits prune: older than `cutoff`, or with an id up to `boundary`.
+struct PruneCondition {
+    sql: String,
+    cutoff: Option<String>,
+    boundary: Option<i64>,
+}
+
+impl PruneCondition {
+    fn bind<'q>(
+        &self,
+        query: sqlx::query::Query<'q, Sqlite, sqlx::sqlite::SqliteArguments<'q>>,
+    ) -> sqlx::query::Query<'q, Sqlite, sqlx::sqlite::SqliteArguments<'q>> {
+        let query = match &self.cutoff {
+            Some(cutoff) => query.bind(cutoff.clone()),
+            None => query,
+        };
+        match self.boundary {
+            Some(id) => query.bind(id),
+            None => query,
+        }
+    }
+}
+
+/// `None` when the table has no limit or nothing to prune.
+async fn prune_condition(
+    pool: &Pool<Sqlite>,
+    table: &Table,
+    max_age_days: i64,
+    max_rows: i64,
+) -> Result<Option<PruneCondition>, String> {
+    let mut clauses = Vec::new();
+    let mut cutoff = None;
+    let mut boundary = None;
+
+    if max_age_days > 0 {
+        let limit = Local::now() - ChronoDuration::days(max_age_days);
+        clauses.push(format!("{} < ?", table.time_column));
+        cutoff = Some(limit.format("%Y-%m-%d %H:%M:%S").to_string());
+    }
+    if max_rows > 0 {
+        // Newest rows have the highest ids: everything up to the first one past the limit goes.
+        boundary = sqlx::query_scalar(&format!(
+            "SELECT id FROM {} ORDER BY id DESC LIMIT 1 OFFSET ?",
+            table.name
+        ))
+        .bind(max_rows)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        if boundary.is_some() {
+            clauses.push("id <= ?".to_string());
+        }
+    }
+
+    Ok((!clauses.is_empty()).then(|| PruneCondition {
+        sql: clauses.join(" OR "),
+        cutoff,
+        boundary,
+    }))
+}
+
+/// Delete (and optionally archive) the rows due for pruning, in batches so
+/// writers are never blocked for long.
+async fn prune_table(
+    pool: &Pool<Sqlite>,
+    table: &Table,
+    max_age_days: i64,
+    max_rows: i64,
+    archive_dir: Option<&Path>,
+    report: &mut RetentionReport,
+) -> Result<i64, String> {
+    let Some(condition) = prune_condition(pool, table, max_age_days, max_rows).await? else {
+        return Ok(0);
+    };
+
+    let columns: Vec<&str> = table
+        .int_columns
+        .iter()
+        .chain(table.text_columns.iter())
+        .copied()
+        .collect();
+    let select_sql = format!(
+        "SELECT {} FROM {} WHERE ({}) ORDER BY id LIMIT {}",
+        columns.join(", "),
+        table.name,
+        condition.sql,
+        BATCH_SIZE
+    );
+    let delete_sql = format!(
+        "DELETE FROM {} WHERE ({}) AND id <= ?",
+        table.name, condition.sql
+    );
+
+    let mut archive: Option<(PathBuf, GzEncoder<fs::File>)> = None;
+    let mut deleted = 0;
+
+    loop {
+        let rows = condition
+            .bind(sqlx::query(&select_sql))
+            .fetch_all(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        let last_id: i64 = match rows.last() {
+            Some(row) => row.try_get("id").map_err(|e| e.to_string())?,
+            None => break,
+        };
+
+        if let Some(dir) = archive_dir {
+            if archive.is_none() {
+                fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                let path = dir.join(format!(
+                    "{}_{}.jsonl.gz",
+                    table.name,
+                    Local::now().format("%Y%m%d_%H%M%S")
+                ));
+                let file = fs::File::create(&path).map_err(|e| e.to_string())?;
+                archive = Some((path, GzEncoder::new(file, Compression::default())));
+            }
+            if let Some((_, writer)) = archive.as_mut() {
+                for row in &rows {
+                    writeln!(writer, "{}", table.to_json(row)).map_err(|e| e.to_string())?;
+                }
+                // Rows are only deleted once they are safely on disk.
+                writer.flush().map_err(|e| e.to_string())?;
+            }
+        }
+
+        deleted += condition
+            .bind(sqlx::query(&delete_sql))
+            .bind(last_id)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?
+            .rows_affected() as i64;
+
+        if (rows.len() as i64) < BATCH_SIZE {
+            break;
+        }
+    }
+
+    if let Some((path, writer)) = archive {
+        writer.finish().map_err(|e| e.to_string())?;
+        report
+            .archive_files
+            .push(path.to_string_lossy().to_string());
+    }
+    Ok(deleted)
+}
+
+/// Apply the retention policy once.
+pub(crate) async fn enforce(
+    pool: &Pool<Sqlite>,
+    policy: &RetentionPolicy,
+    default_archive_dir: &Path,
+) -> Result<RetentionReport, String> {
+    let archive_dir = policy.archive.then(|| {
+        policy
+            .archive_dir
+            .as_deref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| default_archive_dir.to_path_buf())
+    });
+
+    announce_first_run(pool, policy).await;
+
+    let mut report = RetentionReport::default();
+    report.production_deleted = prune_table(
+        pool,
+        &PRODUCTION_DATA,
+        policy.production_max_age_days,
+        policy.production_max_rows,
+        archive_dir.as_deref(),
+        &mut report,
+    )
+    .await?;
+    report.logs_deleted = prune_table(
+        pool,
+        &LOGS,
+        policy.logs_max_age_days,
+        policy.logs_max_rows,
+        archive_dir.as_deref(),
+        &mut report,
+    )
+    .await?;
+
+    let now = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let _ =
+        sqlx::query("INSERT OR REPLACE INTO config (key, value) VALUES ('retention_last_run', ?)")
+            .bind(&now)
+            .execute(pool)
+            .await;
+    if report.production_deleted > 0 || report.logs_deleted > 0 {
+        let _ = sqlx::query(
+            "INSERT INTO logs (line_id, level, source, message, details, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(None::<i64>)
+        .bind("INFO")
+        .bind("Rétention")
+        .bind(format!(
+            "Rétention: {} historique(s) et {} journal(aux) supprimé(s)",
+            report.production_deleted, report.logs_deleted
+        ))
+        .bind((!report.archive_files.is_empty()).then(|| report.archive_files.join("\n")))
+        .bind(now)
+        .execute(pool)
+        .await;
+    }
+
+    Ok(report)
+}
+
+/// The first run of a newly enabled policy deletes history for good: say so loudly,
+/// in the application log and in the `logs` table. Disabling the policy re-arms it.
+async fn announce_first_run(pool: &Pool<Sqlite>, policy: &RetentionPolicy) {
+    if !policy.is_active() {
+        let _ = sqlx::query("DELETE FROM config WHERE key = 'retention_active_since'")
+            .execute(pool)
+            .await;
+        return;
+    }
+    let announced = sqlx::query_scalar::<_, String>(
+        "SELECT value FROM config WHERE key = 'retention_active_since'",
+    )
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten()
+    .is_some();
+    if announced {
+        return;
+    }
+
+    let message = format!(
+        "Rétention: première application de la politique ({})",
+        policy.describe()
+    );
+    log::warn!("{}", message);
+    let now = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let _ = sqlx::query(
+        "INSERT INTO logs (line_id, level, source, message, details, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(None::<i64>)
+    .bind("WARNING")
+    .bind("Rétention")
+    .bind(&message)
+    .bind(None::<String>)
+    .bind(&now)
+    .execute(pool)
+    .await;
+    let _ = sqlx::query(
+        "INSERT OR REPLACE INTO config (key, value) VALUES ('retention_active_since', ?)",
+    )
+    .bind(&now)
+    .execute(pool)
+    .await;
+}
+
+/// Enforce the retention policy periodically in the background.
+pub fn spawn_job(pool: Pool<Sqlite>, default_archive_dir: PathBuf) {
+    crate::runtime::spawn(async move {
+        tokio::time::sleep(FIRST_RUN_DELAY).await;
+        loop {
+            let policy = RetentionPolicy::load(&pool).await;
+            if let Err(e) = enforce(&pool, &policy, &default_archive_dir).await {
+                log::error!("Rétention: {}", e);
+            }
+            tokio::time::sleep(RUN_INTERVAL).await;
+        }
+    });
+}
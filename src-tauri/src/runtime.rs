@@ -0,0 +1,30 @@
+//! Async runtime for background work: Tauri's in the desktop build, a Tokio
+//! runtime of its own when built without the `desktop` feature.
+
+#[cfg(feature = "desktop")]
+pub(crate) use tauri::async_runtime::{block_on, spawn, JoinHandle};
+
+#[cfg(not(feature = "desktop"))]
+pub(crate) use tokio::task::JoinHandle;
+
+#[cfg(not(feature = "desktop"))]
+fn runtime() -> &'static tokio::runtime::Runtime {
+    static RUNTIME: std::sync::OnceLock<tokio::runtime::Runtime> = std::sync::OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Runtime::new().expect("runtime Tokio impossible à démarrer")
+    })
+}
+
+#[cfg(not(feature = "desktop"))]
+pub(crate) fn spawn<F>(task: F) -> JoinHandle<F::Output>
+where
+    F: std::future::Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    runtime().spawn(task)
+}
+
+#[cfg(not(feature = "desktop"))]
+pub(crate) fn block_on<F: std::future::Future>(task: F) -> F::Output {
+    runtime().block_on(task)
+}
@@ -1,20 +1,55 @@
+use crate::db::DbState;
+use crate::host::Host;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
 use std::collections::HashMap;
-use std::sync::{mpsc, Mutex};
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::time::Duration;
-use tauri::{AppHandle, Manager, State};
+#[cfg(feature = "desktop")]
+use tauri::{AppHandle, State};
 
+/// Running tasks are also recorded in `config` under this prefix, so the
+/// headless service runs the same tasks as the desktop app.
+const TASK_KEY_PREFIX: &str = "scheduler_task_";
+
+pub(crate) const TASK_TYPES: [&str; 5] = [
+    "ATEIS_PRODUIT_SYNC",
+    "ATEIS_OF_SYNC",
+    "LOGITRON_PRODUIT",
+    "LOGITRON_OF",
+    "ATEIS_EXPORT",
+];
+
+/// Cheap to clone: clones share the same task list.
+#[derive(Clone)]
 pub struct SchedulerState {
     // Map of task_type -> stop_channel_sender
-    pub tasks: Mutex<HashMap<String, mpsc::Sender<()>>>,
+    pub tasks: Arc<Mutex<HashMap<String, mpsc::Sender<()>>>>,
 }
 
 impl SchedulerState {
     pub fn new() -> Self {
         Self {
-            tasks: Mutex::new(HashMap::new()),
+            tasks: Arc::new(Mutex::new(HashMap::new())),
         }
     }
+
+    /// Stop every running task (service shutdown).
+    pub(crate) fn stop_all(&self) {
+        if let Ok(mut tasks) = self.tasks.lock() {
+            for (_, tx) in tasks.drain() {
+                let _ = tx.send(());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ScheduledTask {
+    pub interval_minutes: u64,
+    pub param: Option<String>,
 }
 
 #[derive(Clone, serde::Serialize)]
@@ -23,13 +58,53 @@ pub struct SchedulerStatus {
     pub running: bool,
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn start_scheduler(
     app_handle: AppHandle,
     state: State<'_, SchedulerState>,
+    db: State<'_, DbState>,
     task_type: String,
     interval_minutes: u64,
     param: Option<String>,
+) -> Result<(), String> {
+    let task = ScheduledTask {
+        interval_minutes,
+        param,
+    };
+    start_task(
+        &state,
+        Host::Desktop(app_handle),
+        db.inner(),
+        task_type,
+        task,
+    )
+    .await
+}
+
+/// Start a task and record it so it is restarted by the headless service.
+pub(crate) async fn start_task(
+    state: &SchedulerState,
+    host: Host,
+    db: &DbState,
+    task_type: String,
+    task: ScheduledTask,
+) -> Result<(), String> {
+    if !TASK_TYPES.contains(&task_type.as_str()) {
+        return Err(format!("Tâche inconnue: {}", task_type));
+    }
+    spawn_task(state, host, db.clone(), task_type.clone(), task.clone())?;
+    save_task(&db.pool, &task_type, &task).await
+}
+
+/// Run a task now and then every `interval_minutes` on a background thread;
+/// a no-op if it is already running.
+pub(crate) fn spawn_task(
+    state: &SchedulerState,
+    host: Host,
+    db: DbState,
+    task_type: String,
+    task: ScheduledTask,
 ) -> Result<(), String> {
     let mut tasks = state.tasks.lock().map_err(|e| e.to_string())?;
 
@@ -40,9 +115,7 @@ pub async fn start_scheduler(
     let (tx, rx) = mpsc::channel();
     tasks.insert(task_type.clone(), tx);
 
-    let interval_sec = interval_minutes * 60;
-    let task_type_clone = task_type.clone();
-    let param_clone = param.clone();
+    let interval_sec = task.interval_minutes * 60;
 
     thread::spawn(move || {
         let mut first_run = true;
@@ -63,157 +136,149 @@ pub async fn start_scheduler(
             }
             first_run = false;
 
-            // Execute Task based on type
-            let app_handle_clone = app_handle.clone();
-            let tt = task_type_clone.clone();
-            let p_val = param_clone.clone();
-
-            tauri::async_runtime::block_on(async move {
-                match tt.as_str() {
-                    "ATEIS_PRODUIT_SYNC" => {
-                        let _ = crate::commands::hfsql::sync_ateis_produit(
-                            app_handle_clone.clone(),
-                            app_handle_clone.state::<crate::db::DbState>(),
-                        )
-                        .await;
-                    }
-                    "ATEIS_OF_SYNC" => {
-                        let _ = crate::commands::hfsql::sync_ateis_of(
-                            app_handle_clone.clone(),
-                            app_handle_clone.state::<crate::db::DbState>(),
-                        )
-                        .await;
-                    }
-                    "LOGITRON_PRODUIT" => {
-                        // Use param as path if provided, else resolve default
-                        let state = app_handle_clone.state::<crate::db::DbState>();
-                        let output_path = if let Some(p) = p_val.as_ref().filter(|s| !s.is_empty())
-                        {
-                            p.clone()
-                        } else {
-                            // Fallback logic
-                            let base_path =
-                                match crate::commands::hfsql::get_hfsql_config(state.clone()).await
-                                {
-                                    Ok(cfg) => cfg.log_path.filter(|p| !p.is_empty()),
-                                    Err(_) => None,
-                                };
-                            let output_dir = base_path.unwrap_or_else(|| {
-                                app_handle_clone
-                                    .path()
-                                    .desktop_dir()
-                                    .ok()
-                                    .map(|p| p.join("T").join("BLOG").to_string_lossy().to_string())
-                                    .unwrap_or_else(|| r"C:\T\BLOG".to_string())
-                            });
-                            std::path::Path::new(&output_dir)
-                                .join("LOGITRON_PRODUIT.DAT")
-                                .to_string_lossy()
-                                .to_string()
-                        };
-
-                        let _ = crate::commands::exports::export_logitron_produit_dat(
-                            app_handle_clone.clone(),
-                            state.clone(),
-                            output_path,
-                            Some(true),
-                        )
-                        .await;
-                    }
-                    "LOGITRON_OF" => {
-                        // Use param as path if provided, else resolve default
-                        let state = app_handle_clone.state::<crate::db::DbState>();
-                        let output_path = if let Some(p) = p_val.as_ref().filter(|s| !s.is_empty())
-                        {
-                            p.clone()
-                        } else {
-                            // Fallback logic
-                            let base_path =
-                                match crate::commands::hfsql::get_hfsql_config(state.clone()).await
-                                {
-                                    Ok(cfg) => cfg.log_path.filter(|p| !p.is_empty()),
-                                    Err(_) => None,
-                                };
-                            let output_dir = base_path.unwrap_or_else(|| {
-                                app_handle_clone
-                                    .path()
-                                    .desktop_dir()
-                                    .ok()
-                                    .map(|p| p.join("T").join("BLOG").to_string_lossy().to_string())
-                                    .unwrap_or_else(|| r"C:\T\BLOG".to_string())
-                            });
-                            std::path::Path::new(&output_dir)
-                                .join("LOGITRON_ORDRE_FABRICATION.DAT")
-                                .to_string_lossy()
-                                .to_string()
-                        };
-
-                        let _ = crate::commands::exports::export_ordre_fabrication_dat(
-                            app_handle_clone.clone(),
-                            state.clone(),
-                            output_path,
-                        )
-                        .await;
-                    }
-                    "ATEIS_EXPORT" => {
-                        // Keep ATEIS_EXPORT as combined for now if not requested otherwise, or split if needed.
-                        // User only mentioned Logitron issues. Leaving ATEIS_EXPORT logic as is but fixing path resolution if needed.
-                        let state = app_handle_clone.state::<crate::db::DbState>();
-                        let base_path =
-                            match crate::commands::hfsql::get_hfsql_config(state.clone()).await {
-                                Ok(cfg) => cfg.log_path.filter(|p| !p.is_empty()),
-                                Err(_) => None,
-                            };
-
-                        let output_dir = base_path.unwrap_or_else(|| {
-                            app_handle_clone
-                                .path()
-                                .desktop_dir()
-                                .ok()
-                                .map(|p| p.join("T").join("BLOG").to_string_lossy().to_string())
-                                .unwrap_or_else(|| r"C:\T\BLOG".to_string())
-                        });
-
-                        let path_prod = std::path::Path::new(&output_dir).join("ATEIS_PRODUIT.DAT");
-                        let path_of = std::path::Path::new(&output_dir).join("ATEIS_OF.DAT");
-
-                        let _ = crate::commands::exports::export_ateis_produit_dat(
-                            state.clone(),
-                            path_prod.to_string_lossy().to_string(),
-                        )
-                        .await;
-
-                        let _ = crate::commands::exports::export_ateis_of_dat(
-                            state.clone(),
-                            path_of.to_string_lossy().to_string(),
-                        )
-                        .await;
-                    }
-                    _ => {
-                        eprintln!("Unknown task type: {}", tt);
-                    }
-                }
-            });
+            crate::runtime::block_on(run_task(&host, &db, &task_type, task.param.as_deref()));
         }
     });
 
     Ok(())
 }
 
+/// Output folder for the `.DAT` exports: the HFSQL log folder, else the host default.
+async fn default_output_dir(host: &Host, db: &DbState) -> String {
+    crate::commands::hfsql::load_hfsql_config(&db.pool)
+        .await
+        .ok()
+        .and_then(|cfg| cfg.log_path)
+        .filter(|p| !p.is_empty())
+        .unwrap_or_else(|| host.default_output_dir())
+}
+
+/// Use param as path if provided, else resolve default
+async fn export_path(host: &Host, db: &DbState, param: Option<&str>, file_name: &str) -> String {
+    match param.filter(|p| !p.is_empty()) {
+        Some(p) => p.to_string(),
+        None => Path::new(&default_output_dir(host, db).await)
+            .join(file_name)
+            .to_string_lossy()
+            .to_string(),
+    }
+}
+
+async fn run_task(host: &Host, db: &DbState, task_type: &str, param: Option<&str>) {
+    use crate::commands::{exports, hfsql};
+
+    let result = match task_type {
+        "ATEIS_PRODUIT_SYNC" => hfsql::run_ateis_produit_sync(host.clone(), db)
+            .await
+            .map(|_| ()),
+        "ATEIS_OF_SYNC" => hfsql::run_ateis_of_sync(host.clone(), db).await.map(|_| ()),
+        "LOGITRON_PRODUIT" => {
+            let output_path = export_path(host, db, param, "LOGITRON_PRODUIT.DAT").await;
+            exports::run_logitron_produit_export(host, db, output_path, Some(true))
+                .await
+                .map(|_| ())
+        }
+        "LOGITRON_OF" => {
+            let output_path = export_path(host, db, param, "LOGITRON_ORDRE_FABRICATION.DAT").await;
+            exports::run_ordre_fabrication_export(host, db, output_path)
+                .await
+                .map(|_| ())
+        }
+        "ATEIS_EXPORT" => {
+            let output_dir = default_output_dir(host, db).await;
+            let path_prod = Path::new(&output_dir).join("ATEIS_PRODUIT.DAT");
+            let path_of = Path::new(&output_dir).join("ATEIS_OF.DAT");
+
+            let produit =
+                exports::run_ateis_produit_export(db, path_prod.to_string_lossy().to_string())
+                    .await;
+            let of = exports::run_ateis_of_export(db, path_of.to_string_lossy().to_string()).await;
+            produit.and(of).map(|_| ())
+        }
+        _ => Err(format!("Unknown task type: {}", task_type)),
+    };
+
+    crate::metrics::record_task_run(task_type, result.is_ok());
+    if let Err(e) = result {
+        log::warn!("Tâche planifiée {}: {}", task_type, e);
+    }
+}
+
+async fn save_task(
+    pool: &Pool<Sqlite>,
+    task_type: &str,
+    task: &ScheduledTask,
+) -> Result<(), String> {
+    let value = serde_json::to_string(task).map_err(|e| e.to_string())?;
+    sqlx::query("INSERT OR REPLACE INTO config (key, value) VALUES (?, ?)")
+        .bind(format!("{}{}", TASK_KEY_PREFIX, task_type))
+        .bind(value)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Tasks left running in the desktop app, by task type.
+pub(crate) async fn load_tasks(
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<(String, ScheduledTask)>, String> {
+    let rows = sqlx::query_as::<_, (String, String)>(
+        "SELECT key, value FROM config WHERE substr(key, 1, length(?)) = ?",
+    )
+    .bind(TASK_KEY_PREFIX)
+    .bind(TASK_KEY_PREFIX)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let task_type = key.strip_prefix(TASK_KEY_PREFIX)?.to_string();
+            match serde_json::from_str(&value) {
+                Ok(task) => Some((task_type, task)),
+                Err(e) => {
+                    log::warn!("Tâche planifiée {} ignorée: {}", task_type, e);
+                    None
+                }
+            }
+        })
+        .collect())
+}
+
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn stop_scheduler(
     state: State<'_, SchedulerState>,
+    db: State<'_, DbState>,
     task_type: String,
 ) -> Result<(), String> {
-    let mut tasks = state.tasks.lock().map_err(|e| e.to_string())?;
+    stop_task(&state, &db.pool, &task_type).await
+}
 
-    if let Some(tx) = tasks.remove(&task_type) {
-        let _ = tx.send(());
+pub(crate) async fn stop_task(
+    state: &SchedulerState,
+    pool: &Pool<Sqlite>,
+    task_type: &str,
+) -> Result<(), String> {
+    {
+        let mut tasks = state.tasks.lock().map_err(|e| e.to_string())?;
+
+        if let Some(tx) = tasks.remove(task_type) {
+            let _ = tx.send(());
+        }
     }
 
+    sqlx::query("DELETE FROM config WHERE key = ?")
+        .bind(format!("{}{}", TASK_KEY_PREFIX, task_type))
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
     Ok(())
 }
 
+#[cfg(feature = "desktop")]
 #[tauri::command]
 pub async fn get_scheduler_status(
     state: State<'_, SchedulerState>,
@@ -222,3 +287,11 @@ pub async fn get_scheduler_status(
     let tasks = state.tasks.lock().map_err(|e| e.to_string())?;
     Ok(tasks.contains_key(&task_type))
 }
+
+/// Task types currently running.
+pub(crate) fn running_tasks(state: &SchedulerState) -> Result<Vec<String>, String> {
+    let tasks = state.tasks.lock().map_err(|e| e.to_string())?;
+    let mut running: Vec<String> = tasks.keys().cloned().collect();
+    running.sort();
+    Ok(running)
+}
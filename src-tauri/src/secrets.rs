@@ -0,0 +1,236 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use sqlx::{Pool, Sqlite};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Stored passwords are `enc:v1:` followed by base64(nonce || AES-256-GCM ciphertext).
+const PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+const KEY_FILE: &str = "secret.key";
+#[cfg(any(windows, target_os = "macos"))]
+const KEYRING_SERVICE: &str = "visor";
+#[cfg(any(windows, target_os = "macos"))]
+const KEYRING_USER: &str = "credentials-key";
+
+/// What the settings screens get back instead of a stored password; saving
+/// it back unchanged keeps the stored password.
+pub(crate) const PASSWORD_MASK: &str = "********";
+
+/// Tables whose `password` column is encrypted.
+const PASSWORD_TABLES: [&str; 3] = ["sql_server_config", "sql_server_profiles", "hfsql_config"];
+
+static CIPHER: OnceLock<Aes256Gcm> = OnceLock::new();
+
+/// Load the credentials key, creating it on first start. The key lives in the
+/// OS keyring where there is one (Windows Credential Manager, macOS Keychain),
+/// otherwise in `secret.key` next to the database, readable by the owner only.
+/// An existing key file always wins so that a key never changes once used, and
+/// a new key is only generated when no key exists and nothing is encrypted yet:
+/// an unreadable keyring or a lost key stops the startup instead.
+pub async fn init(app_dir: &Path, pool: &Pool<Sqlite>) -> Result<(), String> {
+    let key = match load_key(app_dir)? {
+        Some(key) => key,
+        None if has_encrypted_values(pool).await? => {
+            return Err(format!(
+                "Clé de chiffrement introuvable alors que la base contient des mots de passe chiffrés: \
+                 restaurez {} ou l'entrée du trousseau, ou ressaisissez les mots de passe dans une base neuve",
+                app_dir.join(KEY_FILE).display()
+            ))
+        }
+        None => create_key(app_dir)?,
+    };
+    let _ = CIPHER.set(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)));
+    Ok(())
+}
+
+fn cipher() -> Result<&'static Aes256Gcm, String> {
+    CIPHER
+        .get()
+        .ok_or_else(|| "Clé de chiffrement non initialisée".to_string())
+}
+
+fn decode_key(encoded: &str) -> Result<Vec<u8>, String> {
+    let key = BASE64
+        .decode(encoded.trim())
+        .map_err(|e| format!("Clé de chiffrement invalide: {}", e))?;
+    if key.len() != 32 {
+        return Err("Clé de chiffrement invalide: taille incorrecte".to_string());
+    }
+    Ok(key)
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+fn keyring_entry() -> keyring::Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+}
+
+/// The existing key, if any. A keyring that cannot be read is an error, not a
+/// missing key, so that a transient failure never replaces the key.
+fn load_key(app_dir: &Path) -> Result<Option<Vec<u8>>, String> {
+    let path = app_dir.join(KEY_FILE);
+    if path.exists() {
+        let encoded = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        return decode_key(&encoded).map(Some);
+    }
+
+    #[cfg(any(windows, target_os = "macos"))]
+    match keyring_entry().and_then(|entry| entry.get_password()) {
+        Ok(encoded) => return decode_key(&encoded).map(Some),
+        Err(keyring::Error::NoEntry) => {}
+        Err(e) => {
+            return Err(format!(
+                "Trousseau du système illisible, démarrage interrompu pour ne pas remplacer la clé de chiffrement: {}",
+                e
+            ))
+        }
+    }
+
+    Ok(None)
+}
+
+fn create_key(app_dir: &Path) -> Result<Vec<u8>, String> {
+    let key = Aes256Gcm::generate_key(OsRng);
+    let encoded = BASE64.encode(key);
+
+    #[cfg(any(windows, target_os = "macos"))]
+    match keyring_entry().and_then(|entry| entry.set_password(&encoded)) {
+        Ok(()) => return Ok(key.to_vec()),
+        Err(e) => log::warn!(
+            "Trousseau indisponible, clé stockée dans {}: {}",
+            KEY_FILE,
+            e
+        ),
+    }
+
+    write_key_file(&app_dir.join(KEY_FILE), &encoded)?;
+    Ok(key.to_vec())
+}
+
+/// Whether a previous key has been used: encrypted passwords, or encrypted
+/// values in `config` (API token, alert channel passwords).
+async fn has_encrypted_values(pool: &Pool<Sqlite>) -> Result<bool, String> {
+    let pattern = format!("{}%", PREFIX);
+    for table in PASSWORD_TABLES {
+        let count: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(1) FROM {} WHERE password LIKE ?",
+            table
+        ))
+        .bind(&pattern)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        if count > 0 {
+            return Ok(true);
+        }
+    }
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM config WHERE value LIKE ?")
+        .bind(format!("%{}%", PREFIX))
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(count > 0)
+}
+
+fn write_key_file(path: &Path, encoded: &str) -> Result<(), String> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    // On Windows the application data directory is already private to the user.
+    let mut file = options.open(path).map_err(|e| e.to_string())?;
+    file.write_all(encoded.as_bytes())
+        .map_err(|e| e.to_string())
+}
+
+pub(crate) fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// Encrypt a password for storage; empty passwords stay empty.
+pub(crate) fn encrypt(plain: &str) -> Result<String, String> {
+    if plain.is_empty() {
+        return Ok(String::new());
+    }
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher()?
+        .encrypt(&nonce, plain.as_bytes())
+        .map_err(|e| e.to_string())?;
+    let mut data = nonce.to_vec();
+    data.extend_from_slice(&ciphertext);
+    Ok(format!("{}{}", PREFIX, BASE64.encode(data)))
+}
+
+/// Decrypt a stored password. Values not yet encrypted are returned as is.
+pub(crate) fn decrypt(stored: &str) -> Result<String, String> {
+    let encoded = match stored.strip_prefix(PREFIX) {
+        Some(encoded) => encoded,
+        None => return Ok(stored.to_string()),
+    };
+    let unreadable = || {
+        "Mot de passe illisible (clé de chiffrement changée ?), ressaisissez-le dans Paramètres"
+            .to_string()
+    };
+    let data = BASE64.decode(encoded).map_err(|_| unreadable())?;
+    if data.len() < NONCE_LEN {
+        return Err(unreadable());
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let plain = cipher()?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| unreadable())?;
+    String::from_utf8(plain).map_err(|_| unreadable())
+}
+
+pub(crate) fn decrypt_password(password: &mut Option<String>) -> Result<(), String> {
+    if let Some(stored) = password.as_deref() {
+        *password = Some(decrypt(stored)?);
+    }
+    Ok(())
+}
+
+pub(crate) fn mask_password(password: &mut Option<String>) {
+    if password.as_deref().is_some_and(|p| !p.is_empty()) {
+        *password = Some(PASSWORD_MASK.to_string());
+    }
+}
+
+/// Value to store for a password coming from a settings screen: `None` when
+/// the mask was sent back, meaning the stored password is kept.
+pub(crate) fn password_to_store(password: Option<&str>) -> Result<Option<String>, String> {
+    match password {
+        None => Ok(None),
+        Some(p) if p == PASSWORD_MASK => Ok(None),
+        Some(p) => encrypt(p).map(Some),
+    }
+}
+
+/// Encrypt passwords still stored in plaintext (databases created before encryption).
+pub(crate) async fn encrypt_plaintext_passwords(pool: &Pool<Sqlite>) -> Result<(), String> {
+    for table in PASSWORD_TABLES {
+        let rows = sqlx::query_as::<_, (i64, String)>(&format!(
+            "SELECT id, password FROM {} WHERE password IS NOT NULL AND password != ''",
+            table
+        ))
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        for (id, password) in rows.into_iter().filter(|(_, p)| !is_encrypted(p)) {
+            sqlx::query(&format!("UPDATE {} SET password = ? WHERE id = ?", table))
+                .bind(encrypt(&password)?)
+                .bind(id)
+                .execute(pool)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
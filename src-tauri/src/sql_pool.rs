@@ -0,0 +1,325 @@
+use crate::commands::sql_server::{
+    check_connectable, connect_timeout, open_connection, SqlClient, SqlServerConfig,
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const DEFAULT_MAX_SIZE: usize = 4;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 300;
+/// Connections idle for longer than this are checked with `SELECT 1` before reuse.
+const HEALTH_CHECK_AFTER: Duration = Duration::from_secs(30);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+const REAP_INTERVAL: Duration = Duration::from_secs(60);
+/// Start of the error returned when no slot frees up in time; the processor
+/// treats it as transient and queues the file.
+pub(crate) const POOL_SATURATED: &str = "Pool SQL Server saturé";
+
+struct IdleConnection {
+    client: SqlClient,
+    since: Instant,
+}
+
+struct ProfilePool {
+    /// Digest of the settings the pooled connections were opened with.
+    fingerprint: String,
+    idle_timeout: Duration,
+    max_size: usize,
+    permits: Arc<Semaphore>,
+    idle: Vec<IdleConnection>,
+}
+
+impl ProfilePool {
+    fn new(fingerprint: String, cfg: &SqlServerConfig) -> Self {
+        Self {
+            fingerprint,
+            idle_timeout: Duration::from_secs(
+                cfg.pool_idle_timeout_secs
+                    .and_then(|s| u64::try_from(s).ok())
+                    .filter(|s| *s > 0)
+                    .unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS),
+            ),
+            max_size: max_size(cfg),
+            permits: Arc::new(Semaphore::new(max_size(cfg))),
+            idle: Vec::new(),
+        }
+    }
+
+    /// Take new settings. Idle connections opened with the old ones are
+    /// closed, but the semaphore is kept: connections still checked out keep
+    /// counting against the pool size.
+    fn reconfigure(&mut self, fingerprint: String, cfg: &SqlServerConfig) {
+        let old_size = self.max_size;
+        let permits = self.permits.clone();
+        *self = Self {
+            permits: permits.clone(),
+            ..Self::new(fingerprint, cfg)
+        };
+
+        if self.max_size > old_size {
+            permits.add_permits(self.max_size - old_size);
+        } else if self.max_size < old_size {
+            // Withdraw the extra slots as checked-out connections come back.
+            let extra = (old_size - self.max_size) as u32;
+            crate::runtime::spawn(async move {
+                if let Ok(slots) = permits.acquire_many_owned(extra).await {
+                    slots.forget();
+                }
+            });
+        }
+    }
+
+    fn drop_expired(&mut self) {
+        let idle_timeout = self.idle_timeout;
+        self.idle.retain(|c| c.since.elapsed() < idle_timeout);
+    }
+}
+
+fn max_size(cfg: &SqlServerConfig) -> usize {
+    cfg.pool_max_size
+        .and_then(|n| usize::try_from(n).ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_SIZE)
+}
+
+/// Anything that changes how a connection is opened invalidates the pool.
+fn fingerprint(cfg: &SqlServerConfig) -> String {
+    let settings = format!(
+        "{:?}",
+        (
+            &cfg.server,
+            cfg.port,
+            &cfg.instance_name,
+            &cfg.database,
+            &cfg.username,
+            &cfg.password,
+            &cfg.encryption,
+            cfg.trust_cert,
+            &cfg.ca_cert_path,
+            &cfg.app_name,
+            cfg.pool_max_size,
+            cfg.pool_idle_timeout_secs,
+        )
+    );
+    format!("{:x}", Sha256::digest(settings.as_bytes()))
+}
+
+async fn is_alive(client: &mut SqlClient) -> bool {
+    let ping = async { client.simple_query("SELECT 1").await?.into_results().await };
+    matches!(
+        tokio::time::timeout(HEALTH_CHECK_TIMEOUT, ping).await,
+        Ok(Ok(_))
+    )
+}
+
+/// Long-lived SQL Server connections shared by the processor, exports and
+/// syncs, with one pool per connection profile.
+#[derive(Clone, Default)]
+pub struct SqlServerPools {
+    pools: Arc<Mutex<HashMap<i64, ProfilePool>>>,
+}
+
+/// A connection checked out of a pool. It goes back to the pool only when
+/// `release` is called, i.e. once the caller knows it is in a clean state;
+/// otherwise it is closed when dropped.
+pub struct PooledConnection {
+    client: Option<SqlClient>,
+    profile_id: i64,
+    fingerprint: String,
+    pools: SqlServerPools,
+    reusable: bool,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl SqlServerPools {
+    fn lock(&self) -> MutexGuard<'_, HashMap<i64, ProfilePool>> {
+        self.pools.lock().expect("sql pools mutex poisoned")
+    }
+
+    /// Check out a connection for `cfg`, reusing an idle one when it is still
+    /// alive and opening a new one otherwise. Waits for a free slot when the
+    /// profile's pool is full.
+    pub async fn get(&self, cfg: &SqlServerConfig) -> Result<PooledConnection, String> {
+        let fp = fingerprint(cfg);
+        let permits = {
+            let mut pools = self.lock();
+            let pool = pools
+                .entry(cfg.id)
+                .or_insert_with(|| ProfilePool::new(fp.clone(), cfg));
+            if pool.fingerprint != fp {
+                pool.reconfigure(fp.clone(), cfg);
+            }
+            pool.permits.clone()
+        };
+
+        let permit = tokio::time::timeout(connect_timeout(cfg), permits.acquire_owned())
+            .await
+            .map_err(|_| format!("{} ({} connexions en cours)", POOL_SATURATED, max_size(cfg)))?
+            .map_err(|e| e.to_string())?;
+
+        loop {
+            let candidate = {
+                let mut pools = self.lock();
+                match pools.get_mut(&cfg.id).filter(|p| p.fingerprint == fp) {
+                    Some(pool) => {
+                        pool.drop_expired();
+                        pool.idle.pop()
+                    }
+                    None => None,
+                }
+            };
+            let mut idle = match candidate {
+                Some(idle) => idle,
+                None => break,
+            };
+            if idle.since.elapsed() < HEALTH_CHECK_AFTER || is_alive(&mut idle.client).await {
+                return Ok(self.wrap(idle.client, cfg.id, fp, permit));
+            }
+            // Dead connection (server restart, network drop): try the next one or reconnect.
+        }
+
+        let client = open_connection(cfg).await?;
+        Ok(self.wrap(client, cfg.id, fp, permit))
+    }
+
+    /// `get`, for a profile that must be enabled and complete.
+    pub async fn connect(&self, cfg: &SqlServerConfig) -> Result<PooledConnection, String> {
+        check_connectable(cfg)?;
+        self.get(cfg).await
+    }
+
+    fn wrap(
+        &self,
+        client: SqlClient,
+        profile_id: i64,
+        fingerprint: String,
+        permit: OwnedSemaphorePermit,
+    ) -> PooledConnection {
+        PooledConnection {
+            client: Some(client),
+            profile_id,
+            fingerprint,
+            pools: self.clone(),
+            reusable: false,
+            _permit: permit,
+        }
+    }
+
+    /// Close the idle connections of a profile (e.g. once it is deleted).
+    pub fn forget(&self, profile_id: i64) {
+        self.lock().remove(&profile_id);
+    }
+
+    /// Periodically close connections idle for longer than their profile's idle timeout.
+    pub fn spawn_reaper(&self) {
+        let pools = self.clone();
+        crate::runtime::spawn(async move {
+            loop {
+                tokio::time::sleep(REAP_INTERVAL).await;
+                for pool in pools.lock().values_mut() {
+                    pool.drop_expired();
+                }
+            }
+        });
+    }
+}
+
+impl PooledConnection {
+    /// Return the connection to its pool once done with it.
+    pub fn release(mut self) {
+        self.reusable = true;
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = SqlClient;
+
+    fn deref(&self) -> &SqlClient {
+        self.client
+            .as_ref()
+            .expect("pooled connection already released")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut SqlClient {
+        self.client
+            .as_mut()
+            .expect("pooled connection already released")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if !self.reusable {
+            return;
+        }
+        if let Some(client) = self.client.take() {
+            let mut pools = self.pools.lock();
+            if let Some(pool) = pools
+                .get_mut(&self.profile_id)
+                .filter(|p| p.fingerprint == self.fingerprint)
+            {
+                pool.idle.push(IdleConnection {
+                    client,
+                    since: Instant::now(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(password: &str, pool_max_size: i64) -> SqlServerConfig {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": "Défaut",
+            "server": "srv",
+            "database": "x3",
+            "username": "visor",
+            "password": password,
+            "enabled": true,
+            "pool_max_size": pool_max_size,
+        }))
+        .unwrap()
+    }
+
+    async fn available_becomes(permits: &Semaphore, expected: usize) {
+        for _ in 0..200 {
+            if permits.available_permits() == expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(permits.available_permits(), expected);
+    }
+
+    #[tokio::test]
+    async fn checked_out_connections_still_count_after_a_settings_change() {
+        let first = cfg("a", 2);
+        let mut pool = ProfilePool::new(fingerprint(&first), &first);
+        let permits = pool.permits.clone();
+        let held = permits.clone().acquire_many_owned(2).await.unwrap();
+
+        let changed = cfg("b", 2);
+        pool.reconfigure(fingerprint(&changed), &changed);
+        assert_eq!(pool.permits.available_permits(), 0);
+
+        let bigger = cfg("b", 3);
+        pool.reconfigure(fingerprint(&bigger), &bigger);
+        assert_eq!(pool.permits.available_permits(), 1);
+
+        let smaller = cfg("b", 1);
+        pool.reconfigure(fingerprint(&smaller), &smaller);
+        available_becomes(&permits, 0).await;
+        drop(held);
+        available_becomes(&permits, 1).await;
+        assert!(Arc::ptr_eq(&pool.permits, &permits));
+    }
+}
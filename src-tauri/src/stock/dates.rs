@@ -0,0 +1,199 @@
+use crate::stock::transforms::{
+    apply_transformation, parse_combined_heuristic, parse_date_heuristic, parse_datetime_heuristic,
+    parse_time_heuristic,
+};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::fmt::Write;
+
+pub(crate) const TIMEZONE_KEY: &str = "timezone";
+
+/// ISO 8601, which SQL Server converts to `datetime`/`datetime2` whatever the
+/// session language and DATEFORMAT.
+pub(crate) const SQL_DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Per-mapping date handling, stored as JSON in the mapping's `date_format` column, e.g.
+/// `{"input_formats": ["%Y%m%d%H%M%S"], "output_type": "datetime"}`.
+/// It applies to the `date`, `datetime`, `datetime_combine`, `heure` and
+/// `current_datetime` steps of the mapping's transformation. Mappings without
+/// options use the defaults: built-in heuristics, and unparsable values make the row invalid.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct DateOptions {
+    /// chrono formats tried in order; empty keeps the built-in heuristics.
+    pub input_formats: Vec<String>,
+    /// chrono format of the produced text; defaults depend on the step.
+    pub output_format: Option<String>,
+    /// `string` (default) or `datetime` (ISO 8601 for a SQL datetime column).
+    pub output_type: Option<String>,
+    /// When set, an unparsable value becomes "now" (`000000` for `heure`) instead of
+    /// making the row invalid. Each replacement is reported for the file's log.
+    pub lenient: bool,
+}
+
+/// Values shared by all rows of a file.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TransformContext {
+    pub now: NaiveDateTime,
+    pub tz: Option<Tz>,
+}
+
+impl TransformContext {
+    pub(crate) fn new(tz: Option<Tz>) -> Self {
+        let now = match tz {
+            Some(tz) => Utc::now().with_timezone(&tz).naive_local(),
+            None => Local::now().naive_local(),
+        };
+        Self { now, tz }
+    }
+}
+
+fn check_format(format: &str) -> Result<(), String> {
+    if format.trim().is_empty() || StrftimeItems::new(format).any(|i| matches!(i, Item::Error)) {
+        return Err(format!("format de date invalide: {}", format));
+    }
+    Ok(())
+}
+
+impl DateOptions {
+    pub(crate) fn parse(raw: &str) -> Result<Self, String> {
+        let opts: Self = serde_json::from_str(raw).map_err(|e| e.to_string())?;
+        for f in &opts.input_formats {
+            check_format(f)?;
+        }
+        if let Some(f) = &opts.output_format {
+            check_format(f)?;
+        }
+        match opts
+            .output_type
+            .as_deref()
+            .map(str::to_lowercase)
+            .as_deref()
+        {
+            None | Some("") | Some("string") | Some("datetime") => {}
+            Some(other) => return Err(format!("type de sortie inconnu: {}", other)),
+        }
+        Ok(opts)
+    }
+
+    fn output_format<'a>(&'a self, step: &str) -> &'a str {
+        if self
+            .output_type
+            .as_deref()
+            .is_some_and(|t| t.eq_ignore_ascii_case("datetime"))
+        {
+            return SQL_DATETIME_FORMAT;
+        }
+        if let Some(f) = &self.output_format {
+            return f;
+        }
+        match step {
+            "date" => "%d/%m/%Y",
+            "heure" => "%H%M%S",
+            _ => "%d/%m/%Y %H:%M:%S",
+        }
+    }
+
+    fn parse_value(&self, step: &str, value: &str, tz: Option<Tz>) -> Option<NaiveDateTime> {
+        if self.input_formats.is_empty() {
+            let v = value.trim();
+            return match step {
+                "date" => parse_date_heuristic(v).and_then(|d| d.and_hms_opt(0, 0, 0)),
+                "heure" => {
+                    parse_time_heuristic(v).map(|t| NaiveDateTime::new(NaiveDate::default(), t))
+                }
+                "datetime_combine" => parse_combined_heuristic(value),
+                _ => parse_datetime_heuristic(v),
+            };
+        }
+
+        // `a-b` columns arrive as `date;time`.
+        let v = value.trim().replace(';', " ");
+        self.input_formats
+            .iter()
+            .find_map(|f| parse_with_format(&v, f, tz))
+    }
+
+    /// Apply a date step to `value`. An unparsable value is an error, unless the
+    /// options are lenient: it is then replaced and the replacement noted in `substitutions`.
+    pub(crate) fn convert(
+        &self,
+        step: &str,
+        value: String,
+        ctx: &TransformContext,
+        substitutions: &mut Vec<String>,
+    ) -> Result<String, String> {
+        let parsed = if step == "current_datetime" {
+            Some(ctx.now)
+        } else {
+            self.parse_value(step, &value, ctx.tz)
+        };
+
+        match parsed {
+            Some(dt) => {
+                let mut out = String::new();
+                write!(out, "{}", dt.format(self.output_format(step)))
+                    .map_err(|_| format!("format de sortie inapplicable à '{}'", value.trim()))?;
+                Ok(out)
+            }
+            None if !self.lenient => Err(if value.trim().is_empty() {
+                "date manquante".to_string()
+            } else {
+                format!("date illisible '{}'", value.trim())
+            }),
+            None => {
+                let replaced = apply_transformation(value.clone(), step, ctx.now);
+                substitutions.push(format!(
+                    "date illisible '{}' remplacée par {}",
+                    value.trim(),
+                    replaced
+                ));
+                Ok(replaced)
+            }
+        }
+    }
+}
+
+/// Values carrying an offset (`%z`, `%:z`) are converted to the configured zone.
+fn parse_with_format(v: &str, format: &str, tz: Option<Tz>) -> Option<NaiveDateTime> {
+    if let Ok(dt) = DateTime::parse_from_str(v, format) {
+        return Some(match tz {
+            Some(tz) => dt.with_timezone(&tz).naive_local(),
+            None => dt.with_timezone(&Local).naive_local(),
+        });
+    }
+    if let Ok(dt) = NaiveDateTime::parse_from_str(v, format) {
+        return Some(dt);
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(v, format) {
+        return d.and_hms_opt(0, 0, 0);
+    }
+    // A time alone is only meaningful for `heure`; the date part is ignored there.
+    NaiveTime::parse_from_str(v, format)
+        .ok()
+        .map(|t| NaiveDateTime::new(NaiveDate::default(), t))
+}
+
+pub(crate) fn parse_timezone(name: &str) -> Result<Option<Tz>, String> {
+    let name = name.trim();
+    if name.is_empty() || name.eq_ignore_ascii_case("local") {
+        return Ok(None);
+    }
+    name.parse::<Tz>()
+        .map(Some)
+        .map_err(|_| format!("Fuseau horaire inconnu: {}", name))
+}
+
+/// Zone used for "now" and for values carrying an offset. Unset means the system zone.
+pub(crate) async fn load_timezone(pool: &Pool<Sqlite>) -> Option<Tz> {
+    let name: Option<String> = sqlx::query_scalar("SELECT value FROM config WHERE key = ?")
+        .bind(TIMEZONE_KEY)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+    name.and_then(|n| parse_timezone(&n).ok().flatten())
+}
@@ -0,0 +1,200 @@
+use chrono::Local;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite};
+use std::collections::HashSet;
+
+/// Keys looked up per query; older SQLite builds accept at most 999 parameters.
+const KEYS_PER_QUERY: usize = 500;
+
+/// What to do with a file (or rows) already ingested for the same line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DuplicatePolicy {
+    /// Do not insert again; the file is archived and marked SKIPPED.
+    Skip,
+    /// Move the file to the rejected folder.
+    Reject,
+    /// Insert anyway.
+    Force,
+}
+
+impl DuplicatePolicy {
+    pub(crate) fn parse(value: Option<&str>) -> Self {
+        match value.map(|v| v.trim().to_uppercase()).as_deref() {
+            Some("REJECT") => Self::Reject,
+            Some("FORCE") => Self::Force,
+            _ => Self::Skip,
+        }
+    }
+}
+
+pub(crate) fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// When and under which name a file with the same content was ingested.
+pub(crate) async fn find_ingested_file(
+    pool: &Pool<Sqlite>,
+    line_id: i64,
+    hash: &str,
+) -> Option<(String, String)> {
+    sqlx::query_as::<_, (String, String)>(
+        "SELECT filename, ingested_at FROM ingested_files WHERE line_id = ? AND content_hash = ?",
+    )
+    .bind(line_id)
+    .bind(hash)
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Keys (e.g. SSCC values) among `keys` already ingested for the line.
+pub(crate) async fn find_ingested_keys(
+    pool: &Pool<Sqlite>,
+    line_id: i64,
+    keys: &[String],
+) -> HashSet<String> {
+    let mut found = HashSet::new();
+    for chunk in keys.chunks(KEYS_PER_QUERY) {
+        let sql = format!(
+            "SELECT key_value FROM ingested_keys WHERE line_id = ? AND key_value IN ({})",
+            vec!["?"; chunk.len()].join(", ")
+        );
+        let mut query = sqlx::query_scalar::<_, String>(&sql).bind(line_id);
+        for key in chunk {
+            query = query.bind(key);
+        }
+        if let Ok(rows) = query.fetch_all(pool).await {
+            found.extend(rows);
+        }
+    }
+    found
+}
+
+/// Remember a successfully inserted file and its row keys.
+pub(crate) async fn record_ingested(
+    pool: &Pool<Sqlite>,
+    line_id: i64,
+    hash: &str,
+    filename: &str,
+    keys: &[String],
+) -> Result<(), sqlx::Error> {
+    let now = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        "INSERT OR IGNORE INTO ingested_files (line_id, content_hash, filename, ingested_at) VALUES (?, ?, ?, ?)",
+    )
+    .bind(line_id)
+    .bind(hash)
+    .bind(filename)
+    .bind(&now)
+    .execute(&mut *tx)
+    .await?;
+
+    for key in keys {
+        sqlx::query(
+            "INSERT OR IGNORE INTO ingested_keys (line_id, key_value, filename, ingested_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(line_id)
+        .bind(key)
+        .bind(filename)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn pool_with_lines() -> Pool<Sqlite> {
+        let pool = crate::migrations::memory_pool().await;
+        sqlx::query(
+            "INSERT INTO lines (id, name, path, prefix) VALUES (1, 'L1', '/in', 'A'), (2, 'L2', '/in2', 'A')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool
+    }
+
+    fn keys(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn policy_defaults_to_skip() {
+        assert_eq!(
+            DuplicatePolicy::parse(Some(" reject ")),
+            DuplicatePolicy::Reject
+        );
+        assert_eq!(
+            DuplicatePolicy::parse(Some("FORCE")),
+            DuplicatePolicy::Force
+        );
+        assert_eq!(DuplicatePolicy::parse(Some("other")), DuplicatePolicy::Skip);
+        assert_eq!(DuplicatePolicy::parse(None), DuplicatePolicy::Skip);
+    }
+
+    #[test]
+    fn hash_is_sha256_of_the_content() {
+        assert_eq!(
+            content_hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_ne!(content_hash("A;1\n"), content_hash("A;1\r\n"));
+    }
+
+    #[tokio::test]
+    async fn ingested_files_are_found_per_line() {
+        let pool = pool_with_lines().await;
+        let hash = content_hash("A;1\n");
+        assert_eq!(find_ingested_file(&pool, 1, &hash).await, None);
+
+        record_ingested(&pool, 1, &hash, "a.csv", &[])
+            .await
+            .unwrap();
+        // Same content again under another name keeps the first record.
+        record_ingested(&pool, 1, &hash, "b.csv", &[])
+            .await
+            .unwrap();
+
+        let (filename, _) = find_ingested_file(&pool, 1, &hash).await.unwrap();
+        assert_eq!(filename, "a.csv");
+        assert_eq!(find_ingested_file(&pool, 2, &hash).await, None);
+    }
+
+    #[tokio::test]
+    async fn ingested_keys_are_found_per_line() {
+        let pool = pool_with_lines().await;
+        record_ingested(&pool, 1, "h1", "a.csv", &keys(&["S1", "S2"]))
+            .await
+            .unwrap();
+        record_ingested(&pool, 2, "h2", "b.csv", &keys(&["S3"]))
+            .await
+            .unwrap();
+
+        let found = find_ingested_keys(&pool, 1, &keys(&["S2", "S3", "S4"])).await;
+        assert_eq!(found, HashSet::from(["S2".to_string()]));
+        assert!(find_ingested_keys(&pool, 1, &[]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn many_keys_are_looked_up_in_chunks() {
+        let pool = pool_with_lines().await;
+        let all: Vec<String> = (0..KEYS_PER_QUERY * 2 + 7)
+            .map(|i| format!("S{}", i))
+            .collect();
+        let stored: Vec<String> = all.iter().step_by(3).cloned().collect();
+        record_ingested(&pool, 1, "h1", "a.csv", &stored)
+            .await
+            .unwrap();
+
+        let found = find_ingested_keys(&pool, 1, &all).await;
+        assert_eq!(found, stored.into_iter().collect::<HashSet<_>>());
+    }
+}
@@ -0,0 +1,280 @@
+use crate::commands::lines::Line;
+use crate::stock::encoding::resolve_encoding;
+use crate::stock::fixed_width::FixedWidth;
+
+pub(crate) struct DataRecords {
+    pub header: Option<csv::StringRecord>,
+    pub records: Vec<csv::StringRecord>,
+    pub error: Option<String>,
+}
+
+/// How a line's files are split into records. Defaults match the historical
+/// ATEIS format: `;`-separated, `"` quoted, no header, no footer.
+#[derive(Debug, Clone)]
+pub(crate) struct CsvDialect {
+    pub delimiter: u8,
+    pub quote: Option<u8>,
+    pub header_rows: usize,
+    pub footer_rows: usize,
+    pub comment_prefix: Option<String>,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self {
+            delimiter: b';',
+            quote: Some(b'"'),
+            header_rows: 0,
+            footer_rows: 0,
+            comment_prefix: None,
+        }
+    }
+}
+
+impl CsvDialect {
+    pub(crate) fn from_settings(
+        delimiter: Option<&str>,
+        quote: Option<&str>,
+        header_rows: Option<i64>,
+        footer_rows: Option<i64>,
+        comment_prefix: Option<&str>,
+    ) -> Result<Self, String> {
+        let default = Self::default();
+        let delimiter = match delimiter.filter(|d| !d.is_empty()) {
+            Some(d) => parse_separator(d).ok_or_else(|| format!("Séparateur invalide: {}", d))?,
+            None => default.delimiter,
+        };
+        let quote = match quote {
+            None => default.quote,
+            Some(q) if q.is_empty() || q.eq_ignore_ascii_case("NONE") => None,
+            Some(q) => Some(
+                parse_separator(q)
+                    .ok_or_else(|| format!("Caractère de citation invalide: {}", q))?,
+            ),
+        };
+        if quote == Some(delimiter) {
+            return Err(
+                "Le séparateur et le caractère de citation doivent être différents".to_string(),
+            );
+        }
+
+        Ok(Self {
+            delimiter,
+            quote,
+            header_rows: header_rows.unwrap_or(0).max(0) as usize,
+            footer_rows: footer_rows.unwrap_or(0).max(0) as usize,
+            comment_prefix: comment_prefix
+                .filter(|p| !p.trim().is_empty())
+                .map(|p| p.to_string()),
+        })
+    }
+
+    pub(crate) fn reader<'a>(&self, content: &'a str) -> csv::Reader<&'a [u8]> {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .has_headers(false)
+            .flexible(true);
+        match self.quote {
+            Some(q) => builder.quote(q),
+            None => builder.quoting(false),
+        };
+        builder.from_reader(content.as_bytes())
+    }
+
+    /// Drop comment lines, then the configured header and footer rows. The last
+    /// header row is returned separately so mappings can reference columns by name.
+    pub(crate) fn data_records(&self, content: &str) -> DataRecords {
+        let mut records = Vec::new();
+        let mut error = None;
+
+        for result in self.reader(content).records() {
+            match result {
+                Ok(record) => {
+                    if self.is_comment(&record) {
+                        continue;
+                    }
+                    records.push(record);
+                }
+                Err(e) => {
+                    error = Some(e.to_string());
+                    break;
+                }
+            }
+        }
+
+        let start = self.header_rows.min(records.len());
+        let header = start.checked_sub(1).map(|i| records[i].clone());
+        // A truncated file (parse error) has no footer to strip.
+        let footer = if error.is_none() { self.footer_rows } else { 0 };
+        let end = records.len().saturating_sub(footer).max(start);
+        DataRecords {
+            header,
+            records: records.drain(start..end).collect(),
+            error,
+        }
+    }
+
+    /// Same header/footer/comment handling for line-oriented (fixed-width) files.
+    /// Blank lines are ignored.
+    pub(crate) fn data_lines<'a>(&self, content: &'a str) -> Vec<&'a str> {
+        let lines: Vec<&str> = content
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .filter(|l| match &self.comment_prefix {
+                Some(prefix) => !l.trim_start().starts_with(prefix.as_str()),
+                None => true,
+            })
+            .collect();
+
+        let start = self.header_rows.min(lines.len());
+        let end = lines.len().saturating_sub(self.footer_rows).max(start);
+        lines[start..end].to_vec()
+    }
+
+    fn is_comment(&self, record: &csv::StringRecord) -> bool {
+        match (&self.comment_prefix, record.get(0)) {
+            (Some(prefix), Some(first)) => first.trim_start().starts_with(prefix.as_str()),
+            _ => false,
+        }
+    }
+}
+
+/// Accepts a single ASCII character or a name for the usual invisible ones.
+fn parse_separator(value: &str) -> Option<u8> {
+    match value.to_uppercase().as_str() {
+        "\\T" | "TAB" | "TABULATION" => Some(b'\t'),
+        "SPACE" | "ESPACE" => Some(b' '),
+        _ => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii() => Some(c as u8),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Reject dialect and encoding settings the processor would not be able to use.
+pub(crate) fn validate_file_settings(line: &Line) -> Result<(), String> {
+    if line.csv_header_rows.unwrap_or(0) < 0 || line.csv_footer_rows.unwrap_or(0) < 0 {
+        return Err("Le nombre de lignes d'en-tête/pied doit être positif".to_string());
+    }
+
+    CsvDialect::from_settings(
+        line.csv_delimiter.as_deref(),
+        line.csv_quote.as_deref(),
+        line.csv_header_rows,
+        line.csv_footer_rows,
+        line.csv_comment_prefix.as_deref(),
+    )?;
+
+    if let Some(enc) = line
+        .file_encoding
+        .as_deref()
+        .map(str::trim)
+        .filter(|e| !e.is_empty() && !e.eq_ignore_ascii_case("AUTO"))
+    {
+        resolve_encoding(enc)?;
+    }
+
+    match line
+        .input_mode
+        .as_deref()
+        .map(|m| m.trim().to_uppercase())
+        .as_deref()
+    {
+        None | Some("") | Some("CSV") => {}
+        Some("FIXED") => {
+            FixedWidth::from_settings(line.fixed_trim.as_deref(), line.fixed_pad_char.as_deref())?;
+        }
+        Some(other) => return Err(format!("Mode de lecture inconnu: {}", other)),
+    }
+
+    match line
+        .invalid_rows_policy
+        .as_deref()
+        .map(|p| p.trim().to_uppercase())
+        .as_deref()
+    {
+        None | Some("") | Some("REJECT_FILE") | Some("SPLIT") => {}
+        Some(other) => return Err(format!("Politique de lignes invalides inconnue: {}", other)),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dialect(header_rows: i64, footer_rows: i64, comment_prefix: Option<&str>) -> CsvDialect {
+        CsvDialect::from_settings(
+            Some(";"),
+            None,
+            Some(header_rows),
+            Some(footer_rows),
+            comment_prefix,
+        )
+        .unwrap()
+    }
+
+    fn fields(records: &[csv::StringRecord]) -> Vec<Vec<&str>> {
+        records.iter().map(|r| r.iter().collect()).collect()
+    }
+
+    #[test]
+    fn settings_accept_names_and_refuse_clashes() {
+        let tab =
+            CsvDialect::from_settings(Some("tab"), Some("none"), None, None, Some("  ")).unwrap();
+        assert_eq!(tab.delimiter, b'\t');
+        assert_eq!(tab.quote, None);
+        assert_eq!(tab.comment_prefix, None);
+        assert!(CsvDialect::from_settings(Some(";;"), None, None, None, None).is_err());
+        assert!(CsvDialect::from_settings(Some("é"), None, None, None, None).is_err());
+        assert!(CsvDialect::from_settings(Some("'"), Some("'"), None, None, None).is_err());
+    }
+
+    #[test]
+    fn records_skip_comments_header_and_footer() {
+        let content = "# export\nCODE;QTE\n# note\nA;1\nB;\"2;5\"\nTOTAL;2\n";
+        let data = dialect(1, 1, Some("#")).data_records(content);
+        assert_eq!(data.error, None);
+        assert_eq!(
+            data.header.unwrap().iter().collect::<Vec<_>>(),
+            vec!["CODE", "QTE"]
+        );
+        assert_eq!(
+            fields(&data.records),
+            vec![vec!["A", "1"], vec!["B", "2;5"]]
+        );
+    }
+
+    #[test]
+    fn records_of_a_short_file_are_empty() {
+        let data = dialect(2, 3, None).data_records("CODE;QTE\nA;1\nB;2\n");
+        assert!(data.records.is_empty());
+        assert_eq!(
+            data.header.unwrap().iter().collect::<Vec<_>>(),
+            vec!["A", "1"]
+        );
+
+        let data = dialect(0, 0, None).data_records("");
+        assert!(data.header.is_none());
+        assert!(data.records.is_empty());
+    }
+
+    #[test]
+    fn lines_skip_blanks_comments_header_and_footer() {
+        let content = "HDR 2024\r\n\r\n  -- comment\r\n0001AB\r\n0002CD\r\nEND\r\n";
+        assert_eq!(
+            dialect(1, 1, Some("--")).data_lines(content),
+            vec!["0001AB", "0002CD"]
+        );
+        assert_eq!(
+            dialect(0, 0, None).data_lines(content),
+            vec!["HDR 2024", "  -- comment", "0001AB", "0002CD", "END"]
+        );
+        assert!(dialect(4, 4, None).data_lines(content).is_empty());
+    }
+}
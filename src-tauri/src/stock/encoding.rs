@@ -1,20 +1,107 @@
-use encoding_rs::{UTF_8, WINDOWS_1252};
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
 use std::fs;
 use std::path::Path;
 
-/// Read file with multiple encoding attempts (like Python's encoding fallback)
-pub(crate) fn read_file_with_encoding_fallback(path: &Path) -> Result<String, String> {
+/// Read a file with the line's configured encoding. `None`, empty or `AUTO` keeps the
+/// fallback behaviour, after honoring a UTF-8/UTF-16 byte order mark if present.
+pub(crate) fn read_file_with_encoding(
+    path: &Path,
+    encoding: Option<&str>,
+) -> Result<String, String> {
     let bytes = fs::read(path).map_err(|e| e.to_string())?;
 
-    if let Ok(s) = std::str::from_utf8(&bytes) {
-        return Ok(s.to_string());
+    let label = match encoding.map(str::trim).filter(|l| !l.is_empty()) {
+        Some(l) if !l.eq_ignore_ascii_case("AUTO") => l,
+        _ => {
+            if let Some((enc, bom_len)) = Encoding::for_bom(&bytes) {
+                let (cow, _) = enc.decode_without_bom_handling(&bytes[bom_len..]);
+                return Ok(cow.into_owned());
+            }
+            return Ok(decode_with_fallback(&bytes));
+        }
+    };
+
+    let enc = resolve_encoding(label)?;
+    // `decode` lets a BOM override the configured encoding, then strips it.
+    let (cow, used, had_errors) = enc.decode(&bytes);
+    if had_errors {
+        return Err(format!("Contenu invalide pour l'encodage {}", used.name()));
     }
+    Ok(cow.into_owned())
+}
 
-    let (cow, _, had_errors) = UTF_8.decode(&bytes);
+/// Map an encoding label (`UTF-8`, `UTF-16LE`, `windows-1252`, `latin1`...) to its decoder.
+pub(crate) fn resolve_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| format!("Encodage inconnu: {}", label))
+}
+
+/// Multiple encoding attempts (like Python's encoding fallback)
+fn decode_with_fallback(bytes: &[u8]) -> String {
+    if let Ok(s) = std::str::from_utf8(bytes) {
+        return s.to_string();
+    }
+
+    let (cow, _, had_errors) = UTF_8.decode(bytes);
     if !had_errors {
-        return Ok(cow.into_owned());
+        return cow.into_owned();
     }
 
-    let (cow, _, _) = WINDOWS_1252.decode(&bytes);
-    Ok(cow.into_owned())
+    let (cow, _, _) = WINDOWS_1252.decode(bytes);
+    cow.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(name: &str, bytes: &[u8], encoding: Option<&str>) -> Result<String, String> {
+        let path =
+            std::env::temp_dir().join(format!("visor-encoding-{}-{}", std::process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        let result = read_file_with_encoding(&path, encoding);
+        let _ = fs::remove_file(&path);
+        result
+    }
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn auto_honors_byte_order_marks() {
+        let mut le = vec![0xFF, 0xFE];
+        le.extend(utf16le("Qté;5"));
+        assert_eq!(read("le", &le, None).unwrap(), "Qté;5");
+
+        let mut be = vec![0xFE, 0xFF];
+        be.extend("Qté;5".encode_utf16().flat_map(u16::to_be_bytes));
+        assert_eq!(read("be", &be, Some("auto")).unwrap(), "Qté;5");
+
+        assert_eq!(
+            read("utf8", b"\xEF\xBB\xBFQt\xC3\xA9", Some("")).unwrap(),
+            "Qté"
+        );
+    }
+
+    #[test]
+    fn auto_falls_back_to_windows_1252() {
+        assert_eq!(read("1252", b"Qt\xE9 \x80", None).unwrap(), "Qté €");
+    }
+
+    #[test]
+    fn configured_encoding_is_strict() {
+        assert_eq!(
+            read("le-nobom", &utf16le("Qté"), Some("UTF-16LE")).unwrap(),
+            "Qté"
+        );
+        assert_eq!(read("latin1", b"Qt\xE9", Some("latin1")).unwrap(), "Qté");
+        // A BOM still wins over the configured encoding.
+        assert_eq!(
+            read("bom", b"\xEF\xBB\xBFQt\xC3\xA9", Some("windows-1252")).unwrap(),
+            "Qté"
+        );
+        assert!(read("bad", b"Qt\xE9", Some("UTF-8")).is_err());
+        assert!(resolve_encoding("klingon").is_err());
+    }
 }
@@ -0,0 +1,91 @@
+/// Which side of a fixed-width field the padding is stripped from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TrimRule {
+    Both,
+    Left,
+    Right,
+    None,
+}
+
+impl TrimRule {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_uppercase().as_str() {
+            "" | "BOTH" | "TRIM" => Some(Self::Both),
+            "LEFT" | "LTRIM" => Some(Self::Left),
+            "RIGHT" | "RTRIM" => Some(Self::Right),
+            "NONE" | "RAW" => Some(Self::None),
+            _ => None,
+        }
+    }
+
+    fn apply(self, field: &str, pad: char) -> String {
+        let stripped = match self {
+            Self::Both => field.trim_matches(pad),
+            Self::Left => field.trim_start_matches(pad),
+            Self::Right => field.trim_end_matches(pad),
+            Self::None => return field.to_string(),
+        };
+        // A zero-padded field made only of zeros is the value 0, not an empty value.
+        if stripped.is_empty() && pad == '0' && !field.is_empty() {
+            return "0".to_string();
+        }
+        stripped.to_string()
+    }
+}
+
+/// Settings for lines whose files hold fixed-width records. Mappings then
+/// reference fields as `start:length` (0-based, in characters), optionally
+/// followed by a trim rule overriding the line's one, e.g. `12:8:left`.
+#[derive(Debug, Clone)]
+pub(crate) struct FixedWidth {
+    pub trim: TrimRule,
+    pub pad_char: char,
+}
+
+impl FixedWidth {
+    pub(crate) fn from_settings(
+        trim: Option<&str>,
+        pad_char: Option<&str>,
+    ) -> Result<Self, String> {
+        let trim = match trim {
+            Some(t) => {
+                TrimRule::parse(t).ok_or_else(|| format!("Règle de découpe invalide: {}", t))?
+            }
+            None => TrimRule::Both,
+        };
+        let pad_char = match pad_char.filter(|p| !p.is_empty()) {
+            Some(p) => {
+                let mut chars = p.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => return Err(format!("Caractère de remplissage invalide: {}", p)),
+                }
+            }
+            None => ' ',
+        };
+        Ok(Self { trim, pad_char })
+    }
+
+    /// Value of the field described by `spec` in `line`, or `None` when `spec`
+    /// is not a valid `start:length[:rule]` range. A short line yields a short
+    /// (possibly empty) field.
+    pub(crate) fn extract(&self, line: &str, spec: &str) -> Option<String> {
+        let (start, length, rule) = parse_range(spec)?;
+        let field: String = line.chars().skip(start).take(length).collect();
+        Some(rule.unwrap_or(self.trim).apply(&field, self.pad_char))
+    }
+}
+
+fn parse_range(spec: &str) -> Option<(usize, usize, Option<TrimRule>)> {
+    let mut parts = spec.split(':');
+    let start = parts.next()?.trim().parse::<usize>().ok()?;
+    let length = parts.next()?.trim().parse::<usize>().ok()?;
+    let rule = match parts.next() {
+        Some(r) => Some(TrimRule::parse(r)?),
+        None => None,
+    };
+    if parts.next().is_some() || length == 0 {
+        return None;
+    }
+    Some((start, length, rule))
+}
@@ -8,6 +8,13 @@ pub(crate) fn is_file_locked(path: &Path) -> bool {
     }
 }
 
+/// Whether a file in a watch folder is one the line should process.
+pub(crate) fn is_input_file(filename: &str, prefix: &str) -> bool {
+    let upper = filename.to_uppercase();
+    let allowed_ext = upper.ends_with(".TMP") || upper.ends_with(".CSV") || upper.ends_with(".TXT");
+    allowed_ext && upper.contains(&prefix.to_uppercase())
+}
+
 pub(crate) fn scan_existing_files(path: &Path, prefix: &str) -> Vec<PathBuf> {
     let mut matches = Vec::new();
 
@@ -23,9 +30,7 @@ pub(crate) fn scan_existing_files(path: &Path, prefix: &str) -> Vec<PathBuf> {
                 None => continue,
             };
 
-            let upper = filename.to_uppercase();
-            let allowed_ext = upper.ends_with(".TMP") || upper.ends_with(".CSV") || upper.ends_with(".TXT");
-            if allowed_ext && upper.contains(&prefix.to_uppercase()) {
+            if is_input_file(filename, prefix) {
                 matches.push(p);
             }
         }
@@ -33,3 +38,35 @@ pub(crate) fn scan_existing_files(path: &Path, prefix: &str) -> Vec<PathBuf> {
 
     matches
 }
+
+/// Move `src` into `dir` as `<stem>_<timestamp>.<ext>`, creating `dir` if needed.
+pub(crate) fn move_to_dir_with_timestamp(
+    src: &Path,
+    dir: &Path,
+    filename: &str,
+) -> std::io::Result<PathBuf> {
+    if !dir.exists() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let dest = dir.join(timestamped_filename(filename, None));
+    fs::rename(src, &dest)?;
+    Ok(dest)
+}
+
+/// `name_YYYYmmdd_HHMMSS.ext`, or `name_YYYYmmdd_HHMMSS.<extension>` when an
+/// extension replacement is given.
+pub(crate) fn timestamped_filename(filename: &str, extension: Option<&str>) -> String {
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+    let name = Path::new(filename);
+    let ext = extension
+        .or_else(|| name.extension().and_then(|s| s.to_str()))
+        .map(|s| format!(".{}", s))
+        .unwrap_or_default();
+    format!(
+        "{}_{}{}",
+        name.file_stem().and_then(|s| s.to_str()).unwrap_or(filename),
+        timestamp,
+        ext
+    )
+}
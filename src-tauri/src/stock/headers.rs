@@ -0,0 +1,199 @@
+use crate::commands::mappings::MappingRow;
+use std::collections::HashMap;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Header names are matched ignoring case, accents and surrounding spaces.
+pub(crate) fn normalize_header(name: &str) -> String {
+    name.trim()
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn is_index_reference(file_column: &str) -> bool {
+    let col = file_column.trim();
+    if col.parse::<usize>().is_ok() {
+        return true;
+    }
+    match col.split_once('-') {
+        Some((a, b)) => a.trim().parse::<usize>().is_ok() && b.trim().parse::<usize>().is_ok(),
+        None => false,
+    }
+}
+
+/// The header name a mapping reads from, if it reads a file column by name.
+/// Mappings filled from a line parameter never read the file.
+fn header_reference(m: &MappingRow) -> Option<&str> {
+    if m.parameter.as_deref().is_some_and(|p| !p.is_empty()) {
+        return None;
+    }
+    m.file_column
+        .as_deref()
+        .filter(|c| !c.trim().is_empty() && !is_index_reference(c))
+}
+
+/// Whether any mapping references a column by header name rather than by index.
+pub(crate) fn uses_header_names(mappings: &[MappingRow]) -> bool {
+    mappings.iter().any(|m| header_reference(m).is_some())
+}
+
+/// Rewrite header-name references (`Lot`, `Date-Heure`, `N° SSCC?`...) into column
+/// indexes for this file. A trailing `?` marks the column optional: it is left
+/// empty when the header is absent. Missing required headers are an error.
+pub(crate) fn resolve_header_columns(
+    mappings: &[MappingRow],
+    header: Option<&csv::StringRecord>,
+) -> Result<Vec<MappingRow>, String> {
+    if !uses_header_names(mappings) {
+        return Ok(mappings.to_vec());
+    }
+
+    let header = header.ok_or(
+        "Colonnes référencées par nom d'en-tête mais aucune ligne d'en-tête n'est configurée",
+    )?;
+
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for (i, name) in header.iter().enumerate() {
+        index.entry(normalize_header(name)).or_insert(i);
+    }
+    let lookup = |name: &str| -> Option<String> {
+        if let Ok(i) = name.trim().parse::<usize>() {
+            return Some(i.to_string());
+        }
+        index.get(&normalize_header(name)).map(|i| i.to_string())
+    };
+
+    let mut missing = Vec::new();
+    let mut resolved = Vec::with_capacity(mappings.len());
+    for m in mappings {
+        let mut m = m.clone();
+        if let Some(col) = header_reference(&m).map(|c| c.trim().to_string()) {
+            let (name, optional) = match col.strip_suffix('?') {
+                Some(n) => (n, true),
+                None => (col.as_str(), false),
+            };
+
+            // A whole-name match wins, so headers containing '-' keep working;
+            // otherwise `A-B` joins two columns like the index form does.
+            let found = lookup(name).or_else(|| {
+                let (a, b) = name.split_once('-')?;
+                Some(format!("{}-{}", lookup(a)?, lookup(b)?))
+            });
+
+            match found {
+                Some(c) => m.file_column = Some(c),
+                None if optional => m.file_column = None,
+                None => missing.push(name.to_string()),
+            }
+        }
+        resolved.push(m);
+    }
+
+    if !missing.is_empty() {
+        return Err(format!(
+            "En-tête(s) manquant(s) dans le fichier: {}",
+            missing.join(", ")
+        ));
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(sql_field: &str, file_column: &str, parameter: Option<&str>) -> MappingRow {
+        MappingRow {
+            id: None,
+            line_id: 1,
+            sort_order: 0,
+            sql_field: sql_field.to_string(),
+            file_column: Some(file_column.to_string()),
+            parameter: parameter.map(str::to_string),
+            transformation: None,
+            description: None,
+            enabled: Some(true),
+            validation: None,
+            date_format: None,
+            sql_type: None,
+        }
+    }
+
+    fn header() -> csv::StringRecord {
+        csv::StringRecord::from(vec![" N° SSCC ", "Date", "Heure", "Qté", "Date-Heure"])
+    }
+
+    fn columns(mappings: &[MappingRow]) -> Vec<Option<String>> {
+        resolve_header_columns(mappings, Some(&header()))
+            .unwrap()
+            .into_iter()
+            .map(|m| m.file_column)
+            .collect()
+    }
+
+    #[test]
+    fn names_match_ignoring_case_accents_and_spaces() {
+        let cols = columns(&[
+            mapping("SSCC", "n° sscc", None),
+            mapping("QTY", " QTE ", None),
+            mapping("LOT", "7", None),
+            mapping("FCY", "Site", Some("site")),
+        ]);
+        assert_eq!(
+            cols,
+            vec![
+                Some("0".to_string()),
+                Some("3".to_string()),
+                Some("7".to_string()),
+                Some("Site".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn dash_joins_two_columns_unless_a_header_has_the_whole_name() {
+        let cols = columns(&[
+            mapping("A", "Date-Heure", None),
+            mapping("B", "date - HEURE?", None),
+            mapping("C", "Heure-Qté", None),
+            mapping("D", "Heure-4", None),
+        ]);
+        assert_eq!(
+            cols,
+            vec![
+                Some("4".to_string()),
+                Some("1-2".to_string()),
+                Some("2-3".to_string()),
+                Some("2-4".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn optional_columns_may_be_absent() {
+        let cols = columns(&[mapping("A", "Poids?", None), mapping("B", "Qté?", None)]);
+        assert_eq!(cols, vec![None, Some("3".to_string())]);
+    }
+
+    #[test]
+    fn missing_headers_are_listed() {
+        let err = resolve_header_columns(
+            &[
+                mapping("A", "Poids", None),
+                mapping("B", "Date", None),
+                mapping("C", "Lot-Date", None),
+            ],
+            Some(&header()),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "En-tête(s) manquant(s) dans le fichier: Poids, Lot-Date"
+        );
+
+        assert!(resolve_header_columns(&[mapping("A", "Date", None)], None).is_err());
+        // Index-only mappings need no header row.
+        assert!(resolve_header_columns(&[mapping("A", "1-2", None)], None).is_ok());
+    }
+}
@@ -4,6 +4,26 @@ mod processor;
 mod encoding;
 mod fs_utils;
 mod transforms;
+mod dates;
+mod dedup;
+mod dialect;
+mod fixed_width;
+mod headers;
+mod pipeline;
+mod validation;
+mod sql_types;
+mod template;
+pub(crate) mod queue;
 
+pub(crate) use dates::{parse_timezone, TIMEZONE_KEY};
+pub(crate) use dialect::validate_file_settings;
+pub(crate) use fs_utils::is_input_file;
+pub(crate) use pipeline::check_transformations;
+pub(crate) use sql_types::{check_sql_types, SqlType};
+pub(crate) use template::{check_sql_template, insert_target, parse_insert_columns, SqlTemplate};
+pub(crate) use validation::check_mapping_rules;
+pub use processor::{FileOutcome, FilePreview, StockProcessor};
 pub use registry::WatcherState;
+#[cfg(feature = "desktop")]
 pub use watcher::{start_watcher, stop_watcher};
+pub(crate) use watcher::{unwatch_line, watch_line};
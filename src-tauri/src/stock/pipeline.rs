@@ -0,0 +1,631 @@
+use crate::commands::mappings::MappingRow;
+use crate::stock::dates::{DateOptions, TransformContext};
+use crate::stock::transforms::{apply_split, apply_transformation};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use regex::Regex;
+use std::fmt::Write;
+
+/// Historical single-name transformations, still usable as pipeline steps.
+const LEGACY_STEPS: &[&str] = &[
+    "date",
+    "heure",
+    "datetime",
+    "decimal",
+    "tinyint",
+    "current_datetime",
+    "datetime_combine",
+];
+
+/// Legacy steps whose parsing and output follow the mapping's date options.
+const DATE_STEPS: &[&str] = &[
+    "date",
+    "heure",
+    "datetime",
+    "current_datetime",
+    "datetime_combine",
+];
+
+#[derive(Debug, Clone)]
+enum Step {
+    Legacy(String),
+    SplitPlus(&'static str),
+    Trim,
+    LTrim,
+    RTrim,
+    Upper,
+    Lower,
+    Split {
+        sep: String,
+        index: usize,
+    },
+    Truncate(usize),
+    Substring {
+        start: usize,
+        len: Option<usize>,
+    },
+    Pad {
+        width: usize,
+        fill: char,
+        left: bool,
+    },
+    Replace {
+        from: String,
+        to: String,
+    },
+    Capture {
+        re: Regex,
+        group: usize,
+    },
+    Default(String),
+    Lookup {
+        table: Vec<(String, String)>,
+        fallback: Option<String>,
+    },
+    Date {
+        format_in: String,
+        format_out: String,
+    },
+}
+
+/// A mapping's `transformation`: steps separated by `|`, applied left to right,
+/// e.g. `trim | split('+', 0) | truncate(10) | upper`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Pipeline {
+    steps: Vec<Step>,
+    dates: DateOptions,
+}
+
+impl Pipeline {
+    pub(crate) fn parse(source: &str) -> Result<Self, String> {
+        let mut steps = Vec::new();
+        for part in split_top_level(source, '|')? {
+            let part = part.trim();
+            if part.is_empty() {
+                if source.trim().is_empty() {
+                    continue;
+                }
+                return Err("étape vide dans la chaîne de transformations".to_string());
+            }
+            steps.push(parse_step(part)?);
+        }
+        Ok(Self {
+            steps,
+            dates: DateOptions::default(),
+        })
+    }
+
+    /// Pipeline of a mapping: its transformation chain plus its date options.
+    pub(crate) fn compile(transformation: &str, date_format: Option<&str>) -> Result<Self, String> {
+        let mut pipeline = Self::parse(transformation)?;
+        if let Some(raw) = date_format.map(str::trim).filter(|f| !f.is_empty()) {
+            pipeline.dates =
+                DateOptions::parse(raw).map_err(|e| format!("options de date: {}", e))?;
+        }
+        Ok(pipeline)
+    }
+
+    /// Errors only come from unparsable dates; the row is then invalid. With lenient
+    /// date options the value is replaced instead and the replacement added to `substitutions`.
+    pub(crate) fn apply(
+        &self,
+        value: String,
+        ctx: &TransformContext,
+        substitutions: &mut Vec<String>,
+    ) -> Result<String, String> {
+        self.steps.iter().try_fold(value, |v, step| {
+            step.apply(v, &self.dates, ctx, substitutions)
+        })
+    }
+}
+
+impl Step {
+    fn apply(
+        &self,
+        value: String,
+        dates: &DateOptions,
+        ctx: &TransformContext,
+        substitutions: &mut Vec<String>,
+    ) -> Result<String, String> {
+        let out = match self {
+            Step::Legacy(name) if DATE_STEPS.contains(&name.as_str()) => {
+                dates.convert(name, value, ctx, substitutions)?
+            }
+            Step::Legacy(name) => apply_transformation(value, name, ctx.now),
+            Step::SplitPlus(part) => apply_split(&value, part),
+            Step::Trim => value.trim().to_string(),
+            Step::LTrim => value.trim_start().to_string(),
+            Step::RTrim => value.trim_end().to_string(),
+            Step::Upper => value.to_uppercase(),
+            Step::Lower => value.to_lowercase(),
+            Step::Split { sep, index } => value
+                .split(sep.as_str())
+                .nth(*index)
+                .unwrap_or("")
+                .to_string(),
+            Step::Truncate(n) => value.chars().take(*n).collect(),
+            Step::Substring { start, len } => {
+                let rest = value.chars().skip(*start);
+                match len {
+                    Some(l) => rest.take(*l).collect(),
+                    None => rest.collect(),
+                }
+            }
+            Step::Pad { width, fill, left } => {
+                let count = value.chars().count();
+                let padding = fill.to_string().repeat(width.saturating_sub(count));
+                if *left {
+                    padding + &value
+                } else {
+                    value + &padding
+                }
+            }
+            Step::Replace { from, to } => value.replace(from.as_str(), to),
+            Step::Capture { re, group } => re
+                .captures(&value)
+                .and_then(|c| c.get(*group))
+                .map(|m| m.as_str().to_string())
+                .unwrap_or_default(),
+            Step::Default(default) => {
+                if value.trim().is_empty() {
+                    default.clone()
+                } else {
+                    value
+                }
+            }
+            Step::Lookup { table, fallback } => {
+                let key = value.trim();
+                match table.iter().find(|(k, _)| k == key) {
+                    Some((_, v)) => v.clone(),
+                    None => fallback.clone().unwrap_or(value),
+                }
+            }
+            Step::Date {
+                format_in,
+                format_out,
+            } => match reformat_date(&value, format_in, format_out) {
+                Some(v) => v,
+                None if !dates.lenient => {
+                    return Err(format!(
+                        "date illisible '{}' (format {})",
+                        value.trim(),
+                        format_in
+                    ))
+                }
+                None => {
+                    substitutions.push(format!(
+                        "date illisible '{}' (format {}) conservée telle quelle",
+                        value.trim(),
+                        format_in
+                    ));
+                    value
+                }
+            },
+        };
+        Ok(out)
+    }
+}
+
+/// Parse `value` with `format_in` (date-time, date or time) and render it with `format_out`.
+fn reformat_date(value: &str, format_in: &str, format_out: &str) -> Option<String> {
+    let v = value.trim();
+    let mut out = String::new();
+    let written = if let Ok(dt) = NaiveDateTime::parse_from_str(v, format_in) {
+        write!(out, "{}", dt.format(format_out))
+    } else if let Ok(d) = NaiveDate::parse_from_str(v, format_in) {
+        write!(out, "{}", d.and_hms_opt(0, 0, 0)?.format(format_out))
+    } else if let Ok(t) = NaiveTime::parse_from_str(v, format_in) {
+        // Rendering a date field from a time alone fails and is caught below.
+        write!(out, "{}", t.format(format_out))
+    } else {
+        return None;
+    };
+    written.ok().map(|_| out)
+}
+
+fn parse_step(step: &str) -> Result<Step, String> {
+    let (name, args) = match step.find('(') {
+        Some(open) => {
+            let inner = step[open + 1..]
+                .strip_suffix(')')
+                .ok_or_else(|| format!("parenthèse fermante manquante: {}", step))?;
+            (step[..open].trim(), parse_args(inner)?)
+        }
+        None => (step, Vec::new()),
+    };
+    let lower = name.to_lowercase();
+
+    let expect = |min: usize, max: usize| -> Result<(), String> {
+        if args.len() < min || args.len() > max {
+            let expected = if min == max {
+                min.to_string()
+            } else {
+                format!("{} à {}", min, max)
+            };
+            return Err(format!(
+                "{}: {} argument(s) attendu(s), {} fourni(s)",
+                name,
+                expected,
+                args.len()
+            ));
+        }
+        Ok(())
+    };
+
+    let step = match lower.as_str() {
+        "date" | "date_format" if lower == "date_format" || !args.is_empty() => {
+            // `date` without arguments is the historical heuristic parser above.
+            expect(2, 2)?;
+            for f in &args {
+                if f.is_empty() || StrftimeItems::new(f).any(|i| matches!(i, Item::Error)) {
+                    return Err(format!("date: format invalide: {}", f));
+                }
+            }
+            Step::Date {
+                format_in: args[0].clone(),
+                format_out: args[1].clone(),
+            }
+        }
+        l if LEGACY_STEPS.contains(&l) => {
+            expect(0, 0)?;
+            Step::Legacy(lower.clone())
+        }
+        "split_before_plus" | "split_after_plus" => {
+            expect(0, 0)?;
+            Step::SplitPlus(if lower == "split_before_plus" {
+                "before"
+            } else {
+                "after"
+            })
+        }
+        "trim" => expect(0, 0).map(|_| Step::Trim)?,
+        "ltrim" => expect(0, 0).map(|_| Step::LTrim)?,
+        "rtrim" => expect(0, 0).map(|_| Step::RTrim)?,
+        "upper" => expect(0, 0).map(|_| Step::Upper)?,
+        "lower" => expect(0, 0).map(|_| Step::Lower)?,
+        "split" => {
+            expect(2, 2)?;
+            if args[0].is_empty() {
+                return Err("split: séparateur vide".to_string());
+            }
+            Step::Split {
+                sep: args[0].clone(),
+                index: parse_number(name, &args[1])?,
+            }
+        }
+        "truncate" => {
+            expect(1, 1)?;
+            Step::Truncate(parse_number(name, &args[0])?)
+        }
+        "substring" => {
+            expect(1, 2)?;
+            Step::Substring {
+                start: parse_number(name, &args[0])?,
+                len: args.get(1).map(|a| parse_number(name, a)).transpose()?,
+            }
+        }
+        "pad" => {
+            expect(1, 3)?;
+            let fill = match args.get(1).map(String::as_str) {
+                None => ' ',
+                Some(f) => {
+                    let mut chars = f.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => c,
+                        _ => return Err(format!("pad: caractère de remplissage invalide: {}", f)),
+                    }
+                }
+            };
+            let left = match args.get(2).map(|s| s.to_lowercase()).as_deref() {
+                None | Some("left") => true,
+                Some("right") => false,
+                Some(other) => return Err(format!("pad: côté invalide (left/right): {}", other)),
+            };
+            Step::Pad {
+                width: parse_number(name, &args[0])?,
+                fill,
+                left,
+            }
+        }
+        "replace" => {
+            expect(2, 2)?;
+            if args[0].is_empty() {
+                return Err("replace: texte à remplacer vide".to_string());
+            }
+            Step::Replace {
+                from: args[0].clone(),
+                to: args[1].clone(),
+            }
+        }
+        "regex" => {
+            expect(1, 2)?;
+            let re = Regex::new(&args[0]).map_err(|e| format!("regex: {}", e))?;
+            let group = match args.get(1) {
+                Some(g) => parse_number(name, g)?,
+                None if re.captures_len() > 1 => 1,
+                None => 0,
+            };
+            if group >= re.captures_len() {
+                return Err(format!("regex: groupe {} inexistant", group));
+            }
+            Step::Capture { re, group }
+        }
+        "default" => {
+            expect(1, 1)?;
+            Step::Default(args[0].clone())
+        }
+        "lookup" => {
+            if args.is_empty() {
+                return Err("lookup: au moins une correspondance 'clé=valeur' attendue".to_string());
+            }
+            let mut table = Vec::new();
+            let mut fallback = None;
+            for arg in &args {
+                match arg.split_once('=') {
+                    Some((k, v)) => table.push((k.trim().to_string(), v.to_string())),
+                    None if fallback.is_none() => fallback = Some(arg.clone()),
+                    None => return Err(format!("lookup: correspondance invalide: {}", arg)),
+                }
+            }
+            Step::Lookup { table, fallback }
+        }
+        _ => return Err(format!("transformation inconnue: {}", name)),
+    };
+    Ok(step)
+}
+
+fn parse_number(step: &str, arg: &str) -> Result<usize, String> {
+    arg.trim()
+        .parse::<usize>()
+        .map_err(|_| format!("{}: nombre attendu, reçu '{}'", step, arg))
+}
+
+/// Split on `sep` outside quotes and parentheses.
+fn split_top_level(source: &str, sep: char) -> Result<Vec<String>, String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut depth = 0usize;
+    let mut chars = source.chars();
+
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) => {
+                current.push(c);
+                if c == '\\' {
+                    if let Some(next) = chars.next() {
+                        current.push(next);
+                    }
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None => match c {
+                '\'' | '"' => {
+                    quote = Some(c);
+                    current.push(c);
+                }
+                '(' => {
+                    depth += 1;
+                    current.push(c);
+                }
+                ')' => {
+                    depth = depth.checked_sub(1).ok_or("parenthèse fermante en trop")?;
+                    current.push(c);
+                }
+                c if c == sep && depth == 0 => parts.push(std::mem::take(&mut current)),
+                c => current.push(c),
+            },
+        }
+    }
+
+    if quote.is_some() {
+        return Err("guillemet non fermé".to_string());
+    }
+    if depth != 0 {
+        return Err("parenthèse non fermée".to_string());
+    }
+    parts.push(current);
+    Ok(parts)
+}
+
+/// Arguments are quoted strings (`'+'`, `"a,b"`, with `\'`, `\"`, `\\`, `\t`, `\n`
+/// escapes) or bare words/numbers.
+fn parse_args(inner: &str) -> Result<Vec<String>, String> {
+    if inner.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    split_top_level(inner, ',')?
+        .into_iter()
+        .map(|raw| {
+            let arg = raw.trim();
+            let quoted = arg.len() >= 2
+                && ((arg.starts_with('\'') && arg.ends_with('\''))
+                    || (arg.starts_with('"') && arg.ends_with('"')));
+            if !quoted {
+                if arg.is_empty() {
+                    return Err("argument vide".to_string());
+                }
+                return Ok(arg.to_string());
+            }
+            let mut out = String::new();
+            let mut chars = arg[1..arg.len() - 1].chars();
+            while let Some(c) = chars.next() {
+                if c == '\\' {
+                    match chars.next() {
+                        Some('t') => out.push('\t'),
+                        Some('n') => out.push('\n'),
+                        Some(q @ ('\'' | '"' | '\\')) => out.push(q),
+                        // Kept as is so regex classes like `\d` survive.
+                        Some(other) => {
+                            out.push('\\');
+                            out.push(other);
+                        }
+                        None => out.push('\\'),
+                    }
+                } else {
+                    out.push(c);
+                }
+            }
+            Ok(out)
+        })
+        .collect()
+}
+
+/// One compiled pipeline per mapping, in mapping order.
+pub(crate) fn compile_transformations(mappings: &[MappingRow]) -> Result<Vec<Pipeline>, String> {
+    mappings
+        .iter()
+        .map(|m| {
+            Pipeline::compile(
+                m.transformation.as_deref().unwrap_or(""),
+                m.date_format.as_deref(),
+            )
+            .map_err(|e| format!("Transformation invalide pour {}: {}", m.sql_field, e))
+        })
+        .collect()
+}
+
+/// Check that every mapping's transformation chain parses; used when mappings are saved.
+pub(crate) fn check_transformations(mappings: &[MappingRow]) -> Result<(), String> {
+    compile_transformations(mappings).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn ctx() -> TransformContext {
+        TransformContext {
+            now: NaiveDate::from_ymd_opt(2024, 1, 31)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap(),
+            tz: None,
+        }
+    }
+
+    fn run(source: &str, value: &str) -> String {
+        Pipeline::parse(source)
+            .unwrap()
+            .apply(value.to_string(), &ctx(), &mut Vec::new())
+            .unwrap()
+    }
+
+    #[test]
+    fn chain_applies_left_to_right() {
+        assert_eq!(
+            run("split('+',0) | truncate(10)", "ABCDEFGHIJKL+123"),
+            "ABCDEFGHIJ"
+        );
+        assert_eq!(run("trim | split('+', 1) | upper", "  lot+ab12 "), "AB12");
+        assert_eq!(run("", " as is "), " as is ");
+    }
+
+    #[test]
+    fn separators_inside_quotes_are_arguments() {
+        assert_eq!(run("replace('|', '-') | upper", "a|b"), "A-B");
+        assert_eq!(run("split(\",\", 1)", "a,b,c"), "b");
+        assert_eq!(run("replace(')', '(')", "x)"), "x(");
+        assert_eq!(run("lookup('A=x|y', 'B=z', '?')", "A"), "x|y");
+        assert_eq!(run("lookup('A=x|y', 'B=z', '?')", "C"), "?");
+    }
+
+    #[test]
+    fn quoted_arguments_unescape() {
+        assert_eq!(run(r"replace('\'', '\\')", "l'an"), r"l\an");
+        assert_eq!(run(r"replace(';', '\t')", "a;b"), "a\tb");
+        // Unknown escapes stay so regex classes work.
+        assert_eq!(run(r"regex('(\d+)')", "lot 042 b"), "042");
+    }
+
+    #[test]
+    fn legacy_steps_still_parse() {
+        assert_eq!(run("split_before_plus", "A+B"), "A");
+        assert_eq!(run("decimal", "1 234,5"), "1234.5");
+        assert_eq!(run("heure", "20240131143000"), "143000");
+        assert_eq!(run("date", "20240131"), "31/01/2024");
+        assert_eq!(run("date('%Y%m%d', '%d.%m.%Y')", "20240131"), "31.01.2024");
+    }
+
+    #[test]
+    fn malformed_chains_are_rejected() {
+        for source in [
+            "trim | | upper",
+            "trim |",
+            "split('+', 0",
+            "split('+, 0)",
+            "truncate(10))",
+            "truncate(x)",
+            "split('', 0)",
+            "upper(1)",
+            "unknown",
+            "date('%Q', '%d')",
+        ] {
+            assert!(
+                Pipeline::parse(source).is_err(),
+                "{} should not parse",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn unparsable_dates_invalidate_the_row_by_default() {
+        let mut substitutions = Vec::new();
+        for (source, value) in [
+            ("date", "pas une date"),
+            ("datetime", ""),
+            ("datetime_combine", "31/01/2024"),
+            ("heure", "14h"),
+            ("date('%Y%m%d', '%d/%m/%Y')", "2024-01-31"),
+        ] {
+            let pipeline = Pipeline::parse(source).unwrap();
+            assert!(
+                pipeline
+                    .apply(value.to_string(), &ctx(), &mut substitutions)
+                    .is_err(),
+                "{} should reject '{}'",
+                source,
+                value
+            );
+        }
+        assert!(substitutions.is_empty());
+    }
+
+    #[test]
+    fn lenient_dates_are_replaced_and_reported() {
+        let mut substitutions = Vec::new();
+        let pipeline = Pipeline::compile("datetime", Some(r#"{"lenient": true}"#)).unwrap();
+        let value = pipeline
+            .apply("??".to_string(), &ctx(), &mut substitutions)
+            .unwrap();
+        assert_eq!(value, "31/01/2024 12:00:00");
+        assert_eq!(substitutions.len(), 1);
+        assert!(substitutions[0].contains("'??'"));
+    }
+
+    #[test]
+    fn heure_reads_digit_times_without_input_formats() {
+        let pipeline = Pipeline::compile("heure", Some("{}")).unwrap();
+        let mut substitutions = Vec::new();
+        for (value, expected) in [
+            ("143000", "143000"),
+            ("1430", "143000"),
+            ("14:30:15", "143015"),
+            ("202401311430", "143000"),
+        ] {
+            assert_eq!(
+                pipeline
+                    .apply(value.to_string(), &ctx(), &mut substitutions)
+                    .unwrap(),
+                expected
+            );
+        }
+        assert!(pipeline
+            .apply("256000".to_string(), &ctx(), &mut substitutions)
+            .is_err());
+        assert!(substitutions.is_empty());
+    }
+}
@@ -1,78 +1,232 @@
-use crate::commands::sql_server::SqlServerConfig;
-use crate::stock::encoding::read_file_with_encoding_fallback;
-use crate::stock::fs_utils::is_file_locked;
-use crate::stock::transforms::{apply_split, apply_transformation};
+use crate::commands::mappings::{load_effective_mappings, MappingRow};
+use crate::commands::sql_server::{check_connectable, load_sql_profile, query_timeout, SqlClient};
+use crate::sql_pool::SqlServerPools;
+use crate::stock::dates::{load_timezone, TransformContext};
+use crate::stock::dedup::{self, DuplicatePolicy};
+use crate::stock::dialect::CsvDialect;
+use crate::stock::encoding::read_file_with_encoding;
+use crate::stock::fixed_width::FixedWidth;
+use crate::stock::fs_utils::{is_file_locked, is_input_file, move_to_dir_with_timestamp};
+use crate::stock::headers::resolve_header_columns;
+use crate::stock::pipeline::{compile_transformations, Pipeline};
+use crate::stock::queue::{self, QueueItem, RetryPolicy, QUEUE_DIR_NAME};
+use crate::stock::sql_types::{ColumnTypes, SqlValue};
+use crate::stock::template::SqlTemplate;
+use crate::stock::validation::{write_rejected_rows, InvalidRowsPolicy, RowValidator};
 use chrono::Local;
 use log::info;
-use serde::Deserialize;
+use serde::Serialize;
 use serde_json::json;
-use sqlx::{FromRow, Pool, Row, Sqlite};
+use sqlx::{Pool, Row, Sqlite};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
-use tiberius::{AuthMethod, Client, Config as SqlConfig, ToSql};
-use tokio_util::compat::TokioAsyncWriteCompatExt;
+use std::time::{Duration, Instant};
+use tiberius::ToSql;
+
+/// SQL Server accepts at most 1000 row constructors per VALUES clause.
+const MAX_VALUES_ROWS: usize = 1000;
+/// SQL Server accepts at most 2100 parameters per request; keep some margin.
+const MAX_BATCH_PARAMS: usize = 2000;
+
+/// Parameters bound for one mapped row, in `@P1..@Pn` order.
+fn build_row_params(template: &SqlTemplate, mapped: &HashMap<String, String>) -> Vec<String> {
+    template
+        .fields
+        .iter()
+        .map(|f| mapped.get(f).cloned().unwrap_or_default())
+        .collect()
+}
 
-fn parse_insert_columns(query: &str) -> Vec<String> {
+/// Rewrite every `@Pn` placeholder of `sql` as `@P(n + offset)`.
+fn shift_placeholders(sql: &str, offset: usize) -> String {
+    let bytes = sql.as_bytes();
+    let mut out = String::with_capacity(sql.len() + 16);
+    let mut i = 0;
+    let mut copied = 0;
+
+    while i + 1 < bytes.len() {
+        if bytes[i] == b'@' && (bytes[i + 1] == b'P' || bytes[i + 1] == b'p') {
+            let start = i + 2;
+            let mut end = start;
+            while end < bytes.len() && bytes[end].is_ascii_digit() {
+                end += 1;
+            }
+            if end > start {
+                let n: usize = sql[start..end].parse().unwrap_or(0);
+                out.push_str(&sql[copied..i]);
+                out.push_str(&format!("@P{}", n + offset));
+                copied = end;
+                i = end;
+                continue;
+            }
+        }
+        i += 1;
+    }
+
+    out.push_str(&sql[copied..]);
+    out
+}
+
+/// Highest `@Pn` index used in `sql`.
+fn max_placeholder(sql: &str) -> usize {
+    let lower = sql.to_lowercase();
+    lower
+        .match_indices("@p")
+        .filter_map(|(pos, _)| {
+            let digits: String = lower[pos + 2..]
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            digits.parse::<usize>().ok()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Expand an `INSERT ... VALUES (...)` template into a multi-row insert of
+/// `row_count` tuples, each using `stride` consecutive parameters.
+/// Returns `None` when the template is not a single-tuple INSERT.
+fn build_batched_insert(query: &str, stride: usize, row_count: usize) -> Option<String> {
     let lower = query.to_lowercase();
-    let insert_pos = lower.find("insert");
-    if insert_pos.is_none() {
-        return Vec::new();
+    if !lower.trim_start().starts_with("insert") {
+        return None;
     }
 
-    let open_paren = query[insert_pos.unwrap()..]
-        .find('(')
-        .map(|i| i + insert_pos.unwrap());
-    let values_pos = lower.find(") values");
-    if open_paren.is_none() || values_pos.is_none() {
-        return Vec::new();
+    let values_pos = lower.rfind("values")?;
+    let open = values_pos + query[values_pos..].find('(')?;
+    if !query[values_pos + "values".len()..open].trim().is_empty() {
+        return None;
+    }
+
+    // Parentheses inside string literals ('' escapes toggle twice) do not count.
+    let mut depth = 0usize;
+    let mut in_literal = false;
+    let mut close = None;
+    for (i, c) in query[open..].char_indices() {
+        match c {
+            '\'' => in_literal = !in_literal,
+            _ if in_literal => {}
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(open + i);
+                    break;
+                }
+            }
+            _ => {}
+        }
     }
+    let close = close?;
 
-    let open = open_paren.unwrap();
-    let close = values_pos.unwrap();
-    if close <= open {
-        return Vec::new();
+    let rest = &query[close + 1..];
+    if !rest.trim().trim_end_matches(';').trim().is_empty() {
+        return None;
     }
 
-    query[open + 1..close]
-        .split(',')
-        .map(|s| {
-            s.trim()
-                .trim_matches('[')
-                .trim_matches(']')
-                .trim()
-                .to_string()
-        })
-        .filter(|s| !s.is_empty())
-        .collect()
+    let tuple = &query[open..=close];
+    if max_placeholder(tuple) > stride {
+        return None;
+    }
+
+    let tuples: Vec<String> = (0..row_count)
+        .map(|k| shift_placeholders(tuple, k * stride))
+        .collect();
+
+    Some(format!("{}{}{}", &query[..open], tuples.join(",\n"), rest))
 }
 
-fn build_param_values_from_query(query: &str, mapped: &HashMap<String, String>) -> Vec<String> {
-    let cols = parse_insert_columns(query);
-    if cols.is_empty() {
-        return Vec::new();
+/// Why `insert_rows` stopped.
+enum RowsError {
+    /// The server answered with an error: the connection is still usable.
+    Server(String),
+    /// No answer in time. The response may still arrive, so nothing else may
+    /// be sent on the connection, not even a ROLLBACK.
+    TimedOut(String),
+}
+
+impl RowsError {
+    fn context(self, prefix: String) -> Self {
+        match self {
+            RowsError::Server(e) => RowsError::Server(format!("{}: {}", prefix, e)),
+            RowsError::TimedOut(e) => RowsError::TimedOut(format!("{}: {}", prefix, e)),
+        }
     }
-    cols.iter()
-        .map(|c| mapped.get(c).cloned().unwrap_or_default())
-        .collect()
 }
 
-pub struct StockProcessor {
-    pool: Pool<Sqlite>,
+async fn execute_within(
+    client: &mut SqlClient,
+    sql: &str,
+    params: &[&dyn ToSql],
+    timeout: Duration,
+) -> Result<(), RowsError> {
+    match tokio::time::timeout(timeout, client.execute(sql, params)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(RowsError::Server(e.to_string())),
+        Err(_) => Err(RowsError::TimedOut(format!(
+            "Délai d'exécution dépassé ({}s)",
+            timeout.as_secs()
+        ))),
+    }
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Clone, FromRow, Deserialize)]
-struct MappingRow {
-    pub id: Option<i64>,
-    pub line_id: i64,
-    pub sort_order: i64,
-    pub sql_field: String,
-    pub file_column: Option<String>,
-    pub parameter: Option<String>,
-    pub transformation: Option<String>,
-    pub description: Option<String>,
+/// Insert all rows on an open connection, either one statement per row or
+/// grouped into multi-row VALUES statements when `batch_size` > 1.
+async fn insert_rows(
+    client: &mut SqlClient,
+    query: &str,
+    all_params: &[Vec<SqlValue>],
+    batch_size: usize,
+    timeout: Duration,
+) -> Result<usize, RowsError> {
+    let stride = all_params.first().map(|p| p.len()).unwrap_or(0);
+    let per_batch = batch_size
+        .min(MAX_VALUES_ROWS)
+        .min(MAX_BATCH_PARAMS / stride.max(1))
+        .max(1);
+    let uniform = all_params.iter().all(|p| p.len() == stride);
+
+    let mut inserted = 0usize;
+
+    if per_batch > 1 && uniform && build_batched_insert(query, stride, 1).is_some() {
+        for chunk in all_params.chunks(per_batch) {
+            let sql = build_batched_insert(query, stride, chunk.len()).ok_or_else(|| {
+                RowsError::Server("Template SQL incompatible avec l'insertion par lots".to_string())
+            })?;
+            let params_refs: Vec<&dyn ToSql> = chunk
+                .iter()
+                .flat_map(|p| p.iter().map(SqlValue::as_sql))
+                .collect();
+            execute_within(client, &sql, &params_refs, timeout)
+                .await
+                .map_err(|e| {
+                    e.context(format!(
+                        "Lot de {} lignes après la ligne {}",
+                        chunk.len(),
+                        inserted
+                    ))
+                })?;
+            inserted += chunk.len();
+        }
+        return Ok(inserted);
+    }
+
+    for params in all_params {
+        let params_refs: Vec<&dyn ToSql> = params.iter().map(SqlValue::as_sql).collect();
+        execute_within(client, query, &params_refs, timeout)
+            .await
+            .map_err(|e| e.context(format!("Ligne {}", inserted + 1)))?;
+        inserted += 1;
+    }
+
+    Ok(inserted)
+}
+
+#[derive(Clone)]
+pub struct StockProcessor {
+    pool: Pool<Sqlite>,
+    sql: SqlServerPools,
 }
 
 #[derive(Debug, Clone)]
@@ -85,6 +239,172 @@ struct LineConfig {
     pub log_path: Option<String>,
     pub file_format: Option<String>,
     pub rejected_path: Option<String>,
+    pub insert_batch_size: Option<i64>,
+    pub duplicate_policy: Option<String>,
+    pub dedup_key_field: Option<String>,
+    pub csv_delimiter: Option<String>,
+    pub csv_quote: Option<String>,
+    pub csv_header_rows: Option<i64>,
+    pub csv_footer_rows: Option<i64>,
+    pub csv_comment_prefix: Option<String>,
+    pub file_encoding: Option<String>,
+    pub input_mode: Option<String>,
+    pub fixed_trim: Option<String>,
+    pub fixed_pad_char: Option<String>,
+    pub invalid_rows_policy: Option<String>,
+    pub sql_profile_id: Option<i64>,
+}
+
+impl LineConfig {
+    fn dialect(&self) -> Result<CsvDialect, String> {
+        CsvDialect::from_settings(
+            self.csv_delimiter.as_deref(),
+            self.csv_quote.as_deref(),
+            self.csv_header_rows,
+            self.csv_footer_rows,
+            self.csv_comment_prefix.as_deref(),
+        )
+    }
+
+    /// Fixed-width settings, or `None` for delimited (CSV) lines.
+    fn fixed_width(&self) -> Result<Option<FixedWidth>, String> {
+        match self
+            .input_mode
+            .as_deref()
+            .map(|m| m.trim().to_uppercase())
+            .as_deref()
+        {
+            Some("FIXED") => FixedWidth::from_settings(
+                self.fixed_trim.as_deref(),
+                self.fixed_pad_char.as_deref(),
+            )
+            .map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Rows of a file after parsing and mapping. `raw` holds each row's fields as read,
+/// for the invalid-rows companion file, and `row_errors` the transformation errors
+/// (unparsable dates) that make a row invalid. `substitutions` lists the dates replaced
+/// under lenient date options. `error` holds the first parse error; rows read before it are kept.
+struct ParsedContent {
+    rows: Vec<HashMap<String, String>>,
+    raw: Vec<Vec<String>>,
+    row_errors: Vec<Vec<String>>,
+    substitutions: Vec<String>,
+    delimiter: u8,
+    error: Option<String>,
+}
+
+impl ParsedContent {
+    fn failed(error: String, delimiter: u8) -> Self {
+        Self {
+            rows: Vec::new(),
+            raw: Vec::new(),
+            row_errors: Vec::new(),
+            substitutions: Vec::new(),
+            delimiter,
+            error: Some(error),
+        }
+    }
+}
+
+/// Result of running a file through a line's parsing and mapping without inserting it.
+#[derive(Debug, Serialize)]
+pub struct FilePreview {
+    pub line_id: i64,
+    pub format_name: String,
+    pub total_rows: usize,
+    pub mappings: Vec<MappingRow>,
+    pub query: Option<String>,
+    pub columns: Vec<String>,
+    pub rows: Vec<PreviewRow>,
+    pub content_hash: String,
+    pub already_ingested: Option<String>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreviewRow {
+    pub index: usize,
+    pub mapped: HashMap<String, String>,
+    pub params: Vec<String>,
+    pub invalid: Vec<String>,
+}
+
+/// A file taken out of the watch folder (or the retry queue) and ready to be ingested.
+struct PendingFile {
+    line_id: i64,
+    filename: String,
+    work_path: PathBuf,
+    queue_dir: PathBuf,
+    archived_path: Option<String>,
+    queued: Option<QueueItem>,
+}
+
+/// What became of a file handed to `process_file`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FileOutcome {
+    /// Left where it was: gone, not an input file of the line, or still being written.
+    Ignored { reason: String },
+    /// Rows committed to SQL Server.
+    Inserted { rows: usize },
+    /// Already ingested: archived without inserting anything.
+    Skipped { reason: String },
+    /// SQL Server unreachable: moved to the retry queue.
+    Queued {
+        attempts: i64,
+        next_attempt_at: String,
+        error: String,
+    },
+    /// Moved to the rejected folder (or deleted when there is none).
+    Rejected { error: String },
+    /// COMMIT not confirmed: to be checked in SQL Server before sending it again.
+    InDoubt { error: String },
+}
+
+impl std::fmt::Display for FileOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileOutcome::Ignored { reason } => write!(f, "non traité: {}", reason),
+            FileOutcome::Inserted { rows } => write!(f, "intégré, {} ligne(s) insérée(s)", rows),
+            FileOutcome::Skipped { reason } => write!(f, "ignoré (doublon): {}", reason),
+            FileOutcome::Queued {
+                attempts,
+                next_attempt_at,
+                error,
+            } => write!(
+                f,
+                "en file d'attente (tentative {}, prochain essai {}): {}",
+                attempts, next_attempt_at, error
+            ),
+            FileOutcome::Rejected { error } => write!(f, "rejeté: {}", error),
+            FileOutcome::InDoubt { error } => write!(f, "à vérifier: {}", error),
+        }
+    }
+}
+
+/// Why a file's inserts failed.
+enum InsertError {
+    /// Nothing was committed: the file can be retried.
+    Failed(String),
+    /// COMMIT was sent but not confirmed: the rows may be in SQL Server.
+    /// Retrying could insert them twice, so the file goes to manual review.
+    InDoubt(String),
+}
+
+impl From<String> for InsertError {
+    fn from(e: String) -> Self {
+        InsertError::Failed(e)
+    }
+}
+
+impl From<&str> for InsertError {
+    fn from(e: &str) -> Self {
+        InsertError::Failed(e.to_string())
+    }
 }
 
 struct DiskLogger;
@@ -97,7 +417,7 @@ impl DiskLogger {
         };
 
         if let Err(e) = fs::create_dir_all(log_dir) {
-            eprintln!("Failed to create log dir: {}", e);
+            log::warn!("Failed to create log dir: {}", e);
             return;
         }
 
@@ -134,7 +454,7 @@ impl DiskLogger {
         };
 
         if let Err(e) = fs::create_dir_all(log_dir) {
-            eprintln!("Failed to create log dir: {}", e);
+            log::warn!("Failed to create log dir: {}", e);
             return;
         }
 
@@ -175,12 +495,8 @@ impl DiskLogger {
 }
 
 impl StockProcessor {
-    pub fn new(pool: Pool<Sqlite>) -> Self {
-        Self { pool }
-    }
-
-    pub(crate) fn pool_clone(&self) -> Pool<Sqlite> {
-        self.pool.clone()
+    pub fn new(pool: Pool<Sqlite>, sql: SqlServerPools) -> Self {
+        Self { pool, sql }
     }
 
     async fn execute_sql_server_inserts(
@@ -188,27 +504,10 @@ impl StockProcessor {
         line_config: Option<&LineConfig>,
         mappings: &[MappingRow],
         rows: &[HashMap<String, String>],
-    ) -> Result<(), String> {
-        let cfg = self
-            .load_sql_server_config()
-            .await
-            .ok_or("SQL Server non configuré")?;
-
-        if !cfg.enabled {
-            return Err("SQL Server désactivé (activez la connexion dans Paramètres)".to_string());
-        }
+    ) -> Result<usize, InsertError> {
+        let cfg = load_sql_profile(&self.pool, line_config.and_then(|l| l.sql_profile_id)).await?;
 
-        if cfg.server.as_deref().unwrap_or("").trim().is_empty() {
-            return Err("SQL Server: serveur manquant".to_string());
-        }
-
-        if cfg.username.as_deref().unwrap_or("").trim().is_empty() {
-            return Err("SQL Server: utilisateur manquant".to_string());
-        }
-
-        if cfg.password.as_deref().unwrap_or("").trim().is_empty() {
-            return Err("SQL Server: mot de passe manquant".to_string());
-        }
+        check_connectable(&cfg)?;
 
         let format_name = line_config
             .and_then(|l| l.file_format.clone())
@@ -219,63 +518,103 @@ impl StockProcessor {
             .await
             .ok_or("Template SQL manquant")?;
 
-        let mut tiberius_config = SqlConfig::new();
-        tiberius_config.host(cfg.server.as_deref().unwrap_or(""));
-        tiberius_config.port(1433);
-        tiberius_config.authentication(AuthMethod::sql_server(
-            cfg.username.unwrap_or_default(),
-            cfg.password.unwrap_or_default(),
-        ));
-        tiberius_config.trust_cert();
-        if let Some(db) = cfg.database {
-            tiberius_config.database(db);
+        let connect_started = Instant::now();
+        let mut client = self.sql.get(&cfg).await?;
+        crate::metrics::observe_sql_connect(connect_started.elapsed());
+
+        let template = SqlTemplate::compile(&query, mappings)?;
+        let types = ColumnTypes::new(mappings)?;
+        let all_params: Vec<Vec<SqlValue>> = rows
+            .iter()
+            .enumerate()
+            .map(|(i, mapped)| {
+                types
+                    .bind(&template.fields, mapped)
+                    .map_err(|e| format!("Ligne {}: {}", i + 1, e))
+            })
+            .collect::<Result<_, _>>()?;
+
+        if let Some(mapped) = rows.first() {
+            let fcy = mapped.get("FCY_0").cloned().unwrap_or_default();
+            info!("SQL params order: {:?}", template.fields);
+            info!("SQL mapped FCY_0: {}", fcy);
         }
 
-        let tcp = tokio::net::TcpStream::connect(tiberius_config.get_addr())
-            .await
-            .map_err(|e| e.to_string())?;
-        tcp.set_nodelay(true).map_err(|e| e.to_string())?;
-        let mut client = Client::connect(tiberius_config, tcp.compat_write())
-            .await
-            .map_err(|e| e.to_string())?;
-
-        let mut logged_debug = false;
-        for mapped in rows {
-            let mut params = build_param_values_from_query(&query, mapped);
-            if params.is_empty() {
-                params = mappings
-                    .iter()
-                    .map(|m| mapped.get(&m.sql_field).cloned().unwrap_or_default())
-                    .collect();
-            }
-
-            if !logged_debug {
-                logged_debug = true;
-                let cols = parse_insert_columns(&query);
-                let fcy = mapped.get("FCY_0").cloned().unwrap_or_default();
-                info!("SQL params order: {:?}", cols);
-                info!("SQL mapped FCY_0: {}", fcy);
-            }
-
-            let owned: Vec<String> = params;
-            let params_refs: Vec<&dyn ToSql> = owned.iter().map(|s| s as &dyn ToSql).collect();
-            client
-                .execute(query.as_str(), &params_refs[..])
-                .await
-                .map_err(|e| e.to_string())?;
+        let batch_size = line_config
+            .and_then(|l| l.insert_batch_size)
+            .unwrap_or(1)
+            .max(1) as usize;
+
+        // The whole file is one transaction: either every row is committed or none is.
+        let insert_started = Instant::now();
+        let observe = |status| crate::metrics::observe_sql_insert(insert_started.elapsed(), status);
+        let begun = match client.simple_query("BEGIN TRANSACTION").await {
+            Ok(stream) => stream.into_results().await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = begun {
+            observe("failed");
+            return Err(e.to_string().into());
         }
 
-        Ok(())
-    }
-
-    async fn load_sql_server_config(&self) -> Option<SqlServerConfig> {
-        sqlx::query_as::<_, SqlServerConfig>(
-            "SELECT id, server, database, username, password, enabled FROM sql_server_config WHERE id = 1",
+        match insert_rows(
+            &mut client,
+            &template.sql,
+            &all_params,
+            batch_size,
+            query_timeout(&cfg),
         )
-        .fetch_optional(&self.pool)
         .await
-        .ok()
-        .flatten()
+        {
+            Ok(inserted) => {
+                // Once COMMIT is sent, a failure does not say whether it was applied.
+                let committed = match client.simple_query("COMMIT TRANSACTION").await {
+                    Ok(stream) => stream.into_results().await.map(|_| ()),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = committed {
+                    observe("in_doubt");
+                    return Err(InsertError::InDoubt(format!(
+                        "Validation (COMMIT) non confirmée, {} ligne(s) peut-être insérée(s): {}",
+                        inserted, e
+                    )));
+                }
+                client.release();
+                observe("committed");
+                Ok(inserted)
+            }
+            Err(RowsError::TimedOut(e)) => {
+                // Dropped without a ROLLBACK, whose answer could be mistaken for the
+                // pending INSERT's: SQL Server discards the transaction with the session.
+                drop(client);
+                observe("timed_out");
+                Err(format!(
+                    "{} (connexion fermée, transaction annulée par le serveur)",
+                    e
+                )
+                .into())
+            }
+            Err(RowsError::Server(e)) => {
+                // A connection whose rollback failed is in an unknown state: close it.
+                // SQL Server discards the uncommitted transaction with the session.
+                let rolled_back = match client.simple_query("ROLLBACK TRANSACTION").await {
+                    Ok(stream) => stream.into_results().await.is_ok(),
+                    Err(_) => false,
+                };
+                if rolled_back {
+                    client.release();
+                    observe("rolled_back");
+                    Err(format!("{} (transaction annulée, aucune ligne insérée)", e).into())
+                } else {
+                    observe("failed");
+                    Err(format!(
+                        "{} (transaction non validée, annulation non confirmée: connexion fermée)",
+                        e
+                    )
+                    .into())
+                }
+            }
+        }
     }
 
     async fn load_query_template(&self, format_name: &str) -> Option<String> {
@@ -291,7 +630,7 @@ impl StockProcessor {
 
     async fn load_line_config(&self, line_id: i64) -> Option<LineConfig> {
         let row = sqlx::query(
-            "SELECT name, site, unite, flag_dec, code_ligne, log_path, file_format, rejected_path \n             FROM lines WHERE id = ?",
+            "SELECT name, site, unite, flag_dec, code_ligne, log_path, file_format, rejected_path, insert_batch_size, \n                    duplicate_policy, dedup_key_field, csv_delimiter, csv_quote, csv_header_rows, \n                    csv_footer_rows, csv_comment_prefix, file_encoding, input_mode, fixed_trim, fixed_pad_char, \n                    invalid_rows_policy, sql_profile_id \n             FROM lines WHERE id = ?",
         )
         .bind(line_id)
         .fetch_optional(&self.pool)
@@ -307,6 +646,20 @@ impl StockProcessor {
             log_path: row.get("log_path"),
             file_format: row.get("file_format"),
             rejected_path: row.get("rejected_path"),
+            insert_batch_size: row.get("insert_batch_size"),
+            duplicate_policy: row.get("duplicate_policy"),
+            dedup_key_field: row.get("dedup_key_field"),
+            csv_delimiter: row.get("csv_delimiter"),
+            csv_quote: row.get("csv_quote"),
+            csv_header_rows: row.get("csv_header_rows"),
+            csv_footer_rows: row.get("csv_footer_rows"),
+            csv_comment_prefix: row.get("csv_comment_prefix"),
+            file_encoding: row.get("file_encoding"),
+            input_mode: row.get("input_mode"),
+            fixed_trim: row.get("fixed_trim"),
+            fixed_pad_char: row.get("fixed_pad_char"),
+            invalid_rows_policy: row.get("invalid_rows_policy"),
+            sql_profile_id: row.get("sql_profile_id"),
         })
     }
 
@@ -362,6 +715,7 @@ impl StockProcessor {
             || lower.contains("code: 18456") // Login failed
             || lower.contains("target machine actively refused")
             || lower.contains("sql server désactivé") // Connection disabled within app
+            || msg.starts_with(crate::sql_pool::POOL_SATURATED) // Busy pool: a slot will free up
     }
 
     pub async fn process_file(
@@ -370,18 +724,19 @@ impl StockProcessor {
         path: PathBuf,
         prefix: String,
         archived_path: Option<String>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> Result<FileOutcome, Box<dyn std::error::Error>> {
         if !path.exists() {
-            return Ok(());
+            return Ok(FileOutcome::Ignored {
+                reason: format!("{} introuvable", path.display()),
+            });
         }
 
         let filename = path.file_name().unwrap().to_str().unwrap().to_string();
 
-        let upper = filename.to_uppercase();
-        let allowed_ext =
-            upper.ends_with(".TMP") || upper.ends_with(".CSV") || upper.ends_with(".TXT");
-        if !upper.contains(&prefix.to_uppercase()) || !allowed_ext {
-            return Ok(());
+        if !is_input_file(&filename, &prefix) {
+            return Ok(FileOutcome::Ignored {
+                reason: format!("{} n'est pas un fichier de la ligne", filename),
+            });
         }
 
         let line_config = self.load_line_config(line_id).await;
@@ -390,6 +745,7 @@ impl StockProcessor {
             .map(|c| c.name.clone())
             .unwrap_or_else(|| format!("line_{}", line_id));
         let log_path = line_config.as_ref().and_then(|c| c.log_path.clone());
+        let encoding = line_config.as_ref().and_then(|c| c.file_encoding.clone());
 
         tokio::time::sleep(Duration::from_millis(500)).await;
 
@@ -400,10 +756,12 @@ impl StockProcessor {
                 &format!("Fichier {} en cours d'utilisation", filename),
                 "WARNING",
             );
-            return Ok(());
+            return Ok(FileOutcome::Ignored {
+                reason: format!("{} en cours d'utilisation", filename),
+            });
         }
 
-        let content = match read_file_with_encoding_fallback(&path) {
+        let content = match read_file_with_encoding(&path, encoding.as_deref()) {
             Ok(c) => c,
             Err(e) => {
                 let msg = format!("Erreur lecture fichier {}: {}", filename, e);
@@ -411,6 +769,7 @@ impl StockProcessor {
                 self.add_db_log(line_id, "ERROR", "FileProcessor", &msg, None)
                     .await;
                 self.update_line_stats(line_id, false).await;
+                crate::metrics::record_file(line_id, false, 0);
                 return Err(e.into());
             }
         };
@@ -447,31 +806,267 @@ impl StockProcessor {
             return Err(e.into());
         }
 
-        let mut rdr = csv::ReaderBuilder::new()
-            .delimiter(b';')
-            .has_headers(false)
-            .flexible(true)
-            .from_reader(content.as_bytes());
+        let file = PendingFile {
+            line_id,
+            filename,
+            work_path: temp_path,
+            queue_dir: source_parent.join(QUEUE_DIR_NAME),
+            archived_path,
+            queued: None,
+        };
+        let result = self.ingest(file, line_config, content).await;
+
+        if temp_subdir
+            .read_dir()
+            .map(|rd| rd.count() == 0)
+            .unwrap_or(false)
+        {
+            let _ = fs::remove_dir(&temp_subdir);
+        }
+
+        result
+    }
+
+    /// Retry the queued files of a line whose backoff delay has elapsed.
+    pub async fn process_due_queue(&self, line_id: i64, archived_path: Option<String>) {
+        let items = queue::claim_due(&self.pool, line_id).await;
+        if items.is_empty() {
+            return;
+        }
+
+        let line_config = self.load_line_config(line_id).await;
+        let line_name = line_config
+            .as_ref()
+            .map(|c| c.name.clone())
+            .unwrap_or_else(|| format!("line_{}", line_id));
+        let log_path = line_config.as_ref().and_then(|c| c.log_path.clone());
+        let encoding = line_config.as_ref().and_then(|c| c.file_encoding.clone());
+
+        for item in items {
+            let work_path = PathBuf::from(&item.file_path);
+            let content = match read_file_with_encoding(&work_path, encoding.as_deref()) {
+                Ok(c) => c,
+                Err(e) => {
+                    let msg = format!(
+                        "Fichier en file d'attente {} illisible, retiré de la file: {}",
+                        item.filename, e
+                    );
+                    DiskLogger::log_ligne(&line_name, &log_path, &msg, "ERROR");
+                    self.add_db_log(line_id, "ERROR", "Queue", &msg, None).await;
+                    queue::remove(&self.pool, item.id).await;
+                    continue;
+                }
+            };
+
+            let file = PendingFile {
+                line_id,
+                filename: item.filename.clone(),
+                queue_dir: work_path
+                    .parent()
+                    .map(Path::to_path_buf)
+                    .unwrap_or_else(|| PathBuf::from(".")),
+                work_path,
+                archived_path: archived_path.clone(),
+                queued: Some(item),
+            };
+
+            if let Err(e) = self.ingest(file, line_config.clone(), content).await {
+                log::error!("Error processing queued file: {}", e);
+            }
+        }
+    }
+
+    /// Move a file that could not reach SQL Server into the queue folder and
+    /// schedule its next attempt. Returns the time of the next attempt.
+    async fn defer_file(
+        &self,
+        file: &PendingFile,
+        attempts: i64,
+        error: &str,
+        policy: &RetryPolicy,
+    ) -> Result<String, String> {
+        let queued_path = match &file.queued {
+            Some(item) => PathBuf::from(&item.file_path),
+            None => {
+                fs::create_dir_all(&file.queue_dir).map_err(|e| e.to_string())?;
+                let dest = file.queue_dir.join(format!(
+                    "{}_{}_{}",
+                    file.line_id,
+                    Local::now().format("%Y%m%d%H%M%S%3f"),
+                    file.filename
+                ));
+                fs::rename(&file.work_path, &dest).map_err(|e| e.to_string())?;
+                dest
+            }
+        };
+
+        let next_attempt_at = policy.next_attempt_at(attempts);
+        let scheduled = queue::schedule_retry(
+            &self.pool,
+            file.queued.as_ref().map(|q| q.id),
+            file.line_id,
+            &file.filename,
+            &queued_path.to_string_lossy(),
+            attempts,
+            &next_attempt_at,
+            error,
+        )
+        .await;
+
+        if let Err(e) = scheduled {
+            if file.queued.is_none() {
+                let _ = fs::rename(&queued_path, &file.work_path);
+            }
+            return Err(e.to_string());
+        }
+
+        Ok(next_attempt_at)
+    }
+
+    /// Parse and map a file exactly as `ingest` would, and return the values that would
+    /// be bound, without moving the file or connecting to SQL Server.
+    pub async fn preview(
+        &self,
+        line_id: i64,
+        file_path: Option<&Path>,
+        content: Option<String>,
+        max_rows: usize,
+    ) -> Result<FilePreview, String> {
+        let line_config = self
+            .load_line_config(line_id)
+            .await
+            .ok_or_else(|| format!("Ligne {} introuvable", line_id))?;
+
+        let content = match (content, file_path) {
+            (Some(c), _) => c,
+            (None, Some(path)) => {
+                read_file_with_encoding(path, line_config.file_encoding.as_deref())
+                    .map_err(|e| format!("Erreur lecture fichier {}: {}", path.display(), e))?
+            }
+            (None, None) => return Err("Aucun fichier ni contenu fourni".to_string()),
+        };
+
+        let format_name = line_config
+            .file_format
+            .clone()
+            .unwrap_or_else(|| "ATEIS".to_string());
+        let line_config = Some(line_config);
+
+        let mappings = load_effective_mappings(&self.pool, line_id, &format_name).await?;
+        let query = self.load_query_template(&format_name).await;
+
+        let mut errors = Vec::new();
+        if mappings.is_empty() {
+            errors.push(format!(
+                "Aucun mapping configuré pour le modèle {}",
+                format_name.to_uppercase()
+            ));
+        }
+        if query.is_none() {
+            errors.push("Template SQL manquant".to_string());
+        }
+
+        let ctx = TransformContext::new(load_timezone(&self.pool).await);
+        let parsed = parse_content(&content, &mappings, &line_config, &ctx);
+        if let Some(e) = parsed.error {
+            errors.push(e);
+        }
+        if parsed.rows.is_empty() {
+            errors.push("Fichier vide ou format invalide".to_string());
+        }
+        let validator = match RowValidator::new(&mappings) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                errors.push(e);
+                None
+            }
+        };
+        let types = match ColumnTypes::new(&mappings) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                errors.push(e);
+                None
+            }
+        };
+        let template = match SqlTemplate::compile(query.as_deref().unwrap_or(""), &mappings) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                errors.push(e);
+                None
+            }
+        };
+
+        let content_hash = dedup::content_hash(&content);
+        let already_ingested = dedup::find_ingested_file(&self.pool, line_id, &content_hash)
+            .await
+            .map(|(name, at)| format!("{} le {}", name, at));
+
+        let total_rows = parsed.rows.len();
+        let rows = parsed
+            .rows
+            .into_iter()
+            .zip(parsed.row_errors)
+            .take(max_rows)
+            .enumerate()
+            .map(|(index, (mapped, mut invalid))| {
+                if let Some(v) = &validator {
+                    invalid.extend(v.check(&mapped));
+                }
+                if let Some(t) = &types {
+                    invalid.extend(t.check(&mapped));
+                }
+                PreviewRow {
+                    index,
+                    params: template
+                        .as_ref()
+                        .map(|t| build_row_params(t, &mapped))
+                        .unwrap_or_default(),
+                    invalid,
+                    mapped,
+                }
+            })
+            .collect();
+
+        Ok(FilePreview {
+            line_id,
+            format_name,
+            total_rows,
+            columns: template.map(|t| t.fields).unwrap_or_default(),
+            mappings,
+            query,
+            rows,
+            content_hash,
+            already_ingested,
+            errors,
+        })
+    }
+
+    async fn ingest(
+        &self,
+        file: PendingFile,
+        line_config: Option<LineConfig>,
+        content: String,
+    ) -> Result<FileOutcome, Box<dyn std::error::Error>> {
+        let line_id = file.line_id;
+        let filename = file.filename.as_str();
+        let temp_path = file.work_path.as_path();
+        let line_name = line_config
+            .as_ref()
+            .map(|c| c.name.clone())
+            .unwrap_or_else(|| format!("line_{}", line_id));
+        let log_path = line_config.as_ref().and_then(|c| c.log_path.clone());
 
         let format_name = line_config
             .as_ref()
             .and_then(|l| l.file_format.clone())
             .unwrap_or_else(|| "ATEIS".to_string());
 
-        let mappings = sqlx::query_as::<_, MappingRow>(
-            "SELECT id, 0 as line_id, sort_order, sql_field, file_column, parameter, transformation, description \
-         FROM model_mappings WHERE format_name = ? ORDER BY sort_order ASC, id ASC",
-        )
-        .bind(format_name.to_uppercase())
-        .fetch_all(&self.pool)
-        .await
-        .unwrap_or_default();
+        let mappings = load_effective_mappings(&self.pool, line_id, &format_name)
+            .await
+            .unwrap_or_default();
 
-        let mut row_count = 0;
-        let mut first_mapped: Option<serde_json::Value> = None;
         let mut had_error = false;
         let mut error_msg: Option<String> = None;
-        let mut all_mapped_values: Vec<HashMap<String, String>> = Vec::new();
 
         if mappings.is_empty() {
             had_error = true;
@@ -481,62 +1076,243 @@ impl StockProcessor {
             ));
         }
 
-        for result in rdr.records() {
-            let record = match result {
-                Ok(r) => r,
+        let ctx = TransformContext::new(load_timezone(&self.pool).await);
+        let parsed = parse_content(&content, &mappings, &line_config, &ctx);
+        if let Some(e) = parsed.error {
+            had_error = true;
+            error_msg = Some(e);
+        }
+        // Lenient date options: every replaced value is logged and kept in production_data.
+        let date_substitutions = parsed.substitutions;
+        if !date_substitutions.is_empty() {
+            for substitution in &date_substitutions {
+                let msg = format!("{}: {}", filename, substitution);
+                DiskLogger::log_ligne(&line_name, &log_path, &msg, "WARNING");
+            }
+            let msg = format!(
+                "{} date(s) illisible(s) remplacée(s) dans {} (options de date tolérantes): {}",
+                date_substitutions.len(),
+                filename,
+                date_substitutions
+                    .iter()
+                    .take(5)
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            );
+            self.add_db_log(line_id, "WARNING", "Transformation", &msg, None)
+                .await;
+        }
+        let mut all_mapped_values = parsed.rows;
+        let row_count = all_mapped_values.len();
+        let first_mapped = all_mapped_values.first().map(|m| json!(m));
+
+        if row_count == 0 {
+            had_error = true;
+            if error_msg.is_none() {
+                error_msg = Some("Fichier vide ou format invalide".to_string());
+            }
+        }
+
+        // Row-level validation and type conversion: reject the whole file, or keep the
+        // valid rows and set the invalid ones aside for a `.rejected.csv` companion.
+        let mut invalid_rows: Vec<(Vec<String>, String)> = Vec::new();
+        if !had_error {
+            match RowValidator::new(&mappings).and_then(|v| Ok((v, ColumnTypes::new(&mappings)?))) {
                 Err(e) => {
                     had_error = true;
-                    error_msg = Some(e.to_string());
-                    break;
+                    error_msg = Some(e);
                 }
-            };
+                Ok((validator, types))
+                    if !validator.is_empty()
+                        || !types.is_empty()
+                        || parsed.row_errors.iter().any(|e| !e.is_empty()) =>
+                {
+                    let mut valid = Vec::with_capacity(all_mapped_values.len());
+                    let rows = all_mapped_values
+                        .into_iter()
+                        .zip(parsed.raw)
+                        .zip(parsed.row_errors);
+                    for (i, ((mapped, raw), mut reasons)) in rows.enumerate() {
+                        reasons.extend(validator.check(&mapped));
+                        reasons.extend(types.check(&mapped));
+                        if reasons.is_empty() {
+                            valid.push(mapped);
+                        } else {
+                            invalid_rows
+                                .push((raw, format!("Ligne {}: {}", i + 1, reasons.join(", "))));
+                        }
+                    }
+                    all_mapped_values = valid;
 
-            row_count += 1;
+                    if !invalid_rows.is_empty() {
+                        let policy = InvalidRowsPolicy::parse(
+                            line_config
+                                .as_ref()
+                                .and_then(|c| c.invalid_rows_policy.as_deref()),
+                        );
+                        if policy == InvalidRowsPolicy::RejectFile || all_mapped_values.is_empty() {
+                            had_error = true;
+                            error_msg = Some(format!(
+                                "{} ligne(s) invalide(s): {}",
+                                invalid_rows.len(),
+                                invalid_rows
+                                    .iter()
+                                    .take(5)
+                                    .map(|(_, reason)| reason.as_str())
+                                    .collect::<Vec<_>>()
+                                    .join(" | ")
+                            ));
+                        }
+                    }
+                }
+                Ok(_) => {}
+            }
+        }
 
-            let mapped = map_record_with_mappings_and_params(&record, &mappings, &line_config);
-            if first_mapped.is_none() {
-                first_mapped = Some(json!(mapped));
+        // Duplicate detection: whole-file content hash, then the optional per-row key (e.g. SSCC)
+        let duplicate_policy = DuplicatePolicy::parse(
+            line_config
+                .as_ref()
+                .and_then(|c| c.duplicate_policy.as_deref()),
+        );
+        let hash = dedup::content_hash(&content);
+        let key_field = line_config
+            .as_ref()
+            .and_then(|c| c.dedup_key_field.clone())
+            .filter(|f| !f.trim().is_empty());
+        let mut skipped = false;
+        let mut skipped_rows = 0usize;
+        let mut duplicate_note: Option<String> = None;
+
+        if !had_error && duplicate_policy != DuplicatePolicy::Force {
+            if let Some((previous_name, ingested_at)) =
+                dedup::find_ingested_file(&self.pool, line_id, &hash).await
+            {
+                let note = format!(
+                    "Contenu identique déjà intégré ({} le {})",
+                    previous_name, ingested_at
+                );
+                if duplicate_policy == DuplicatePolicy::Reject {
+                    had_error = true;
+                    error_msg = Some(note.clone());
+                } else {
+                    skipped = true;
+                    skipped_rows = all_mapped_values.len();
+                }
+                duplicate_note = Some(note);
+            } else if let Some(field) = &key_field {
+                let keys: Vec<String> = all_mapped_values
+                    .iter()
+                    .filter_map(|m| m.get(field).cloned())
+                    .filter(|k| !k.trim().is_empty())
+                    .collect();
+                let known = dedup::find_ingested_keys(&self.pool, line_id, &keys).await;
+                if !known.is_empty() {
+                    let mut sample: Vec<&String> = known.iter().collect();
+                    sample.sort();
+                    sample.truncate(5);
+                    let note = format!(
+                        "{} valeur(s) {} déjà intégrée(s): {}",
+                        known.len(),
+                        field,
+                        sample
+                            .iter()
+                            .map(|k| k.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                    if duplicate_policy == DuplicatePolicy::Reject {
+                        had_error = true;
+                        error_msg = Some(note.clone());
+                    } else {
+                        let before = all_mapped_values.len();
+                        all_mapped_values
+                            .retain(|m| m.get(field).map(|k| !known.contains(k)).unwrap_or(true));
+                        skipped_rows = before - all_mapped_values.len();
+                        skipped = all_mapped_values.is_empty();
+                    }
+                    duplicate_note = Some(note);
+                }
             }
-            all_mapped_values.push(mapped);
         }
 
-        if row_count == 0 {
-            had_error = true;
-            error_msg = Some("Fichier vide ou format invalide".to_string());
+        if let Some(note) = &duplicate_note {
+            let decision = if had_error {
+                "fichier rejeté"
+            } else if skipped {
+                "fichier ignoré"
+            } else {
+                "lignes en double ignorées"
+            };
+            let msg = format!("Doublon détecté pour {} ({}): {}", filename, decision, note);
+            DiskLogger::log_ligne(&line_name, &log_path, &msg, "WARNING");
+            self.add_db_log(line_id, "WARNING", "Dedup", &msg, Some(&hash))
+                .await;
         }
 
-        if !had_error && !all_mapped_values.is_empty() {
-            if let Err(e) = self
+        let mut inserted_rows = 0usize;
+        let mut in_doubt = false;
+        if !had_error && !skipped && !all_mapped_values.is_empty() {
+            let outcome = self
                 .execute_sql_server_inserts(line_config.as_ref(), &mappings, &all_mapped_values)
-                .await
-            {
-                if Self::is_connection_error(&e) {
-                    let msg = format!("Erreur connexion SQL (fichier reporté) : {}", e);
-                    DiskLogger::log_ligne(&line_name, &log_path, &msg, "WARNING");
-                    self.add_db_log(line_id, "WARNING", "SQLServer", &msg, None)
-                        .await;
-
+                .await;
+            if let Err(InsertError::InDoubt(e)) = &outcome {
+                // Never re-queued: the operator checks SQL Server before dropping the file again.
+                let msg = format!(
+                    "Fichier {} à vérifier manuellement avant tout nouvel envoi: {}",
+                    filename, e
+                );
+                DiskLogger::log_ligne(&line_name, &log_path, &msg, "CRITICAL");
+                self.add_db_log(line_id, "ERROR", "SQLServer", &msg, None)
+                    .await;
+                self.update_line_stats(line_id, false).await;
+                had_error = true;
+                in_doubt = true;
+                error_msg = Some(msg);
+            } else if let Err(InsertError::Failed(e)) = &outcome {
+                if Self::is_connection_error(e) {
                     // Update line stats to ERROR for visual feedback
                     self.update_line_stats(line_id, false).await;
 
-                    // Attempt to restore file
-                    if let Err(restore_err) = fs::rename(&temp_path, &path) {
-                        // Critical failure: cannot restore file. Must fallback to error folder to save data.
-                        let crit_msg = format!(
-                            "Echec restauration fichier après erreur connexion: {}",
-                            restore_err
+                    let attempts = file.queued.as_ref().map(|q| q.attempts).unwrap_or(0) + 1;
+                    let policy = RetryPolicy::load(&self.pool).await;
+
+                    if attempts >= policy.max_attempts {
+                        // Retry budget exhausted: fall through to standard error handling (move to rejected)
+                        let msg = format!(
+                            "Erreur connexion SQL après {} tentatives, fichier abandonné : {}",
+                            attempts, e
                         );
-                        DiskLogger::log_ligne(&line_name, &log_path, &crit_msg, "CRITICAL");
-                        // Let it fall through to standard error handling (move to rejected)
                         had_error = true;
-                        error_msg = Some(format!("{} | {}", msg, crit_msg));
+                        error_msg = Some(msg);
                     } else {
-                        // File restored successfully.
-                        // Clean up temp dir if empty
-                        if temp_subdir.read_dir().unwrap().count() == 0 {
-                            let _ = fs::remove_dir(&temp_subdir);
+                        match self.defer_file(&file, attempts, e, &policy).await {
+                            Ok(next_attempt_at) => {
+                                let msg = format!(
+                                    "Erreur connexion SQL (fichier {} en file d'attente, tentative {}/{}, prochain essai {}) : {}",
+                                    filename, attempts, policy.max_attempts, next_attempt_at, e
+                                );
+                                DiskLogger::log_ligne(&line_name, &log_path, &msg, "WARNING");
+                                self.add_db_log(line_id, "WARNING", "SQLServer", &msg, None)
+                                    .await;
+                                return Ok(FileOutcome::Queued {
+                                    attempts,
+                                    next_attempt_at,
+                                    error: e.clone(),
+                                });
+                            }
+                            Err(queue_err) => {
+                                // Critical failure: cannot queue file. Must fallback to error folder to save data.
+                                let crit_msg = format!(
+                                    "Echec mise en file d'attente après erreur connexion: {}",
+                                    queue_err
+                                );
+                                DiskLogger::log_ligne(&line_name, &log_path, &crit_msg, "CRITICAL");
+                                had_error = true;
+                                error_msg = Some(format!("{} | {}", e, crit_msg));
+                            }
                         }
-                        return Ok(());
                     }
                 } else {
                     let msg = format!("Erreur SQL Server pour {}: {}", filename, e);
@@ -555,16 +1331,105 @@ impl StockProcessor {
                     error_msg = Some(msg);
                 }
             }
+            inserted_rows = outcome.unwrap_or(0);
+
+            if !had_error {
+                let keys: Vec<String> = key_field
+                    .as_ref()
+                    .map(|field| {
+                        all_mapped_values
+                            .iter()
+                            .filter_map(|m| m.get(field).cloned())
+                            .filter(|k| !k.trim().is_empty())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                if let Err(e) =
+                    dedup::record_ingested(&self.pool, line_id, &hash, filename, &keys).await
+                {
+                    log::error!("Failed to record ingested hash: {}", e);
+                }
+            }
         }
 
-        // If we had a connection error that successfully restored, we returned early.
-        // If we are here, it's either success or a different error/restore failed.
+        let mut rejected_rows_file: Option<String> = None;
+        if !had_error && !invalid_rows.is_empty() {
+            let dir = line_config
+                .as_ref()
+                .and_then(|c| c.rejected_path.clone())
+                .or_else(|| file.archived_path.clone());
+            let msg = match dir {
+                Some(dir) => match write_rejected_rows(Path::new(&dir), filename, parsed.delimiter, &invalid_rows) {
+                    Ok(path) => {
+                        let path = path.to_string_lossy().to_string();
+                        let msg = format!(
+                            "{} ligne(s) invalide(s) écartée(s) de {} vers {}",
+                            invalid_rows.len(),
+                            filename,
+                            path
+                        );
+                        rejected_rows_file = Some(path);
+                        msg
+                    }
+                    Err(e) => format!(
+                        "{} ligne(s) invalide(s) écartée(s) de {}, écriture du fichier de rejet impossible: {}",
+                        invalid_rows.len(),
+                        filename,
+                        e
+                    ),
+                },
+                None => format!(
+                    "{} ligne(s) invalide(s) écartée(s) de {} (aucun dossier de rejet configuré)",
+                    invalid_rows.len(),
+                    filename
+                ),
+            };
+            DiskLogger::log_ligne(&line_name, &log_path, &msg, "WARNING");
+            self.add_db_log(line_id, "WARNING", "Validation", &msg, None)
+                .await;
+        }
 
-        let status = if had_error { "ERROR" } else { "SUCCESS" };
+        // If the file was deferred to the retry queue, we returned early.
+        // If we are here, it's either success or a final error: the file leaves the queue.
+        if let Some(item) = &file.queued {
+            queue::remove(&self.pool, item.id).await;
+        }
+
+        let status = if in_doubt {
+            "IN_DOUBT"
+        } else if had_error {
+            "ERROR"
+        } else if skipped {
+            "SKIPPED"
+        } else {
+            "SUCCESS"
+        };
+        let transaction = if in_doubt {
+            "UNKNOWN"
+        } else if had_error {
+            "ROLLBACK"
+        } else if skipped {
+            "NONE"
+        } else {
+            "COMMIT"
+        };
+        // Inserts are all-or-nothing: a failure before COMMIT leaves nothing inserted.
         let message = json!({
             "rows": row_count,
+            "inserted": inserted_rows,
+            "transaction": transaction,
             "sample": first_mapped,
             "error": error_msg,
+            "content_hash": hash,
+            "invalid_rows": invalid_rows.len(),
+            "invalid_sample": invalid_rows.iter().take(5).map(|(_, reason)| reason).collect::<Vec<_>>(),
+            "rejected_rows_file": rejected_rows_file,
+            "date_substitutions": date_substitutions,
+            "duplicate": duplicate_note.as_ref().map(|note| json!({
+                "policy": format!("{:?}", duplicate_policy).to_uppercase(),
+                "skipped_rows": skipped_rows,
+                "detail": note,
+            })),
         })
         .to_string();
 
@@ -573,7 +1438,7 @@ impl StockProcessor {
             "INSERT INTO production_data (line_id, filename, status, message, processed_at) \n         VALUES (?, ?, ?, ?, ?)",
         )
         .bind(line_id)
-        .bind(&filename)
+        .bind(filename)
         .bind(status)
         .bind(&message)
         .bind(&processed_at)
@@ -581,6 +1446,7 @@ impl StockProcessor {
         .await?;
 
         self.update_line_stats(line_id, !had_error).await;
+        crate::metrics::record_file(line_id, !had_error, inserted_rows);
 
         if had_error {
             let msg = format!(
@@ -593,79 +1459,143 @@ impl StockProcessor {
                 .await;
 
             if let Some(reject_dir) = line_config.as_ref().and_then(|c| c.rejected_path.clone()) {
-                let reject_path = Path::new(&reject_dir);
-                if !reject_path.exists() {
-                    let _ = fs::create_dir_all(reject_path);
-                }
-
-                let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
-                let new_filename = format!(
-                    "{}_{}{}",
-                    Path::new(&filename).file_stem().unwrap().to_str().unwrap(),
-                    timestamp,
-                    Path::new(&filename)
-                        .extension()
-                        .unwrap_or_default()
-                        .to_str()
-                        .map(|s| format!(".{}", s))
-                        .unwrap_or_default()
-                );
-                let dest_path = reject_path.join(new_filename);
-
-                if let Err(e) = fs::rename(&temp_path, &dest_path) {
-                    eprintln!("Failed to move to rejected folder: {}", e);
-                    let _ = fs::remove_file(&temp_path);
+                if let Err(e) =
+                    move_to_dir_with_timestamp(temp_path, Path::new(&reject_dir), filename)
+                {
+                    log::error!("Failed to move to rejected folder: {}", e);
+                    let _ = fs::remove_file(temp_path);
                 }
             } else {
-                let _ = fs::remove_file(&temp_path);
+                let _ = fs::remove_file(temp_path);
             }
+        } else if skipped {
+            let msg = format!(
+                "Fichier {} ignoré (doublon) - archivé sans insertion",
+                filename
+            );
+            DiskLogger::log_ligne(&line_name, &log_path, &msg, "INFO");
+            self.add_db_log(line_id, "INFO", "FileProcessor", &msg, None)
+                .await;
         } else {
             let msg = format!(
                 "Fichier {} traité avec succès - {} enregistrements",
-                filename, row_count
+                filename, inserted_rows
             );
             DiskLogger::log_ligne(&line_name, &log_path, &msg, "INFO");
             self.add_db_log(line_id, "SUCCESS", "FileProcessor", &msg, None)
                 .await;
+        }
 
-            if let Some(archive_dir) = archived_path {
-                let archive_dir_path = Path::new(&archive_dir);
-                if !archive_dir_path.exists() {
-                    if let Err(e) = fs::create_dir_all(archive_dir_path) {
-                        eprintln!("Failed to create archive directory: {}", e);
-                        let _ = fs::remove_file(&temp_path);
-                        return Ok(());
-                    }
-                }
-
-                let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
-                let new_filename = format!(
-                    "{}_{}{}",
-                    Path::new(&filename).file_stem().unwrap().to_str().unwrap(),
-                    timestamp,
-                    Path::new(&filename)
-                        .extension()
-                        .unwrap_or_default()
-                        .to_str()
-                        .map(|s| format!(".{}", s))
-                        .unwrap_or_default()
-                );
-
-                let dest_path = archive_dir_path.join(new_filename);
-                if let Err(e) = fs::rename(&temp_path, &dest_path) {
-                    eprintln!("Failed to archive file: {}", e);
-                    let _ = fs::remove_file(&temp_path);
+        if !had_error {
+            if let Some(archive_dir) = &file.archived_path {
+                if let Err(e) =
+                    move_to_dir_with_timestamp(temp_path, Path::new(archive_dir), filename)
+                {
+                    log::error!("Failed to archive file: {}", e);
+                    let _ = fs::remove_file(temp_path);
                 }
             } else {
-                let _ = fs::remove_file(&temp_path);
+                let _ = fs::remove_file(temp_path);
             }
         }
 
-        if temp_subdir.read_dir().unwrap().count() == 0 {
-            let _ = fs::remove_dir(&temp_subdir);
-        }
+        let error = error_msg.unwrap_or_default();
+        Ok(if in_doubt {
+            FileOutcome::InDoubt { error }
+        } else if had_error {
+            FileOutcome::Rejected { error }
+        } else if skipped {
+            FileOutcome::Skipped {
+                reason: duplicate_note.unwrap_or_default(),
+            }
+        } else {
+            FileOutcome::Inserted {
+                rows: inserted_rows,
+            }
+        })
+    }
+}
+
+fn parse_content(
+    content: &str,
+    mappings: &[MappingRow],
+    line_config: &Option<LineConfig>,
+    ctx: &TransformContext,
+) -> ParsedContent {
+    let settings = match line_config {
+        Some(c) => c.dialect().and_then(|d| Ok((d, c.fixed_width()?))),
+        None => Ok((CsvDialect::default(), None)),
+    };
+    let (dialect, fixed_width) = match settings {
+        Ok(s) => s,
+        Err(e) => return ParsedContent::failed(e, b';'),
+    };
+    let pipelines = match compile_transformations(mappings) {
+        Ok(p) => p,
+        Err(e) => return ParsedContent::failed(e, dialect.delimiter),
+    };
 
-        Ok(())
+    let mut substitutions = Vec::new();
+    if let Some(fw) = &fixed_width {
+        let lines = dialect.data_lines(content);
+        let (rows, row_errors) = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                map_record_with_mappings_and_params(
+                    &InputRecord::Fixed(line, fw),
+                    mappings,
+                    &pipelines,
+                    line_config,
+                    ctx,
+                    i + 1,
+                    &mut substitutions,
+                )
+            })
+            .unzip();
+        return ParsedContent {
+            rows,
+            raw: lines.iter().map(|l| vec![l.to_string()]).collect(),
+            row_errors,
+            substitutions,
+            delimiter: dialect.delimiter,
+            error: None,
+        };
+    }
+
+    let data = dialect.data_records(content);
+    let mappings = match resolve_header_columns(mappings, data.header.as_ref()) {
+        Ok(m) => m,
+        Err(e) => return ParsedContent::failed(e, dialect.delimiter),
+    };
+    let (rows, row_errors) = data
+        .records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            map_record_with_mappings_and_params(
+                &InputRecord::Delimited(record),
+                &mappings,
+                &pipelines,
+                line_config,
+                ctx,
+                i + 1,
+                &mut substitutions,
+            )
+        })
+        .unzip();
+
+    ParsedContent {
+        rows,
+        raw: data
+            .records
+            .iter()
+            .map(|r| r.iter().map(str::to_string).collect())
+            .collect(),
+        row_errors,
+        substitutions,
+        delimiter: dialect.delimiter,
+        error: data.error,
     }
 }
 
@@ -685,7 +1615,18 @@ fn get_parameter_value(param: &str, line_config: &Option<LineConfig>) -> String
     }
 }
 
-fn get_file_value(record: &csv::StringRecord, file_column: &str) -> String {
+/// One record of an input file, in the shape given by the line's input mode.
+enum InputRecord<'a> {
+    Delimited(&'a csv::StringRecord),
+    Fixed(&'a str, &'a FixedWidth),
+}
+
+fn get_file_value(record: &InputRecord, file_column: &str) -> String {
+    let record = match record {
+        InputRecord::Delimited(r) => *r,
+        InputRecord::Fixed(line, fw) => return fw.extract(line, file_column).unwrap_or_default(),
+    };
+
     if let Some((a, b)) = file_column.split_once('-') {
         let a_idx = a.trim().parse::<usize>().ok();
         let b_idx = b.trim().parse::<usize>().ok();
@@ -702,13 +1643,18 @@ fn get_file_value(record: &csv::StringRecord, file_column: &str) -> String {
 }
 
 fn map_record_with_mappings_and_params(
-    record: &csv::StringRecord,
+    record: &InputRecord,
     mappings: &[MappingRow],
+    pipelines: &[Pipeline],
     line_config: &Option<LineConfig>,
-) -> HashMap<String, String> {
+    ctx: &TransformContext,
+    row: usize,
+    substitutions: &mut Vec<String>,
+) -> (HashMap<String, String>, Vec<String>) {
     let mut out = HashMap::new();
+    let mut errors = Vec::new();
 
-    for m in mappings {
+    for (i, m) in mappings.iter().enumerate() {
         let mut value = if let Some(param) = &m.parameter {
             if !param.is_empty() {
                 get_parameter_value(param, line_config)
@@ -731,20 +1677,106 @@ fn map_record_with_mappings_and_params(
             String::new()
         };
 
-        if let Some(t) = &m.transformation {
-            if !t.is_empty() {
-                if t == "split_before_plus" {
-                    value = apply_split(&value, "before");
-                } else if t == "split_after_plus" {
-                    value = apply_split(&value, "after");
-                } else {
-                    value = apply_transformation(value, t);
+        if let Some(pipeline) = pipelines.get(i) {
+            let mut replaced = Vec::new();
+            value = match pipeline.apply(value, ctx, &mut replaced) {
+                Ok(v) => v,
+                Err(e) => {
+                    errors.push(format!("{} {}", m.sql_field, e));
+                    String::new()
                 }
-            }
+            };
+            substitutions.extend(
+                replaced
+                    .into_iter()
+                    .map(|r| format!("Ligne {}: {} {}", row, m.sql_field, r)),
+            );
         }
 
         out.insert(m.sql_field.clone(), value);
     }
 
-    out
+    (out, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_shift_by_whole_number() {
+        assert_eq!(
+            shift_placeholders("(@P1, @P10, @p2)", 10),
+            "(@P11, @P20, @P12)"
+        );
+        assert_eq!(
+            shift_placeholders("(@P, @Px, 'a@b')", 3),
+            "(@P, @Px, 'a@b')"
+        );
+        assert_eq!(shift_placeholders("@P9", 0), "@P9");
+        assert_eq!(max_placeholder("(@P2, @p10, @P9)"), 10);
+        assert_eq!(max_placeholder("(GETDATE())"), 0);
+    }
+
+    #[test]
+    fn batched_insert_repeats_the_values_tuple() {
+        let query = "INSERT INTO T (A, B) VALUES (@P1, @P2);";
+        assert_eq!(
+            build_batched_insert(query, 2, 3).as_deref(),
+            Some("INSERT INTO T (A, B) VALUES (@P1, @P2),\n(@P3, @P4),\n(@P5, @P6);")
+        );
+
+        let nested = "insert into T (A, B) values (UPPER(@P1), COALESCE(@P2, GETDATE()))";
+        assert_eq!(
+            build_batched_insert(nested, 2, 2).as_deref(),
+            Some(
+                "insert into T (A, B) values (UPPER(@P1), COALESCE(@P2, GETDATE())),\n\
+                 (UPPER(@P3), COALESCE(@P4, GETDATE()))"
+            )
+        );
+
+        let literal = "INSERT INTO T (A, B) VALUES (@P1, ')')";
+        assert_eq!(
+            build_batched_insert(literal, 1, 2).as_deref(),
+            Some("INSERT INTO T (A, B) VALUES (@P1, ')'),\n(@P2, ')')")
+        );
+    }
+
+    #[test]
+    fn batched_insert_needs_a_single_trailing_tuple() {
+        for query in [
+            "INSERT INTO T (A) VALUES (@P1); UPDATE C SET N = N + 1",
+            "INSERT INTO T (A) VALUES (@P1) OPTION (RECOMPILE)",
+            "INSERT INTO T (A) SELECT @P1",
+            "UPDATE T SET A = @P1",
+            "INSERT INTO T (A) VALUES (@P1",
+            "INSERT INTO T (A) VALUES (@P1, ')'",
+        ] {
+            assert_eq!(build_batched_insert(query, 1, 2), None, "{}", query);
+        }
+        // A tuple using more parameters than one row binds.
+        assert_eq!(
+            build_batched_insert("INSERT INTO T (A) VALUES (@P2)", 1, 2),
+            None
+        );
+    }
+
+    #[test]
+    fn busy_pools_and_lost_connections_are_transient() {
+        for msg in [
+            "Pool SQL Server saturé (4 connexions en cours)",
+            "Login failed for user 'visor'. code: 18456",
+            "127.0.0.1:1433: Connection refused (os error 111)",
+            "An existing connection was forcibly closed by the remote host. (os error 10054)",
+        ] {
+            assert!(StockProcessor::is_connection_error(msg), "{}", msg);
+        }
+        for msg in [
+            "Invalid column name 'QTE'.",
+            "Violation of PRIMARY KEY constraint 'PK_STOCK'.",
+            "Conversion failed when converting date and/or time from character string.",
+        ] {
+            assert!(!StockProcessor::is_connection_error(msg), "{}", msg);
+        }
+    }
 }
@@ -0,0 +1,405 @@
+use crate::stock::fs_utils::move_to_dir_with_timestamp;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+use std::path::Path;
+
+/// Folder (next to the watched folder) holding files waiting for a retry.
+pub(crate) const QUEUE_DIR_NAME: &str = "visor_queue";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct QueueItem {
+    pub id: i64,
+    pub line_id: i64,
+    pub filename: String,
+    pub file_path: String,
+    pub attempts: i64,
+    pub next_attempt_at: String,
+    pub last_error: Option<String>,
+    pub status: String,
+    pub created_at: String,
+}
+
+/// Retry policy for files deferred after a SQL Server connection error,
+/// stored in the `config` table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    pub max_attempts: i64,
+    pub base_delay_secs: i64,
+    pub max_delay_secs: i64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            base_delay_secs: 30,
+            max_delay_secs: 3600,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub(crate) async fn load(pool: &Pool<Sqlite>) -> Self {
+        let defaults = Self::default();
+        let get = |key: &'static str| async move {
+            sqlx::query_scalar::<_, String>("SELECT value FROM config WHERE key = ?")
+                .bind(key)
+                .fetch_optional(pool)
+                .await
+                .ok()
+                .flatten()
+                .and_then(|v| v.trim().parse::<i64>().ok())
+        };
+
+        Self {
+            max_attempts: get("queue_max_attempts")
+                .await
+                .unwrap_or(defaults.max_attempts)
+                .max(1),
+            base_delay_secs: get("queue_base_delay_secs")
+                .await
+                .unwrap_or(defaults.base_delay_secs)
+                .max(1),
+            max_delay_secs: get("queue_max_delay_secs")
+                .await
+                .unwrap_or(defaults.max_delay_secs)
+                .max(1),
+        }
+    }
+
+    pub(crate) async fn save(&self, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        for (key, value) in [
+            ("queue_max_attempts", self.max_attempts),
+            ("queue_base_delay_secs", self.base_delay_secs),
+            ("queue_max_delay_secs", self.max_delay_secs),
+        ] {
+            sqlx::query("INSERT OR REPLACE INTO config (key, value) VALUES (?, ?)")
+                .bind(key)
+                .bind(value.to_string())
+                .execute(pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Exponential backoff: base * 2^(attempts - 1), capped at `max_delay_secs`.
+    pub(crate) fn delay_secs(&self, attempts: i64) -> i64 {
+        let exp = (attempts - 1).clamp(0, 30) as u32;
+        self.base_delay_secs
+            .saturating_mul(2i64.saturating_pow(exp))
+            .min(self.max_delay_secs)
+    }
+
+    pub(crate) fn next_attempt_at(&self, attempts: i64) -> String {
+        (Local::now() + chrono::Duration::seconds(self.delay_secs(attempts)))
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn schedule_retry(
+    pool: &Pool<Sqlite>,
+    existing_id: Option<i64>,
+    line_id: i64,
+    filename: &str,
+    file_path: &str,
+    attempts: i64,
+    next_attempt_at: &str,
+    last_error: &str,
+) -> Result<(), sqlx::Error> {
+    let now = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    if let Some(id) = existing_id {
+        sqlx::query(
+            "UPDATE processing_queue SET attempts = ?, next_attempt_at = ?, last_error = ?, \
+             status = 'PENDING', updated_at = ? WHERE id = ?",
+        )
+        .bind(attempts)
+        .bind(next_attempt_at)
+        .bind(last_error)
+        .bind(&now)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    } else {
+        sqlx::query(
+            "INSERT INTO processing_queue (line_id, filename, file_path, attempts, next_attempt_at, last_error, status, created_at, updated_at) \
+             VALUES (?, ?, ?, ?, ?, ?, 'PENDING', ?, ?)",
+        )
+        .bind(line_id)
+        .bind(filename)
+        .bind(file_path)
+        .bind(attempts)
+        .bind(next_attempt_at)
+        .bind(last_error)
+        .bind(&now)
+        .bind(&now)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Fetch the due items of a line and mark them as in progress, so that a
+/// concurrent scan does not pick them up twice.
+pub(crate) async fn claim_due(pool: &Pool<Sqlite>, line_id: i64) -> Vec<QueueItem> {
+    let now = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    let due = sqlx::query_as::<_, QueueItem>(
+        "SELECT id, line_id, filename, file_path, attempts, next_attempt_at, last_error, status, created_at \
+         FROM processing_queue WHERE line_id = ? AND status = 'PENDING' AND next_attempt_at <= ? \
+         ORDER BY next_attempt_at ASC, id ASC",
+    )
+    .bind(line_id)
+    .bind(&now)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+
+    let mut claimed = Vec::new();
+    for mut item in due {
+        let updated = sqlx::query(
+            "UPDATE processing_queue SET status = 'PROCESSING', updated_at = ? WHERE id = ? AND status = 'PENDING'",
+        )
+        .bind(&now)
+        .bind(item.id)
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected())
+        .unwrap_or(0);
+
+        if updated == 1 {
+            item.status = "PROCESSING".to_string();
+            claimed.push(item);
+        }
+    }
+
+    claimed
+}
+
+/// Items left in progress by a previous run of the process that watches the lines
+/// go back to pending. Only that process may call this: for anyone else (the
+/// command line, say), an item in progress may be in progress right now.
+pub(crate) async fn recover_interrupted(pool: &Pool<Sqlite>) {
+    let _ =
+        sqlx::query("UPDATE processing_queue SET status = 'PENDING' WHERE status = 'PROCESSING'")
+            .execute(pool)
+            .await;
+}
+
+pub(crate) async fn remove(pool: &Pool<Sqlite>, id: i64) {
+    let _ = sqlx::query("DELETE FROM processing_queue WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await;
+}
+
+pub(crate) async fn list(
+    pool: &Pool<Sqlite>,
+    line_id: Option<i64>,
+) -> Result<Vec<QueueItem>, sqlx::Error> {
+    sqlx::query_as::<_, QueueItem>(
+        "SELECT id, line_id, filename, file_path, attempts, next_attempt_at, last_error, status, created_at \
+         FROM processing_queue WHERE (? IS NULL OR line_id = ?) ORDER BY next_attempt_at ASC, id ASC",
+    )
+    .bind(line_id)
+    .bind(line_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Make a pending item due immediately; the line watcher picks it up on its next scan.
+pub(crate) async fn retry_now(pool: &Pool<Sqlite>, id: i64) -> Result<bool, sqlx::Error> {
+    let now = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let res = sqlx::query(
+        "UPDATE processing_queue SET next_attempt_at = ?, updated_at = ? WHERE id = ? AND status = 'PENDING'",
+    )
+    .bind(&now)
+    .bind(&now)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected() == 1)
+}
+
+/// Drop a pending item from the queue and move its file to the line's rejected folder.
+pub(crate) async fn cancel(pool: &Pool<Sqlite>, id: i64) -> Result<QueueItem, String> {
+    let item = sqlx::query_as::<_, QueueItem>(
+        "SELECT id, line_id, filename, file_path, attempts, next_attempt_at, last_error, status, created_at \
+         FROM processing_queue WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or("Élément introuvable dans la file d'attente")?;
+
+    if item.status != "PENDING" {
+        return Err("Fichier en cours de traitement, réessayez dans quelques secondes".to_string());
+    }
+
+    let rejected_path: Option<String> =
+        sqlx::query_scalar("SELECT rejected_path FROM lines WHERE id = ?")
+            .bind(item.line_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| e.to_string())?
+            .flatten();
+
+    let src = Path::new(&item.file_path);
+    if src.exists() {
+        match rejected_path.filter(|p| !p.trim().is_empty()) {
+            Some(dir) => {
+                move_to_dir_with_timestamp(src, Path::new(&dir), &item.filename)
+                    .map_err(|e| e.to_string())?;
+            }
+            None => {
+                return Err(
+                    "Aucun dossier de rejet configuré pour cette ligne, fichier conservé"
+                        .to_string(),
+                );
+            }
+        }
+    }
+
+    remove(pool, id).await;
+    Ok(item)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAST: &str = "2000-01-01 00:00:00";
+    const FUTURE: &str = "2999-01-01 00:00:00";
+
+    async fn pool_with_line() -> Pool<Sqlite> {
+        let pool = crate::migrations::memory_pool().await;
+        sqlx::query("INSERT INTO lines (id, name, path, prefix) VALUES (1, 'L1', '/in', 'ATEIS')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool
+    }
+
+    async fn status(pool: &Pool<Sqlite>, id: i64) -> (String, i64) {
+        sqlx::query_as("SELECT status, attempts FROM processing_queue WHERE id = ?")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy::default();
+        let delays: Vec<i64> = (0..=8).map(|a| policy.delay_secs(a)).collect();
+        assert_eq!(delays, vec![30, 30, 60, 120, 240, 480, 960, 1920, 3600]);
+        assert_eq!(policy.delay_secs(i64::MAX), 3600);
+
+        let steep = RetryPolicy {
+            max_attempts: 3,
+            base_delay_secs: i64::MAX / 2,
+            max_delay_secs: i64::MAX,
+        };
+        assert_eq!(steep.delay_secs(40), i64::MAX);
+    }
+
+    #[tokio::test]
+    async fn due_items_are_claimed_once() {
+        let pool = pool_with_line().await;
+        schedule_retry(&pool, None, 1, "a.csv", "/q/a.csv", 1, PAST, "down")
+            .await
+            .unwrap();
+        schedule_retry(&pool, None, 1, "b.csv", "/q/b.csv", 1, FUTURE, "down")
+            .await
+            .unwrap();
+
+        let claimed = claim_due(&pool, 1).await;
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].filename, "a.csv");
+        assert_eq!(claimed[0].status, "PROCESSING");
+        assert!(claim_due(&pool, 1).await.is_empty());
+        assert!(claim_due(&pool, 2).await.is_empty());
+
+        // A cancel while the file is in progress is refused.
+        assert!(cancel(&pool, claimed[0].id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn interrupted_items_go_back_to_pending() {
+        let pool = pool_with_line().await;
+        schedule_retry(&pool, None, 1, "a.csv", "/q/a.csv", 1, PAST, "down")
+            .await
+            .unwrap();
+        let id = claim_due(&pool, 1).await[0].id;
+
+        recover_interrupted(&pool).await;
+        assert_eq!(status(&pool, id).await, ("PENDING".to_string(), 1));
+        assert_eq!(claim_due(&pool, 1).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rescheduling_updates_the_same_item() {
+        let pool = pool_with_line().await;
+        schedule_retry(&pool, None, 1, "a.csv", "/q/a.csv", 1, PAST, "down")
+            .await
+            .unwrap();
+        let id = claim_due(&pool, 1).await[0].id;
+
+        schedule_retry(
+            &pool,
+            Some(id),
+            1,
+            "a.csv",
+            "/q/a.csv",
+            2,
+            FUTURE,
+            "still down",
+        )
+        .await
+        .unwrap();
+        assert_eq!(status(&pool, id).await, ("PENDING".to_string(), 2));
+        assert!(claim_due(&pool, 1).await.is_empty());
+
+        assert!(retry_now(&pool, id).await.unwrap());
+        assert_eq!(claim_due(&pool, 1).await.len(), 1);
+        // Only pending items can be made due.
+        assert!(!retry_now(&pool, id).await.unwrap());
+
+        remove(&pool, id).await;
+        assert!(list(&pool, Some(1)).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancel_keeps_the_file_without_a_rejected_folder() {
+        let pool = pool_with_line().await;
+        let file = std::env::temp_dir().join(format!("visor-queue-{}.csv", std::process::id()));
+        std::fs::write(&file, "A;1\n").unwrap();
+        schedule_retry(
+            &pool,
+            None,
+            1,
+            "a.csv",
+            file.to_str().unwrap(),
+            1,
+            FUTURE,
+            "down",
+        )
+        .await
+        .unwrap();
+        let id = list(&pool, None).await.unwrap()[0].id;
+
+        assert!(cancel(&pool, id).await.is_err());
+        assert!(file.exists());
+        assert_eq!(list(&pool, None).await.unwrap().len(), 1);
+        let _ = std::fs::remove_file(&file);
+
+        // Once the file is gone there is nothing left to move.
+        assert_eq!(cancel(&pool, id).await.unwrap().filename, "a.csv");
+        assert!(list(&pool, None).await.unwrap().is_empty());
+    }
+}
@@ -1,18 +1,62 @@
 use std::collections::HashMap;
-use std::sync::{mpsc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
 
+/// Cheap to clone: clones share the same registry.
+#[derive(Clone)]
 pub struct WatcherState {
-    pub(crate) watchers: Mutex<HashMap<i64, WatcherHandle>>,
+    pub(crate) watchers: Arc<Mutex<HashMap<i64, WatcherHandle>>>,
+    /// Set when another process holds the data folder's watch lock: this one
+    /// then watches nothing.
+    unavailable: Arc<Mutex<Option<String>>>,
 }
 
 pub(crate) struct WatcherHandle {
     pub(crate) stop_tx: mpsc::Sender<()>,
+    /// Also held by the watcher thread: a count of one means the thread has exited.
+    pub(crate) alive: Arc<()>,
 }
 
 impl WatcherState {
     pub fn new() -> Self {
         Self {
-            watchers: Mutex::new(HashMap::new()),
+            watchers: Arc::new(Mutex::new(HashMap::new())),
+            unavailable: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub(crate) fn set_unavailable(&self, reason: String) {
+        *self.unavailable.lock().expect("watchers mutex poisoned") = Some(reason);
+    }
+
+    /// Why lines may not be watched in this process, if they may not.
+    pub(crate) fn unavailable(&self) -> Option<String> {
+        self.unavailable
+            .lock()
+            .expect("watchers mutex poisoned")
+            .clone()
+    }
+
+    /// Registered lines and whether their watcher thread is still running.
+    pub(crate) fn status(&self) -> HashMap<i64, bool> {
+        self.watchers
+            .lock()
+            .expect("watchers mutex poisoned")
+            .iter()
+            .map(|(id, handle)| (*id, Arc::strong_count(&handle.alive) > 1))
+            .collect()
+    }
+
+    /// Stop every running watcher (service shutdown).
+    pub(crate) fn stop_all(&self) {
+        let handles: Vec<WatcherHandle> = self
+            .watchers
+            .lock()
+            .expect("watchers mutex poisoned")
+            .drain()
+            .map(|(_, handle)| handle)
+            .collect();
+        for handle in handles {
+            let _ = handle.stop_tx.send(());
         }
     }
 }
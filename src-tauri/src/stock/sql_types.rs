@@ -0,0 +1,398 @@
+use crate::commands::mappings::MappingRow;
+use crate::stock::transforms::{parse_date_heuristic, parse_datetime_heuristic};
+use chrono::{NaiveDate, NaiveDateTime};
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::collections::HashMap;
+use std::str::FromStr;
+use tiberius::ToSql;
+
+/// Target column type declared on a mapping (`sql_type` column), e.g.
+/// `nvarchar(20)`, `int`, `decimal(18,3)`, `datetime2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SqlType {
+    /// `nvarchar(n)`, `nvarchar(max)` or `nvarchar`; `None` means unbounded.
+    NVarChar(Option<usize>),
+    TinyInt,
+    SmallInt,
+    Int,
+    BigInt,
+    Decimal {
+        precision: u32,
+        scale: u32,
+    },
+    Bit,
+    Date,
+    DateTime2,
+}
+
+/// A value converted to its native type, ready to be bound as a parameter.
+/// `None` binds SQL `NULL`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SqlValue {
+    Text(Option<String>),
+    TinyInt(Option<u8>),
+    SmallInt(Option<i16>),
+    Int(Option<i32>),
+    BigInt(Option<i64>),
+    Decimal(Option<Decimal>),
+    Bit(Option<bool>),
+    Date(Option<NaiveDate>),
+    DateTime(Option<NaiveDateTime>),
+}
+
+impl SqlValue {
+    pub(crate) fn as_sql(&self) -> &dyn ToSql {
+        match self {
+            Self::Text(v) => v,
+            Self::TinyInt(v) => v,
+            Self::SmallInt(v) => v,
+            Self::Int(v) => v,
+            Self::BigInt(v) => v,
+            Self::Decimal(v) => v,
+            Self::Bit(v) => v,
+            Self::Date(v) => v,
+            Self::DateTime(v) => v,
+        }
+    }
+}
+
+/// `(a,b)` arguments of a type such as `decimal(18,3)`.
+fn type_args(raw: &str) -> Result<(&str, Vec<&str>), String> {
+    match raw.find('(') {
+        None => Ok((raw, Vec::new())),
+        Some(open) => {
+            let inner = raw[open + 1..]
+                .strip_suffix(')')
+                .ok_or_else(|| format!("Type SQL invalide: {}", raw))?;
+            Ok((
+                raw[..open].trim(),
+                inner.split(',').map(str::trim).collect(),
+            ))
+        }
+    }
+}
+
+fn parse_arg(raw: &str, arg: &str) -> Result<u32, String> {
+    arg.parse::<u32>()
+        .map_err(|_| format!("Type SQL invalide: {}", raw))
+}
+
+impl SqlType {
+    pub(crate) fn parse(raw: &str) -> Result<Self, String> {
+        let lower = raw.trim().to_lowercase();
+        let (name, args) = type_args(&lower)?;
+
+        let ty = match (name, args.as_slice()) {
+            ("nvarchar" | "varchar" | "nchar" | "char", []) => Self::NVarChar(None),
+            ("nvarchar" | "varchar" | "nchar" | "char", ["max"]) => Self::NVarChar(None),
+            ("nvarchar" | "varchar" | "nchar" | "char", [n]) => {
+                Self::NVarChar(Some(parse_arg(raw, n)? as usize))
+            }
+            ("tinyint", []) => Self::TinyInt,
+            ("smallint", []) => Self::SmallInt,
+            ("int", []) => Self::Int,
+            ("bigint", []) => Self::BigInt,
+            ("decimal" | "numeric", []) => Self::Decimal {
+                precision: 18,
+                scale: 0,
+            },
+            ("decimal" | "numeric", [p]) => Self::Decimal {
+                precision: parse_arg(raw, p)?,
+                scale: 0,
+            },
+            ("decimal" | "numeric", [p, s]) => Self::Decimal {
+                precision: parse_arg(raw, p)?,
+                scale: parse_arg(raw, s)?,
+            },
+            ("bit", []) => Self::Bit,
+            ("date", []) => Self::Date,
+            ("datetime2" | "datetime", []) => Self::DateTime2,
+            _ => return Err(format!("Type SQL non supporté: {}", raw.trim())),
+        };
+
+        // Values go through rust_decimal, which holds 28 significant digits:
+        // SQL Server's decimal(29..38) is refused rather than silently truncated.
+        if let Self::Decimal { precision, scale } = ty {
+            if !(1..=28).contains(&precision) || scale > precision {
+                return Err(format!("Type SQL invalide: {}", raw.trim()));
+            }
+        }
+        Ok(ty)
+    }
+
+    /// Convert a mapped value; empty values become `NULL` except for text.
+    pub(crate) fn convert(&self, value: &str) -> Result<SqlValue, String> {
+        let v = value.trim();
+        if v.is_empty() {
+            return Ok(match self {
+                Self::NVarChar(_) => SqlValue::Text(Some(value.to_string())),
+                Self::TinyInt => SqlValue::TinyInt(None),
+                Self::SmallInt => SqlValue::SmallInt(None),
+                Self::Int => SqlValue::Int(None),
+                Self::BigInt => SqlValue::BigInt(None),
+                Self::Decimal { .. } => SqlValue::Decimal(None),
+                Self::Bit => SqlValue::Bit(None),
+                Self::Date => SqlValue::Date(None),
+                Self::DateTime2 => SqlValue::DateTime(None),
+            });
+        }
+
+        match *self {
+            Self::NVarChar(max) => {
+                let len = value.chars().count();
+                match max {
+                    Some(max) if len > max => Err(format!("trop long ({} > {})", len, max)),
+                    _ => Ok(SqlValue::Text(Some(value.to_string()))),
+                }
+            }
+            Self::TinyInt => parse_integer(v).map(|n| SqlValue::TinyInt(Some(n))),
+            Self::SmallInt => parse_integer(v).map(|n| SqlValue::SmallInt(Some(n))),
+            Self::Int => parse_integer(v).map(|n| SqlValue::Int(Some(n))),
+            Self::BigInt => parse_integer(v).map(|n| SqlValue::BigInt(Some(n))),
+            Self::Decimal { precision, scale } => {
+                let d = Decimal::from_str(&v.replace(',', "."))
+                    .map_err(|_| format!("décimal invalide: {}", v))?
+                    .round_dp_with_strategy(scale, RoundingStrategy::MidpointAwayFromZero);
+                let int_digits = d.trunc().abs().to_string().trim_start_matches('0').len() as u32;
+                if int_digits > precision - scale {
+                    return Err(format!("dépasse decimal({},{}): {}", precision, scale, v));
+                }
+                Ok(SqlValue::Decimal(Some(d)))
+            }
+            Self::Bit => match v.to_lowercase().as_str() {
+                "1" | "true" | "vrai" | "o" | "oui" | "y" | "yes" => Ok(SqlValue::Bit(Some(true))),
+                "0" | "false" | "faux" | "n" | "non" | "no" => Ok(SqlValue::Bit(Some(false))),
+                _ => Err(format!("booléen invalide: {}", v)),
+            },
+            Self::Date => parse_datetime(v)
+                .map(|dt| dt.date())
+                .or_else(|| parse_date_heuristic(v))
+                .map(|d| SqlValue::Date(Some(d)))
+                .ok_or_else(|| format!("date invalide: {}", v)),
+            Self::DateTime2 => parse_datetime(v)
+                .map(|dt| SqlValue::DateTime(Some(dt)))
+                .ok_or_else(|| format!("date/heure invalide: {}", v)),
+        }
+    }
+}
+
+fn parse_integer<T: FromStr>(v: &str) -> Result<T, String> {
+    // Accept "12.0" / "12,00" as produced by decimal-looking files, but not "12.5".
+    let normalized = v.replace(',', ".");
+    let int_part = match normalized.split_once('.') {
+        Some((int, frac)) if frac.chars().all(|c| c == '0') => int,
+        _ => normalized.as_str(),
+    };
+    int_part
+        .parse::<T>()
+        .map_err(|_| format!("entier invalide ou hors limites: {}", v))
+}
+
+/// Date and time as written by transformations (ISO `T` form) or as found in files.
+fn parse_datetime(v: &str) -> Option<NaiveDateTime> {
+    [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%d/%m/%Y %H:%M:%S%.f",
+    ]
+    .iter()
+    .find_map(|fmt| NaiveDateTime::parse_from_str(v, fmt).ok())
+    .or_else(|| parse_datetime_heuristic(v))
+}
+
+/// Declared target types of a model, keyed by SQL field. Fields without a
+/// type keep being bound as text.
+pub(crate) struct ColumnTypes {
+    types: HashMap<String, SqlType>,
+}
+
+impl ColumnTypes {
+    pub(crate) fn new(mappings: &[MappingRow]) -> Result<Self, String> {
+        let mut types = HashMap::new();
+        for m in mappings {
+            let raw = match m.sql_type.as_deref().map(str::trim) {
+                Some(t) if !t.is_empty() => t,
+                _ => continue,
+            };
+            let ty = SqlType::parse(raw).map_err(|e| format!("{} ({})", e, m.sql_field))?;
+            types.insert(m.sql_field.clone(), ty);
+        }
+        Ok(Self { types })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    fn convert(&self, field: &str, value: &str) -> Result<SqlValue, String> {
+        match self.types.get(field) {
+            Some(ty) => ty.convert(value).map_err(|e| format!("{} {}", field, e)),
+            None => Ok(SqlValue::Text(Some(value.to_string()))),
+        }
+    }
+
+    /// Conversion errors of a mapped row; empty when every typed field converts.
+    pub(crate) fn check(&self, row: &HashMap<String, String>) -> Vec<String> {
+        let mut fields: Vec<&String> = self.types.keys().collect();
+        fields.sort();
+        fields
+            .into_iter()
+            .filter_map(|f| {
+                self.convert(f, row.get(f).map(String::as_str).unwrap_or(""))
+                    .err()
+            })
+            .collect()
+    }
+
+    /// Native values for `fields`, in order.
+    pub(crate) fn bind(
+        &self,
+        fields: &[String],
+        row: &HashMap<String, String>,
+    ) -> Result<Vec<SqlValue>, String> {
+        fields
+            .iter()
+            .map(|f| self.convert(f, row.get(f).map(String::as_str).unwrap_or("")))
+            .collect()
+    }
+}
+
+/// Check that every mapping's declared type parses; used when mappings are saved.
+pub(crate) fn check_sql_types(mappings: &[MappingRow]) -> Result<(), String> {
+    ColumnTypes::new(mappings).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ty(raw: &str) -> SqlType {
+        SqlType::parse(raw).unwrap()
+    }
+
+    fn decimal(raw: &str, value: &str) -> Result<SqlValue, String> {
+        ty(raw).convert(value)
+    }
+
+    fn dec(value: &str) -> SqlValue {
+        SqlValue::Decimal(Some(Decimal::from_str(value).unwrap()))
+    }
+
+    #[test]
+    fn parses_declared_types() {
+        assert_eq!(ty(" NVARCHAR(20) "), SqlType::NVarChar(Some(20)));
+        assert_eq!(ty("varchar(max)"), SqlType::NVarChar(None));
+        assert_eq!(
+            ty("numeric(18, 3)"),
+            SqlType::Decimal {
+                precision: 18,
+                scale: 3
+            }
+        );
+        assert_eq!(
+            ty("decimal"),
+            SqlType::Decimal {
+                precision: 18,
+                scale: 0
+            }
+        );
+        assert_eq!(ty("datetime"), SqlType::DateTime2);
+        for raw in [
+            "decimal(29,2)",
+            "decimal(0)",
+            "decimal(4,5)",
+            "nvarchar(x)",
+            "int(4",
+            "money",
+        ] {
+            assert!(SqlType::parse(raw).is_err(), "{}", raw);
+        }
+        assert!(SqlType::parse("decimal(28,10)").is_ok());
+    }
+
+    #[test]
+    fn decimals_round_then_check_precision() {
+        assert_eq!(decimal("decimal(5,2)", "123,456"), Ok(dec("123.46")));
+        assert_eq!(decimal("decimal(5,2)", "-0.125"), Ok(dec("-0.13")));
+        assert_eq!(decimal("decimal(5,2)", "999.99"), Ok(dec("999.99")));
+        // Rounding can add an integer digit.
+        assert!(decimal("decimal(5,2)", "999.995").is_err());
+        assert!(decimal("decimal(5,2)", "1000").is_err());
+        // The leading 0 of 0.x is not an integer digit.
+        assert_eq!(decimal("decimal(3,3)", "0.5"), Ok(dec("0.500")));
+        assert_eq!(decimal("decimal(3,3)", "-0,9994"), Ok(dec("-0.999")));
+        assert!(decimal("decimal(3,3)", "1.0").is_err());
+        assert!(decimal("decimal(5,2)", "12a").is_err());
+        assert!(decimal("decimal(28,0)", "99999999999999999999999999999999").is_err());
+    }
+
+    #[test]
+    fn integers_accept_a_zero_fraction_only() {
+        assert_eq!(ty("int").convert("12,00"), Ok(SqlValue::Int(Some(12))));
+        assert_eq!(ty("int").convert("12.0"), Ok(SqlValue::Int(Some(12))));
+        assert_eq!(ty("int").convert(" -7 "), Ok(SqlValue::Int(Some(-7))));
+        assert!(ty("int").convert("12.5").is_err());
+        assert!(ty("int").convert("1 000").is_err());
+        assert!(ty("tinyint").convert("256").is_err());
+        assert!(ty("tinyint").convert("-1").is_err());
+        assert_eq!(
+            ty("bigint").convert("9000000000"),
+            Ok(SqlValue::BigInt(Some(9_000_000_000)))
+        );
+    }
+
+    #[test]
+    fn bit_spellings() {
+        for v in ["1", "true", "VRAI", "o", "Oui", "y", "yes"] {
+            assert_eq!(ty("bit").convert(v), Ok(SqlValue::Bit(Some(true))), "{}", v);
+        }
+        for v in ["0", "false", "Faux", "n", "NON", "no"] {
+            assert_eq!(
+                ty("bit").convert(v),
+                Ok(SqlValue::Bit(Some(false))),
+                "{}",
+                v
+            );
+        }
+        assert!(ty("bit").convert("2").is_err());
+    }
+
+    #[test]
+    fn empty_values_are_null_except_text() {
+        assert_eq!(ty("int").convert("  "), Ok(SqlValue::Int(None)));
+        assert_eq!(ty("decimal(5,2)").convert(""), Ok(SqlValue::Decimal(None)));
+        assert_eq!(ty("bit").convert(""), Ok(SqlValue::Bit(None)));
+        assert_eq!(ty("date").convert(""), Ok(SqlValue::Date(None)));
+        assert_eq!(ty("datetime2").convert(""), Ok(SqlValue::DateTime(None)));
+        assert_eq!(
+            ty("nvarchar(5)").convert("  "),
+            Ok(SqlValue::Text(Some("  ".to_string())))
+        );
+    }
+
+    #[test]
+    fn nvarchar_length_counts_characters() {
+        assert_eq!(
+            ty("nvarchar(5)").convert("éèàçù"),
+            Ok(SqlValue::Text(Some("éèàçù".to_string())))
+        );
+        assert!(ty("nvarchar(5)").convert("éèàçùa").is_err());
+        assert!(ty("nvarchar(max)").convert(&"x".repeat(10_000)).is_ok());
+    }
+
+    #[test]
+    fn dates_and_datetimes() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 15).unwrap();
+        assert_eq!(
+            ty("date").convert("2024-03-15T08:30:00"),
+            Ok(SqlValue::Date(Some(date)))
+        );
+        assert_eq!(
+            ty("datetime2").convert("15/03/2024 08:30:00"),
+            Ok(SqlValue::DateTime(Some(
+                date.and_hms_opt(8, 30, 0).unwrap()
+            )))
+        );
+        assert!(ty("date").convert("hier").is_err());
+    }
+}
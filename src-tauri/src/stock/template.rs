@@ -0,0 +1,293 @@
+use crate::commands::mappings::MappingRow;
+
+/// An SQL template ready to execute: named placeholders rewritten to
+/// `@P1..@Pn`, and the mapped field bound to each of them.
+#[derive(Debug, Clone)]
+pub(crate) struct SqlTemplate {
+    pub sql: String,
+    pub fields: Vec<String>,
+}
+
+impl SqlTemplate {
+    /// Templates may name their parameters (`:YSSCC_0` or `{{ITMREF_0}}`), which
+    /// works for any statement (MERGE, `EXEC`, subqueries). Templates written
+    /// with bare `@Pn` keep binding by INSERT column list, or mapping order.
+    pub(crate) fn compile(query: &str, mappings: &[MappingRow]) -> Result<Self, String> {
+        let (sql, names) = rewrite_named_placeholders(query)?;
+        if names.is_empty() {
+            let mut fields = parse_insert_columns(query);
+            if fields.is_empty() {
+                fields = mappings.iter().map(|m| m.sql_field.clone()).collect();
+            }
+            return Ok(Self { sql, fields });
+        }
+
+        let fields = names
+            .iter()
+            .map(|name| {
+                mappings
+                    .iter()
+                    .find(|m| m.sql_field == *name)
+                    .or_else(|| {
+                        mappings
+                            .iter()
+                            .find(|m| m.sql_field.eq_ignore_ascii_case(name))
+                    })
+                    .map(|m| m.sql_field.clone())
+                    .ok_or_else(|| {
+                        format!(
+                            "Paramètre {} du template SQL sans mapping correspondant",
+                            name
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { sql, fields })
+    }
+}
+
+/// Check the placeholder syntax of a template; used when templates are saved.
+pub(crate) fn check_sql_template(query: &str) -> Result<(), String> {
+    rewrite_named_placeholders(query).map(|_| ())
+}
+
+pub(crate) fn parse_insert_columns(query: &str) -> Vec<String> {
+    let lower = query.to_lowercase();
+    let insert_pos = lower.find("insert");
+    if insert_pos.is_none() {
+        return Vec::new();
+    }
+
+    let open_paren = query[insert_pos.unwrap()..]
+        .find('(')
+        .map(|i| i + insert_pos.unwrap());
+    let values_pos = lower.find(") values");
+    if open_paren.is_none() || values_pos.is_none() {
+        return Vec::new();
+    }
+
+    let open = open_paren.unwrap();
+    let close = values_pos.unwrap();
+    if close <= open {
+        return Vec::new();
+    }
+
+    query[open + 1..close]
+        .split(',')
+        .map(|s| {
+            s.trim()
+                .trim_matches('[')
+                .trim_matches(']')
+                .trim()
+                .to_string()
+        })
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Target table of an `INSERT [INTO] table (...)` template.
+pub(crate) fn insert_target(query: &str) -> Option<String> {
+    let lower = query.to_lowercase();
+    let start = lower.find("insert")? + "insert".len();
+    let open = start + query[start..].find('(')?;
+    let target = query[start..open].trim();
+    let target = match target.split_once(char::is_whitespace) {
+        Some((kw, rest)) if kw.eq_ignore_ascii_case("into") => rest.trim(),
+        _ => target,
+    };
+    (!target.is_empty()).then(|| target.to_string())
+}
+
+fn is_name_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_'
+}
+
+fn is_name_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// Rewrite `:NAME` and `{{NAME}}` as `@Pn`, one index per distinct name in order
+/// of first use. String literals, quoted identifiers and comments are left alone.
+fn rewrite_named_placeholders(query: &str) -> Result<(String, Vec<String>), String> {
+    let bytes = query.as_bytes();
+    let mut out = String::with_capacity(query.len());
+    let mut names: Vec<String> = Vec::new();
+    let mut copied = 0;
+    let mut i = 0;
+
+    let mut placeholder = |out: &mut String, name: &str| {
+        let index = match names.iter().position(|n| n == name) {
+            Some(pos) => pos + 1,
+            None => {
+                names.push(name.to_string());
+                names.len()
+            }
+        };
+        out.push_str(&format!("@P{}", index));
+    };
+
+    while i < bytes.len() {
+        let skip_to = match bytes[i] {
+            b'\'' => query[i + 1..].find('\'').map(|p| i + 1 + p + 1),
+            b'"' => query[i + 1..].find('"').map(|p| i + 1 + p + 1),
+            b'[' => query[i + 1..].find(']').map(|p| i + 1 + p + 1),
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                Some(query[i..].find('\n').map(|p| i + p).unwrap_or(bytes.len()))
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => Some(
+                query[i + 2..]
+                    .find("*/")
+                    .map(|p| i + 2 + p + 2)
+                    .unwrap_or(bytes.len()),
+            ),
+            _ => None,
+        };
+        if let Some(next) = skip_to {
+            i = next;
+            continue;
+        }
+
+        if bytes[i] == b'{' && bytes.get(i + 1) == Some(&b'{') {
+            let close = query[i + 2..]
+                .find("}}")
+                .map(|p| i + 2 + p)
+                .ok_or("Template SQL: '{{' sans '}}' correspondant")?;
+            let name = query[i + 2..close].trim();
+            if name.is_empty()
+                || !is_name_start(name.as_bytes()[0])
+                || !name.bytes().all(is_name_char)
+            {
+                return Err(format!("Template SQL: paramètre invalide {{{{{}}}}}", name));
+            }
+            out.push_str(&query[copied..i]);
+            placeholder(&mut out, name);
+            i = close + 2;
+            copied = i;
+            continue;
+        }
+
+        if bytes[i] == b':'
+            && bytes.get(i + 1).copied().is_some_and(is_name_start)
+            && (i == 0 || bytes[i - 1] != b':')
+        {
+            let mut end = i + 1;
+            while end < bytes.len() && is_name_char(bytes[end]) {
+                end += 1;
+            }
+            out.push_str(&query[copied..i]);
+            placeholder(&mut out, &query[i + 1..end]);
+            i = end;
+            copied = i;
+            continue;
+        }
+
+        i += 1;
+    }
+
+    out.push_str(&query[copied..]);
+    Ok((out, names))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mappings(fields: &[&str]) -> Vec<MappingRow> {
+        fields
+            .iter()
+            .enumerate()
+            .map(|(i, f)| MappingRow {
+                id: None,
+                line_id: 1,
+                sort_order: i as i64,
+                sql_field: f.to_string(),
+                file_column: None,
+                parameter: None,
+                transformation: None,
+                description: None,
+                enabled: Some(true),
+                validation: None,
+                date_format: None,
+                sql_type: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge_template_binds_by_name() {
+        let query = "MERGE INTO STOCK AS t USING (SELECT :YSSCC_0 AS sscc, {{ITMREF_0}} AS itm) AS s \
+                     ON t.YSSCC_0 = s.sscc \
+                     WHEN MATCHED THEN UPDATE SET ITMREF_0 = s.itm, QTY_0 = :qty_0 \
+                     WHEN NOT MATCHED THEN INSERT (YSSCC_0, ITMREF_0) VALUES (:YSSCC_0, {{ ITMREF_0 }});";
+        let t = SqlTemplate::compile(query, &mappings(&["ITMREF_0", "QTY_0", "YSSCC_0"])).unwrap();
+        assert_eq!(
+            t.sql,
+            "MERGE INTO STOCK AS t USING (SELECT @P1 AS sscc, @P2 AS itm) AS s \
+             ON t.YSSCC_0 = s.sscc \
+             WHEN MATCHED THEN UPDATE SET ITMREF_0 = s.itm, QTY_0 = @P3 \
+             WHEN NOT MATCHED THEN INSERT (YSSCC_0, ITMREF_0) VALUES (@P1, @P2);"
+        );
+        // Case-insensitive fallback resolves to the mapping's own spelling.
+        assert_eq!(t.fields, ["YSSCC_0", "ITMREF_0", "QTY_0"]);
+    }
+
+    #[test]
+    fn exec_template_binds_by_name() {
+        let t = SqlTemplate::compile(
+            "EXEC dbo.ImportPalette @sscc = :SSCC, @qty = :QTY",
+            &mappings(&["QTY", "SSCC"]),
+        )
+        .unwrap();
+        assert_eq!(t.sql, "EXEC dbo.ImportPalette @sscc = @P1, @qty = @P2");
+        assert_eq!(t.fields, ["SSCC", "QTY"]);
+    }
+
+    #[test]
+    fn casts_literals_and_comments_are_not_placeholders() {
+        let query = "INSERT INTO T (A, B, C) VALUES (:A, CAST(:B AS INT)::varchar, ':C {{C}}') \
+                     -- :D {{D}}\n/* :E {{E}} */ [weird:name] \":F\"";
+        let (sql, names) = rewrite_named_placeholders(query).unwrap();
+        assert_eq!(names, ["A", "B"]);
+        assert_eq!(
+            sql,
+            "INSERT INTO T (A, B, C) VALUES (@P1, CAST(@P2 AS INT)::varchar, ':C {{C}}') \
+             -- :D {{D}}\n/* :E {{E}} */ [weird:name] \":F\""
+        );
+    }
+
+    #[test]
+    fn positional_templates_bind_by_column_list_or_mapping_order() {
+        let t = SqlTemplate::compile(
+            "INSERT INTO T ([B], C, A) VALUES (@P1, @P2, @P3)",
+            &mappings(&["A", "B", "C"]),
+        )
+        .unwrap();
+        assert_eq!(t.sql, "INSERT INTO T ([B], C, A) VALUES (@P1, @P2, @P3)");
+        assert_eq!(t.fields, ["B", "C", "A"]);
+
+        let t = SqlTemplate::compile("EXEC dbo.Import @P1, @P2", &mappings(&["A", "B"])).unwrap();
+        assert_eq!(t.fields, ["A", "B"]);
+    }
+
+    #[test]
+    fn invalid_placeholders_are_rejected() {
+        assert!(SqlTemplate::compile("EXEC p :MISSING", &mappings(&["A"])).is_err());
+        assert!(check_sql_template("SELECT {{A").is_err());
+        assert!(check_sql_template("SELECT {{1A}}").is_err());
+        assert!(check_sql_template("SELECT {{}}").is_err());
+        assert!(check_sql_template("SELECT '{{' + :A").is_ok());
+    }
+
+    #[test]
+    fn insert_target_skips_into() {
+        assert_eq!(
+            insert_target("INSERT INTO dbo.T (A) VALUES (@P1)").as_deref(),
+            Some("dbo.T")
+        );
+        assert_eq!(
+            insert_target("insert [T] (A) values (@P1)").as_deref(),
+            Some("[T]")
+        );
+        assert_eq!(insert_target("EXEC p @P1"), None);
+    }
+}
@@ -1,4 +1,4 @@
-use chrono::Local;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 
 pub(crate) fn apply_split(value: &str, part: &str) -> String {
     if let Some((before, after)) = value.split_once('+') {
@@ -14,111 +14,24 @@ pub(crate) fn apply_split(value: &str, part: &str) -> String {
     }
 }
 
-pub(crate) fn apply_transformation(value: String, transformation: &str) -> String {
+/// Historical single-name transformations. Date steps fall back to `now` when
+/// the value cannot be parsed; only lenient `dates::DateOptions` get there.
+pub(crate) fn apply_transformation(value: String, transformation: &str, now: NaiveDateTime) -> String {
     match transformation {
         "date" => {
             let v = value.trim();
-            if v.is_empty() {
-                return Local::now().format("%d/%m/%Y").to_string();
-            }
-
-            if v.chars().all(|c| c.is_ascii_digit()) && v.len() >= 8 {
-                if let Ok(dt) = chrono::NaiveDate::parse_from_str(&v[0..8], "%Y%m%d") {
-                    return dt.format("%d/%m/%Y").to_string();
-                }
-            }
-
-            let formats = [
-                "%d/%m/%Y",
-                "%Y-%m-%d",
-                "%d-%m-%Y",
-                "%d.%m.%Y",
-                "%d/%m/%y",
-                "%d-%m-%y",
-                "%d.%m.%y",
-                "%Y%m%d",
-            ];
-
-            for fmt in formats {
-                if let Ok(dt) = chrono::NaiveDate::parse_from_str(v, fmt) {
-                    return dt.format("%d/%m/%Y").to_string();
-                }
-            }
-            Local::now().format("%d/%m/%Y").to_string()
-        }
-        "heure" => {
-            let digits: String = value.chars().filter(|c| c.is_ascii_digit()).collect();
-            if digits.len() >= 14 {
-                digits[8..14].to_string()
-            } else if digits.len() >= 12 {
-                format!("{}00", &digits[8..12])
-            } else if digits.len() >= 6 {
-                digits[..6].to_string()
-            } else if digits.len() >= 4 {
-                format!("{}00", &digits[..4])
-            } else {
-                "000000".to_string()
-            }
-        }
-        "datetime" => {
-            let v = value.trim();
-            if v.is_empty() {
-                return Local::now().format("%d/%m/%Y %H:%M:%S").to_string();
-            }
-
-            if v.chars().all(|c| c.is_ascii_digit()) {
-                if v.len() >= 14 {
-                    if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(&v[..14], "%Y%m%d%H%M%S") {
-                        return dt.format("%d/%m/%Y %H:%M:%S").to_string();
-                    }
-                }
-                if v.len() >= 8 {
-                    if let Ok(d) = chrono::NaiveDate::parse_from_str(&v[..8], "%Y%m%d") {
-                        let t_str = if v.len() >= 14 { &v[8..14] } else { "000000" };
-                        if let Ok(t) = chrono::NaiveTime::parse_from_str(t_str, "%H%M%S") {
-                            return chrono::NaiveDateTime::new(d, t)
-                                .format("%d/%m/%Y %H:%M:%S")
-                                .to_string();
-                        }
-                        return d.and_hms_opt(0, 0, 0)
-                            .unwrap()
-                            .format("%d/%m/%Y %H:%M:%S")
-                            .to_string();
-                    }
-                }
-            }
-
-            let formats = [
-                "%d/%m/%Y %H:%M:%S",
-                "%d/%m/%Y %H:%M",
-                "%Y-%m-%d %H:%M:%S",
-                "%Y-%m-%d %H:%M",
-                "%Y%m%d %H%M%S",
-                "%Y%m%d%H%M%S",
-                "%d/%m/%Y",
-                "%Y-%m-%d",
-                "%Y%m%d",
-            ];
-
-            for fmt in formats {
-                if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(v, fmt) {
-                    return dt.format("%d/%m/%Y %H:%M:%S").to_string();
-                }
-                if let Ok(d) = chrono::NaiveDate::parse_from_str(v, fmt) {
-                    let dt = d
-                        .and_hms_opt(0, 0, 0)
-                        .unwrap_or_else(|| {
-                            chrono::NaiveDateTime::new(
-                                d,
-                                chrono::NaiveTime::from_hms_opt(0, 0, 0).unwrap(),
-                            )
-                        });
-                    return dt.format("%d/%m/%Y %H:%M:%S").to_string();
-                }
-            }
-
-            Local::now().format("%d/%m/%Y %H:%M:%S").to_string()
+            parse_date_heuristic(v)
+                .unwrap_or_else(|| now.date())
+                .format("%d/%m/%Y")
+                .to_string()
         }
+        "heure" => parse_time_heuristic(&value)
+            .map(|t| t.format("%H%M%S").to_string())
+            .unwrap_or_else(|| "000000".to_string()),
+        "datetime" => parse_datetime_heuristic(value.trim())
+            .unwrap_or(now)
+            .format("%d/%m/%Y %H:%M:%S")
+            .to_string(),
         "decimal" => {
             let cleaned = value.replace(',', ".");
             cleaned
@@ -130,41 +43,124 @@ pub(crate) fn apply_transformation(value: String, transformation: &str) -> Strin
             let n = value.trim().parse::<i64>().unwrap_or(1);
             if n == 2 { "2".to_string() } else { "1".to_string() }
         }
-        "current_datetime" => Local::now().format("%d/%m/%Y %H:%M:%S").to_string(),
-        "datetime_combine" => {
-            let parts: Vec<&str> = value.split(';').collect();
-            if parts.len() < 2 {
-                return Local::now().format("%d/%m/%Y %H:%M:%S").to_string();
-            }
-            let date_part = parts[0].trim();
-            let time_part = parts[1].trim();
+        "current_datetime" => now.format("%d/%m/%Y %H:%M:%S").to_string(),
+        "datetime_combine" => parse_combined_heuristic(&value)
+            .unwrap_or(now)
+            .format("%d/%m/%Y %H:%M:%S")
+            .to_string(),
+        _ => value,
+    }
+}
 
-            let date_formats = ["%d/%m/%Y", "%Y-%m-%d", "%d-%m-%Y", "%d.%m.%Y", "%Y%m%d"];
-            let time_formats = ["%H:%M:%S", "%H.%M.%S", "%H%M%S", "%H:%M", "%H.%M"];
+/// Date in one of the usual line formats (`20240131...`, `31/01/2024`, `2024-01-31`...).
+pub(crate) fn parse_date_heuristic(v: &str) -> Option<NaiveDate> {
+    if v.is_empty() {
+        return None;
+    }
 
-            let mut date_obj: Option<chrono::NaiveDate> = None;
-            let mut time_obj: Option<chrono::NaiveTime> = None;
-            for fmt in date_formats {
-                if let Ok(d) = chrono::NaiveDate::parse_from_str(date_part, fmt) {
-                    date_obj = Some(d);
-                    break;
-                }
+    if v.chars().all(|c| c.is_ascii_digit()) && v.len() >= 8 {
+        if let Ok(dt) = NaiveDate::parse_from_str(&v[0..8], "%Y%m%d") {
+            return Some(dt);
+        }
+    }
+
+    let formats = [
+        "%d/%m/%Y",
+        "%Y-%m-%d",
+        "%d-%m-%Y",
+        "%d.%m.%Y",
+        "%d/%m/%y",
+        "%d-%m-%y",
+        "%d.%m.%y",
+        "%Y%m%d",
+    ];
+
+    formats
+        .iter()
+        .find_map(|fmt| NaiveDate::parse_from_str(v, fmt).ok())
+}
+
+/// Date and time in one of the usual line formats; a date alone means midnight.
+pub(crate) fn parse_datetime_heuristic(v: &str) -> Option<NaiveDateTime> {
+    if v.is_empty() {
+        return None;
+    }
+
+    if v.chars().all(|c| c.is_ascii_digit()) {
+        if v.len() >= 14 {
+            if let Ok(dt) = NaiveDateTime::parse_from_str(&v[..14], "%Y%m%d%H%M%S") {
+                return Some(dt);
             }
-            for fmt in time_formats {
-                if let Ok(t) = chrono::NaiveTime::parse_from_str(time_part, fmt) {
-                    time_obj = Some(t);
-                    break;
+        }
+        if v.len() >= 8 {
+            if let Ok(d) = NaiveDate::parse_from_str(&v[..8], "%Y%m%d") {
+                let t_str = if v.len() >= 14 { &v[8..14] } else { "000000" };
+                if let Ok(t) = NaiveTime::parse_from_str(t_str, "%H%M%S") {
+                    return Some(NaiveDateTime::new(d, t));
                 }
+                return d.and_hms_opt(0, 0, 0);
             }
+        }
+    }
 
-            if let (Some(d), Some(t)) = (date_obj, time_obj) {
-                chrono::NaiveDateTime::new(d, t)
-                    .format("%d/%m/%Y %H:%M:%S")
-                    .to_string()
-            } else {
-                Local::now().format("%d/%m/%Y %H:%M:%S").to_string()
-            }
+    let formats = [
+        "%d/%m/%Y %H:%M:%S",
+        "%d/%m/%Y %H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y%m%d %H%M%S",
+        "%Y%m%d%H%M%S",
+        "%d/%m/%Y",
+        "%Y-%m-%d",
+        "%Y%m%d",
+    ];
+
+    for fmt in formats {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(v, fmt) {
+            return Some(dt);
+        }
+        if let Ok(d) = NaiveDate::parse_from_str(v, fmt) {
+            return d.and_hms_opt(0, 0, 0);
         }
-        _ => value,
     }
+    None
+}
+
+/// Time from the digits of the value: `HHMMSS`/`HHMM`, or the time part of
+/// `YYYYMMDDHHMMSS`/`YYYYMMDDHHMM`, so `14:30:00` and `20240131143000` both work.
+pub(crate) fn parse_time_heuristic(v: &str) -> Option<NaiveTime> {
+    let digits: String = v.chars().filter(|c| c.is_ascii_digit()).collect();
+    let hhmmss = if digits.len() >= 14 {
+        digits[8..14].to_string()
+    } else if digits.len() >= 12 {
+        format!("{}00", &digits[8..12])
+    } else if digits.len() >= 6 {
+        digits[..6].to_string()
+    } else if digits.len() >= 4 {
+        format!("{}00", &digits[..4])
+    } else {
+        return None;
+    };
+    NaiveTime::parse_from_str(&hhmmss, "%H%M%S").ok()
+}
+
+/// `date;time` pair produced by an `a-b` file column.
+pub(crate) fn parse_combined_heuristic(value: &str) -> Option<NaiveDateTime> {
+    let parts: Vec<&str> = value.split(';').collect();
+    if parts.len() < 2 {
+        return None;
+    }
+    let date_part = parts[0].trim();
+    let time_part = parts[1].trim();
+
+    let date_formats = ["%d/%m/%Y", "%Y-%m-%d", "%d-%m-%Y", "%d.%m.%Y", "%Y%m%d"];
+    let time_formats = ["%H:%M:%S", "%H.%M.%S", "%H%M%S", "%H:%M", "%H.%M"];
+
+    let d = date_formats
+        .iter()
+        .find_map(|fmt| NaiveDate::parse_from_str(date_part, fmt).ok())?;
+    let t = time_formats
+        .iter()
+        .find_map(|fmt| NaiveTime::parse_from_str(time_part, fmt).ok())?;
+    Some(NaiveDateTime::new(d, t))
 }
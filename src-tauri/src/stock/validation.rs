@@ -0,0 +1,168 @@
+use crate::commands::mappings::MappingRow;
+use crate::stock::fs_utils::timestamped_filename;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Declarative checks stored as JSON in a mapping's `validation` column, e.g.
+/// `{"required": true, "max_length": 20}` or `{"numeric": true, "min": 0, "max": 99999}`.
+/// They apply to the mapped value, after transformation.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct FieldRules {
+    pub required: bool,
+    pub regex: Option<String>,
+    pub numeric: bool,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub max_length: Option<usize>,
+    pub allowed: Option<Vec<String>>,
+}
+
+/// What to do with a file holding invalid rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum InvalidRowsPolicy {
+    /// Reject the whole file (default).
+    RejectFile,
+    /// Insert the valid rows and write the invalid ones to a `.rejected.csv` companion.
+    Split,
+}
+
+impl InvalidRowsPolicy {
+    pub(crate) fn parse(value: Option<&str>) -> Self {
+        match value.map(|v| v.trim().to_uppercase()).as_deref() {
+            Some("SPLIT") => Self::Split,
+            _ => Self::RejectFile,
+        }
+    }
+}
+
+struct CompiledRules {
+    sql_field: String,
+    rules: FieldRules,
+    regex: Option<Regex>,
+}
+
+pub(crate) struct RowValidator {
+    fields: Vec<CompiledRules>,
+}
+
+impl RowValidator {
+    pub(crate) fn new(mappings: &[MappingRow]) -> Result<Self, String> {
+        let mut fields = Vec::new();
+        for m in mappings {
+            let raw = match m.validation.as_deref().map(str::trim) {
+                Some(v) if !v.is_empty() => v,
+                _ => continue,
+            };
+            let rules: FieldRules = serde_json::from_str(raw)
+                .map_err(|e| format!("Validation invalide pour {}: {}", m.sql_field, e))?;
+            let regex = match &rules.regex {
+                Some(r) if !r.is_empty() => Some(Regex::new(r).map_err(|e| {
+                    format!("Expression régulière invalide pour {}: {}", m.sql_field, e)
+                })?),
+                _ => None,
+            };
+            fields.push(CompiledRules {
+                sql_field: m.sql_field.clone(),
+                rules,
+                regex,
+            });
+        }
+        Ok(Self { fields })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Reasons why a mapped row is invalid; empty when the row passes every rule.
+    pub(crate) fn check(&self, row: &HashMap<String, String>) -> Vec<String> {
+        let mut reasons = Vec::new();
+
+        for f in &self.fields {
+            let value = row.get(&f.sql_field).map(|v| v.trim()).unwrap_or("");
+            let rules = &f.rules;
+
+            if value.is_empty() {
+                if rules.required {
+                    reasons.push(format!("{} obligatoire", f.sql_field));
+                }
+                continue;
+            }
+
+            if let Some(max_len) = rules.max_length {
+                let len = value.chars().count();
+                if len > max_len {
+                    reasons.push(format!("{} trop long ({} > {})", f.sql_field, len, max_len));
+                }
+            }
+
+            if let Some(re) = &f.regex {
+                if !re.is_match(value) {
+                    reasons.push(format!("{} ne respecte pas le format attendu", f.sql_field));
+                }
+            }
+
+            if let Some(allowed) = &rules.allowed {
+                if !allowed.iter().any(|a| a.trim() == value) {
+                    reasons.push(format!("{} valeur non autorisée: {}", f.sql_field, value));
+                }
+            }
+
+            if rules.numeric || rules.min.is_some() || rules.max.is_some() {
+                match value.replace(',', ".").parse::<f64>() {
+                    Ok(n) => {
+                        if rules.min.is_some_and(|min| n < min)
+                            || rules.max.is_some_and(|max| n > max)
+                        {
+                            reasons.push(format!(
+                                "{} hors limites ({} pas dans [{}, {}])",
+                                f.sql_field,
+                                value,
+                                rules.min.map(|v| v.to_string()).unwrap_or_default(),
+                                rules.max.map(|v| v.to_string()).unwrap_or_default()
+                            ));
+                        }
+                    }
+                    Err(_) => reasons.push(format!("{} non numérique: {}", f.sql_field, value)),
+                }
+            }
+        }
+
+        reasons
+    }
+}
+
+/// Check that every mapping's validation rules parse; used when mappings are saved.
+pub(crate) fn check_mapping_rules(mappings: &[MappingRow]) -> Result<(), String> {
+    RowValidator::new(mappings).map(|_| ())
+}
+
+/// Write invalid rows, as read from the file plus a reason column, next to the
+/// rejected files. Returns the companion file path.
+pub(crate) fn write_rejected_rows(
+    dir: &Path,
+    filename: &str,
+    delimiter: u8,
+    rows: &[(Vec<String>, String)],
+) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let dest = dir.join(timestamped_filename(filename, Some("rejected.csv")));
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_path(&dest)
+        .map_err(|e| e.to_string())?;
+    for (raw, reason) in rows {
+        let mut record = raw.clone();
+        record.push(reason.clone());
+        writer.write_record(&record).map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())?;
+
+    Ok(dest)
+}
@@ -6,8 +6,10 @@ use std::collections::HashMap;
 use std::path::Path;
 use std::sync::{mpsc, Arc, Mutex};
 use std::time::{Duration, Instant, SystemTime};
+#[cfg(feature = "desktop")]
 use tauri::{AppHandle, Manager};
 
+#[cfg(feature = "desktop")]
 pub fn start_watcher(
     app_handle: AppHandle,
     line_id: i64,
@@ -15,8 +17,29 @@ pub fn start_watcher(
     prefix: String,
     archived_path: Option<String>,
 ) {
-    let state = app_handle.state::<WatcherState>();
+    watch_line(
+        &app_handle.state::<WatcherState>(),
+        &app_handle.state::<crate::db::DbState>(),
+        line_id,
+        path,
+        prefix,
+        archived_path,
+    );
+}
 
+/// Watch a line's folder on a background thread; a no-op if it is already watched.
+pub(crate) fn watch_line(
+    state: &WatcherState,
+    db: &crate::db::DbState,
+    line_id: i64,
+    path: String,
+    prefix: String,
+    archived_path: Option<String>,
+) {
+    if let Some(reason) = state.unavailable() {
+        log::warn!("Ligne {} non surveillée: {}", line_id, reason);
+        return;
+    }
     {
         let watchers = state.watchers.lock().expect("watchers mutex poisoned");
         if watchers.contains_key(&line_id) {
@@ -24,18 +47,26 @@ pub fn start_watcher(
         }
     }
 
-    let pool = app_handle.state::<crate::db::DbState>().pool.clone();
-    let processor = StockProcessor::new(pool);
+    let processor = StockProcessor::new(db.pool.clone(), db.sql.clone());
 
     let (stop_tx, stop_rx) = mpsc::channel::<()>();
+    let alive = Arc::new(());
     let processed_files = Arc::new(Mutex::new(HashMap::<String, SystemTime>::new()));
 
     {
         let mut watchers = state.watchers.lock().expect("watchers mutex poisoned");
-        watchers.insert(line_id, WatcherHandle { stop_tx });
+        watchers.insert(
+            line_id,
+            WatcherHandle {
+                stop_tx,
+                alive: alive.clone(),
+            },
+        );
     }
 
     std::thread::spawn(move || {
+        // Dropped when the thread ends, however it ends.
+        let _alive = alive;
         let (tx, rx) = std::sync::mpsc::channel();
 
         let mut watcher =
@@ -43,7 +74,7 @@ pub fn start_watcher(
 
         let watch_path = Path::new(&path);
         if !watch_path.exists() {
-            eprintln!("Watch path does not exist: {}", path);
+            log::warn!("Watch path does not exist: {}", path);
             return;
         }
 
@@ -56,13 +87,12 @@ pub fn start_watcher(
             processed.insert(file_key, SystemTime::now());
             drop(processed);
 
-            let pr = processor.pool_clone();
-            let proc = StockProcessor::new(pr);
+            let proc = processor.clone();
             let pref = prefix.clone();
             let arch = archived_path.clone();
-            tauri::async_runtime::spawn(async move {
+            crate::runtime::spawn(async move {
                 if let Err(e) = proc.process_file(line_id, p, pref, arch).await {
-                    eprintln!("Error processing existing file: {}", e);
+                    log::error!("Error processing existing file: {}", e);
                 }
             });
         }
@@ -100,16 +130,23 @@ pub fn start_watcher(
                     processed.insert(file_key, SystemTime::now());
                     drop(processed);
 
-                    let pr = processor.pool_clone();
-                    let proc = StockProcessor::new(pr);
+                    let proc = processor.clone();
                     let pref = prefix.clone();
                     let arch = archived_path.clone();
-                    tauri::async_runtime::spawn(async move {
+                    crate::runtime::spawn(async move {
                         if let Err(e) = proc.process_file(line_id, p, pref, arch).await {
-                            eprintln!("Error processing polled file: {}", e);
+                            log::error!("Error processing polled file: {}", e);
                         }
                     });
                 }
+
+                // Retry files deferred after a SQL Server outage once their backoff has elapsed
+                let proc = processor.clone();
+                let arch = archived_path.clone();
+                crate::runtime::spawn(async move {
+                    proc.process_due_queue(line_id, arch).await;
+                });
+
                 last_scan = Instant::now();
             }
 
@@ -117,7 +154,7 @@ pub fn start_watcher(
                 Ok(v) => v,
                 Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
                 Err(e) => {
-                    eprintln!("watch channel error: {:?}", e);
+                    log::error!("watch channel error: {:?}", e);
                     break;
                 }
             };
@@ -139,29 +176,32 @@ pub fn start_watcher(
                             drop(processed);
 
                             let p = path_buf.clone();
-                            let pr = processor.pool_clone();
-                            let proc = StockProcessor::new(pr);
+                            let proc = processor.clone();
                             let pref = prefix.clone();
                             let arch = archived_path.clone();
 
-                            tauri::async_runtime::spawn(async move {
+                            crate::runtime::spawn(async move {
                                 if let Err(e) = proc.process_file(line_id, p, pref, arch).await {
-                                    eprintln!("Error processing file: {}", e);
+                                    log::error!("Error processing file: {}", e);
                                 }
                             });
                         }
                     }
                 }
                 Err(e) => {
-                    eprintln!("watch error: {:?}", e);
+                    log::error!("watch error: {:?}", e);
                 }
             }
         }
     });
 }
 
+#[cfg(feature = "desktop")]
 pub fn stop_watcher(app_handle: AppHandle, line_id: i64) {
-    let state = app_handle.state::<WatcherState>();
+    unwatch_line(&app_handle.state::<WatcherState>(), line_id);
+}
+
+pub(crate) fn unwatch_line(state: &WatcherState, line_id: i64) {
     let handle = {
         let mut watchers = state.watchers.lock().expect("watchers mutex poisoned");
         watchers.remove(&line_id)
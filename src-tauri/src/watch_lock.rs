@@ -0,0 +1,113 @@
+//! One process at a time watches the lines of a data folder: the desktop app,
+//! the headless service or `visor watch`. Two of them would pick up the same
+//! files and reset each other's queue items.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, Write};
+use std::path::Path;
+
+const LOCK_FILE: &str = "watchers.lock";
+
+/// Held for as long as the process watches; the OS releases it when the process
+/// exits, however it exits.
+pub(crate) struct WatchLock {
+    _file: File,
+}
+
+/// Take the lock of `data_dir`, or explain who holds it.
+pub(crate) fn acquire(data_dir: &Path) -> Result<WatchLock, String> {
+    fs::create_dir_all(data_dir).map_err(|e| e.to_string())?;
+    let path = data_dir.join(LOCK_FILE);
+    let mut file = match open_locked(&path) {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            // Unreadable on Windows while held: the message then goes without it.
+            let holder = fs::read_to_string(&path)
+                .ok()
+                .map(|pid| pid.trim().to_string())
+                .filter(|pid| !pid.is_empty())
+                .map(|pid| format!(" (processus {})", pid))
+                .unwrap_or_default();
+            return Err(format!(
+                "Les lignes de {} sont déjà surveillées par l'application, le service ou visor watch{}: \
+                 arrêtez-le avant d'en démarrer un autre",
+                data_dir.display(),
+                holder
+            ));
+        }
+        Err(e) => return Err(format!("Verrou {} impossible: {}", path.display(), e)),
+    };
+
+    // Informational only: the lock itself is what counts.
+    let _ = file
+        .set_len(0)
+        .and_then(|_| file.rewind())
+        .and_then(|_| write!(file, "{}", std::process::id()))
+        .and_then(|_| file.flush());
+    Ok(WatchLock { _file: file })
+}
+
+/// The lock file, locked; `None` when another process holds it.
+#[cfg(unix)]
+fn open_locked(path: &Path) -> io::Result<Option<File>> {
+    use std::os::unix::io::AsRawFd;
+
+    const LOCK_EX: i32 = 2;
+    const LOCK_NB: i32 = 4;
+    extern "C" {
+        fn flock(fd: i32, operation: i32) -> i32;
+    }
+
+    // Not truncated here: the holder's pid must survive a failed attempt.
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    // SAFETY: plain libc call on a descriptor owned by `file`.
+    if unsafe { flock(file.as_raw_fd(), LOCK_EX | LOCK_NB) } == 0 {
+        return Ok(Some(file));
+    }
+    let err = io::Error::last_os_error();
+    if err.kind() == io::ErrorKind::WouldBlock {
+        return Ok(None);
+    }
+    Err(err)
+}
+
+/// Windows: opening without sharing is the lock.
+#[cfg(windows)]
+fn open_locked(path: &Path) -> io::Result<Option<File>> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    match OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .share_mode(0)
+        .open(path)
+    {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if e.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_held_lock_is_refused_until_dropped() {
+        let dir = std::env::temp_dir().join(format!("visor-lock-{}", std::process::id()));
+        let lock = acquire(&dir).unwrap();
+        let err = acquire(&dir).err().unwrap();
+        assert!(err.contains("déjà surveillées"), "{}", err);
+        drop(lock);
+        acquire(&dir).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+    }
+}